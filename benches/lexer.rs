@@ -0,0 +1,82 @@
+//! Lexer throughput on a generated, multi-megabyte preprocessed translation unit.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+// The compiler is a binary crate, so the modules the lexer needs are compiled in directly.
+// Re-exporting them at the root keeps their `crate::` paths resolving. Only part of them is
+// used, and their tests aren't run here, leaving the tests' imports unused.
+#[allow(dead_code, unused_imports)]
+#[path = "../src"]
+mod ccomp {
+    pub mod diagnostics;
+    pub mod lexer;
+    pub mod source_map;
+}
+
+use ccomp::{diagnostics, lexer, source_map};
+
+use diagnostics::Diagnostics;
+use lexer::Lexer;
+use source_map::SourceMap;
+
+const INPUT_SIZE: usize = 16 << 20;
+const RUNS: usize = 10;
+
+/// A function as `gcc -E` leaves it, preceded by the line marker naming where it came from.
+/// `{n}` is replaced to keep every copy's names distinct.
+const UNIT: &str = "# 12 \"bench/unit_{n}.h\" 2\n\
+                    typedef unsigned long size_t_{n};\n\
+                    extern int printf(const char *restrict fmt, ...);\n\
+                    static int f_{n}(int *p, int n) {\n\
+                    \tfor (int i = 0; i < n; ++i) {\n\
+                    \t\tp[i] <<= 2;\n\
+                    \t\tp[i] |= p[i] >> 1 && n != 0 ? -1 : 2147483647;\n\
+                    \t}\n\
+                    \treturn sizeof(int) + _Alignof(long) > 1;\n\
+                    }\n";
+
+fn preprocessed_input() -> String {
+    let mut code = String::with_capacity(INPUT_SIZE + UNIT.len());
+    let mut n = 0;
+    while code.len() < INPUT_SIZE {
+        code.push_str(&UNIT.replace("{n}", &n.to_string()));
+        n += 1;
+    }
+    code
+}
+
+fn main() {
+    let code = preprocessed_input();
+    let mib = code.len() as f64 / (1 << 20) as f64;
+
+    let mut times = Vec::with_capacity(RUNS);
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        // Lexing records the line markers it meets, so every run needs a fresh file.
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("bench.i".to_owned(), code.clone());
+        let mut diagnostics = Diagnostics::new(0);
+
+        let start = Instant::now();
+        let lexed = black_box(Lexer::new(&source_map, file).tokenize(&mut diagnostics));
+        times.push(start.elapsed());
+
+        assert!(!diagnostics.has_errors(), "the input failed to lex.");
+        tokens = lexed.len();
+    }
+    times.sort();
+
+    let throughput = |time: Duration| mib / time.as_secs_f64();
+    println!(
+        "lexer: {tokens} tokens from {mib:.1} MiB, median {:?} ({:.1} MiB/s), best {:?} ({:.1} MiB/s)",
+        times[RUNS / 2],
+        throughput(times[RUNS / 2]),
+        times[0],
+        throughput(times[0]),
+    );
+}
@@ -1,40 +1,122 @@
 use std::path::PathBuf;
 
-use crate::CompileStage;
+use crate::{
+    diagnostics::{self, Code, Diagnostic},
+    optimizer::Optimizations,
+    CompileStage,
+};
 
-pub fn parse(args: Vec<String>) -> (PathBuf, CompileStage) {
+/// Parses the command line, the program's name first.
+///
+/// # Errors
+///
+/// Fails on an unknown argument or a bad flag value, a missing source file, or more than one
+/// stage flag.
+pub fn parse(
+    args: Vec<String>,
+) -> Result<(PathBuf, CompileStage, Optimizations, diagnostics::Options), Diagnostic> {
     let mut flags = 0_u8;
     let mut file_path = PathBuf::new();
+    let mut optimizations = Optimizations::default();
+    let mut diagnostic_options = diagnostics::Options::default();
 
-    args.into_iter().enumerate().for_each(|(i, arg)| {
-        if i == 0 {
-            return;
-        }
+    for arg in args.into_iter().skip(1) {
         match arg.as_ref() {
             "--lex" => flags |= 0x01,
             "--parse" => flags |= 0x02,
             "--code-gen" => flags |= 0x04,
             "-S" => flags |= 0x08,
-            "--help" | "-h" => panic!("Help is not implemented yet."),
+            "--tacky" => flags |= 0x10,
+            "--fold-constants" => optimizations.fold_constants = true,
+            "--eliminate-unreachable-code" => optimizations.eliminate_unreachable_code = true,
+            "--propagate-copies" => optimizations.propagate_copies = true,
+            "--eliminate-dead-stores" => optimizations.eliminate_dead_stores = true,
+            "--optimize" => optimizations = Optimizations::all(),
+            "--inline-functions" => optimizations.inline_functions = true,
+            "--optimize-loops" => optimizations.optimize_loops = true,
+            "--propagate-constants" => optimizations.propagate_constants = true,
+            "--eliminate-common-subexpressions" => {
+                optimizations.eliminate_common_subexpressions = true
+            }
+            arg if arg.starts_with("-ferror-limit=") => {
+                let value = &arg["-ferror-limit=".len()..];
+                diagnostic_options.error_limit = value.parse().map_err(|_| {
+                    Diagnostic::error(format!("invalid value '{value}' for '-ferror-limit'"))
+                        .with_code(Code::InvalidArgumentValue)
+                        .with_note(Diagnostic::note(
+                            "expected the number of errors to stop after, or 0 for no limit",
+                        ))
+                })?;
+            }
+            arg if arg.starts_with("--diagnostics-format=") => {
+                let value = &arg["--diagnostics-format=".len()..];
+                diagnostic_options.format = format(value).ok_or_else(|| {
+                    Diagnostic::error(format!(
+                        "invalid value '{value}' for '--diagnostics-format'"
+                    ))
+                    .with_code(Code::InvalidArgumentValue)
+                    .with_note(Diagnostic::note("expected 'human', 'json' or 'sarif'"))
+                })?;
+            }
+            "--help" | "-h" => return Err(Diagnostic::error("help is not implemented yet")),
             arg if arg.ends_with(".c") => file_path.push(arg),
-            arg => panic!("Unknown argument: '{arg}'. Use -h or --help flag for more information."),
+            arg => {
+                return Err(Diagnostic::error(format!("unknown argument '{arg}'"))
+                    .with_code(Code::UnknownArgument)
+                    .with_note(help()))
+            }
         }
-    });
+    }
 
     if file_path.cmp(&PathBuf::new()).is_eq() {
-        panic!("No source file is specified. Use -h or --help flag for more information.")
+        return Err(Diagnostic::error("no source file is specified")
+            .with_code(Code::MissingSourceFile)
+            .with_note(help()));
     }
 
-    match flags {
-        0 => (file_path, CompileStage::All),
-        1 => (file_path, CompileStage::Lex),
-        2 => (file_path, CompileStage::Parse),
-        4 => (file_path, CompileStage::CodeGen),
-        8 => (file_path, CompileStage::EmitCode),
-        _ => panic!("Only one of the '--lex', '--parse', '--code-gen', or '-S' flags should be passed. Use -h or --help flag for more information."),
+    let compile_stage =
+        match flags {
+            0 => CompileStage::All,
+            1 => CompileStage::Lex,
+            2 => CompileStage::Parse,
+            4 => CompileStage::CodeGen,
+            8 => CompileStage::EmitCode,
+            16 => CompileStage::Tacky,
+            _ => return Err(Diagnostic::error(
+                "only one of the '--lex', '--parse', '--tacky', '--code-gen', or '-S' flags should be passed",
+            )
+            .with_code(Code::ConflictingStages)
+            .with_note(help())),
+        };
+
+    Ok((file_path, compile_stage, optimizations, diagnostic_options))
+}
+
+/// The format the command line asks diagnostics to be printed in, for reporting the error
+/// `parse` failed with: the last valid `--diagnostics-format`, wherever it is, or the human
+/// format if there's none.
+pub fn diagnostics_format(args: &[String]) -> diagnostics::Format {
+    args.iter()
+        .skip(1)
+        .rev()
+        .filter_map(|arg| arg.strip_prefix("--diagnostics-format="))
+        .find_map(format)
+        .unwrap_or_default()
+}
+
+fn format(value: &str) -> Option<diagnostics::Format> {
+    match value {
+        "human" => Some(diagnostics::Format::Human),
+        "json" => Some(diagnostics::Format::Json),
+        "sarif" => Some(diagnostics::Format::Sarif),
+        _ => None,
     }
 }
 
+fn help() -> Diagnostic {
+    Diagnostic::note("use -h or --help flag for more information")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,17 +125,27 @@ mod tests {
     #[test]
     fn test_parse_path() {
         let args = vec!["program".to_string(), "/path/to/file.c".to_string()];
-        let (file_path, compile_stage) = parse(args);
+        let (file_path, compile_stage, optimizations, diagnostic_options) = parse(args).unwrap();
 
         assert_eq!(file_path.file_name(), Some(OsStr::new("file.c")));
         assert_eq!(compile_stage, CompileStage::All);
+        assert_eq!(optimizations, Optimizations::default());
+        assert_eq!(diagnostic_options, diagnostics::Options::default());
     }
 
     #[test]
     fn test_parse_no_flag() {
         let args = vec!["program".to_string(), "file.c".to_string()];
-        let result = parse(args);
-        assert_eq!(result, (Path::new("file.c").to_owned(), CompileStage::All));
+        let result = parse(args).unwrap();
+        assert_eq!(
+            result,
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::All,
+                Optimizations::default(),
+                diagnostics::Options::default()
+            )
+        );
     }
 
     #[test]
@@ -63,8 +155,16 @@ mod tests {
             "--lex".to_string(),
             "file.c".to_string(),
         ];
-        let result = parse(args);
-        assert_eq!(result, (Path::new("file.c").to_owned(), CompileStage::Lex));
+        let result = parse(args).unwrap();
+        assert_eq!(
+            result,
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::Lex,
+                Optimizations::default(),
+                diagnostics::Options::default()
+            )
+        );
     }
 
     #[test]
@@ -74,10 +174,34 @@ mod tests {
             "--parse".to_string(),
             "file.c".to_string(),
         ];
-        let result = parse(args);
+        let result = parse(args).unwrap();
         assert_eq!(
             result,
-            (Path::new("file.c").to_owned(), CompileStage::Parse)
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::Parse,
+                Optimizations::default(),
+                diagnostics::Options::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_tacky_flag() {
+        let args = vec![
+            "program".to_string(),
+            "--tacky".to_string(),
+            "file.c".to_string(),
+        ];
+        let result = parse(args).unwrap();
+        assert_eq!(
+            result,
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::Tacky,
+                Optimizations::default(),
+                diagnostics::Options::default()
+            )
         );
     }
 
@@ -88,10 +212,15 @@ mod tests {
             "--code-gen".to_string(),
             "file.c".to_string(),
         ];
-        let result = parse(args);
+        let result = parse(args).unwrap();
         assert_eq!(
             result,
-            (Path::new("file.c").to_owned(), CompileStage::CodeGen)
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::CodeGen,
+                Optimizations::default(),
+                diagnostics::Options::default()
+            )
         );
     }
 
@@ -102,42 +231,261 @@ mod tests {
             "-S".to_string(),
             "file.c".to_string(),
         ];
-        let result = parse(args);
+        let result = parse(args).unwrap();
         assert_eq!(
             result,
-            (Path::new("file.c").to_owned(), CompileStage::EmitCode)
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::EmitCode,
+                Optimizations::default(),
+                diagnostics::Options::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_fold_constants_flag() {
+        let args = vec![
+            "program".to_string(),
+            "--fold-constants".to_string(),
+            "-S".to_string(),
+            "file.c".to_string(),
+        ];
+        let result = parse(args).unwrap();
+        assert_eq!(
+            result,
+            (
+                Path::new("file.c").to_owned(),
+                CompileStage::EmitCode,
+                Optimizations {
+                    fold_constants: true,
+                    ..Default::default()
+                },
+                diagnostics::Options::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_eliminate_unreachable_code_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--eliminate-unreachable-code".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                eliminate_unreachable_code: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_propagate_copies_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--propagate-copies".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                propagate_copies: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_eliminate_dead_stores_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--eliminate-dead-stores".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                eliminate_dead_stores: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_optimize_flag() {
+        let args = vec![
+            "program".to_string(),
+            "--optimize".to_string(),
+            "file.c".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(optimizations, Optimizations::all());
+    }
+
+    #[test]
+    fn test_parse_inline_functions_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--inline-functions".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                inline_functions: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_optimize_loops_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--optimize-loops".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                optimize_loops: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_propagate_constants_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--propagate-constants".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                propagate_constants: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_eliminate_common_subexpressions_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--eliminate-common-subexpressions".to_string(),
+        ];
+        let (_, _, optimizations, _) = parse(args).unwrap();
+        assert_eq!(
+            optimizations,
+            Optimizations {
+                eliminate_common_subexpressions: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_error_limit_flag() {
+        let args = vec![
+            "program".to_string(),
+            "-ferror-limit=3".to_string(),
+            "file.c".to_string(),
+        ];
+        let (_, _, _, diagnostic_options) = parse(args).unwrap();
+        assert_eq!(
+            diagnostic_options,
+            diagnostics::Options {
+                error_limit: 3,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_diagnostics_format_flag() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--diagnostics-format=sarif".to_string(),
+        ];
+        let (_, _, _, diagnostic_options) = parse(args).unwrap();
+        assert_eq!(
+            diagnostic_options,
+            diagnostics::Options {
+                format: diagnostics::Format::Sarif,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_diagnostics_format() {
+        let args = vec![
+            "program".to_string(),
+            "file.c".to_string(),
+            "--diagnostics-format=xml".to_string(),
+        ];
+        let error = parse(args).unwrap_err();
+        assert_eq!(error.code, Some(Code::InvalidArgumentValue));
+        assert_eq!(
+            error.message,
+            "invalid value 'xml' for '--diagnostics-format'"
         );
     }
 
     #[test]
-    #[should_panic(expected = "No source file is specified.")]
+    fn test_parse_invalid_error_limit() {
+        let args = vec![
+            "program".to_string(),
+            "-ferror-limit=many".to_string(),
+            "file.c".to_string(),
+        ];
+        let error = parse(args).unwrap_err();
+        assert_eq!(error.code, Some(Code::InvalidArgumentValue));
+        assert_eq!(error.message, "invalid value 'many' for '-ferror-limit'");
+    }
+
+    #[test]
     fn test_parse_no_file() {
         let args = vec!["program".to_string()];
-        parse(args);
+        let error = parse(args).unwrap_err();
+        assert_eq!(error.code, Some(Code::MissingSourceFile));
+        assert_eq!(error.message, "no source file is specified");
     }
 
     #[test]
-    #[should_panic(expected = "No source file is specified.")]
     fn test_parse_no_file_with_flag() {
         let args = vec!["program".to_string(), "--lex".to_string()];
-        parse(args);
+        let error = parse(args).unwrap_err();
+        assert_eq!(error.code, Some(Code::MissingSourceFile));
     }
 
     #[test]
-    #[should_panic(expected = "Unknown argument: '--unknown'.")]
     fn test_parse_unknown_flag() {
         let args = vec![
             "program".to_string(),
             "file.c".to_string(),
             "--unknown".to_string(),
         ];
-        parse(args);
+        let error = parse(args).unwrap_err();
+        assert_eq!(error.code, Some(Code::UnknownArgument));
+        assert_eq!(error.message, "unknown argument '--unknown'");
+        assert_eq!(error.notes, vec![help()]);
     }
 
     #[test]
-    #[should_panic(
-        expected = "Only one of the '--lex', '--parse', '--code-gen', or '-S' flags should be passed."
-    )]
     fn test_parse_multiple_flags() {
         let args = vec![
             "program".to_string(),
@@ -145,6 +493,34 @@ mod tests {
             "--parse".to_string(),
             "file.c".to_string(),
         ];
-        parse(args);
+        let error = parse(args).unwrap_err();
+        assert_eq!(error.code, Some(Code::ConflictingStages));
+        assert_eq!(
+            error.message,
+            "only one of the '--lex', '--parse', '--tacky', '--code-gen', or '-S' flags should be passed"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_format_after_error() {
+        let args = vec![
+            "program".to_string(),
+            "--unknown".to_string(),
+            "--diagnostics-format=json".to_string(),
+            "file.c".to_string(),
+        ];
+        assert!(parse(args.clone()).is_err());
+        assert_eq!(diagnostics_format(&args), diagnostics::Format::Json);
+    }
+
+    #[test]
+    fn test_diagnostics_format_ignores_invalid_values() {
+        let args = vec![
+            "program".to_string(),
+            "--diagnostics-format=sarif".to_string(),
+            "--diagnostics-format=xml".to_string(),
+        ];
+        assert_eq!(diagnostics_format(&args), diagnostics::Format::Sarif);
+        assert_eq!(diagnostics_format(&args[..1]), diagnostics::Format::Human);
     }
 }
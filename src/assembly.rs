@@ -0,0 +1,413 @@
+//! x86-64 assembly, as produced from TACKY.
+//!
+//! Values start out in pseudo-registers, one per TACKY variable. Register allocation then
+//! assigns hardware registers to as many of them as it can; the rest are given stack slots.
+
+pub mod codegen;
+pub mod emission;
+pub mod instruction_fixup;
+pub mod peephole;
+pub mod pseudo_replacement;
+pub mod register_allocation;
+pub mod tail_calls;
+
+use std::collections::HashMap;
+
+use crate::tacky::StaticInit;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AsmType {
+    Byte,
+    Longword,
+    Quadword,
+    Double,
+    /// An array, which only ever lives in memory.
+    ByteArray {
+        size: i64,
+        alignment: i64,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub enum Reg {
+    AX,
+    BX,
+    CX,
+    DX,
+    DI,
+    SI,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    SP,
+    BP,
+    XMM0,
+    XMM1,
+    XMM2,
+    XMM3,
+    XMM4,
+    XMM5,
+    XMM6,
+    XMM7,
+    XMM8,
+    XMM9,
+    XMM10,
+    XMM11,
+    XMM12,
+    XMM13,
+    XMM14,
+    XMM15,
+}
+
+/// Registers used to pass integer and pointer arguments, in order.
+pub const INT_PARAM_REGS: [Reg; 6] = [Reg::DI, Reg::SI, Reg::DX, Reg::CX, Reg::R8, Reg::R9];
+
+/// Registers used to pass `double` arguments, in order.
+pub const DOUBLE_PARAM_REGS: [Reg; 8] = [
+    Reg::XMM0,
+    Reg::XMM1,
+    Reg::XMM2,
+    Reg::XMM3,
+    Reg::XMM4,
+    Reg::XMM5,
+    Reg::XMM6,
+    Reg::XMM7,
+];
+
+impl Reg {
+    /// Whether a function that uses the register must save it and restore it before
+    /// returning, per the System V ABI. No `xmm` register is.
+    ///
+    /// `SP` and `BP` must be preserved too, but the prologue and epilogue take care of them,
+    /// so they're never pushed and popped like the others.
+    pub fn is_callee_saved(self) -> bool {
+        matches!(self, Reg::BX | Reg::R12 | Reg::R13 | Reg::R14 | Reg::R15)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
+pub enum Operand {
+    Imm(i64),
+    Reg(Reg),
+    /// A TACKY scalar variable, until register allocation replaces it.
+    Pseudo(String),
+    /// `offset` bytes into a TACKY aggregate variable, which always lives in memory.
+    PseudoMem(String, i64),
+    /// `offset(reg)`.
+    Memory(Reg, i64),
+    /// `(base, index, scale)`.
+    Indexed {
+        base: Reg,
+        index: Reg,
+        scale: u8,
+    },
+    /// A static variable or constant, addressed relative to `%rip`.
+    Data(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum CondCode {
+    E,
+    NE,
+    G,
+    GE,
+    L,
+    LE,
+    A,
+    AE,
+    B,
+    BE,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    Shr,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mult,
+    DivDouble,
+    And,
+    Or,
+    Xor,
+    Sal,
+    Sar,
+    Shr,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instruction {
+    Mov(AsmType, Operand, Operand),
+    Movsx {
+        src_type: AsmType,
+        dst_type: AsmType,
+        src: Operand,
+        dst: Operand,
+    },
+    MovZeroExtend {
+        src_type: AsmType,
+        dst_type: AsmType,
+        src: Operand,
+        dst: Operand,
+    },
+    Lea(Operand, Operand),
+    Cvttsd2si(AsmType, Operand, Operand),
+    Cvtsi2sd(AsmType, Operand, Operand),
+    Unary(UnaryOp, AsmType, Operand),
+    Binary(BinaryOp, AsmType, Operand, Operand),
+    Cmp(AsmType, Operand, Operand),
+    /// Sets the flags from the bitwise and of its operands, like `Cmp` does from a subtraction.
+    Test(AsmType, Operand, Operand),
+    Idiv(AsmType, Operand),
+    Div(AsmType, Operand),
+    /// Sign-extends `%eax` into `%edx`, or `%rax` into `%rdx`.
+    Cdq(AsmType),
+    Jmp(String),
+    JmpCC(CondCode, String),
+    SetCC(CondCode, Operand),
+    Label(String),
+    Push(Operand),
+    Pop(Reg),
+    Call(String),
+    Ret,
+    /// Tears down the stack frame and restores the registers `Ret` would, then jumps to the
+    /// function, which returns straight to this function's caller.
+    TailCall(String),
+}
+
+impl Instruction {
+    /// Returns the instruction's operands, to be rewritten in place.
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Instruction::Mov(_, src, dst)
+            | Instruction::Movsx { src, dst, .. }
+            | Instruction::MovZeroExtend { src, dst, .. }
+            | Instruction::Lea(src, dst)
+            | Instruction::Cvttsd2si(_, src, dst)
+            | Instruction::Cvtsi2sd(_, src, dst)
+            | Instruction::Binary(_, _, src, dst)
+            | Instruction::Cmp(_, src, dst)
+            | Instruction::Test(_, src, dst) => vec![src, dst],
+            Instruction::Unary(_, _, operand)
+            | Instruction::Idiv(_, operand)
+            | Instruction::Div(_, operand)
+            | Instruction::SetCC(_, operand)
+            | Instruction::Push(operand) => vec![operand],
+            Instruction::Cdq(_)
+            | Instruction::Jmp(_)
+            | Instruction::JmpCC(..)
+            | Instruction::Label(_)
+            | Instruction::Pop(_)
+            | Instruction::Call(_)
+            | Instruction::Ret
+            | Instruction::TailCall(_) => vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub global: bool,
+    pub instructions: Vec<Instruction>,
+    /// Callee-saved registers the function overwrites, in the order they're pushed on entry.
+    /// They're popped in reverse before every `Ret` and `TailCall`.
+    pub callee_saved: Vec<Reg>,
+    /// Bytes of arguments the function's callers pass on the stack, from `16(%rbp)` up.
+    pub stack_arg_bytes: i64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub alignment: i64,
+    pub init: Vec<StaticInit>,
+}
+
+/// A read-only `double` that instructions load from memory, since none takes an immediate one.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StaticConstant {
+    pub name: String,
+    pub alignment: i64,
+    pub value: f64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TopLevel {
+    Function(Function),
+    StaticVariable(StaticVariable),
+    StaticConstant(StaticConstant),
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub top_level: Vec<TopLevel>,
+    pub symbols: BackendSymbolTable,
+}
+
+/// Turns the pseudo-registers of every function in `program` into hardware registers and
+/// stack slots, then rewrites its instructions until they're valid and as short as they get.
+pub fn finish(program: &mut Program) {
+    for top_level in &mut program.top_level {
+        if let TopLevel::Function(function) = top_level {
+            register_allocation::allocate_registers(function, &program.symbols);
+            pseudo_replacement::replace_pseudos(function, &program.symbols);
+            instruction_fixup::fix_up_instructions(function);
+            peephole::simplify(function);
+            tail_calls::eliminate_tail_calls(function, &program.symbols);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AsmSymbol {
+    Obj {
+        ty: AsmType,
+        is_static: bool,
+    },
+    Fun {
+        defined: bool,
+        /// Registers the function reads its arguments from.
+        param_regs: Vec<Reg>,
+        /// Bytes of arguments the function's callers pass on the stack, not counting padding.
+        stack_arg_bytes: i64,
+        /// Registers the function leaves its return value in.
+        return_regs: Vec<Reg>,
+    },
+}
+
+/// The backend's view of every name: the assembly type of each object and the registers
+/// each function's calling sequence uses.
+#[derive(Debug, Default)]
+pub struct BackendSymbolTable {
+    symbols: HashMap<String, AsmSymbol>,
+}
+
+impl BackendSymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, symbol: AsmSymbol) {
+        self.symbols.insert(name.to_owned(), symbol);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AsmSymbol> {
+        self.symbols.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::c::run;
+
+    #[test]
+    fn test_callee_saved_excludes_stack_and_frame_pointers() {
+        assert!(!Reg::SP.is_callee_saved());
+        assert!(!Reg::BP.is_callee_saved());
+        assert!(Reg::BX.is_callee_saved());
+        assert!(!Reg::XMM8.is_callee_saved());
+    }
+
+    #[test]
+    fn test_run_calls() {
+        let code = "
+            long factorial(long n) {
+                if (n <= 1)
+                    return 1;
+                return n * factorial(n - 1);
+            }
+            double sum(double a, double b, double c, double d, double e, double f, double g,
+                       double h, double i, long j, int k, int l, int m, int n, int o, int p,
+                       double q) {
+                return a + b + c + d + e + f + g + h + i + j + k + l + m + n + o + p + q;
+            }
+            int main(void) {
+                if (factorial(10) != 3628800)
+                    return 1;
+                if (sum(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17) != 153)
+                    return 2;
+                return 0;
+            }
+        ";
+        assert_eq!(run(code), 0);
+    }
+
+    #[test]
+    fn test_run_spills() {
+        // More values are live across the loop than there are registers.
+        let code = "
+            int main(void) {
+                int a = 1, b = 2, c = 3, d = 4, e = 5, f = 6, g = 7, h = 8;
+                int i = 9, j = 10, k = 11, l = 12, m = 13, n = 14, o = 15, p = 16;
+                for (int z = 0; z < 2; z = z + 1) {
+                    a = a + b; b = b + c; c = c + d; d = d + e; e = e + f; f = f + g;
+                    g = g + h; h = h + i; i = i + j; j = j + k; k = k + l; l = l + m;
+                    m = m + n; n = n + o; o = o + p; p = p + a;
+                }
+                return a + b + c + d + e + f + g + h + i + j + k + l + m + n + o + p - 553;
+            }
+        ";
+        assert_eq!(run(code), 0);
+    }
+
+    #[test]
+    fn test_run_conversions() {
+        let code = "
+            unsigned char to_uchar(double d) { return d; }
+            int main(void) {
+                unsigned long big = 18000000000000000000ul;
+                double d = big;
+                unsigned long back = d;
+                if (back != 18000000000000000000ul)
+                    return 1;
+                if (to_uchar(200.7) != 200)
+                    return 2;
+                char c = 300;
+                if (c != 44)
+                    return 3;
+                unsigned int u = 4294967295u;
+                if (u < 5 || (double)u != 4294967295.0)
+                    return 4;
+                if (-d > 0 || -7 / 2 != -3 || -7 % 2 != -1 || (-7 >> 1) != -4)
+                    return 5;
+                return 0;
+            }
+        ";
+        assert_eq!(run(code), 0);
+    }
+
+    #[test]
+    fn test_run_arrays_and_statics() {
+        let code = "
+            static int counter = 5;
+            long table[2][3] = {{1, 2, 3}, {4, 5, 6}};
+            int sum(int *xs, int n) {
+                int total = 0;
+                for (int i = 0; i < n; i = i + 1)
+                    total = total + xs[i];
+                return total;
+            }
+            int main(void) {
+                int squares[10];
+                for (int i = 0; i < 10; i = i + 1)
+                    squares[i] = i * i;
+                counter = counter + 1;
+                return sum(squares, 10) - 285 + table[1][2] - 6 + counter - 6;
+            }
+        ";
+        assert_eq!(run(code), 0);
+    }
+}
@@ -0,0 +1,944 @@
+//! Lowers TACKY to assembly that still refers to TACKY variables as pseudo-registers.
+
+use std::{borrow::Borrow, collections::HashMap};
+
+use crate::tacky::{
+    self, BinaryOp as TackyBinaryOp, Const, Storage, Type, UnaryOp as TackyUnaryOp, Val,
+};
+
+use super::{
+    AsmSymbol, AsmType, BackendSymbolTable, BinaryOp, CondCode, Function, Instruction, Operand,
+    Program, Reg, StaticConstant, StaticVariable, TopLevel, UnaryOp, DOUBLE_PARAM_REGS,
+    INT_PARAM_REGS,
+};
+
+/// The largest `double` that still converts to an `i64`, plus one: `2^63`.
+const I64_LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+struct Generator<'a> {
+    symbols: &'a mut tacky::SymbolTable,
+    /// The name of each `double` constant, by bit pattern and alignment.
+    constants: &'a mut HashMap<(u64, i64), String>,
+    instructions: Vec<Instruction>,
+}
+
+/// Lowers `program` to assembly with pseudo-registers, following the System V calling
+/// convention, and builds the backend symbol table the later passes look names up in.
+pub fn generate(program: tacky::Program) -> Program {
+    let tacky::Program {
+        top_level,
+        mut symbols,
+    } = program;
+    let backend_symbols = backend_symbols(&top_level, &symbols);
+
+    let mut constants = HashMap::new();
+    let mut asm_top_level = Vec::new();
+    for item in top_level {
+        match item {
+            tacky::TopLevel::Function(function) => {
+                let function = Generator {
+                    symbols: &mut symbols,
+                    constants: &mut constants,
+                    instructions: Vec::new(),
+                }
+                .function(function);
+                asm_top_level.push(TopLevel::Function(function));
+            }
+            tacky::TopLevel::StaticVariable(variable) => {
+                asm_top_level.push(TopLevel::StaticVariable(StaticVariable {
+                    name: variable.name,
+                    global: variable.global,
+                    alignment: alignment(&variable.ty),
+                    init: variable.init,
+                }));
+            }
+        }
+    }
+
+    let mut constants: Vec<_> = constants.into_iter().collect();
+    constants.sort_by(|a, b| a.1.cmp(&b.1));
+    for ((bits, alignment), name) in constants {
+        asm_top_level.push(TopLevel::StaticConstant(StaticConstant {
+            name,
+            alignment,
+            value: f64::from_bits(bits),
+        }));
+    }
+
+    Program {
+        top_level: asm_top_level,
+        symbols: backend_symbols,
+    }
+}
+
+/// Declares every object and function in `symbols` for the backend.
+///
+/// TACKY only records a function's return type, so the registers a function reads its
+/// arguments from come from its parameters if it's defined here, and from the arguments it's
+/// called with otherwise, which the type checker has already converted to the parameter types.
+fn backend_symbols(
+    top_level: &[tacky::TopLevel],
+    symbols: &tacky::SymbolTable,
+) -> BackendSymbolTable {
+    let mut param_types: HashMap<&str, Vec<&Type>> = HashMap::new();
+    for item in top_level {
+        let tacky::TopLevel::Function(function) = item else {
+            continue;
+        };
+        param_types.insert(
+            &function.name,
+            function
+                .params
+                .iter()
+                .map(|param| var_type(symbols, param))
+                .collect(),
+        );
+    }
+    let defined: Vec<&str> = param_types.keys().copied().collect();
+    for item in top_level {
+        let tacky::TopLevel::Function(function) = item else {
+            continue;
+        };
+        for instruction in &function.body {
+            if let tacky::Instruction::FunCall { name, args, .. } = instruction {
+                param_types
+                    .entry(name)
+                    .or_insert_with(|| args.iter().map(|arg| val_type(symbols, arg)).collect());
+            }
+        }
+    }
+
+    let mut backend_symbols = BackendSymbolTable::new();
+    for (name, symbol) in symbols.iter() {
+        let asm_symbol = match symbol.storage {
+            Storage::Function => {
+                let (param_regs, stack_arg_bytes) = match param_types.get(name.as_str()) {
+                    Some(types) => {
+                        let (int_regs, double_regs, stack) = classify(types);
+                        (
+                            int_regs.into_iter().chain(double_regs).collect(),
+                            8 * stack.len() as i64,
+                        )
+                    }
+                    None => (Vec::new(), 0),
+                };
+                let return_regs = match symbol.ty {
+                    Type::Void => vec![],
+                    Type::Double => vec![Reg::XMM0],
+                    _ => vec![Reg::AX],
+                };
+                AsmSymbol::Fun {
+                    defined: defined.contains(&name.as_str()),
+                    param_regs,
+                    stack_arg_bytes,
+                    return_regs,
+                }
+            }
+            Storage::Static => AsmSymbol::Obj {
+                ty: asm_type(&symbol.ty),
+                is_static: true,
+            },
+            Storage::Local => AsmSymbol::Obj {
+                ty: asm_type(&symbol.ty),
+                is_static: false,
+            },
+        };
+        backend_symbols.insert(name, asm_symbol);
+    }
+    backend_symbols
+}
+
+/// Splits values of `types` into the registers the first of them are passed in, integer and
+/// `double`, and the indices of the ones passed on the stack, in order.
+fn classify<T: Borrow<Type>>(types: &[T]) -> (Vec<Reg>, Vec<Reg>, Vec<usize>) {
+    let mut int_regs = Vec::new();
+    let mut double_regs = Vec::new();
+    let mut stack = Vec::new();
+    for (i, ty) in types.iter().enumerate() {
+        if *ty.borrow() == Type::Double {
+            match DOUBLE_PARAM_REGS.get(double_regs.len()) {
+                Some(reg) => double_regs.push(*reg),
+                None => stack.push(i),
+            }
+        } else {
+            match INT_PARAM_REGS.get(int_regs.len()) {
+                Some(reg) => int_regs.push(*reg),
+                None => stack.push(i),
+            }
+        }
+    }
+    (int_regs, double_regs, stack)
+}
+
+impl Generator<'_> {
+    fn function(mut self, function: tacky::Function) -> Function {
+        let types: Vec<Type> = function
+            .params
+            .iter()
+            .map(|param| var_type(self.symbols, param).clone())
+            .collect();
+        let (int_regs, double_regs, stack) = classify(&types);
+
+        let mut int_regs = int_regs.into_iter();
+        let mut double_regs = double_regs.into_iter();
+        let mut stack_offset = 16;
+        for (i, param) in function.params.iter().enumerate() {
+            let ty = asm_type(&types[i]);
+            let src = if stack.contains(&i) {
+                let src = Operand::Memory(Reg::BP, stack_offset);
+                stack_offset += 8;
+                src
+            } else if ty == AsmType::Double {
+                Operand::Reg(double_regs.next().unwrap())
+            } else {
+                Operand::Reg(int_regs.next().unwrap())
+            };
+            self.push(Instruction::Mov(ty, src, Operand::Pseudo(param.clone())));
+        }
+
+        for instruction in function.body {
+            self.instruction(instruction);
+        }
+
+        Function {
+            name: function.name,
+            global: function.global,
+            instructions: self.instructions,
+            callee_saved: Vec::new(),
+            stack_arg_bytes: 8 * stack.len() as i64,
+        }
+    }
+
+    fn instruction(&mut self, instruction: tacky::Instruction) {
+        match instruction {
+            tacky::Instruction::Return(val) => {
+                if let Some(val) = val {
+                    let ty = self.asm_type(&val);
+                    let reg = if ty == AsmType::Double {
+                        Reg::XMM0
+                    } else {
+                        Reg::AX
+                    };
+                    let src = self.operand(&val);
+                    self.push(Instruction::Mov(ty, src, Operand::Reg(reg)));
+                }
+                self.push(Instruction::Ret);
+            }
+            tacky::Instruction::SignExtend { src, dst } => {
+                let instruction = Instruction::Movsx {
+                    src_type: self.asm_type(&src),
+                    dst_type: self.asm_type(&dst),
+                    src: self.operand(&src),
+                    dst: self.operand(&dst),
+                };
+                self.push(instruction);
+            }
+            tacky::Instruction::ZeroExtend { src, dst } => {
+                let instruction = Instruction::MovZeroExtend {
+                    src_type: self.asm_type(&src),
+                    dst_type: self.asm_type(&dst),
+                    src: self.operand(&src),
+                    dst: self.operand(&dst),
+                };
+                self.push(instruction);
+            }
+            tacky::Instruction::Truncate { src, dst } => {
+                let ty = self.asm_type(&dst);
+                let (src, dst) = (self.operand(&src), self.operand(&dst));
+                self.push(Instruction::Mov(ty, src, dst));
+            }
+            tacky::Instruction::DoubleToInt { src, dst } => {
+                self.double_to_int(&src, &dst, true);
+            }
+            tacky::Instruction::DoubleToUInt { src, dst } => {
+                self.double_to_int(&src, &dst, false);
+            }
+            tacky::Instruction::IntToDouble { src, dst } => {
+                let (src_type, src, dst) =
+                    (self.asm_type(&src), self.operand(&src), self.operand(&dst));
+                if src_type == AsmType::Byte {
+                    self.push(Instruction::Movsx {
+                        src_type,
+                        dst_type: AsmType::Longword,
+                        src,
+                        dst: Operand::Reg(Reg::AX),
+                    });
+                    self.push(Instruction::Cvtsi2sd(
+                        AsmType::Longword,
+                        Operand::Reg(Reg::AX),
+                        dst,
+                    ));
+                } else {
+                    self.push(Instruction::Cvtsi2sd(src_type, src, dst));
+                }
+            }
+            tacky::Instruction::UIntToDouble { src, dst } => self.uint_to_double(&src, &dst),
+            tacky::Instruction::Unary { op, src, dst } => self.unary(op, &src, &dst),
+            tacky::Instruction::Binary {
+                op,
+                src1,
+                src2,
+                dst,
+            } => self.binary(op, &src1, &src2, &dst),
+            tacky::Instruction::Copy { src, dst } => {
+                let ty = self.asm_type(&src);
+                let (src, dst) = (self.operand(&src), self.operand(&dst));
+                self.push(Instruction::Mov(ty, src, dst));
+            }
+            tacky::Instruction::GetAddress { src, dst } => {
+                let (src, dst) = (self.operand(&src), self.operand(&dst));
+                self.push(Instruction::Lea(src, dst));
+            }
+            tacky::Instruction::Load { src_ptr, dst } => {
+                let ty = self.asm_type(&dst);
+                let (src_ptr, dst) = (self.operand(&src_ptr), self.operand(&dst));
+                self.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    src_ptr,
+                    Operand::Reg(Reg::AX),
+                ));
+                self.push(Instruction::Mov(ty, Operand::Memory(Reg::AX, 0), dst));
+            }
+            tacky::Instruction::Store { src, dst_ptr } => {
+                let ty = self.asm_type(&src);
+                let (src, dst_ptr) = (self.operand(&src), self.operand(&dst_ptr));
+                self.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    dst_ptr,
+                    Operand::Reg(Reg::AX),
+                ));
+                self.push(Instruction::Mov(ty, src, Operand::Memory(Reg::AX, 0)));
+            }
+            tacky::Instruction::AddPtr {
+                ptr,
+                index,
+                scale,
+                dst,
+            } => self.add_ptr(&ptr, &index, scale as i64, &dst),
+            tacky::Instruction::CopyToOffset { src, dst, offset } => {
+                let ty = self.asm_type(&src);
+                let src = self.operand(&src);
+                self.push(Instruction::Mov(
+                    ty,
+                    src,
+                    Operand::PseudoMem(dst, offset as i64),
+                ));
+            }
+            tacky::Instruction::CopyFromOffset { src, offset, dst } => {
+                let ty = self.asm_type(&dst);
+                let dst = self.operand(&dst);
+                self.push(Instruction::Mov(
+                    ty,
+                    Operand::PseudoMem(src, offset as i64),
+                    dst,
+                ));
+            }
+            tacky::Instruction::Jump(target) => self.push(Instruction::Jmp(target)),
+            tacky::Instruction::JumpIfZero { condition, target } => {
+                self.compare_with_zero(&condition);
+                self.push(Instruction::JmpCC(CondCode::E, target));
+            }
+            tacky::Instruction::JumpIfNotZero { condition, target } => {
+                self.compare_with_zero(&condition);
+                self.push(Instruction::JmpCC(CondCode::NE, target));
+            }
+            tacky::Instruction::Label(label) => self.push(Instruction::Label(label)),
+            tacky::Instruction::FunCall { name, args, dst } => self.call(name, &args, dst.as_ref()),
+        }
+    }
+
+    fn unary(&mut self, op: TackyUnaryOp, src: &Val, dst: &Val) {
+        let ty = self.asm_type(src);
+        let (src, dst) = (self.operand(src), self.operand(dst));
+        match op {
+            TackyUnaryOp::Not => {
+                self.compare_with_zero_operand(ty, src);
+                self.push(Instruction::Mov(
+                    AsmType::Longword,
+                    Operand::Imm(0),
+                    dst.clone(),
+                ));
+                self.push(Instruction::SetCC(CondCode::E, dst));
+            }
+            TackyUnaryOp::Negate if ty == AsmType::Double => {
+                // Flips the sign bit. `xorpd` reads 16 bytes, so the mask is aligned to 16.
+                let mask = self.double_constant(-0.0, 16);
+                self.push(Instruction::Mov(ty, src, dst.clone()));
+                self.push(Instruction::Binary(BinaryOp::Xor, ty, mask, dst));
+            }
+            TackyUnaryOp::Negate | TackyUnaryOp::Complement => {
+                let op = match op {
+                    TackyUnaryOp::Negate => UnaryOp::Neg,
+                    _ => UnaryOp::Not,
+                };
+                self.push(Instruction::Mov(ty, src, dst.clone()));
+                self.push(Instruction::Unary(op, ty, dst));
+            }
+        }
+    }
+
+    fn binary(&mut self, op: TackyBinaryOp, src1: &Val, src2: &Val, dst: &Val) {
+        let ty = self.asm_type(src1);
+        let signed = self.is_signed(src1);
+        let (src1_op, src2_op, dst_op) =
+            (self.operand(src1), self.operand(src2), self.operand(dst));
+
+        let arithmetic = match op {
+            TackyBinaryOp::Add => Some(BinaryOp::Add),
+            TackyBinaryOp::Subtract => Some(BinaryOp::Sub),
+            TackyBinaryOp::Multiply => Some(BinaryOp::Mult),
+            TackyBinaryOp::Divide if ty == AsmType::Double => Some(BinaryOp::DivDouble),
+            TackyBinaryOp::BitAnd => Some(BinaryOp::And),
+            TackyBinaryOp::BitOr => Some(BinaryOp::Or),
+            TackyBinaryOp::BitXor => Some(BinaryOp::Xor),
+            _ => None,
+        };
+        if let Some(op) = arithmetic {
+            self.push(Instruction::Mov(ty, src1_op, dst_op.clone()));
+            self.push(Instruction::Binary(op, ty, src2_op, dst_op));
+            return;
+        }
+
+        match op {
+            TackyBinaryOp::Divide | TackyBinaryOp::Remainder => {
+                self.push(Instruction::Mov(ty, src1_op, Operand::Reg(Reg::AX)));
+                if signed {
+                    self.push(Instruction::Cdq(ty));
+                    self.push(Instruction::Idiv(ty, src2_op));
+                } else {
+                    self.push(Instruction::Mov(ty, Operand::Imm(0), Operand::Reg(Reg::DX)));
+                    self.push(Instruction::Div(ty, src2_op));
+                }
+                let result = match op {
+                    TackyBinaryOp::Divide => Reg::AX,
+                    _ => Reg::DX,
+                };
+                self.push(Instruction::Mov(ty, Operand::Reg(result), dst_op));
+            }
+            TackyBinaryOp::ShiftLeft | TackyBinaryOp::ShiftRight => {
+                let op = match (op, signed) {
+                    (TackyBinaryOp::ShiftLeft, _) => BinaryOp::Sal,
+                    (_, true) => BinaryOp::Sar,
+                    (_, false) => BinaryOp::Shr,
+                };
+                self.push(Instruction::Mov(ty, src1_op, dst_op.clone()));
+                // A shift count that isn't an immediate has to be in `%cl`.
+                let count = match src2_op {
+                    Operand::Imm(_) => src2_op,
+                    _ => {
+                        let count_type = self.asm_type(src2);
+                        self.push(Instruction::Mov(count_type, src2_op, Operand::Reg(Reg::CX)));
+                        Operand::Reg(Reg::CX)
+                    }
+                };
+                self.push(Instruction::Binary(op, ty, count, dst_op));
+            }
+            _ => {
+                // Doubles and unsigned integers set the carry flag when compared, not the
+                // sign and overflow flags.
+                let unsigned = ty == AsmType::Double || !signed;
+                let cond = match (op, unsigned) {
+                    (TackyBinaryOp::Equal, _) => CondCode::E,
+                    (TackyBinaryOp::NotEqual, _) => CondCode::NE,
+                    (TackyBinaryOp::LessThan, false) => CondCode::L,
+                    (TackyBinaryOp::LessThan, true) => CondCode::B,
+                    (TackyBinaryOp::LessOrEqual, false) => CondCode::LE,
+                    (TackyBinaryOp::LessOrEqual, true) => CondCode::BE,
+                    (TackyBinaryOp::GreaterThan, false) => CondCode::G,
+                    (TackyBinaryOp::GreaterThan, true) => CondCode::A,
+                    (TackyBinaryOp::GreaterOrEqual, false) => CondCode::GE,
+                    (TackyBinaryOp::GreaterOrEqual, true) => CondCode::AE,
+                    _ => unreachable!("every other operator is handled above."),
+                };
+                self.push(Instruction::Cmp(ty, src2_op, src1_op));
+                self.push(Instruction::Mov(
+                    AsmType::Longword,
+                    Operand::Imm(0),
+                    dst_op.clone(),
+                ));
+                self.push(Instruction::SetCC(cond, dst_op));
+            }
+        }
+    }
+
+    fn add_ptr(&mut self, ptr: &Val, index: &Val, scale: i64, dst: &Val) {
+        let (ptr, dst) = (self.operand(ptr), self.operand(dst));
+        self.push(Instruction::Mov(
+            AsmType::Quadword,
+            ptr,
+            Operand::Reg(Reg::AX),
+        ));
+
+        if let Val::Constant(index) = index {
+            let offset = const_value(*index) * scale;
+            self.push(Instruction::Lea(Operand::Memory(Reg::AX, offset), dst));
+            return;
+        }
+
+        let index = self.operand(index);
+        self.push(Instruction::Mov(
+            AsmType::Quadword,
+            index,
+            Operand::Reg(Reg::DX),
+        ));
+        let scale = if matches!(scale, 1 | 2 | 4 | 8) {
+            scale
+        } else {
+            self.push(Instruction::Binary(
+                BinaryOp::Mult,
+                AsmType::Quadword,
+                Operand::Imm(scale),
+                Operand::Reg(Reg::DX),
+            ));
+            1
+        };
+        self.push(Instruction::Lea(
+            Operand::Indexed {
+                base: Reg::AX,
+                index: Reg::DX,
+                scale: scale as u8,
+            },
+            dst,
+        ));
+    }
+
+    fn double_to_int(&mut self, src: &Val, dst: &Val, signed: bool) {
+        let dst_type = self.asm_type(dst);
+        let (src, dst) = (self.operand(src), self.operand(dst));
+        let ax = Operand::Reg(Reg::AX);
+
+        match (dst_type, signed) {
+            // Converting to a wider type gives the same low bits whenever the result fits.
+            (AsmType::Byte, _) | (AsmType::Longword, false) => {
+                let wide = match dst_type {
+                    AsmType::Byte => AsmType::Longword,
+                    _ => AsmType::Quadword,
+                };
+                self.push(Instruction::Cvttsd2si(wide, src, ax.clone()));
+                self.push(Instruction::Mov(dst_type, ax, dst));
+            }
+            (AsmType::Quadword, false) => {
+                // Values of 2^63 and up are converted less 2^63, which is added back after.
+                let limit = self.double_constant(I64_LIMIT, 8);
+                let out_of_range = self.symbols.make_label("out_of_range");
+                let end = self.symbols.make_label("conversion_end");
+                self.push(Instruction::Cmp(
+                    AsmType::Double,
+                    limit.clone(),
+                    src.clone(),
+                ));
+                self.push(Instruction::JmpCC(CondCode::AE, out_of_range.clone()));
+                self.push(Instruction::Cvttsd2si(
+                    AsmType::Quadword,
+                    src.clone(),
+                    dst.clone(),
+                ));
+                self.push(Instruction::Jmp(end.clone()));
+                self.push(Instruction::Label(out_of_range));
+                self.push(Instruction::Mov(
+                    AsmType::Double,
+                    src,
+                    Operand::Reg(Reg::XMM1),
+                ));
+                self.push(Instruction::Binary(
+                    BinaryOp::Sub,
+                    AsmType::Double,
+                    limit,
+                    Operand::Reg(Reg::XMM1),
+                ));
+                self.push(Instruction::Cvttsd2si(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::XMM1),
+                    dst.clone(),
+                ));
+                self.push(Instruction::Binary(
+                    BinaryOp::Add,
+                    AsmType::Quadword,
+                    Operand::Imm(i64::MIN),
+                    dst,
+                ));
+                self.push(Instruction::Label(end));
+            }
+            _ => self.push(Instruction::Cvttsd2si(dst_type, src, dst)),
+        }
+    }
+
+    fn uint_to_double(&mut self, src: &Val, dst: &Val) {
+        let src_type = self.asm_type(src);
+        let (src, dst) = (self.operand(src), self.operand(dst));
+        let (ax, dx) = (Operand::Reg(Reg::AX), Operand::Reg(Reg::DX));
+
+        match src_type {
+            AsmType::Byte | AsmType::Longword => {
+                let wide = match src_type {
+                    AsmType::Byte => AsmType::Longword,
+                    _ => AsmType::Quadword,
+                };
+                self.push(Instruction::MovZeroExtend {
+                    src_type,
+                    dst_type: wide,
+                    src,
+                    dst: ax.clone(),
+                });
+                self.push(Instruction::Cvtsi2sd(wide, ax, dst));
+            }
+            _ => {
+                // Values of 2^63 and up are halved, rounding to odd so the result rounds the
+                // same way, converted, then doubled.
+                let out_of_range = self.symbols.make_label("out_of_range");
+                let end = self.symbols.make_label("conversion_end");
+                self.push(Instruction::Cmp(
+                    AsmType::Quadword,
+                    Operand::Imm(0),
+                    src.clone(),
+                ));
+                self.push(Instruction::JmpCC(CondCode::L, out_of_range.clone()));
+                self.push(Instruction::Cvtsi2sd(
+                    AsmType::Quadword,
+                    src.clone(),
+                    dst.clone(),
+                ));
+                self.push(Instruction::Jmp(end.clone()));
+                self.push(Instruction::Label(out_of_range));
+                self.push(Instruction::Mov(AsmType::Quadword, src, ax.clone()));
+                self.push(Instruction::Mov(AsmType::Quadword, ax.clone(), dx.clone()));
+                self.push(Instruction::Unary(
+                    UnaryOp::Shr,
+                    AsmType::Quadword,
+                    dx.clone(),
+                ));
+                self.push(Instruction::Binary(
+                    BinaryOp::And,
+                    AsmType::Quadword,
+                    Operand::Imm(1),
+                    ax.clone(),
+                ));
+                self.push(Instruction::Binary(
+                    BinaryOp::Or,
+                    AsmType::Quadword,
+                    ax,
+                    dx.clone(),
+                ));
+                self.push(Instruction::Cvtsi2sd(AsmType::Quadword, dx, dst.clone()));
+                self.push(Instruction::Binary(
+                    BinaryOp::Add,
+                    AsmType::Double,
+                    dst.clone(),
+                    dst,
+                ));
+                self.push(Instruction::Label(end));
+            }
+        }
+    }
+
+    fn call(&mut self, name: String, args: &[Val], dst: Option<&Val>) {
+        let types: Vec<Type> = args
+            .iter()
+            .map(|arg| val_type(self.symbols, arg).clone())
+            .collect();
+        let (int_regs, double_regs, stack) = classify(&types);
+
+        // The stack pointer must be 16-byte aligned at the call.
+        let padding = if stack.len() % 2 == 1 { 8 } else { 0 };
+        if padding > 0 {
+            self.push(Instruction::Binary(
+                BinaryOp::Sub,
+                AsmType::Quadword,
+                Operand::Imm(padding),
+                Operand::Reg(Reg::SP),
+            ));
+        }
+
+        let mut int_regs = int_regs.into_iter();
+        let mut double_regs = double_regs.into_iter();
+        for (i, arg) in args.iter().enumerate() {
+            if stack.contains(&i) {
+                continue;
+            }
+            let ty = self.asm_type(arg);
+            let reg = if ty == AsmType::Double {
+                double_regs.next().unwrap()
+            } else {
+                int_regs.next().unwrap()
+            };
+            let arg = self.operand(arg);
+            self.push(Instruction::Mov(ty, arg, Operand::Reg(reg)));
+        }
+
+        for &i in stack.iter().rev() {
+            let ty = self.asm_type(&args[i]);
+            let arg = self.operand(&args[i]);
+            match (&arg, ty) {
+                (Operand::Imm(_), _) | (_, AsmType::Quadword | AsmType::Double) => {
+                    self.push(Instruction::Push(arg));
+                }
+                // `push` always reads 8 bytes, which may run past a smaller object.
+                _ => {
+                    self.push(Instruction::Mov(ty, arg, Operand::Reg(Reg::AX)));
+                    self.push(Instruction::Push(Operand::Reg(Reg::AX)));
+                }
+            }
+        }
+
+        self.push(Instruction::Call(name));
+
+        let bytes = 8 * stack.len() as i64 + padding;
+        if bytes > 0 {
+            self.push(Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Quadword,
+                Operand::Imm(bytes),
+                Operand::Reg(Reg::SP),
+            ));
+        }
+
+        if let Some(dst) = dst {
+            let ty = self.asm_type(dst);
+            let reg = if ty == AsmType::Double {
+                Reg::XMM0
+            } else {
+                Reg::AX
+            };
+            let dst = self.operand(dst);
+            self.push(Instruction::Mov(ty, Operand::Reg(reg), dst));
+        }
+    }
+
+    /// Sets the zero flag if `val` is zero.
+    fn compare_with_zero(&mut self, val: &Val) {
+        let ty = self.asm_type(val);
+        let val = self.operand(val);
+        self.compare_with_zero_operand(ty, val);
+    }
+
+    fn compare_with_zero_operand(&mut self, ty: AsmType, operand: Operand) {
+        let zero = match ty {
+            AsmType::Double => self.double_constant(0.0, 8),
+            _ => Operand::Imm(0),
+        };
+        self.push(Instruction::Cmp(ty, zero, operand));
+    }
+
+    fn operand(&mut self, val: &Val) -> Operand {
+        match val {
+            Val::Constant(Const::Double(d)) => self.double_constant(*d, 8),
+            Val::Constant(c) => Operand::Imm(const_value(*c)),
+            Val::Var(name) => match var_type(self.symbols, name) {
+                Type::Array(..) => Operand::PseudoMem(name.clone(), 0),
+                _ => Operand::Pseudo(name.clone()),
+            },
+        }
+    }
+
+    /// The static constant holding `value`, declared the first time it's needed.
+    fn double_constant(&mut self, value: f64, alignment: i64) -> Operand {
+        let key = (value.to_bits(), alignment);
+        if !self.constants.contains_key(&key) {
+            let name = format!(".L{}", self.symbols.make_label("double"));
+            self.constants.insert(key, name);
+        }
+        Operand::Data(self.constants[&key].clone())
+    }
+
+    fn asm_type(&self, val: &Val) -> AsmType {
+        asm_type(val_type(self.symbols, val))
+    }
+
+    fn is_signed(&self, val: &Val) -> bool {
+        matches!(
+            val_type(self.symbols, val),
+            Type::Char | Type::SChar | Type::Int | Type::Long
+        )
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+}
+
+fn val_type<'a>(symbols: &'a tacky::SymbolTable, val: &Val) -> &'a Type {
+    match val {
+        Val::Constant(c) => match c {
+            Const::Char(_) => &Type::Char,
+            Const::UChar(_) => &Type::UChar,
+            Const::Int(_) => &Type::Int,
+            Const::UInt(_) => &Type::UInt,
+            Const::Long(_) => &Type::Long,
+            Const::ULong(_) => &Type::ULong,
+            Const::Double(_) => &Type::Double,
+        },
+        Val::Var(name) => var_type(symbols, name),
+    }
+}
+
+fn var_type<'a>(symbols: &'a tacky::SymbolTable, name: &str) -> &'a Type {
+    match symbols.get(name) {
+        Some(symbol) => &symbol.ty,
+        None => panic!("variable '{name}' is missing from the symbol table."),
+    }
+}
+
+/// The value of an integer constant, as the bits an immediate operand holds.
+fn const_value(c: Const) -> i64 {
+    match c {
+        Const::Char(c) => c.into(),
+        Const::UChar(c) => c.into(),
+        Const::Int(i) => i.into(),
+        Const::UInt(u) => u.into(),
+        Const::Long(l) => l,
+        Const::ULong(u) => u as i64,
+        Const::Double(_) => unreachable!("doubles are loaded from static constants."),
+    }
+}
+
+fn asm_type(ty: &Type) -> AsmType {
+    match ty {
+        Type::Char | Type::SChar | Type::UChar => AsmType::Byte,
+        Type::Int | Type::UInt => AsmType::Longword,
+        Type::Long | Type::ULong | Type::Pointer(_) => AsmType::Quadword,
+        Type::Double => AsmType::Double,
+        Type::Array(..) => AsmType::ByteArray {
+            size: size(ty),
+            alignment: alignment(ty),
+        },
+        Type::Void => panic!("void has no assembly type."),
+    }
+}
+
+fn size(ty: &Type) -> i64 {
+    match ty {
+        Type::Char | Type::SChar | Type::UChar => 1,
+        Type::Int | Type::UInt => 4,
+        Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => 8,
+        Type::Array(element, len) => size(element) * *len as i64,
+        Type::Void => panic!("void has no size."),
+    }
+}
+
+/// Arrays of 16 bytes or more are aligned to 16, per the System V ABI.
+fn alignment(ty: &Type) -> i64 {
+    match ty {
+        Type::Array(element, _) if size(ty) < 16 => alignment(element),
+        Type::Array(..) => 16,
+        ty => size(ty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{
+        assembly::{pseudo, reg},
+        c::tacky,
+    };
+
+    fn instructions(code: &str) -> Vec<Instruction> {
+        let program = generate(tacky(code));
+        match &program.top_level[0] {
+            TopLevel::Function(function) => function.instructions.clone(),
+            top_level => panic!("expected a function, found {top_level:?}."),
+        }
+    }
+
+    #[test]
+    fn test_parameters() {
+        let instructions = instructions(
+            "double f(int a, double b, int c, int d, int e, int f, int g, long h) { return b; }",
+        );
+        assert_eq!(
+            instructions[..8],
+            [
+                Instruction::Mov(AsmType::Longword, reg(Reg::DI), pseudo("a.0")),
+                Instruction::Mov(AsmType::Double, reg(Reg::XMM0), pseudo("b.1")),
+                Instruction::Mov(AsmType::Longword, reg(Reg::SI), pseudo("c.2")),
+                Instruction::Mov(AsmType::Longword, reg(Reg::DX), pseudo("d.3")),
+                Instruction::Mov(AsmType::Longword, reg(Reg::CX), pseudo("e.4")),
+                Instruction::Mov(AsmType::Longword, reg(Reg::R8), pseudo("f.5")),
+                Instruction::Mov(AsmType::Longword, reg(Reg::R9), pseudo("g.6")),
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Memory(Reg::BP, 16),
+                    pseudo("h.7")
+                ),
+            ]
+        );
+        assert_eq!(
+            instructions[8],
+            Instruction::Mov(AsmType::Double, pseudo("b.1"), reg(Reg::XMM0))
+        );
+    }
+
+    #[test]
+    fn test_call_with_stack_arguments() {
+        let instructions = instructions(
+            "int g(int a, int b, int c, int d, int e, int f, int g, int h, int i);
+             int f(void) { return g(1, 2, 3, 4, 5, 6, 7, 8, 9); }",
+        );
+        let sp = || reg(Reg::SP);
+        assert_eq!(
+            instructions[..13],
+            [
+                // Three stack arguments are padded to keep the stack aligned.
+                Instruction::Binary(BinaryOp::Sub, AsmType::Quadword, Operand::Imm(8), sp()),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(1), reg(Reg::DI)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(2), reg(Reg::SI)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(3), reg(Reg::DX)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(4), reg(Reg::CX)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(5), reg(Reg::R8)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(6), reg(Reg::R9)),
+                Instruction::Push(Operand::Imm(9)),
+                Instruction::Push(Operand::Imm(8)),
+                Instruction::Push(Operand::Imm(7)),
+                Instruction::Call("g".to_owned()),
+                Instruction::Binary(BinaryOp::Add, AsmType::Quadword, Operand::Imm(32), sp()),
+                Instruction::Mov(AsmType::Longword, reg(Reg::AX), pseudo("tmp.0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backend_symbols() {
+        let program = generate(tacky(
+            "double g(long a, double b);
+             static int counter;
+             int arr[5];
+             int f(void) { int local[2]; return g(1, 2) + counter + local[0] + arr[0]; }",
+        ));
+        assert_eq!(
+            program.symbols.get("g"),
+            Some(&AsmSymbol::Fun {
+                defined: false,
+                param_regs: vec![Reg::DI, Reg::XMM0],
+                stack_arg_bytes: 0,
+                return_regs: vec![Reg::XMM0],
+            })
+        );
+        assert_eq!(
+            program.symbols.get("counter"),
+            Some(&AsmSymbol::Obj {
+                ty: AsmType::Longword,
+                is_static: true,
+            })
+        );
+        assert_eq!(
+            program.symbols.get("arr"),
+            Some(&AsmSymbol::Obj {
+                ty: AsmType::ByteArray {
+                    size: 20,
+                    alignment: 16,
+                },
+                is_static: true,
+            })
+        );
+        assert_eq!(
+            program.symbols.get("local.2"),
+            Some(&AsmSymbol::Obj {
+                ty: AsmType::ByteArray {
+                    size: 8,
+                    alignment: 4,
+                },
+                is_static: false,
+            })
+        );
+    }
+}
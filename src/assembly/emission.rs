@@ -0,0 +1,368 @@
+//! Writes a program out as AT&T-syntax assembly for the GNU assembler.
+
+use std::fmt::{self, Write};
+
+use crate::tacky::{Const, StaticInit};
+
+use super::{
+    AsmSymbol, AsmType, BackendSymbolTable, BinaryOp, CondCode, Function, Instruction, Operand,
+    Program, Reg, StaticConstant, StaticVariable, TopLevel, UnaryOp,
+};
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for top_level in &self.top_level {
+            match top_level {
+                TopLevel::Function(function) => write_function(f, function, &self.symbols)?,
+                TopLevel::StaticVariable(variable) => write_static_variable(f, variable)?,
+                TopLevel::StaticConstant(constant) => write_static_constant(f, constant)?,
+            }
+        }
+        // Marks the stack as not executable.
+        writeln!(f, "\t.section .note.GNU-stack,\"\",@progbits")
+    }
+}
+
+fn write_function(
+    f: &mut fmt::Formatter<'_>,
+    function: &Function,
+    symbols: &BackendSymbolTable,
+) -> fmt::Result {
+    if function.global {
+        writeln!(f, "\t.globl {}", function.name)?;
+    }
+    writeln!(f, "\t.text")?;
+    writeln!(f, "{}:", function.name)?;
+    writeln!(f, "\tpushq %rbp")?;
+    writeln!(f, "\tmovq %rsp, %rbp")?;
+    for instruction in &function.instructions {
+        write_instruction(f, instruction, symbols)?;
+    }
+    writeln!(f)
+}
+
+fn write_static_variable(f: &mut fmt::Formatter<'_>, variable: &StaticVariable) -> fmt::Result {
+    if variable.global {
+        writeln!(f, "\t.globl {}", variable.name)?;
+    }
+    let zero = variable
+        .init
+        .iter()
+        .all(|init| matches!(init, StaticInit::Zero(_)));
+    writeln!(f, "\t{}", if zero { ".bss" } else { ".data" })?;
+    writeln!(f, "\t.balign {}", variable.alignment)?;
+    writeln!(f, "{}:", variable.name)?;
+    for init in &variable.init {
+        match init {
+            StaticInit::Zero(bytes) => writeln!(f, "\t.zero {bytes}")?,
+            StaticInit::Const(c) => match c {
+                Const::Char(c) => writeln!(f, "\t.byte {c}")?,
+                Const::UChar(c) => writeln!(f, "\t.byte {c}")?,
+                Const::Int(i) => writeln!(f, "\t.long {i}")?,
+                Const::UInt(u) => writeln!(f, "\t.long {u}")?,
+                Const::Long(l) => writeln!(f, "\t.quad {l}")?,
+                Const::ULong(u) => writeln!(f, "\t.quad {u}")?,
+                Const::Double(d) => writeln!(f, "\t.quad {}", d.to_bits())?,
+            },
+        }
+    }
+    writeln!(f)
+}
+
+fn write_static_constant(f: &mut fmt::Formatter<'_>, constant: &StaticConstant) -> fmt::Result {
+    writeln!(f, "\t.section .rodata")?;
+    writeln!(f, "\t.balign {}", constant.alignment)?;
+    writeln!(f, "{}:", constant.name)?;
+    writeln!(f, "\t.quad {}", constant.value.to_bits())?;
+    // Instructions that read 16 bytes, like `xorpd`, read past the value.
+    if constant.alignment == 16 {
+        writeln!(f, "\t.zero 8")?;
+    }
+    writeln!(f)
+}
+
+fn write_instruction(
+    f: &mut fmt::Formatter<'_>,
+    instruction: &Instruction,
+    symbols: &BackendSymbolTable,
+) -> fmt::Result {
+    let mut line = String::new();
+    match instruction {
+        Instruction::Mov(AsmType::Double, src, dst) => {
+            write!(line, "movsd {}, {}", operand(src, 8), operand(dst, 8))?;
+        }
+        Instruction::Mov(ty, src, dst) => write!(
+            line,
+            "mov{} {}, {}",
+            suffix(*ty),
+            operand(src, size(*ty)),
+            operand(dst, size(*ty))
+        )?,
+        Instruction::Movsx {
+            src_type,
+            dst_type,
+            src,
+            dst,
+        } => write!(
+            line,
+            "movs{}{} {}, {}",
+            suffix(*src_type),
+            suffix(*dst_type),
+            operand(src, size(*src_type)),
+            operand(dst, size(*dst_type))
+        )?,
+        Instruction::MovZeroExtend {
+            src_type,
+            dst_type,
+            src,
+            dst,
+        } => write!(
+            line,
+            "movz{}{} {}, {}",
+            suffix(*src_type),
+            suffix(*dst_type),
+            operand(src, size(*src_type)),
+            operand(dst, size(*dst_type))
+        )?,
+        Instruction::Lea(src, dst) => {
+            write!(line, "leaq {}, {}", operand(src, 8), operand(dst, 8))?;
+        }
+        Instruction::Cvttsd2si(ty, src, dst) => write!(
+            line,
+            "cvttsd2si{} {}, {}",
+            suffix(*ty),
+            operand(src, 8),
+            operand(dst, size(*ty))
+        )?,
+        Instruction::Cvtsi2sd(ty, src, dst) => write!(
+            line,
+            "cvtsi2sd{} {}, {}",
+            suffix(*ty),
+            operand(src, size(*ty)),
+            operand(dst, 8)
+        )?,
+        Instruction::Unary(op, ty, operand_) => {
+            let op = match op {
+                UnaryOp::Neg => "neg",
+                UnaryOp::Not => "not",
+                UnaryOp::Shr => "shr",
+            };
+            write!(line, "{op}{} {}", suffix(*ty), operand(operand_, size(*ty)))?;
+        }
+        Instruction::Binary(op, AsmType::Double, src, dst) => {
+            let op = match op {
+                BinaryOp::Add => "addsd",
+                BinaryOp::Sub => "subsd",
+                BinaryOp::Mult => "mulsd",
+                BinaryOp::DivDouble => "divsd",
+                BinaryOp::And => "andpd",
+                BinaryOp::Or => "orpd",
+                BinaryOp::Xor => "xorpd",
+                BinaryOp::Sal | BinaryOp::Sar | BinaryOp::Shr => {
+                    panic!("doubles can't be shifted.")
+                }
+            };
+            write!(line, "{op} {}, {}", operand(src, 8), operand(dst, 8))?;
+        }
+        Instruction::Binary(op, ty, src, dst) => {
+            let (op, src_size) = match op {
+                BinaryOp::Add => ("add", size(*ty)),
+                BinaryOp::Sub => ("sub", size(*ty)),
+                BinaryOp::Mult => ("imul", size(*ty)),
+                BinaryOp::And => ("and", size(*ty)),
+                BinaryOp::Or => ("or", size(*ty)),
+                BinaryOp::Xor => ("xor", size(*ty)),
+                // A shift count in a register is always `%cl`.
+                BinaryOp::Sal => ("sal", 1),
+                BinaryOp::Sar => ("sar", 1),
+                BinaryOp::Shr => ("shr", 1),
+                BinaryOp::DivDouble => panic!("only doubles are divided with 'divsd'."),
+            };
+            write!(
+                line,
+                "{op}{} {}, {}",
+                suffix(*ty),
+                operand(src, src_size),
+                operand(dst, size(*ty))
+            )?;
+        }
+        Instruction::Cmp(AsmType::Double, a, b) => {
+            write!(line, "comisd {}, {}", operand(a, 8), operand(b, 8))?;
+        }
+        Instruction::Cmp(ty, a, b) => write!(
+            line,
+            "cmp{} {}, {}",
+            suffix(*ty),
+            operand(a, size(*ty)),
+            operand(b, size(*ty))
+        )?,
+        Instruction::Test(ty, a, b) => write!(
+            line,
+            "test{} {}, {}",
+            suffix(*ty),
+            operand(a, size(*ty)),
+            operand(b, size(*ty))
+        )?,
+        Instruction::Idiv(ty, operand_) => {
+            write!(line, "idiv{} {}", suffix(*ty), operand(operand_, size(*ty)))?;
+        }
+        Instruction::Div(ty, operand_) => {
+            write!(line, "div{} {}", suffix(*ty), operand(operand_, size(*ty)))?;
+        }
+        Instruction::Cdq(AsmType::Quadword) => line.push_str("cqo"),
+        Instruction::Cdq(_) => line.push_str("cdq"),
+        Instruction::Jmp(label) => write!(line, "jmp .L{label}")?,
+        Instruction::JmpCC(cond, label) => write!(line, "j{} .L{label}", cond_code(*cond))?,
+        Instruction::SetCC(cond, operand_) => {
+            write!(line, "set{} {}", cond_code(*cond), operand(operand_, 1))?;
+        }
+        // Labels aren't indented.
+        Instruction::Label(label) => return writeln!(f, ".L{label}:"),
+        Instruction::Push(operand_) => write!(line, "pushq {}", operand(operand_, 8))?,
+        Instruction::Pop(reg) => write!(line, "popq {}", register(*reg, 8))?,
+        Instruction::Call(name) => write!(line, "call {}", function_name(name, symbols))?,
+        Instruction::Ret => line.push_str("movq %rbp, %rsp\n\tpopq %rbp\n\tret"),
+        Instruction::TailCall(name) => write!(
+            line,
+            "movq %rbp, %rsp\n\tpopq %rbp\n\tjmp {}",
+            function_name(name, symbols)
+        )?,
+    }
+    writeln!(f, "\t{line}")
+}
+
+/// Functions defined in other files, like the C library's, are called through the procedure
+/// linkage table, since they may end up in a shared library.
+fn function_name(name: &str, symbols: &BackendSymbolTable) -> String {
+    match symbols.get(name) {
+        Some(AsmSymbol::Fun { defined: true, .. }) => name.to_owned(),
+        _ => format!("{name}@PLT"),
+    }
+}
+
+fn suffix(ty: AsmType) -> &'static str {
+    match ty {
+        AsmType::Byte => "b",
+        AsmType::Longword => "l",
+        AsmType::Quadword => "q",
+        AsmType::Double => "sd",
+        AsmType::ByteArray { .. } => panic!("arrays are never operands of an instruction."),
+    }
+}
+
+fn size(ty: AsmType) -> u8 {
+    match ty {
+        AsmType::Byte => 1,
+        AsmType::Longword => 4,
+        AsmType::Quadword | AsmType::Double => 8,
+        AsmType::ByteArray { .. } => panic!("arrays are never operands of an instruction."),
+    }
+}
+
+fn cond_code(cond: CondCode) -> &'static str {
+    match cond {
+        CondCode::E => "e",
+        CondCode::NE => "ne",
+        CondCode::G => "g",
+        CondCode::GE => "ge",
+        CondCode::L => "l",
+        CondCode::LE => "le",
+        CondCode::A => "a",
+        CondCode::AE => "ae",
+        CondCode::B => "b",
+        CondCode::BE => "be",
+    }
+}
+
+/// `operand` as written in an instruction that accesses `size` bytes of it.
+fn operand(operand: &Operand, size: u8) -> String {
+    match operand {
+        Operand::Imm(i) => format!("${i}"),
+        Operand::Reg(reg) => register(*reg, size),
+        Operand::Memory(reg, 0) => format!("({})", register(*reg, 8)),
+        Operand::Memory(reg, offset) => format!("{offset}({})", register(*reg, 8)),
+        Operand::Indexed { base, index, scale } => {
+            format!("({}, {}, {scale})", register(*base, 8), register(*index, 8))
+        }
+        Operand::Data(name) => format!("{name}(%rip)"),
+        Operand::Pseudo(name) | Operand::PseudoMem(name, _) => {
+            panic!("pseudo-register '{name}' should have been replaced before emission.")
+        }
+    }
+}
+
+/// The name of the `size`-byte part of `reg`. `xmm` registers have one name.
+fn register(reg: Reg, size: u8) -> String {
+    let names = match reg {
+        Reg::AX => ["%al", "%eax", "%rax"],
+        Reg::BX => ["%bl", "%ebx", "%rbx"],
+        Reg::CX => ["%cl", "%ecx", "%rcx"],
+        Reg::DX => ["%dl", "%edx", "%rdx"],
+        Reg::DI => ["%dil", "%edi", "%rdi"],
+        Reg::SI => ["%sil", "%esi", "%rsi"],
+        Reg::R8 => ["%r8b", "%r8d", "%r8"],
+        Reg::R9 => ["%r9b", "%r9d", "%r9"],
+        Reg::R10 => ["%r10b", "%r10d", "%r10"],
+        Reg::R11 => ["%r11b", "%r11d", "%r11"],
+        Reg::R12 => ["%r12b", "%r12d", "%r12"],
+        Reg::R13 => ["%r13b", "%r13d", "%r13"],
+        Reg::R14 => ["%r14b", "%r14d", "%r14"],
+        Reg::R15 => ["%r15b", "%r15d", "%r15"],
+        Reg::SP => ["%spl", "%esp", "%rsp"],
+        Reg::BP => ["%bpl", "%ebp", "%rbp"],
+        xmm => return format!("%{}", format!("{xmm:?}").to_lowercase()),
+    };
+    match size {
+        1 => names[0],
+        4 => names[1],
+        _ => names[2],
+    }
+    .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::c::assembly;
+
+    #[test]
+    fn test_emit_function() {
+        assert_eq!(
+            assembly("int f(int a) { return a + 1; }").to_string(),
+            "\t.globl f\n\
+             \t.text\n\
+             f:\n\
+             \tpushq %rbp\n\
+             \tmovq %rsp, %rbp\n\
+             \taddl $1, %edi\n\
+             \tmovl %edi, %eax\n\
+             \tmovq %rbp, %rsp\n\
+             \tpopq %rbp\n\
+             \tret\n\
+             \txorl %eax, %eax\n\
+             \tmovq %rbp, %rsp\n\
+             \tpopq %rbp\n\
+             \tret\n\
+             \n\
+             \t.section .note.GNU-stack,\"\",@progbits\n"
+        );
+    }
+
+    #[test]
+    fn test_emit_static_data_and_calls() {
+        let emitted = assembly(
+            "int g(int x);
+             static double d = 1.5;
+             long zeroes[4];
+             int f(void) { return g(2) + (d > 0.5); }",
+        )
+        .to_string();
+        for line in [
+            "\tcall g@PLT\n",
+            "\t.data\n\t.balign 8\nd:\n\t.quad 4609434218613702656\n",
+            "\t.globl zeroes\n\t.bss\n\t.balign 16\nzeroes:\n\t.zero 32\n",
+            "\t.section .rodata\n\t.balign 8\n.Ldouble.",
+        ] {
+            assert!(emitted.contains(line), "{line:?} missing from:\n{emitted}");
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use super::{AsmType, BinaryOp, Function, Instruction, Operand, Reg};
+
+/// Rewrites instructions whose operands x86-64 doesn't accept, through the scratch registers
+/// register allocation leaves free: `%r10` and `%xmm14` for sources, `%r11` and `%xmm15` for
+/// destinations.
+///
+/// Runs after pseudo-registers are replaced, once it's known which operands are in memory.
+pub fn fix_up_instructions(function: &mut Function) {
+    function.instructions = function.instructions.drain(..).flat_map(fix_up).collect();
+}
+
+fn fix_up(instruction: Instruction) -> Vec<Instruction> {
+    let r10 = Operand::Reg(Reg::R10);
+    let r11 = Operand::Reg(Reg::R11);
+    let xmm14 = Operand::Reg(Reg::XMM14);
+    let xmm15 = Operand::Reg(Reg::XMM15);
+
+    match instruction {
+        // Immediates too big for the destination keep only the bits that fit, as a truncation
+        // would.
+        Instruction::Mov(AsmType::Byte, Operand::Imm(i), dst) if i8::try_from(i).is_err() => {
+            fix_up(Instruction::Mov(
+                AsmType::Byte,
+                Operand::Imm(i as i8 as i64),
+                dst,
+            ))
+        }
+        Instruction::Mov(AsmType::Longword, Operand::Imm(i), dst)
+            if i32::try_from(i).is_err() && u32::try_from(i).is_err() =>
+        {
+            fix_up(Instruction::Mov(
+                AsmType::Longword,
+                Operand::Imm(i as i32 as i64),
+                dst,
+            ))
+        }
+        Instruction::Mov(AsmType::Quadword, src @ Operand::Imm(i), dst)
+            if is_large(i) && is_memory(&dst) =>
+        {
+            vec![
+                Instruction::Mov(AsmType::Quadword, src, r10.clone()),
+                Instruction::Mov(AsmType::Quadword, r10, dst),
+            ]
+        }
+        Instruction::Mov(ty, src, dst) if is_memory(&src) && is_memory(&dst) => {
+            let scratch = match ty {
+                AsmType::Double => xmm14,
+                _ => r10,
+            };
+            vec![
+                Instruction::Mov(ty, src, scratch.clone()),
+                Instruction::Mov(ty, scratch, dst),
+            ]
+        }
+        Instruction::Movsx {
+            src_type,
+            dst_type,
+            src,
+            dst,
+        } if matches!(src, Operand::Imm(_)) || is_memory(&dst) => {
+            let mut instructions = Vec::new();
+            let src = match src {
+                Operand::Imm(_) => {
+                    instructions.push(Instruction::Mov(src_type, src, r10.clone()));
+                    r10
+                }
+                src => src,
+            };
+            instructions.push(Instruction::Movsx {
+                src_type,
+                dst_type,
+                src,
+                dst: r11.clone(),
+            });
+            instructions.push(Instruction::Mov(dst_type, r11, dst));
+            instructions
+        }
+        // Writing a 32-bit register zeroes the upper half, so there's no `movzlq`.
+        Instruction::MovZeroExtend {
+            src_type: AsmType::Longword,
+            src,
+            dst,
+            ..
+        } => match dst {
+            Operand::Reg(_) => vec![Instruction::Mov(AsmType::Longword, src, dst)],
+            dst => vec![
+                Instruction::Mov(AsmType::Longword, src, r11.clone()),
+                Instruction::Mov(AsmType::Quadword, r11, dst),
+            ],
+        },
+        Instruction::MovZeroExtend {
+            src_type,
+            dst_type,
+            src,
+            dst,
+        } if matches!(src, Operand::Imm(_)) || is_memory(&dst) => {
+            let mut instructions = Vec::new();
+            let src = match src {
+                Operand::Imm(_) => {
+                    instructions.push(Instruction::Mov(src_type, src, r10.clone()));
+                    r10
+                }
+                src => src,
+            };
+            instructions.push(Instruction::MovZeroExtend {
+                src_type,
+                dst_type,
+                src,
+                dst: r11.clone(),
+            });
+            instructions.push(Instruction::Mov(dst_type, r11, dst));
+            instructions
+        }
+        Instruction::Lea(src, dst) if is_memory(&dst) => vec![
+            Instruction::Lea(src, r11.clone()),
+            Instruction::Mov(AsmType::Quadword, r11, dst),
+        ],
+        Instruction::Cvttsd2si(ty, src, dst) if is_memory(&dst) => vec![
+            Instruction::Cvttsd2si(ty, src, r11.clone()),
+            Instruction::Mov(ty, r11, dst),
+        ],
+        Instruction::Cvtsi2sd(ty, src, dst)
+            if matches!(src, Operand::Imm(_)) || is_memory(&dst) =>
+        {
+            let mut instructions = Vec::new();
+            let src = match src {
+                Operand::Imm(_) => {
+                    instructions.push(Instruction::Mov(ty, src, r10.clone()));
+                    r10
+                }
+                src => src,
+            };
+            match dst {
+                Operand::Reg(_) => instructions.push(Instruction::Cvtsi2sd(ty, src, dst)),
+                dst => {
+                    instructions.push(Instruction::Cvtsi2sd(ty, src, xmm15.clone()));
+                    instructions.push(Instruction::Mov(AsmType::Double, xmm15, dst));
+                }
+            }
+            instructions
+        }
+        // Every `double` arithmetic instruction writes a register.
+        Instruction::Binary(op, AsmType::Double, src, dst) if is_memory(&dst) => vec![
+            Instruction::Mov(AsmType::Double, dst.clone(), xmm15.clone()),
+            Instruction::Binary(op, AsmType::Double, src, xmm15.clone()),
+            Instruction::Mov(AsmType::Double, xmm15, dst),
+        ],
+        Instruction::Binary(
+            op @ (BinaryOp::Add | BinaryOp::Sub | BinaryOp::And | BinaryOp::Or | BinaryOp::Xor),
+            ty,
+            src,
+            dst,
+        ) if ty != AsmType::Double
+            && (is_memory(&src) && is_memory(&dst) || is_large_imm(ty, &src)) =>
+        {
+            vec![
+                Instruction::Mov(ty, src, r10.clone()),
+                Instruction::Binary(op, ty, r10, dst),
+            ]
+        }
+        Instruction::Binary(BinaryOp::Mult, ty, src, dst)
+            if ty != AsmType::Double && (is_memory(&dst) || is_large_imm(ty, &src)) =>
+        {
+            let mut instructions = Vec::new();
+            let src = if is_large_imm(ty, &src) {
+                instructions.push(Instruction::Mov(ty, src, r10.clone()));
+                r10
+            } else {
+                src
+            };
+            match dst {
+                Operand::Reg(_) => {
+                    instructions.push(Instruction::Binary(BinaryOp::Mult, ty, src, dst))
+                }
+                dst => {
+                    instructions.push(Instruction::Mov(ty, dst.clone(), r11.clone()));
+                    instructions.push(Instruction::Binary(BinaryOp::Mult, ty, src, r11.clone()));
+                    instructions.push(Instruction::Mov(ty, r11, dst));
+                }
+            }
+            instructions
+        }
+        // `comisd` compares against a register.
+        Instruction::Cmp(AsmType::Double, a, b) if !matches!(b, Operand::Reg(_)) => vec![
+            Instruction::Mov(AsmType::Double, b, xmm15.clone()),
+            Instruction::Cmp(AsmType::Double, a, xmm15),
+        ],
+        Instruction::Cmp(ty, a, b)
+            if ty != AsmType::Double
+                && (is_memory(&a) && is_memory(&b)
+                    || is_large_imm(ty, &a)
+                    || matches!(b, Operand::Imm(_))) =>
+        {
+            let mut instructions = Vec::new();
+            let a = if is_memory(&a) && is_memory(&b) || is_large_imm(ty, &a) {
+                instructions.push(Instruction::Mov(ty, a, r10.clone()));
+                r10
+            } else {
+                a
+            };
+            let b = match b {
+                Operand::Imm(_) => {
+                    instructions.push(Instruction::Mov(ty, b, r11.clone()));
+                    r11
+                }
+                b => b,
+            };
+            instructions.push(Instruction::Cmp(ty, a, b));
+            instructions
+        }
+        Instruction::Idiv(ty, src @ Operand::Imm(_)) => vec![
+            Instruction::Mov(ty, src, r10.clone()),
+            Instruction::Idiv(ty, r10),
+        ],
+        Instruction::Div(ty, src @ Operand::Imm(_)) => vec![
+            Instruction::Mov(ty, src, r10.clone()),
+            Instruction::Div(ty, r10),
+        ],
+        Instruction::Push(src @ Operand::Imm(i)) if is_large(i) => vec![
+            Instruction::Mov(AsmType::Quadword, src, r10.clone()),
+            Instruction::Push(r10),
+        ],
+        // There's no `push` for `xmm` registers.
+        Instruction::Push(Operand::Reg(reg)) if is_xmm(reg) => vec![
+            Instruction::Binary(
+                BinaryOp::Sub,
+                AsmType::Quadword,
+                Operand::Imm(8),
+                Operand::Reg(Reg::SP),
+            ),
+            Instruction::Mov(
+                AsmType::Double,
+                Operand::Reg(reg),
+                Operand::Memory(Reg::SP, 0),
+            ),
+        ],
+        instruction => vec![instruction],
+    }
+}
+
+/// Whether `operand` is in memory, once pseudo-registers are replaced.
+fn is_memory(operand: &Operand) -> bool {
+    matches!(
+        operand,
+        Operand::Memory(..) | Operand::Indexed { .. } | Operand::Data(_)
+    )
+}
+
+/// Whether `i` doesn't fit in the sign-extended 32-bit immediate most instructions take.
+fn is_large(i: i64) -> bool {
+    i32::try_from(i).is_err()
+}
+
+fn is_large_imm(ty: AsmType, operand: &Operand) -> bool {
+    ty == AsmType::Quadword && matches!(operand, Operand::Imm(i) if is_large(*i))
+}
+
+fn is_xmm(reg: Reg) -> bool {
+    reg >= Reg::XMM0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assembly::{function, reg, stack};
+
+    fn fixed_up(instructions: Vec<Instruction>) -> Vec<Instruction> {
+        let mut f = function(instructions);
+        fix_up_instructions(&mut f);
+        f.instructions
+    }
+
+    #[test]
+    fn test_memory_to_memory_goes_through_scratch_register() {
+        assert_eq!(
+            fixed_up(vec![
+                Instruction::Mov(AsmType::Longword, stack(-4), stack(-8)),
+                Instruction::Mov(AsmType::Double, stack(-16), stack(-24)),
+            ]),
+            vec![
+                Instruction::Mov(AsmType::Longword, stack(-4), reg(Reg::R10)),
+                Instruction::Mov(AsmType::Longword, reg(Reg::R10), stack(-8)),
+                Instruction::Mov(AsmType::Double, stack(-16), reg(Reg::XMM14)),
+                Instruction::Mov(AsmType::Double, reg(Reg::XMM14), stack(-24)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_large_immediates() {
+        let big = Operand::Imm(1 << 40);
+        assert_eq!(
+            fixed_up(vec![
+                Instruction::Binary(BinaryOp::Add, AsmType::Quadword, big.clone(), reg(Reg::AX)),
+                Instruction::Mov(AsmType::Longword, big.clone(), reg(Reg::AX)),
+                Instruction::Push(big.clone()),
+            ]),
+            vec![
+                Instruction::Mov(AsmType::Quadword, big.clone(), reg(Reg::R10)),
+                Instruction::Binary(
+                    BinaryOp::Add,
+                    AsmType::Quadword,
+                    reg(Reg::R10),
+                    reg(Reg::AX)
+                ),
+                // Truncated, as the high bits aren't stored.
+                Instruction::Mov(AsmType::Longword, Operand::Imm(0), reg(Reg::AX)),
+                Instruction::Mov(AsmType::Quadword, big, reg(Reg::R10)),
+                Instruction::Push(reg(Reg::R10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_destinations_that_must_be_registers() {
+        assert_eq!(
+            fixed_up(vec![
+                Instruction::Binary(
+                    BinaryOp::Mult,
+                    AsmType::Longword,
+                    Operand::Imm(3),
+                    stack(-4)
+                ),
+                Instruction::Cmp(AsmType::Double, reg(Reg::XMM0), stack(-16)),
+                Instruction::Cmp(AsmType::Longword, reg(Reg::AX), Operand::Imm(5)),
+            ]),
+            vec![
+                Instruction::Mov(AsmType::Longword, stack(-4), reg(Reg::R11)),
+                Instruction::Binary(
+                    BinaryOp::Mult,
+                    AsmType::Longword,
+                    Operand::Imm(3),
+                    reg(Reg::R11)
+                ),
+                Instruction::Mov(AsmType::Longword, reg(Reg::R11), stack(-4)),
+                Instruction::Mov(AsmType::Double, stack(-16), reg(Reg::XMM15)),
+                Instruction::Cmp(AsmType::Double, reg(Reg::XMM0), reg(Reg::XMM15)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(5), reg(Reg::R11)),
+                Instruction::Cmp(AsmType::Longword, reg(Reg::AX), reg(Reg::R11)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use super::{AsmType, BinaryOp, Function, Instruction, Operand, Reg};
+
+/// Registers only ever used to fix up a single instruction's operands, so they never hold a
+/// value across a label, jump or call.
+const SCRATCH_REGS: [Reg; 4] = [Reg::R10, Reg::R11, Reg::XMM14, Reg::XMM15];
+
+/// Rewrites short sequences of instructions into shorter or cheaper equivalents, until none
+/// apply:
+///
+/// - `mov x, x` is removed;
+/// - a load into a scratch register that's read once is folded into the instruction reading
+///   it, as a memory operand;
+/// - `imul` by a power of two becomes a shift;
+/// - `mov $0, reg` becomes `xor reg, reg`, where the flags it clobbers aren't read;
+/// - `cmp $0, reg` becomes `test reg, reg`;
+/// - consecutive adjustments of the stack pointer are merged.
+///
+/// Runs after register allocation, once operands are final.
+pub fn simplify(function: &mut Function) {
+    loop {
+        let before = function.instructions.len();
+        function.instructions = simplify_once(&function.instructions);
+        if function.instructions.len() == before {
+            break;
+        }
+    }
+
+    function.instructions = function
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(i, instruction)| strength_reduce(&function.instructions, i, instruction))
+        .collect();
+}
+
+/// Applies the rules that remove instructions, in one pass.
+fn simplify_once(instructions: &[Instruction]) -> Vec<Instruction> {
+    let mut simplified: Vec<Instruction> = Vec::with_capacity(instructions.len());
+    let mut i = 0;
+
+    while i < instructions.len() {
+        let instruction = &instructions[i];
+        match instruction {
+            Instruction::Mov(_, src, dst) if src == dst => {
+                i += 1;
+                continue;
+            }
+            Instruction::Binary(BinaryOp::Mult, ty, Operand::Imm(1), _)
+                if *ty != AsmType::Double =>
+            {
+                i += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        if let Some(adjustment) = stack_adjustment(instruction) {
+            let mut total = adjustment;
+            i += 1;
+            while let Some(adjustment) = instructions.get(i).and_then(stack_adjustment) {
+                total += adjustment;
+                i += 1;
+            }
+            match total {
+                0 => (),
+                total if total > 0 => simplified.push(adjust_stack(BinaryOp::Add, total)),
+                total => simplified.push(adjust_stack(BinaryOp::Sub, -total)),
+            }
+            continue;
+        }
+
+        if let Instruction::Mov(ty, src, Operand::Reg(reg)) = instruction {
+            if let Some(folded) = instructions
+                .get(i + 1)
+                .and_then(|next| fold_load(*ty, src, *reg, next))
+            {
+                if is_dead_after(instructions, i + 1, *reg) {
+                    simplified.push(folded);
+                    i += 2;
+                    continue;
+                }
+            }
+        }
+
+        simplified.push(instruction.clone());
+        i += 1;
+    }
+
+    simplified
+}
+
+/// Replaces an instruction with a cheaper one with the same effect, where there is one.
+fn strength_reduce(
+    instructions: &[Instruction],
+    i: usize,
+    instruction: &Instruction,
+) -> Instruction {
+    match instruction {
+        Instruction::Binary(BinaryOp::Mult, ty, Operand::Imm(n), dst)
+            if *ty != AsmType::Double && *n > 0 && (*n as u64).is_power_of_two() =>
+        {
+            Instruction::Binary(
+                BinaryOp::Sal,
+                *ty,
+                Operand::Imm(n.trailing_zeros().into()),
+                dst.clone(),
+            )
+        }
+        Instruction::Mov(ty, Operand::Imm(0), dst @ Operand::Reg(_))
+            if *ty != AsmType::Double && are_flags_dead_after(instructions, i) =>
+        {
+            // Writing a 32-bit register clears the upper half, so `xorl` zeroes all of it.
+            let ty = match ty {
+                AsmType::Quadword => AsmType::Longword,
+                ty => *ty,
+            };
+            Instruction::Binary(BinaryOp::Xor, ty, dst.clone(), dst.clone())
+        }
+        Instruction::Cmp(ty, Operand::Imm(0), reg @ Operand::Reg(_)) if *ty != AsmType::Double => {
+            Instruction::Test(*ty, reg.clone(), reg.clone())
+        }
+        instruction => instruction.clone(),
+    }
+}
+
+/// Returns how many bytes `instruction` pops off the stack, negative if it pushes, if all it
+/// does is adjust the stack pointer.
+fn stack_adjustment(instruction: &Instruction) -> Option<i64> {
+    match instruction {
+        Instruction::Binary(
+            BinaryOp::Add,
+            AsmType::Quadword,
+            Operand::Imm(n),
+            Operand::Reg(Reg::SP),
+        ) => Some(*n),
+        Instruction::Binary(
+            BinaryOp::Sub,
+            AsmType::Quadword,
+            Operand::Imm(n),
+            Operand::Reg(Reg::SP),
+        ) => Some(-n),
+        _ => None,
+    }
+}
+
+fn adjust_stack(op: BinaryOp, bytes: i64) -> Instruction {
+    Instruction::Binary(
+        op,
+        AsmType::Quadword,
+        Operand::Imm(bytes),
+        Operand::Reg(Reg::SP),
+    )
+}
+
+/// Returns `next` reading the value `mov src, reg` loads from `src` directly instead of from
+/// `reg`, if `reg` is a scratch register and `next` can take a memory operand there.
+fn fold_load(ty: AsmType, src: &Operand, reg: Reg, next: &Instruction) -> Option<Instruction> {
+    if !SCRATCH_REGS.contains(&reg) || !is_memory(src) {
+        return None;
+    }
+    let is_reg = |operand: &Operand| *operand == Operand::Reg(reg);
+    let is_other_reg = |operand: &Operand| matches!(operand, Operand::Reg(other) if *other != reg);
+
+    match next {
+        Instruction::Binary(
+            op @ (BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Mult
+            | BinaryOp::DivDouble
+            | BinaryOp::And
+            | BinaryOp::Or
+            | BinaryOp::Xor),
+            next_ty,
+            operand,
+            dst,
+        ) if *next_ty == ty
+            && is_reg(operand)
+            && is_other_reg(dst)
+            // `andpd`, `orpd` and `xorpd` read 16 bytes from memory, which must be aligned.
+            && !(ty == AsmType::Double
+                && matches!(op, BinaryOp::And | BinaryOp::Or | BinaryOp::Xor)) =>
+        {
+            Some(Instruction::Binary(*op, ty, src.clone(), dst.clone()))
+        }
+        Instruction::Cmp(next_ty, a, b) if *next_ty == ty && is_reg(a) && is_other_reg(b) => {
+            Some(Instruction::Cmp(ty, src.clone(), b.clone()))
+        }
+        // `comisd` can only compare against a register.
+        Instruction::Cmp(next_ty, a, b)
+            if *next_ty == ty
+                && ty != AsmType::Double
+                && (matches!(a, Operand::Imm(_)) || is_other_reg(a))
+                && is_reg(b) =>
+        {
+            Some(Instruction::Cmp(ty, a.clone(), src.clone()))
+        }
+        Instruction::Push(operand) if ty == AsmType::Quadword && is_reg(operand) => {
+            Some(Instruction::Push(src.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `operand` is, or may end up as, a memory operand.
+fn is_memory(operand: &Operand) -> bool {
+    match operand {
+        Operand::Memory(..)
+        | Operand::Indexed { .. }
+        | Operand::Data(_)
+        | Operand::Pseudo(_)
+        | Operand::PseudoMem(..) => true,
+        Operand::Imm(_) | Operand::Reg(_) => false,
+    }
+}
+
+/// Whether the value of scratch register `reg` after instruction `i` is never read.
+fn is_dead_after(instructions: &[Instruction], i: usize, reg: Reg) -> bool {
+    for instruction in &instructions[i + 1..] {
+        match instruction {
+            Instruction::Label(_)
+            | Instruction::Jmp(_)
+            | Instruction::JmpCC(..)
+            | Instruction::Call(_)
+            | Instruction::Ret
+            | Instruction::TailCall(_) => return true,
+            Instruction::Mov(_, src, Operand::Reg(dst))
+            | Instruction::Movsx {
+                src,
+                dst: Operand::Reg(dst),
+                ..
+            }
+            | Instruction::MovZeroExtend {
+                src,
+                dst: Operand::Reg(dst),
+                ..
+            }
+            | Instruction::Lea(src, Operand::Reg(dst))
+            | Instruction::Cvttsd2si(_, src, Operand::Reg(dst))
+            | Instruction::Cvtsi2sd(_, src, Operand::Reg(dst))
+                if *dst == reg =>
+            {
+                return !mentions(src, reg);
+            }
+            instruction => {
+                if operands(instruction)
+                    .into_iter()
+                    .any(|operand| mentions(operand, reg))
+                {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Whether the flags set before instruction `i` are never read after it.
+fn are_flags_dead_after(instructions: &[Instruction], i: usize) -> bool {
+    for instruction in &instructions[i + 1..] {
+        match instruction {
+            Instruction::JmpCC(..) | Instruction::SetCC(..) => return false,
+            Instruction::Cmp(..)
+            | Instruction::Test(..)
+            | Instruction::Label(_)
+            | Instruction::Jmp(_)
+            | Instruction::Call(_)
+            | Instruction::Ret
+            | Instruction::TailCall(_) => return true,
+            _ => (),
+        }
+    }
+    true
+}
+
+fn mentions(operand: &Operand, reg: Reg) -> bool {
+    match operand {
+        Operand::Reg(other) | Operand::Memory(other, _) => *other == reg,
+        Operand::Indexed { base, index, .. } => *base == reg || *index == reg,
+        Operand::Imm(_) | Operand::Pseudo(_) | Operand::PseudoMem(..) | Operand::Data(_) => false,
+    }
+}
+
+fn operands(instruction: &Instruction) -> Vec<&Operand> {
+    match instruction {
+        Instruction::Mov(_, src, dst)
+        | Instruction::Movsx { src, dst, .. }
+        | Instruction::MovZeroExtend { src, dst, .. }
+        | Instruction::Lea(src, dst)
+        | Instruction::Cvttsd2si(_, src, dst)
+        | Instruction::Cvtsi2sd(_, src, dst)
+        | Instruction::Binary(_, _, src, dst)
+        | Instruction::Cmp(_, src, dst)
+        | Instruction::Test(_, src, dst) => vec![src, dst],
+        Instruction::Unary(_, _, operand)
+        | Instruction::Idiv(_, operand)
+        | Instruction::Div(_, operand)
+        | Instruction::SetCC(_, operand)
+        | Instruction::Push(operand) => vec![operand],
+        Instruction::Cdq(_)
+        | Instruction::Jmp(_)
+        | Instruction::JmpCC(..)
+        | Instruction::Label(_)
+        | Instruction::Pop(_)
+        | Instruction::Call(_)
+        | Instruction::Ret
+        | Instruction::TailCall(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembly::CondCode,
+        test_support::assembly::{function, programs, reg, stack},
+    };
+
+    fn simplified(instructions: Vec<Instruction>) -> Vec<Instruction> {
+        let mut function = function(instructions);
+        simplify(&mut function);
+        function.instructions
+    }
+
+    #[test]
+    fn test_remove_mov_to_itself() {
+        assert_eq!(
+            simplified(vec![
+                Instruction::Mov(AsmType::Longword, reg(Reg::AX), reg(Reg::AX)),
+                Instruction::Ret,
+            ]),
+            vec![Instruction::Ret]
+        );
+    }
+
+    #[test]
+    fn test_fold_load_into_memory_operand() {
+        assert_eq!(
+            simplified(vec![
+                Instruction::Mov(AsmType::Longword, stack(-4), reg(Reg::R10)),
+                Instruction::Binary(
+                    BinaryOp::Add,
+                    AsmType::Longword,
+                    reg(Reg::R10),
+                    reg(Reg::AX)
+                ),
+                Instruction::Mov(AsmType::Quadword, stack(-16), reg(Reg::R11)),
+                Instruction::Cmp(AsmType::Quadword, Operand::Imm(3), reg(Reg::R11)),
+                Instruction::Ret,
+            ]),
+            vec![
+                Instruction::Binary(BinaryOp::Add, AsmType::Longword, stack(-4), reg(Reg::AX)),
+                Instruction::Cmp(AsmType::Quadword, Operand::Imm(3), stack(-16)),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_load_read_again() {
+        let instructions = vec![
+            Instruction::Mov(AsmType::Longword, stack(-4), reg(Reg::R10)),
+            Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Longword,
+                reg(Reg::R10),
+                reg(Reg::AX),
+            ),
+            Instruction::Mov(AsmType::Longword, reg(Reg::R10), stack(-8)),
+            Instruction::Ret,
+        ];
+        assert_eq!(simplified(instructions.clone()), instructions);
+    }
+
+    #[test]
+    fn test_keep_double_bitwise_load() {
+        let instructions = vec![
+            Instruction::Mov(AsmType::Double, stack(-8), reg(Reg::XMM14)),
+            Instruction::Binary(
+                BinaryOp::Xor,
+                AsmType::Double,
+                reg(Reg::XMM14),
+                reg(Reg::XMM0),
+            ),
+            Instruction::Ret,
+        ];
+        assert_eq!(simplified(instructions.clone()), instructions);
+    }
+
+    #[test]
+    fn test_multiply_by_power_of_two() {
+        assert_eq!(
+            simplified(vec![
+                Instruction::Binary(
+                    BinaryOp::Mult,
+                    AsmType::Quadword,
+                    Operand::Imm(8),
+                    reg(Reg::CX)
+                ),
+                Instruction::Binary(
+                    BinaryOp::Mult,
+                    AsmType::Longword,
+                    Operand::Imm(1),
+                    reg(Reg::CX)
+                ),
+                Instruction::Binary(
+                    BinaryOp::Mult,
+                    AsmType::Longword,
+                    Operand::Imm(6),
+                    reg(Reg::CX)
+                ),
+            ]),
+            vec![
+                Instruction::Binary(
+                    BinaryOp::Sal,
+                    AsmType::Quadword,
+                    Operand::Imm(3),
+                    reg(Reg::CX)
+                ),
+                Instruction::Binary(
+                    BinaryOp::Mult,
+                    AsmType::Longword,
+                    Operand::Imm(6),
+                    reg(Reg::CX)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero_with_xor_unless_flags_are_read() {
+        assert_eq!(
+            simplified(vec![
+                Instruction::Mov(AsmType::Quadword, Operand::Imm(0), reg(Reg::AX)),
+                Instruction::Cmp(AsmType::Longword, reg(Reg::DI), reg(Reg::SI)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(0), reg(Reg::CX)),
+                Instruction::SetCC(CondCode::L, reg(Reg::CX)),
+                Instruction::Ret,
+            ]),
+            vec![
+                Instruction::Binary(BinaryOp::Xor, AsmType::Longword, reg(Reg::AX), reg(Reg::AX)),
+                Instruction::Cmp(AsmType::Longword, reg(Reg::DI), reg(Reg::SI)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(0), reg(Reg::CX)),
+                Instruction::SetCC(CondCode::L, reg(Reg::CX)),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_with_zero_using_test() {
+        assert_eq!(
+            simplified(vec![
+                Instruction::Cmp(AsmType::Longword, Operand::Imm(0), reg(Reg::AX)),
+                Instruction::JmpCC(CondCode::E, "end".to_owned()),
+                Instruction::Cmp(AsmType::Longword, Operand::Imm(0), stack(-4)),
+            ]),
+            vec![
+                Instruction::Test(AsmType::Longword, reg(Reg::AX), reg(Reg::AX)),
+                Instruction::JmpCC(CondCode::E, "end".to_owned()),
+                Instruction::Cmp(AsmType::Longword, Operand::Imm(0), stack(-4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_stack_adjustments() {
+        assert_eq!(
+            simplified(vec![
+                Instruction::Call("g".to_owned()),
+                adjust_stack(BinaryOp::Add, 16),
+                adjust_stack(BinaryOp::Sub, 8),
+                Instruction::Push(reg(Reg::AX)),
+                adjust_stack(BinaryOp::Sub, 8),
+                adjust_stack(BinaryOp::Add, 8),
+                Instruction::Ret,
+            ]),
+            vec![
+                Instruction::Call("g".to_owned()),
+                adjust_stack(BinaryOp::Add, 8),
+                Instruction::Push(reg(Reg::AX)),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compiled_programs_are_simplified() {
+        for mut program in programs() {
+            let before = program.instructions.clone();
+            simplify(&mut program);
+            assert!(
+                program.instructions != before && program.instructions.len() <= before.len(),
+                "{} wasn't simplified.",
+                program.name
+            );
+        }
+    }
+}
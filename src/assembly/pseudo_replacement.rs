@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use super::{
+    AsmSymbol, AsmType, BackendSymbolTable, BinaryOp, Function, Instruction, Operand, Reg,
+};
+
+/// Replaces the pseudo-registers register allocation left behind with memory operands, and
+/// reserves the stack frame they need.
+///
+/// Static variables are addressed relative to `%rip`; everything else gets a slot below the
+/// frame pointer, aligned to its size. The frame is sized so the stack pointer stays 16-byte
+/// aligned once the callee-saved registers are pushed below it.
+///
+/// Runs after register allocation, since it relies on `function.callee_saved`.
+///
+/// # Panics
+///
+/// Panics if one of the function's pseudo-registers is missing from `symbols`.
+pub fn replace_pseudos(function: &mut Function, symbols: &BackendSymbolTable) {
+    let mut frame = Frame {
+        symbols,
+        slots: HashMap::new(),
+        size: 0,
+    };
+
+    for instruction in &mut function.instructions {
+        for operand in instruction.operands_mut() {
+            if let Some(replacement) = frame.replace(operand) {
+                *operand = replacement;
+            }
+        }
+    }
+
+    let saved = 8 * function.callee_saved.len() as i64;
+    let size = round_up(frame.size + saved, 16) - saved;
+    if size > 0 {
+        function.instructions.insert(
+            0,
+            Instruction::Binary(
+                BinaryOp::Sub,
+                AsmType::Quadword,
+                Operand::Imm(size),
+                Operand::Reg(Reg::SP),
+            ),
+        );
+    }
+}
+
+struct Frame<'a> {
+    symbols: &'a BackendSymbolTable,
+    /// Each pseudo-register's offset from the frame pointer.
+    slots: HashMap<String, i64>,
+    /// The bytes of slots handed out so far.
+    size: i64,
+}
+
+impl Frame<'_> {
+    fn replace(&mut self, operand: &Operand) -> Option<Operand> {
+        let (name, offset) = match operand {
+            Operand::Pseudo(name) => (name, 0),
+            Operand::PseudoMem(name, offset) => (name, *offset),
+            _ => return None,
+        };
+        let Some(AsmSymbol::Obj { ty, is_static }) = self.symbols.get(name) else {
+            panic!("pseudo-register '{name}' is missing from the backend symbol table.");
+        };
+
+        if *is_static {
+            assert!(
+                offset == 0,
+                "static aggregates are only ever addressed as a whole."
+            );
+            return Some(Operand::Data(name.clone()));
+        }
+
+        let slot = match self.slots.get(name) {
+            Some(slot) => *slot,
+            None => {
+                let (size, alignment) = match ty {
+                    AsmType::Byte => (1, 1),
+                    AsmType::Longword => (4, 4),
+                    AsmType::Quadword | AsmType::Double => (8, 8),
+                    AsmType::ByteArray { size, alignment } => (*size, *alignment),
+                };
+                self.size = round_up(self.size + size, alignment);
+                self.slots.insert(name.clone(), -self.size);
+                -self.size
+            }
+        };
+        Some(Operand::Memory(Reg::BP, slot + offset))
+    }
+}
+
+fn round_up(n: i64, multiple: i64) -> i64 {
+    (n + multiple - 1) / multiple * multiple
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::assembly::{function, pseudo, reg, stack};
+
+    fn symbols() -> BackendSymbolTable {
+        let mut symbols = BackendSymbolTable::new();
+        for (name, ty, is_static) in [
+            ("c", AsmType::Byte, false),
+            ("l", AsmType::Quadword, false),
+            ("s", AsmType::Longword, true),
+            (
+                "arr",
+                AsmType::ByteArray {
+                    size: 12,
+                    alignment: 4,
+                },
+                false,
+            ),
+        ] {
+            symbols.insert(name, AsmSymbol::Obj { ty, is_static });
+        }
+        symbols
+    }
+
+    #[test]
+    fn test_slots_are_aligned() {
+        let mut f = function(vec![
+            Instruction::Mov(AsmType::Byte, Operand::Imm(1), pseudo("c")),
+            Instruction::Mov(AsmType::Quadword, Operand::Imm(2), pseudo("l")),
+            Instruction::Mov(
+                AsmType::Longword,
+                Operand::Imm(3),
+                Operand::PseudoMem("arr".to_owned(), 8),
+            ),
+            Instruction::Mov(AsmType::Longword, pseudo("s"), reg(Reg::AX)),
+            Instruction::Ret,
+        ]);
+        replace_pseudos(&mut f, &symbols());
+
+        assert_eq!(
+            f.instructions,
+            vec![
+                Instruction::Binary(
+                    BinaryOp::Sub,
+                    AsmType::Quadword,
+                    Operand::Imm(32),
+                    reg(Reg::SP)
+                ),
+                Instruction::Mov(AsmType::Byte, Operand::Imm(1), stack(-1)),
+                Instruction::Mov(AsmType::Quadword, Operand::Imm(2), stack(-16)),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(3), stack(-20)),
+                Instruction::Mov(
+                    AsmType::Longword,
+                    Operand::Data("s".to_owned()),
+                    reg(Reg::AX)
+                ),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_accounts_for_callee_saved_registers() {
+        let mut f = function(vec![
+            Instruction::Push(reg(Reg::BX)),
+            Instruction::Mov(AsmType::Quadword, Operand::Imm(2), pseudo("l")),
+            Instruction::Pop(Reg::BX),
+            Instruction::Ret,
+        ]);
+        f.callee_saved = vec![Reg::BX];
+        replace_pseudos(&mut f, &symbols());
+
+        // 8 bytes of slots and 8 of saved registers keep the stack aligned without padding.
+        assert_eq!(
+            f.instructions[0],
+            Instruction::Binary(
+                BinaryOp::Sub,
+                AsmType::Quadword,
+                Operand::Imm(8),
+                reg(Reg::SP)
+            )
+        );
+    }
+}
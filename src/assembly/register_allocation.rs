@@ -0,0 +1,793 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use super::{AsmSymbol, AsmType, BackendSymbolTable, Function, Instruction, Operand, Reg};
+use crate::optimizer::{
+    cfg::Cfg,
+    dataflow::{self, Analysis, Direction},
+};
+
+// Caller-saved registers come first, so they're preferred: using a callee-saved register costs
+// a push and a pop. R10, R11, XMM14 and XMM15 are left out as scratch registers for rewriting
+// instructions with invalid operands.
+const GENERAL_REGS: [Reg; 12] = [
+    Reg::AX,
+    Reg::CX,
+    Reg::DX,
+    Reg::DI,
+    Reg::SI,
+    Reg::R8,
+    Reg::R9,
+    Reg::BX,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+];
+
+const XMM_REGS: [Reg; 14] = [
+    Reg::XMM0,
+    Reg::XMM1,
+    Reg::XMM2,
+    Reg::XMM3,
+    Reg::XMM4,
+    Reg::XMM5,
+    Reg::XMM6,
+    Reg::XMM7,
+    Reg::XMM8,
+    Reg::XMM9,
+    Reg::XMM10,
+    Reg::XMM11,
+    Reg::XMM12,
+    Reg::XMM13,
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Class {
+    General,
+    Xmm,
+}
+
+impl Class {
+    fn registers(self) -> &'static [Reg] {
+        match self {
+            Class::General => &GENERAL_REGS,
+            Class::Xmm => &XMM_REGS,
+        }
+    }
+}
+
+/// Replaces pseudo-registers with hardware registers, Chaitin-Briggs style.
+///
+/// General-purpose and `xmm` registers are allocated separately. For each class, moves between
+/// non-interfering pseudo-registers are coalesced as long as that can't make the interference
+/// graph uncolorable, then the graph is colored, spilling the cheapest pseudo-registers when it
+/// has to. Spilled, static and address-taken pseudo-registers are left as they are, to be given
+/// memory operands later.
+///
+/// Callee-saved registers the function ends up using are pushed on entry, popped before every
+/// `Ret` or `TailCall` and recorded in `function.callee_saved`, so the stack frame can stay
+/// aligned.
+///
+/// # Panics
+///
+/// Panics if the function, a function it calls or one of its pseudo-registers is missing from
+/// `symbols`.
+pub fn allocate_registers(function: &mut Function, symbols: &BackendSymbolTable) {
+    let return_regs = match symbols.get(&function.name) {
+        Some(AsmSymbol::Fun { return_regs, .. }) => return_regs.clone(),
+        _ => panic!(
+            "function '{}' is missing from the backend symbol table.",
+            function.name
+        ),
+    };
+    let address_taken = function
+        .instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Lea(Operand::Pseudo(name), _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let liveness = Liveness {
+        symbols,
+        return_regs,
+        address_taken,
+    };
+
+    let mut callee_saved = BTreeSet::new();
+    for class in [Class::General, Class::Xmm] {
+        callee_saved.extend(allocate_class(&mut function.instructions, &liveness, class));
+    }
+
+    save_callee_saved(function, callee_saved.into_iter().collect());
+}
+
+/// Allocates registers of one class, returning the callee-saved ones it used.
+fn allocate_class(
+    instructions: &mut Vec<Instruction>,
+    liveness: &Liveness,
+    class: Class,
+) -> Vec<Reg> {
+    let graph = loop {
+        let mut graph = Graph::build(instructions, liveness, class);
+        let merged = coalesce(&mut graph, instructions, liveness);
+        if merged.is_empty() {
+            break graph;
+        }
+        rewrite(instructions, |operand| find(&merged, operand));
+    };
+
+    let mut costs: HashMap<String, usize> = HashMap::new();
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            if let Operand::Pseudo(name) = operand {
+                *costs.entry(name.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    let colors = graph.color(&costs, class);
+    rewrite(instructions, |operand| match operand {
+        Operand::Pseudo(name) => colors.get(name).map(|reg| Operand::Reg(*reg)),
+        _ => None,
+    });
+
+    let mut used: Vec<Reg> = colors.into_values().collect();
+    used.retain(|reg| reg.is_callee_saved());
+    used
+}
+
+/// Merges the operands of `Mov`s wherever that's safe, returning each merged operand's
+/// replacement. Hardware registers are never replaced.
+fn coalesce(
+    graph: &mut Graph,
+    instructions: &[Instruction],
+    liveness: &Liveness,
+) -> HashMap<Operand, Operand> {
+    let mut merged = HashMap::new();
+
+    for instruction in instructions {
+        let Instruction::Mov(_, src, dst) = instruction else {
+            continue;
+        };
+        let src = find(&merged, src).unwrap_or_else(|| src.clone());
+        let dst = find(&merged, dst).unwrap_or_else(|| dst.clone());
+
+        if src == dst
+            || !graph.contains(&src)
+            || !graph.contains(&dst)
+            || graph.interfere(&src, &dst)
+            || !liveness.same_size(&src, &dst)
+        {
+            continue;
+        }
+
+        let (keep, merge) = match src {
+            Operand::Reg(_) => (src, dst),
+            _ => (dst, src),
+        };
+        let k = graph.k;
+        let safe = graph.briggs(&keep, &merge, k)
+            || matches!(keep, Operand::Reg(_)) && graph.george(&keep, &merge, k);
+
+        if safe {
+            graph.merge(&keep, &merge);
+            merged.insert(merge, keep);
+        }
+    }
+
+    merged
+}
+
+fn find(merged: &HashMap<Operand, Operand>, operand: &Operand) -> Option<Operand> {
+    let mut operand = merged.get(operand)?;
+    while let Some(next) = merged.get(operand) {
+        operand = next;
+    }
+    Some(operand.clone())
+}
+
+/// Replaces every register or pseudo-register operand `replace` returns a replacement for,
+/// then drops the `Mov`s that became no-ops.
+fn rewrite(instructions: &mut Vec<Instruction>, replace: impl Fn(&Operand) -> Option<Operand>) {
+    for instruction in instructions.iter_mut() {
+        for operand in instruction.operands_mut() {
+            if let Some(replacement) = replace(operand) {
+                *operand = replacement;
+            }
+        }
+    }
+
+    instructions
+        .retain(|instruction| !matches!(instruction, Instruction::Mov(_, src, dst) if src == dst));
+}
+
+fn save_callee_saved(function: &mut Function, callee_saved: Vec<Reg>) {
+    let mut instructions: Vec<Instruction> = callee_saved
+        .iter()
+        .map(|reg| Instruction::Push(Operand::Reg(*reg)))
+        .collect();
+
+    for instruction in std::mem::take(&mut function.instructions) {
+        if matches!(instruction, Instruction::Ret | Instruction::TailCall(_)) {
+            instructions.extend(callee_saved.iter().rev().map(|reg| Instruction::Pop(*reg)));
+        }
+        instructions.push(instruction);
+    }
+
+    function.instructions = instructions;
+    function.callee_saved = callee_saved;
+}
+
+/// Backward analysis of which registers and pseudo-registers may still be read.
+///
+/// Only operands that can take part in allocation are tracked: allocatable hardware registers,
+/// and pseudo-registers that are neither static nor have their address taken.
+struct Liveness<'a> {
+    symbols: &'a BackendSymbolTable,
+    return_regs: Vec<Reg>,
+    address_taken: HashSet<String>,
+}
+
+impl Liveness<'_> {
+    fn class(&self, operand: &Operand) -> Option<Class> {
+        match operand {
+            Operand::Reg(reg) if GENERAL_REGS.contains(reg) => Some(Class::General),
+            Operand::Reg(reg) if XMM_REGS.contains(reg) => Some(Class::Xmm),
+            Operand::Pseudo(name) if !self.address_taken.contains(name) => {
+                match self.object(name) {
+                    (_, true) => None,
+                    (AsmType::Double, false) => Some(Class::Xmm),
+                    (_, false) => Some(Class::General),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn object(&self, name: &str) -> (AsmType, bool) {
+        match self.symbols.get(name) {
+            Some(AsmSymbol::Obj { ty, is_static }) => (*ty, *is_static),
+            _ => panic!("pseudo-register '{name}' is missing from the backend symbol table."),
+        }
+    }
+
+    /// Pseudo-registers of different sizes aren't merged, so a spilled one always gets a
+    /// stack slot as big as every access to it.
+    fn same_size(&self, a: &Operand, b: &Operand) -> bool {
+        match (a, b) {
+            (Operand::Pseudo(a), Operand::Pseudo(b)) => self.object(a).0 == self.object(b).0,
+            _ => true,
+        }
+    }
+
+    /// Returns the tracked operands `instruction` reads and the ones it writes.
+    fn reads_writes(&self, instruction: &Instruction) -> (Vec<Operand>, Vec<Operand>) {
+        let regs = |regs: &[Reg]| regs.iter().map(|reg| Operand::Reg(*reg)).collect();
+
+        let (used, updated): (Vec<Operand>, Vec<Operand>) = match instruction {
+            Instruction::Mov(_, src, dst)
+            | Instruction::Movsx { src, dst, .. }
+            | Instruction::MovZeroExtend { src, dst, .. }
+            | Instruction::Cvttsd2si(_, src, dst)
+            | Instruction::Cvtsi2sd(_, src, dst) => (vec![src.clone()], vec![dst.clone()]),
+            // Only the registers in the address are read, not the object at it.
+            Instruction::Lea(src, dst) => (address(src), vec![dst.clone()]),
+            Instruction::Unary(_, _, operand) => (vec![operand.clone()], vec![operand.clone()]),
+            Instruction::Binary(_, _, src, dst) => {
+                (vec![src.clone(), dst.clone()], vec![dst.clone()])
+            }
+            Instruction::Cmp(_, a, b) | Instruction::Test(_, a, b) => {
+                (vec![a.clone(), b.clone()], vec![])
+            }
+            Instruction::Idiv(_, operand) | Instruction::Div(_, operand) => (
+                vec![
+                    operand.clone(),
+                    Operand::Reg(Reg::AX),
+                    Operand::Reg(Reg::DX),
+                ],
+                regs(&[Reg::AX, Reg::DX]),
+            ),
+            Instruction::Cdq(_) => (regs(&[Reg::AX]), regs(&[Reg::DX])),
+            Instruction::SetCC(_, operand) => (vec![], vec![operand.clone()]),
+            Instruction::Push(operand) => (vec![operand.clone()], vec![]),
+            Instruction::Pop(reg) => (vec![], regs(&[*reg])),
+            Instruction::Call(name) => match self.symbols.get(name) {
+                Some(AsmSymbol::Fun { param_regs, .. }) => (
+                    regs(param_regs),
+                    GENERAL_REGS
+                        .iter()
+                        .chain(&XMM_REGS)
+                        .filter(|reg| !reg.is_callee_saved())
+                        .map(|reg| Operand::Reg(*reg))
+                        .collect(),
+                ),
+                _ => panic!("function '{name}' is missing from the backend symbol table."),
+            },
+            Instruction::Ret => (regs(&self.return_regs), vec![]),
+            Instruction::TailCall(name) => match self.symbols.get(name) {
+                Some(AsmSymbol::Fun { param_regs, .. }) => (regs(param_regs), vec![]),
+                _ => panic!("function '{name}' is missing from the backend symbol table."),
+            },
+            Instruction::Jmp(_) | Instruction::JmpCC(..) | Instruction::Label(_) => {
+                (vec![], vec![])
+            }
+        };
+
+        let mut reads = Vec::new();
+        for operand in used {
+            match operand {
+                Operand::Reg(_) | Operand::Pseudo(_) => reads.push(operand),
+                _ => reads.extend(address(&operand)),
+            }
+        }
+        let mut writes = Vec::new();
+        for operand in updated {
+            match operand {
+                Operand::Reg(_) | Operand::Pseudo(_) => writes.push(operand),
+                // Writing to memory reads the registers that address it.
+                _ => reads.extend(address(&operand)),
+            }
+        }
+
+        reads.retain(|operand| self.class(operand).is_some());
+        writes.retain(|operand| self.class(operand).is_some());
+        (reads, writes)
+    }
+}
+
+/// Returns the registers used to compute a memory operand's address.
+fn address(operand: &Operand) -> Vec<Operand> {
+    match operand {
+        Operand::Memory(reg, _) => vec![Operand::Reg(*reg)],
+        Operand::Indexed { base, index, .. } => vec![Operand::Reg(*base), Operand::Reg(*index)],
+        _ => vec![],
+    }
+}
+
+impl Analysis for Liveness<'_> {
+    type Instruction = Instruction;
+    type Fact = BTreeSet<Operand>;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().cloned());
+    }
+
+    fn transfer(&self, instruction: &Instruction, fact: &mut Self::Fact) {
+        let (reads, writes) = self.reads_writes(instruction);
+        for operand in writes {
+            fact.remove(&operand);
+        }
+        fact.extend(reads);
+    }
+}
+
+/// The interference graph of one register class. Every allocatable hardware register of the
+/// class is a node, precolored with itself.
+#[derive(Debug)]
+struct Graph {
+    nodes: BTreeMap<Operand, BTreeSet<Operand>>,
+    /// The number of colors.
+    k: usize,
+}
+
+impl Graph {
+    fn build(instructions: &[Instruction], liveness: &Liveness, class: Class) -> Self {
+        let regs = class.registers();
+        let mut graph = Graph {
+            nodes: BTreeMap::new(),
+            k: regs.len(),
+        };
+
+        for reg in regs {
+            let others = regs
+                .iter()
+                .filter(|other| *other != reg)
+                .map(|other| Operand::Reg(*other))
+                .collect();
+            graph.nodes.insert(Operand::Reg(*reg), others);
+        }
+        for mut instruction in instructions.iter().cloned() {
+            for operand in instruction.operands_mut() {
+                if matches!(operand, Operand::Pseudo(_)) && liveness.class(operand) == Some(class) {
+                    graph.nodes.entry(operand.clone()).or_default();
+                }
+            }
+        }
+
+        let cfg = Cfg::new(instructions.to_vec());
+        let solution = dataflow::solve(&cfg, liveness);
+        for id in cfg.block_ids() {
+            let live_after = solution.instruction_facts(&cfg, liveness, *id);
+
+            for (instruction, live) in cfg.instructions(*id).iter().zip(live_after) {
+                let (_, writes) = liveness.reads_writes(instruction);
+
+                for written in &writes {
+                    for operand in &live {
+                        // The destination of a move may share a register with its source,
+                        // since both hold the same value.
+                        let copied =
+                            matches!(instruction, Instruction::Mov(_, src, _) if src == operand);
+                        if operand != written
+                            && !copied
+                            && graph.contains(written)
+                            && graph.contains(operand)
+                        {
+                            graph.add_edge(written, operand);
+                        }
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+
+    fn contains(&self, node: &Operand) -> bool {
+        self.nodes.contains_key(node)
+    }
+
+    fn neighbors(&self, node: &Operand) -> &BTreeSet<Operand> {
+        &self.nodes[node]
+    }
+
+    fn degree(&self, node: &Operand) -> usize {
+        self.nodes[node].len()
+    }
+
+    fn interfere(&self, a: &Operand, b: &Operand) -> bool {
+        self.nodes[a].contains(b)
+    }
+
+    fn add_edge(&mut self, a: &Operand, b: &Operand) {
+        self.nodes.get_mut(a).unwrap().insert(b.clone());
+        self.nodes.get_mut(b).unwrap().insert(a.clone());
+    }
+
+    /// Folds `merge` into `keep`, which inherits all its edges.
+    fn merge(&mut self, keep: &Operand, merge: &Operand) {
+        for neighbor in self.nodes.remove(merge).unwrap() {
+            self.nodes.get_mut(&neighbor).unwrap().remove(merge);
+            self.add_edge(keep, &neighbor);
+        }
+    }
+
+    /// Briggs' test: merging is safe if the merged node would have fewer than `k` neighbors
+    /// of significant degree, since then it can always be simplified.
+    fn briggs(&self, a: &Operand, b: &Operand, k: usize) -> bool {
+        let neighbors: BTreeSet<&Operand> = self.neighbors(a).union(self.neighbors(b)).collect();
+        let significant = neighbors
+            .into_iter()
+            .filter(|neighbor| {
+                let mut degree = self.degree(neighbor);
+                // A neighbor of both loses an edge when they merge.
+                if self.interfere(neighbor, a) && self.interfere(neighbor, b) {
+                    degree -= 1;
+                }
+                degree >= k
+            })
+            .count();
+
+        significant < k
+    }
+
+    /// George's test: merging `pseudo` into the hardware register `reg` is safe if each of
+    /// its neighbors already interferes with `reg` or has insignificant degree.
+    fn george(&self, reg: &Operand, pseudo: &Operand, k: usize) -> bool {
+        self.neighbors(pseudo)
+            .iter()
+            .all(|neighbor| self.interfere(neighbor, reg) || self.degree(neighbor) < k)
+    }
+
+    /// Colors every pseudo-register it can, returning each one's register.
+    fn color(&self, costs: &HashMap<String, usize>, class: Class) -> HashMap<String, Reg> {
+        let mut remaining: BTreeSet<&Operand> = self
+            .nodes
+            .keys()
+            .filter(|node| matches!(node, Operand::Pseudo(_)))
+            .collect();
+        let mut stack = Vec::new();
+
+        while !remaining.is_empty() {
+            let degree = |node: &Operand| {
+                self.neighbors(node)
+                    .iter()
+                    .filter(|neighbor| {
+                        matches!(neighbor, Operand::Reg(_)) || remaining.contains(neighbor)
+                    })
+                    .count()
+            };
+            let cost = |node: &Operand| match node {
+                Operand::Pseudo(name) => costs.get(name).copied().unwrap_or_default() as f64,
+                _ => f64::INFINITY,
+            };
+
+            // When every node has significant degree, push the one whose spilling costs least
+            // for the edges it removes, and hope it gets a color anyway.
+            let node = match remaining.iter().find(|node| degree(node) < self.k) {
+                Some(node) => *node,
+                None => *remaining
+                    .iter()
+                    .min_by(|a, b| {
+                        let a = cost(a) / degree(a) as f64;
+                        let b = cost(b) / degree(b) as f64;
+                        a.total_cmp(&b)
+                    })
+                    .unwrap(),
+            };
+
+            remaining.remove(node);
+            stack.push(node);
+        }
+
+        let mut colors = HashMap::new();
+        while let Some(node) = stack.pop() {
+            let taken: HashSet<Reg> = self
+                .neighbors(node)
+                .iter()
+                .filter_map(|neighbor| match neighbor {
+                    Operand::Reg(reg) => Some(*reg),
+                    Operand::Pseudo(name) => colors.get(name).copied(),
+                    _ => None,
+                })
+                .collect();
+
+            let free = class.registers().iter().find(|reg| !taken.contains(reg));
+            if let (Operand::Pseudo(name), Some(reg)) = (node, free) {
+                colors.insert(name.clone(), *reg);
+            }
+        }
+
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembly::{BinaryOp, INT_PARAM_REGS},
+        test_support::assembly::{function, pseudo, reg},
+    };
+
+    fn mov(src: Operand, dst: Operand) -> Instruction {
+        Instruction::Mov(AsmType::Longword, src, dst)
+    }
+
+    fn add(src: Operand, dst: Operand) -> Instruction {
+        Instruction::Binary(BinaryOp::Add, AsmType::Longword, src, dst)
+    }
+
+    /// Declares `f`, returning in `%eax`, a function `g` taking one argument in `%edi`, and
+    /// the given objects.
+    fn symbols(objects: &[(&str, AsmType, bool)]) -> BackendSymbolTable {
+        let mut symbols = BackendSymbolTable::new();
+        symbols.insert(
+            "f",
+            AsmSymbol::Fun {
+                defined: true,
+                param_regs: vec![],
+                stack_arg_bytes: 0,
+                return_regs: vec![Reg::AX],
+            },
+        );
+        symbols.insert(
+            "g",
+            AsmSymbol::Fun {
+                defined: false,
+                param_regs: vec![Reg::DI],
+                stack_arg_bytes: 0,
+                return_regs: vec![Reg::AX],
+            },
+        );
+        for (name, ty, is_static) in objects {
+            symbols.insert(
+                name,
+                AsmSymbol::Obj {
+                    ty: *ty,
+                    is_static: *is_static,
+                },
+            );
+        }
+        symbols
+    }
+
+    fn ints(names: &[&str]) -> BackendSymbolTable {
+        let objects: Vec<_> = names
+            .iter()
+            .map(|name| (*name, AsmType::Longword, false))
+            .collect();
+        symbols(&objects)
+    }
+
+    fn has_pseudos(f: &Function) -> bool {
+        f.instructions.clone().iter_mut().any(|instruction| {
+            instruction
+                .operands_mut()
+                .iter()
+                .any(|operand| matches!(operand, Operand::Pseudo(_)))
+        })
+    }
+
+    #[test]
+    fn test_coalesce_moves() {
+        let mut f = function(vec![
+            mov(Operand::Imm(1), pseudo("a")),
+            mov(pseudo("a"), pseudo("b")),
+            add(Operand::Imm(2), pseudo("b")),
+            mov(pseudo("b"), reg(Reg::AX)),
+            Instruction::Ret,
+        ]);
+        allocate_registers(&mut f, &ints(&["a", "b"]));
+
+        assert_eq!(
+            f.instructions,
+            vec![
+                mov(Operand::Imm(1), reg(Reg::AX)),
+                add(Operand::Imm(2), reg(Reg::AX)),
+                Instruction::Ret,
+            ]
+        );
+        assert!(f.callee_saved.is_empty());
+    }
+
+    #[test]
+    fn test_interfering_pseudos_get_different_registers() {
+        let mut f = function(vec![
+            mov(Operand::Imm(1), pseudo("a")),
+            mov(Operand::Imm(2), pseudo("b")),
+            add(pseudo("a"), pseudo("b")),
+            mov(pseudo("b"), reg(Reg::AX)),
+            Instruction::Ret,
+        ]);
+        allocate_registers(&mut f, &ints(&["a", "b"]));
+
+        assert!(!has_pseudos(&f));
+        let Instruction::Binary(_, _, Operand::Reg(a), Operand::Reg(b)) = &f.instructions[2] else {
+            panic!("unexpected instruction {:?}", f.instructions[2]);
+        };
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_value_live_across_call_uses_callee_saved_register() {
+        let mut f = function(vec![
+            mov(Operand::Imm(1), pseudo("x")),
+            mov(Operand::Imm(2), reg(Reg::DI)),
+            Instruction::Call("g".to_owned()),
+            mov(pseudo("x"), reg(Reg::AX)),
+            Instruction::Ret,
+        ]);
+        allocate_registers(&mut f, &ints(&["x"]));
+
+        assert_eq!(
+            f.instructions,
+            vec![
+                Instruction::Push(reg(Reg::BX)),
+                mov(Operand::Imm(1), reg(Reg::BX)),
+                mov(Operand::Imm(2), reg(Reg::DI)),
+                Instruction::Call("g".to_owned()),
+                mov(reg(Reg::BX), reg(Reg::AX)),
+                Instruction::Pop(Reg::BX),
+                Instruction::Ret,
+            ]
+        );
+        assert_eq!(f.callee_saved, vec![Reg::BX]);
+    }
+
+    #[test]
+    fn test_spill_when_out_of_registers() {
+        let names: Vec<String> = (0..14).map(|i| format!("p{i}")).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut instructions: Vec<Instruction> = names
+            .iter()
+            .map(|name| mov(Operand::Imm(1), pseudo(name)))
+            .collect();
+        // `p0` is used the most, so it's the last one that should be spilled.
+        instructions.push(add(pseudo("p0"), pseudo("p0")));
+        instructions.extend(names.iter().map(|name| add(pseudo(name), reg(Reg::AX))));
+        instructions.push(Instruction::Ret);
+        let mut f = function(instructions);
+        allocate_registers(&mut f, &ints(&names));
+
+        let spilled: HashSet<String> = f
+            .instructions
+            .clone()
+            .iter_mut()
+            .flat_map(|instruction| {
+                instruction
+                    .operands_mut()
+                    .into_iter()
+                    .filter_map(|operand| match operand {
+                        Operand::Pseudo(name) => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        // All fourteen values are live at once, along with `%eax`, and there are only twelve
+        // registers.
+        assert_eq!(spilled.len(), 3);
+        assert!(!spilled.contains("p0"));
+    }
+
+    #[test]
+    fn test_classes_are_allocated_separately() {
+        let mut f = function(vec![
+            Instruction::Mov(AsmType::Double, Operand::Data("c".to_owned()), pseudo("d")),
+            Instruction::Cvttsd2si(AsmType::Longword, pseudo("d"), pseudo("i")),
+            mov(pseudo("i"), reg(Reg::AX)),
+            Instruction::Ret,
+        ]);
+        let symbols = symbols(&[
+            ("d", AsmType::Double, false),
+            ("i", AsmType::Longword, false),
+        ]);
+        allocate_registers(&mut f, &symbols);
+
+        assert_eq!(
+            f.instructions,
+            vec![
+                Instruction::Mov(
+                    AsmType::Double,
+                    Operand::Data("c".to_owned()),
+                    reg(Reg::XMM0)
+                ),
+                Instruction::Cvttsd2si(AsmType::Longword, reg(Reg::XMM0), reg(Reg::AX)),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_static_and_address_taken_pseudos_stay_in_memory() {
+        let instructions = vec![
+            mov(Operand::Imm(1), pseudo("s")),
+            mov(Operand::Imm(2), pseudo("a")),
+            Instruction::Lea(pseudo("a"), reg(INT_PARAM_REGS[0])),
+            Instruction::Call("g".to_owned()),
+            Instruction::Ret,
+        ];
+        let mut f = function(instructions.clone());
+        let symbols = symbols(&[
+            ("s", AsmType::Longword, true),
+            ("a", AsmType::Longword, false),
+        ]);
+        allocate_registers(&mut f, &symbols);
+
+        assert_eq!(f.instructions, instructions);
+    }
+
+    #[test]
+    fn test_division_clobbers_dx() {
+        let mut f = function(vec![
+            mov(Operand::Imm(7), pseudo("x")),
+            mov(Operand::Imm(2), pseudo("y")),
+            mov(pseudo("x"), reg(Reg::AX)),
+            Instruction::Cdq(AsmType::Longword),
+            Instruction::Idiv(AsmType::Longword, pseudo("y")),
+            Instruction::Ret,
+        ]);
+        allocate_registers(&mut f, &ints(&["x", "y"]));
+
+        assert_eq!(
+            f.instructions[2..],
+            [
+                Instruction::Cdq(AsmType::Longword),
+                Instruction::Idiv(AsmType::Longword, reg(Reg::CX)),
+                Instruction::Ret,
+            ]
+        );
+    }
+}
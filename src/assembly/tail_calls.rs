@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use super::{
+    AsmSymbol, AsmType, BackendSymbolTable, BinaryOp, Function, Instruction, Operand, Reg,
+};
+
+/// Replaces calls whose result is returned as it is with `TailCall`s, which give the callee the
+/// caller's place on the stack, so recursion in tail position runs in constant stack space.
+///
+/// A call qualifies when nothing but popping the arguments it passed on the stack and
+/// callee-saved registers, and jumping, happens between it and a `Ret`. Stack arguments are
+/// copied into the caller's own incoming argument area, where the callee expects them once the
+/// frame is gone, so the call only qualifies if they fit there; the padding pushed above them
+/// to keep the stack aligned isn't copied. Nothing in a function that
+/// takes the address of one of its stack slots is turned into a tail call, since the callee
+/// might still use the address after the frame is gone.
+///
+/// Runs after register allocation, once the callee-saved registers are popped before `Ret`.
+///
+/// # Panics
+///
+/// Panics if a function `function` calls is missing from `symbols`.
+pub fn eliminate_tail_calls(function: &mut Function, symbols: &BackendSymbolTable) {
+    if function.instructions.iter().any(takes_stack_address) {
+        return;
+    }
+
+    let labels: HashMap<&str, usize> = function
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instruction)| match instruction {
+            Instruction::Label(label) => Some((label.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+
+    let mut instructions = Vec::with_capacity(function.instructions.len());
+    // Whether the instructions up to the next label can no longer be reached.
+    let mut unreachable = false;
+    for (i, instruction) in function.instructions.iter().enumerate() {
+        match instruction {
+            Instruction::Label(_) => unreachable = false,
+            _ if unreachable => continue,
+            Instruction::Call(name) => {
+                let (arg_bytes, next) = match function.instructions.get(i + 1) {
+                    Some(Instruction::Binary(
+                        BinaryOp::Add,
+                        AsmType::Quadword,
+                        Operand::Imm(bytes),
+                        Operand::Reg(Reg::SP),
+                    )) => (*bytes, i + 2),
+                    _ => (0, i + 1),
+                };
+                let stack_arg_bytes = match symbols.get(name) {
+                    Some(AsmSymbol::Fun {
+                        stack_arg_bytes, ..
+                    }) => *stack_arg_bytes,
+                    _ => panic!("function '{name}' is missing from the backend symbol table."),
+                };
+                let pops = (stack_arg_bytes <= function.stack_arg_bytes)
+                    .then(|| epilogue(&function.instructions, &labels, next))
+                    .flatten();
+                if let Some(pops) = pops {
+                    if arg_bytes > 0 {
+                        instructions.extend(move_stack_args(stack_arg_bytes));
+                        instructions.push(function.instructions[i + 1].clone());
+                    }
+                    instructions.extend(pops.into_iter().map(Instruction::Pop));
+                    instructions.push(Instruction::TailCall(name.clone()));
+                    unreachable = true;
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        instructions.push(instruction.clone());
+    }
+
+    function.instructions = instructions;
+}
+
+/// If control starting at instruction `start` returns without doing anything but popping
+/// registers, returns the registers it pops, in order.
+fn epilogue(
+    instructions: &[Instruction],
+    labels: &HashMap<&str, usize>,
+    start: usize,
+) -> Option<Vec<Reg>> {
+    let mut pops = Vec::new();
+    let mut i = start;
+    // Bounds the walk, in case jumps lead around in a loop.
+    for _ in 0..instructions.len() {
+        match instructions.get(i)? {
+            Instruction::Pop(reg) => pops.push(*reg),
+            Instruction::Label(_) => (),
+            Instruction::Jmp(target) => {
+                i = *labels.get(target.as_str())?;
+                continue;
+            }
+            Instruction::Ret => return Some(pops),
+            _ => return None,
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Copies the `bytes` of arguments pushed for a call from the top of the stack to the incoming
+/// argument area, in the same order.
+fn move_stack_args(bytes: i64) -> Vec<Instruction> {
+    (0..bytes / 8)
+        .flat_map(|slot| {
+            [
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Memory(Reg::SP, slot * 8),
+                    Operand::Reg(Reg::R11),
+                ),
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::R11),
+                    Operand::Memory(Reg::BP, 16 + slot * 8),
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// Whether `instruction` computes the address of something in the stack frame.
+fn takes_stack_address(instruction: &Instruction) -> bool {
+    let Instruction::Lea(src, _) = instruction else {
+        return false;
+    };
+    match src {
+        // Pseudo-registers that aren't static end up on the stack.
+        Operand::Pseudo(_) | Operand::PseudoMem(..) => true,
+        Operand::Memory(reg, _) | Operand::Indexed { base: reg, .. } => {
+            matches!(reg, Reg::BP | Reg::SP)
+        }
+        Operand::Imm(_) | Operand::Reg(_) | Operand::Data(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembly::{CondCode, UnaryOp},
+        test_support::{assembly::function, c},
+    };
+
+    /// Functions taking integer arguments, with the bytes of them each takes on the stack.
+    fn symbols(functions: &[(&str, i64)]) -> BackendSymbolTable {
+        let mut symbols = BackendSymbolTable::new();
+        for (name, stack_arg_bytes) in functions {
+            symbols.insert(
+                name,
+                AsmSymbol::Fun {
+                    defined: true,
+                    param_regs: vec![Reg::DI],
+                    stack_arg_bytes: *stack_arg_bytes,
+                    return_regs: vec![Reg::AX],
+                },
+            );
+        }
+        symbols
+    }
+
+    fn eliminated(instructions: Vec<Instruction>) -> Vec<Instruction> {
+        let mut function = function(instructions);
+        eliminate_tail_calls(&mut function, &symbols(&[("f", 0), ("g", 0), ("h", 8)]));
+        function.instructions
+    }
+
+    fn call(name: &str) -> Instruction {
+        Instruction::Call(name.to_owned())
+    }
+
+    fn label(name: &str) -> Instruction {
+        Instruction::Label(name.to_owned())
+    }
+
+    #[test]
+    fn test_self_recursive_call() {
+        // int f(int n) { if (n == 0) return 0; return f(n - 1); }
+        assert_eq!(
+            eliminated(vec![
+                Instruction::Push(Operand::Reg(Reg::BX)),
+                Instruction::Test(
+                    AsmType::Longword,
+                    Operand::Reg(Reg::DI),
+                    Operand::Reg(Reg::DI)
+                ),
+                Instruction::JmpCC(CondCode::E, "zero".to_owned()),
+                Instruction::Binary(
+                    BinaryOp::Sub,
+                    AsmType::Longword,
+                    Operand::Imm(1),
+                    Operand::Reg(Reg::DI),
+                ),
+                call("f"),
+                Instruction::Pop(Reg::BX),
+                Instruction::Ret,
+                label("zero"),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(0), Operand::Reg(Reg::AX)),
+                Instruction::Pop(Reg::BX),
+                Instruction::Ret,
+            ]),
+            vec![
+                Instruction::Push(Operand::Reg(Reg::BX)),
+                Instruction::Test(
+                    AsmType::Longword,
+                    Operand::Reg(Reg::DI),
+                    Operand::Reg(Reg::DI)
+                ),
+                Instruction::JmpCC(CondCode::E, "zero".to_owned()),
+                Instruction::Binary(
+                    BinaryOp::Sub,
+                    AsmType::Longword,
+                    Operand::Imm(1),
+                    Operand::Reg(Reg::DI),
+                ),
+                Instruction::Pop(Reg::BX),
+                Instruction::TailCall("f".to_owned()),
+                label("zero"),
+                Instruction::Mov(AsmType::Longword, Operand::Imm(0), Operand::Reg(Reg::AX)),
+                Instruction::Pop(Reg::BX),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_jumping_to_shared_return() {
+        assert_eq!(
+            eliminated(vec![
+                call("g"),
+                Instruction::Jmp("end".to_owned()),
+                label("end"),
+                Instruction::Pop(Reg::R12),
+                Instruction::Ret,
+            ]),
+            vec![
+                Instruction::Pop(Reg::R12),
+                Instruction::TailCall("g".to_owned()),
+                label("end"),
+                Instruction::Pop(Reg::R12),
+                Instruction::Ret,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calls_not_in_tail_position() {
+        let instructions = vec![
+            call("g"),
+            Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Longword,
+                Operand::Imm(1),
+                Operand::Reg(Reg::AX),
+            ),
+            Instruction::Ret,
+            label("stack_args"),
+            Instruction::Push(Operand::Imm(7)),
+            call("h"),
+            Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Quadword,
+                Operand::Imm(8),
+                Operand::Reg(Reg::SP),
+            ),
+            Instruction::Ret,
+        ];
+        assert_eq!(eliminated(instructions.clone()), instructions);
+    }
+
+    #[test]
+    fn test_stack_args_fitting_in_incoming_area() {
+        // long f(long a, ..., long g, long h) { return f(a, ..., -h, g); }, with `h` in `%rax`.
+        let mut function = function(vec![
+            Instruction::Push(Operand::Memory(Reg::BP, 16)),
+            Instruction::Unary(UnaryOp::Neg, AsmType::Quadword, Operand::Reg(Reg::AX)),
+            Instruction::Push(Operand::Reg(Reg::AX)),
+            call("f"),
+            Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Quadword,
+                Operand::Imm(16),
+                Operand::Reg(Reg::SP),
+            ),
+            Instruction::Ret,
+        ]);
+        function.stack_arg_bytes = 16;
+        eliminate_tail_calls(&mut function, &symbols(&[("f", 16)]));
+
+        let copy = |from, to| {
+            [
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Memory(Reg::SP, from),
+                    Operand::Reg(Reg::R11),
+                ),
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::R11),
+                    Operand::Memory(Reg::BP, to),
+                ),
+            ]
+        };
+        let mut expected = vec![
+            Instruction::Push(Operand::Memory(Reg::BP, 16)),
+            Instruction::Unary(UnaryOp::Neg, AsmType::Quadword, Operand::Reg(Reg::AX)),
+            Instruction::Push(Operand::Reg(Reg::AX)),
+        ];
+        expected.extend(copy(0, 16));
+        expected.extend(copy(8, 24));
+        expected.extend([
+            Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Quadword,
+                Operand::Imm(16),
+                Operand::Reg(Reg::SP),
+            ),
+            Instruction::TailCall("f".to_owned()),
+        ]);
+        assert_eq!(function.instructions, expected);
+    }
+
+    #[test]
+    fn test_padding_above_stack_args_isnt_copied() {
+        // long f(long a, ..., long g) { return f(a, ..., g - 1); }, with `g` in `%rax`.
+        let mut function = function(vec![
+            Instruction::Binary(
+                BinaryOp::Sub,
+                AsmType::Quadword,
+                Operand::Imm(8),
+                Operand::Reg(Reg::SP),
+            ),
+            Instruction::Push(Operand::Reg(Reg::AX)),
+            call("f"),
+            Instruction::Binary(
+                BinaryOp::Add,
+                AsmType::Quadword,
+                Operand::Imm(16),
+                Operand::Reg(Reg::SP),
+            ),
+            Instruction::Ret,
+        ]);
+        function.stack_arg_bytes = 8;
+        eliminate_tail_calls(&mut function, &symbols(&[("f", 8)]));
+
+        assert_eq!(
+            function.instructions[2..],
+            [
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Memory(Reg::SP, 0),
+                    Operand::Reg(Reg::R11),
+                ),
+                Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::R11),
+                    Operand::Memory(Reg::BP, 16),
+                ),
+                Instruction::Binary(
+                    BinaryOp::Add,
+                    AsmType::Quadword,
+                    Operand::Imm(16),
+                    Operand::Reg(Reg::SP),
+                ),
+                Instruction::TailCall("f".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stack_address_taken() {
+        let instructions = vec![
+            Instruction::Lea(Operand::Memory(Reg::BP, -4), Operand::Reg(Reg::DI)),
+            call("g"),
+            Instruction::Ret,
+        ];
+        assert_eq!(eliminated(instructions.clone()), instructions);
+    }
+
+    #[test]
+    fn test_deep_recursion_runs() {
+        // Ten million frames would overflow the default 8 MiB stack without the jump, and
+        // the stack arguments make each frame reuse the incoming area.
+        let code = "
+            long count(long n, long total, long a, long b, long c, long d, long e) {
+                if (n == 0)
+                    return total;
+                return count(n - 1, total + 1, a, b, c, d, e);
+            }
+            int main(void) { return count(10000000, 0, 1, 2, 3, 4, 5) == 10000000; }
+        ";
+        assert_eq!(c::run(code), 1);
+    }
+}
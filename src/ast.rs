@@ -0,0 +1,441 @@
+//! The abstract syntax tree the parser builds from tokens.
+//!
+//! Every node carries the span of the code it was parsed from, which diagnostics about it point
+//! at. Expressions are given their types by the type checker, which also makes implicit
+//! conversions explicit as `Cast`s.
+
+use std::fmt;
+
+use crate::{
+    source_map::Span,
+    tacky::{self, Const},
+};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Type {
+    Char,
+    SChar,
+    UChar,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Double,
+    Void,
+    Pointer(Box<Type>),
+    Array(Box<Type>, usize),
+    Function { params: Vec<Type>, ret: Box<Type> },
+}
+
+impl Type {
+    /// Size in bytes of an object of this type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the type is `void` or a function type, which have no size.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Char | Type::SChar | Type::UChar => 1,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => 8,
+            Type::Array(element, len) => element.size() * len,
+            Type::Void | Type::Function { .. } => panic!("type '{self}' has no size."),
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::Char | Type::SChar | Type::Int | Type::Long)
+    }
+
+    pub fn is_character(&self) -> bool {
+        matches!(self, Type::Char | Type::SChar | Type::UChar)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_character() || matches!(self, Type::Int | Type::UInt | Type::Long | Type::ULong)
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || *self == Type::Double
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_))
+    }
+
+    /// Whether values of the type are single numbers or addresses, which can be compared,
+    /// tested for truth and assigned.
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || self.is_pointer()
+    }
+
+    /// Whether an object of the type can be defined, which needs its size.
+    pub fn is_complete(&self) -> bool {
+        !matches!(self, Type::Void | Type::Function { .. })
+    }
+
+    /// The same type in TACKY, which has no function types.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the type is a function type.
+    pub fn to_tacky(&self) -> tacky::Type {
+        match self {
+            Type::Char => tacky::Type::Char,
+            Type::SChar => tacky::Type::SChar,
+            Type::UChar => tacky::Type::UChar,
+            Type::Int => tacky::Type::Int,
+            Type::UInt => tacky::Type::UInt,
+            Type::Long => tacky::Type::Long,
+            Type::ULong => tacky::Type::ULong,
+            Type::Double => tacky::Type::Double,
+            Type::Void => tacky::Type::Void,
+            Type::Pointer(referenced) => tacky::Type::Pointer(Box::new(referenced.to_tacky())),
+            Type::Array(element, len) => tacky::Type::Array(Box::new(element.to_tacky()), *len),
+            Type::Function { .. } => panic!("function type '{self}' has no TACKY type."),
+        }
+    }
+
+    /// Writes the type as C would spell it, with `name` where a declarator would put the
+    /// declared name.
+    fn fmt_declarator(&self, f: &mut fmt::Formatter<'_>, name: String) -> fmt::Result {
+        let base = match self {
+            Type::Char => "char",
+            Type::SChar => "signed char",
+            Type::UChar => "unsigned char",
+            Type::Int => "int",
+            Type::UInt => "unsigned int",
+            Type::Long => "long",
+            Type::ULong => "unsigned long",
+            Type::Double => "double",
+            Type::Void => "void",
+            Type::Pointer(referenced) => {
+                let name = match **referenced {
+                    Type::Array(..) | Type::Function { .. } => format!("(*{name})"),
+                    _ => format!("*{name}"),
+                };
+                return referenced.fmt_declarator(f, name);
+            }
+            Type::Array(element, len) => {
+                return element.fmt_declarator(f, format!("{name}[{len}]"))
+            }
+            Type::Function { params, ret } => {
+                let params = match params.is_empty() {
+                    true => "void".to_owned(),
+                    false => params
+                        .iter()
+                        .map(Type::to_string)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                };
+                return ret.fmt_declarator(f, format!("{name}({params})"));
+            }
+        };
+        match name.is_empty() {
+            true => write!(f, "{base}"),
+            false => write!(f, "{base} {name}"),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_declarator(f, String::new())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StorageClass {
+    Static,
+    Extern,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub declarations: Vec<Declaration>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Declaration {
+    Function(FunctionDeclaration),
+    Variable(VariableDeclaration),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FunctionDeclaration {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Option<Block>,
+    /// A `Type::Function`.
+    pub ty: Type,
+    pub storage_class: Option<StorageClass>,
+    /// The declared name, which diagnostics about the declaration point at.
+    pub span: Span,
+}
+
+/// A function parameter, which only needs a name if the function is being defined.
+#[derive(Debug, PartialEq)]
+pub struct Param {
+    pub name: Option<String>,
+    /// The name, or where it would have been.
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VariableDeclaration {
+    pub name: String,
+    pub init: Option<Initializer>,
+    pub ty: Type,
+    pub storage_class: Option<StorageClass>,
+    /// The declared name, which diagnostics about the declaration point at.
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Initializer {
+    Single(Expression),
+    /// `{ a, b, c }`, spanning the braces.
+    Compound(Vec<Initializer>, Span),
+}
+
+impl Initializer {
+    pub fn span(&self) -> Span {
+        match self {
+            Initializer::Single(expression) => expression.span,
+            Initializer::Compound(_, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    pub items: Vec<BlockItem>,
+    /// From the opening brace to the closing one.
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BlockItem {
+    Statement(Statement),
+    Declaration(Declaration),
+}
+
+/// Tells loops apart, so `break` and `continue` can name the one they leave or go around.
+pub type LoopId = usize;
+
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatementKind {
+    Return(Option<Expression>),
+    Expression(Expression),
+    If {
+        condition: Expression,
+        then: Box<Statement>,
+        otherwise: Option<Box<Statement>>,
+    },
+    Compound(Block),
+    Break(LoopId),
+    Continue(LoopId),
+    While {
+        condition: Expression,
+        body: Box<Statement>,
+        id: LoopId,
+    },
+    DoWhile {
+        body: Box<Statement>,
+        condition: Expression,
+        id: LoopId,
+    },
+    For {
+        init: ForInit,
+        condition: Option<Box<Expression>>,
+        post: Option<Box<Expression>>,
+        body: Box<Statement>,
+        id: LoopId,
+    },
+    Null,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ForInit {
+    Declarations(Vec<VariableDeclaration>),
+    Expression(Option<Expression>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    /// Set by the type checker.
+    pub ty: Option<Type>,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self {
+            kind,
+            ty: None,
+            span,
+        }
+    }
+
+    /// The type the type checker gave the expression.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the expression hasn't been type-checked.
+    pub fn ty(&self) -> &Type {
+        self.ty
+            .as_ref()
+            .expect("expression should have been type-checked.")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExpressionKind {
+    Constant(Const),
+    Var(String),
+    /// An explicit cast, or one the type checker inserted where C converts a value implicitly.
+    Cast(Type, Box<Expression>),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+    Assignment(Box<Expression>, Box<Expression>),
+    /// `a op= b`. Prefix `++a` and `--a` are parsed as `a += 1` and `a -= 1`.
+    CompoundAssignment(BinaryOp, Box<Expression>, Box<Expression>),
+    /// `a++` or `a--`, evaluating to `a`'s value before it's updated.
+    Postfix(PostfixOp, Box<Expression>),
+    Conditional {
+        condition: Box<Expression>,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
+    },
+    FunctionCall(String, Vec<Expression>),
+    Dereference(Box<Expression>),
+    AddressOf(Box<Expression>),
+    Subscript(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOp {
+    Complement,
+    Negate,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PostfixOp {
+    Increment,
+    Decrement,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            UnaryOp::Complement => "~",
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::LessThan => "<",
+            BinaryOp::LessOrEqual => "<=",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::GreaterOrEqual => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for PostfixOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostfixOp::Increment => write!(f, "++"),
+            PostfixOp::Decrement => write!(f, "--"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_types() {
+        let int_pointer = Type::Pointer(Box::new(Type::Int));
+        let array = Type::Array(Box::new(Type::Array(Box::new(Type::Long), 3)), 2);
+        let function = Type::Function {
+            params: vec![int_pointer.clone(), Type::Double],
+            ret: Box::new(Type::Pointer(Box::new(Type::Char))),
+        };
+
+        assert_eq!(int_pointer.to_string(), "int *");
+        assert_eq!(array.to_string(), "long [2][3]");
+        assert_eq!(Type::Pointer(Box::new(array)).to_string(), "long (*)[2][3]");
+        assert_eq!(function.to_string(), "char *(int *, double)");
+        assert_eq!(
+            Type::Function {
+                params: vec![],
+                ret: Box::new(Type::UInt)
+            }
+            .to_string(),
+            "unsigned int (void)"
+        );
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(Type::UChar.size(), 1);
+        assert_eq!(Type::UInt.size(), 4);
+        assert_eq!(Type::Pointer(Box::new(Type::Void)).size(), 8);
+        assert_eq!(
+            Type::Array(Box::new(Type::Array(Box::new(Type::Int), 3)), 2).size(),
+            24
+        );
+    }
+}
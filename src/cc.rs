@@ -4,7 +4,10 @@ use std::{
     process::{exit, Command},
 };
 
-/// Runs `gcc -E -P INPUT_FILE -o PREPROCESSED_FILE`, and returns a `PathBuf` to the preprocessed file.
+/// Runs `gcc -E INPUT_FILE -o PREPROCESSED_FILE`, and returns a `PathBuf` to the preprocessed file.
+///
+/// The output keeps its line markers, `# 12 "file.c"`, which the `Lexer` reads to tell where each
+/// token originally came from.
 ///
 /// # Exits
 ///
@@ -15,7 +18,6 @@ pub fn preprocessor(file_path: &Path) -> PathBuf {
     let preprocessor = Command::new("gcc")
         .args([
             "-E",
-            "-P",
             file_path.to_str().unwrap(),
             "-o",
             &preprocessed_file_path,
@@ -33,3 +35,28 @@ pub fn preprocessor(file_path: &Path) -> PathBuf {
 
     PathBuf::from(preprocessed_file_path)
 }
+
+/// Runs `gcc ASSEMBLY_FILE -o OUTPUT_FILE`, which assembles the file and links it with the C
+/// library into an executable.
+///
+/// # Exits
+///
+/// This function will terminate the process with a non-zero status code, if the gcc command fails.
+pub fn assemble_and_link(assembly_file_path: &Path, output_file_path: &Path) {
+    let linker = Command::new("gcc")
+        .args([
+            assembly_file_path.to_str().unwrap(),
+            "-o",
+            output_file_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("failed to run assembler and linker");
+
+    io::stdout().write_all(&linker.stdout).unwrap();
+    io::stderr().write_all(&linker.stderr).unwrap();
+
+    if !linker.status.success() {
+        eprintln!("assembler and linker failed.");
+        exit(linker.status.code().unwrap())
+    }
+}
@@ -0,0 +1,517 @@
+pub mod json;
+pub mod sarif;
+
+use std::{fmt, process};
+
+use crate::source_map::{display_width, expand_tabs, SourceMap, Span};
+
+/// How many errors are reported before giving up, unless `-ferror-limit` says otherwise.
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+/// How diagnostics are written to stderr.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Format {
+    /// rustc-style messages quoting the code, for people.
+    #[default]
+    Human,
+    /// One JSON array of diagnostics, see `json::render`.
+    Json,
+    /// A SARIF 2.1.0 log, see `sarif::render`.
+    Sarif,
+}
+
+/// How diagnostics are reported, as set on the command line.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Options {
+    /// Stop after this many errors; 0 means never.
+    pub error_limit: usize,
+    pub format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            error_limit: DEFAULT_ERROR_LIMIT,
+            format: Format::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// Identifies what went wrong independently of the wording of the message, so it can be
+/// looked up and matched on. Printed as `E` and four digits; codes are never reused.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Code {
+    /// A character that can't start any token.
+    UnknownToken,
+    /// A malformed constant, like `123abc`, `10LL` or `1.5f`.
+    InvalidConstant,
+    /// A command-line argument that is neither a flag nor a `.c` file.
+    UnknownArgument,
+    /// No `.c` file on the command line.
+    MissingSourceFile,
+    /// More than one of the flags that stop after a stage.
+    ConflictingStages,
+    /// A flag given a value it doesn't take, like `-ferror-limit=many`.
+    InvalidArgumentValue,
+    /// A name declared again in the same scope as a typedef where it was a variable, or the
+    /// other way around.
+    RedeclaredAsDifferentKind,
+    /// A token where the grammar doesn't allow it, like a missing `;`.
+    UnexpectedToken,
+    /// C the compiler can't compile yet, like `sizeof` or a structure.
+    UnsupportedFeature,
+    /// An integer constant no type can hold, like `18446744073709551616`.
+    ConstantTooLarge,
+    /// Specifiers or a declarator that don't declare anything valid, like `long char x;` or
+    /// `int a[0];`.
+    InvalidDeclaration,
+    /// `break` or `continue` outside of a loop.
+    JumpOutsideLoop,
+    /// A name used where no declaration of it is in scope.
+    UndeclaredIdentifier,
+    /// A declaration that disagrees with an earlier one of the same name, like a second
+    /// definition or a different type.
+    ConflictingDeclaration,
+    /// A value of a type the operator, conversion or statement doesn't accept, like `*1`.
+    TypeMismatch,
+    /// Assigning to, incrementing or taking the address of something that isn't an object,
+    /// like `1 = x`.
+    NotAnLvalue,
+    /// A call with more or fewer arguments than the function has parameters.
+    ArgumentCount,
+    /// An initializer that doesn't fit the variable, like `int a[2] = {1, 2, 3};`, or that
+    /// isn't constant where it must be.
+    InvalidInitializer,
+}
+
+impl Code {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::UnknownToken => "E0001",
+            Code::InvalidConstant => "E0002",
+            Code::UnknownArgument => "E0003",
+            Code::MissingSourceFile => "E0004",
+            Code::ConflictingStages => "E0005",
+            Code::InvalidArgumentValue => "E0006",
+            Code::RedeclaredAsDifferentKind => "E0007",
+            // E0008 flagged optimization flags whose pass didn't run yet; they all do now.
+            Code::UnexpectedToken => "E0009",
+            Code::UnsupportedFeature => "E0010",
+            Code::ConstantTooLarge => "E0011",
+            Code::InvalidDeclaration => "E0012",
+            Code::JumpOutsideLoop => "E0013",
+            Code::UndeclaredIdentifier => "E0014",
+            Code::ConflictingDeclaration => "E0015",
+            Code::TypeMismatch => "E0016",
+            Code::NotAnLvalue => "E0017",
+            Code::ArgumentCount => "E0018",
+            Code::InvalidInitializer => "E0019",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A fix-it: replacing the code `span` covers with `replacement` would resolve a diagnostic.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// A message about the code being compiled, or about how the compiler was invoked.
+///
+/// Built with `error`, `warning` or `note` and then the `with_` methods, e.g.
+/// `Diagnostic::error("unknown token '@'").with_code(Code::UnknownToken).with_span(span)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<Code>,
+    pub message: String,
+    /// What the diagnostic is about; `None` for problems outside the code, like a bad flag.
+    pub span: Option<Span>,
+    /// Printed after the diagnostic, always with `Severity::Note`.
+    pub notes: Vec<Diagnostic>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            span: None,
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn note(message: impl Into<String>) -> Self {
+        Self::new(Severity::Note, message)
+    }
+
+    pub fn with_code(mut self, code: Code) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: Diagnostic) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_suggestion(
+        mut self,
+        message: impl Into<String>,
+        span: Span,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    /// Formats the diagnostic the way rustc does, quoting the line it points at:
+    ///
+    /// ```text
+    /// error[E0001]: unknown token '@'
+    ///  --> main.c:1:5
+    ///   |
+    /// 1 | int @x;
+    ///   |     ^
+    ///   = note: ...
+    /// ```
+    ///
+    /// Notes with a span of their own get their own snippet instead of a `= note:` line.
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let mut out = String::new();
+
+        out.push_str(&self.severity.to_string());
+        if let Some(code) = self.code {
+            out.push_str(&format!("[{code}]"));
+        }
+        out.push_str(&format!(": {}\n", self.message));
+
+        // Width of the line number column.
+        let mut gutter = 0;
+        if let Some(span) = self.span {
+            let location = source_map.location(span);
+            gutter = location.line.to_string().len();
+            let line = source_map.line(span);
+            let text = source_map.text(line);
+
+            // Carets run to the end of the span, or of the line if the span goes past it.
+            let start = span.start - line.start;
+            let end = span.end.min(line.end).max(span.start) - line.start;
+            let carets = (display_width(&text[..end]) - display_width(&text[..start])).max(1);
+
+            out.push_str(&format!("{:gutter$}--> {location}\n", ""));
+            out.push_str(&format!("{:gutter$} |\n", ""));
+            out.push_str(&format!(
+                "{} | {}\n",
+                location.line,
+                expand_tabs(text).trim_end()
+            ));
+            out.push_str(&format!(
+                "{:gutter$} | {:indent$}{}\n",
+                "",
+                "",
+                "^".repeat(carets),
+                indent = location.column - 1
+            ));
+        }
+
+        for note in self.notes.iter().filter(|note| note.span.is_none()) {
+            out.push_str(&format!("{:gutter$} = note: {}\n", "", note.message));
+        }
+        for suggestion in &self.suggestions {
+            out.push_str(&format!(
+                "{:gutter$} = help: {}: `{}`\n",
+                "", suggestion.message, suggestion.replacement
+            ));
+        }
+        for note in self.notes.iter().filter(|note| note.span.is_some()) {
+            out.push_str(&note.render(source_map));
+        }
+
+        out
+    }
+}
+
+/// Collects what every stage finds, so a stage can report a problem and carry on to find the
+/// next one. Stages are expected to stop early once `limit_reached`.
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+    errors: usize,
+    // 0 means no limit.
+    error_limit: usize,
+}
+
+impl Diagnostics {
+    /// Collects diagnostics until `error_limit` errors have been emitted, or forever if it's 0.
+    pub fn new(error_limit: usize) -> Self {
+        Self {
+            diagnostics: Vec::new(),
+            errors: 0,
+            error_limit,
+        }
+    }
+
+    /// Records `diagnostic`, unless it is an error past the error limit.
+    pub fn emit(&mut self, diagnostic: Diagnostic) {
+        if diagnostic.severity == Severity::Error {
+            if self.limit_reached() {
+                return;
+            }
+            self.errors += 1;
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Whether the error limit has been reached, so further errors would be dropped.
+    pub fn limit_reached(&self) -> bool {
+        self.error_limit != 0 && self.errors >= self.error_limit
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+}
+
+/// Prints `diagnostics` to stderr in `format` and, if any of them is an error, exits with
+/// status 1.
+///
+/// Meant to be called once per run, with everything every stage found: the machine-readable
+/// formats print a whole document per call. They're printed even when there is nothing to
+/// report, so a tool reading them can tell a clean run from a crash.
+pub fn report(source_map: &SourceMap, diagnostics: &Diagnostics, format: Format) {
+    match format {
+        Format::Human => {
+            for diagnostic in diagnostics.iter() {
+                eprint!("{}", diagnostic.render(source_map));
+            }
+        }
+        Format::Json => eprintln!("{}", json::render(source_map, diagnostics)),
+        Format::Sarif => eprintln!("{}", sarif::render(source_map, diagnostics)),
+    }
+
+    if diagnostics.has_errors() {
+        if format != Format::Human {
+            process::exit(1);
+        }
+        if diagnostics.limit_reached() {
+            eprintln!(
+                "error: too many errors emitted, stopping now (-ferror-limit={})",
+                diagnostics.error_limit
+            );
+        }
+        match diagnostics.errors {
+            1 => eprintln!("error: aborting due to previous error"),
+            errors => eprintln!("error: aborting due to {errors} previous errors"),
+        }
+        process::exit(1);
+    }
+}
+
+/// Reports `diagnostics` if any of them is an error, which exits, so the stage that found it
+/// is the last to run. Otherwise they're kept for the final `report`.
+pub fn stop_on_errors(source_map: &SourceMap, diagnostics: &Diagnostics, format: Format) {
+    if diagnostics.has_errors() {
+        report(source_map, diagnostics, format);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_error_with_span() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(
+            "main.c".to_owned(),
+            "int main(void) {\n\treturn @x;\n}\n".to_owned(),
+        );
+        let diagnostic = Diagnostic::error("unknown token '@x'")
+            .with_code(Code::UnknownToken)
+            .with_span(Span {
+                file,
+                start: 25,
+                end: 27,
+            })
+            .with_note(Diagnostic::note(
+                "tokens start with a letter, digit or punctuator",
+            ));
+
+        assert_eq!(
+            diagnostic.render(&source_map),
+            "error[E0001]: unknown token '@x'\n \
+             --> main.c:2:16\n  \
+              |\n\
+             2 |         return @x;\n  \
+              |                ^^\n  \
+              = note: tokens start with a letter, digit or punctuator\n"
+        );
+    }
+
+    #[test]
+    fn test_render_span_past_end_of_line() {
+        let mut source_map = SourceMap::new();
+        let mut code = "\n".repeat(9);
+        code.push_str("int f(\n  int x);\n");
+        let file = source_map.add_file("f.c".to_owned(), code);
+        let diagnostic = Diagnostic::warning("declaration spans lines").with_span(Span {
+            file,
+            start: 13,
+            end: 24,
+        });
+
+        assert_eq!(
+            diagnostic.render(&source_map),
+            "warning: declaration spans lines\n  \
+               --> f.c:10:5\n   \
+                |\n\
+             10 | int f(\n   \
+                |     ^^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_note_with_span() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.c".to_owned(), "int x;\nint x;\n".to_owned());
+        let span = |start| Span {
+            file,
+            start,
+            end: start + 1,
+        };
+        let diagnostic = Diagnostic::error("redefinition of 'x'")
+            .with_span(span(11))
+            .with_note(Diagnostic::note("previous definition is here").with_span(span(4)));
+
+        assert_eq!(
+            diagnostic.render(&source_map),
+            "error: redefinition of 'x'\n \
+             --> a.c:2:5\n  \
+              |\n\
+             2 | int x;\n  \
+              |     ^\n\
+             note: previous definition is here\n \
+             --> a.c:1:5\n  \
+              |\n\
+             1 | int x;\n  \
+              |     ^\n"
+        );
+    }
+
+    #[test]
+    fn test_error_limit() {
+        let mut diagnostics = Diagnostics::new(2);
+        diagnostics.emit(Diagnostic::error("first"));
+        diagnostics.emit(Diagnostic::warning("warning"));
+        assert!(!diagnostics.limit_reached());
+        diagnostics.emit(Diagnostic::error("second"));
+        assert!(diagnostics.limit_reached());
+        diagnostics.emit(Diagnostic::error("third"));
+        diagnostics.emit(Diagnostic::warning("later warning"));
+
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["first", "warning", "second", "later warning"]
+        );
+    }
+
+    #[test]
+    fn test_no_error_limit() {
+        let mut diagnostics = Diagnostics::new(0);
+        for _ in 0..100 {
+            diagnostics.emit(Diagnostic::error("again"));
+        }
+        assert!(!diagnostics.limit_reached());
+        assert_eq!(diagnostics.iter().count(), 100);
+    }
+
+    #[test]
+    fn test_render_suggestion() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.c".to_owned(), "int x = 10L;\n".to_owned());
+        let span = Span {
+            file,
+            start: 8,
+            end: 11,
+        };
+        let diagnostic = Diagnostic::error("invalid constant '10L'")
+            .with_span(span)
+            .with_suggestion("remove the suffix", span, "10");
+
+        assert_eq!(
+            diagnostic.render(&source_map),
+            "error: invalid constant '10L'\n \
+             --> a.c:1:9\n  \
+              |\n\
+             1 | int x = 10L;\n  \
+              |         ^^^\n  \
+              = help: remove the suffix: `10`\n"
+        );
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic = Diagnostic::error("unknown argument '--unknown'")
+            .with_code(Code::UnknownArgument)
+            .with_note(Diagnostic::note("use -h or --help for more information"));
+
+        assert_eq!(
+            diagnostic.render(&SourceMap::new()),
+            "error[E0003]: unknown argument '--unknown'\n \
+             = note: use -h or --help for more information\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Diagnostics as JSON, written by hand so the compiler keeps no dependencies.
+//!
+//! The output is an array with one object per diagnostic:
+//!
+//! ```text
+//! {
+//!   "severity": "error",
+//!   "code": "E0002",
+//!   "message": "invalid constant '10L'",
+//!   "span": {"file": "a.c", "start": {"line": 1, "column": 9}, "end": {"line": 1, "column": 12}},
+//!   "notes": [...],
+//!   "suggestions": [{"message": "remove the suffix", "span": {...}, "replacement": "10"}]
+//! }
+//! ```
+//!
+//! `code` and `span` are `null` when the diagnostic has none; notes are objects of the same
+//! shape. Lines and columns count from 1, and `end` is just past the last character.
+
+use std::fmt::{self, Write};
+
+use super::{Diagnostic, Diagnostics, Suggestion};
+use crate::source_map::{SourceMap, Span};
+
+/// A JSON value, just enough of one to write diagnostics with.
+pub enum Json {
+    Null,
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys are written in the order given.
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Number(number) => write!(f, "{number}"),
+            Json::String(string) => {
+                f.write_char('"')?;
+                for ch in string.chars() {
+                    match ch {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        ch if ch.is_control() => write!(f, "\\u{:04x}", ch as u32)?,
+                        ch => f.write_char(ch)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Json::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{value}", Json::from(*key))?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Writes every diagnostic collected so far as a JSON array.
+pub fn render(source_map: &SourceMap, diagnostics: &Diagnostics) -> String {
+    Json::Array(
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic_json(source_map, diagnostic))
+            .collect(),
+    )
+    .to_string()
+}
+
+fn diagnostic_json(source_map: &SourceMap, diagnostic: &Diagnostic) -> Json {
+    Json::Object(vec![
+        ("severity", diagnostic.severity.to_string().as_str().into()),
+        ("code", diagnostic.code.map(|code| code.as_str()).into()),
+        ("message", diagnostic.message.as_str().into()),
+        (
+            "span",
+            diagnostic
+                .span
+                .map_or(Json::Null, |span| span_json(source_map, span)),
+        ),
+        (
+            "notes",
+            Json::Array(
+                diagnostic
+                    .notes
+                    .iter()
+                    .map(|note| diagnostic_json(source_map, note))
+                    .collect(),
+            ),
+        ),
+        (
+            "suggestions",
+            Json::Array(
+                diagnostic
+                    .suggestions
+                    .iter()
+                    .map(|suggestion| suggestion_json(source_map, suggestion))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+fn suggestion_json(source_map: &SourceMap, suggestion: &Suggestion) -> Json {
+    Json::Object(vec![
+        ("message", suggestion.message.as_str().into()),
+        ("span", span_json(source_map, suggestion.span)),
+        ("replacement", suggestion.replacement.as_str().into()),
+    ])
+}
+
+fn span_json(source_map: &SourceMap, span: Span) -> Json {
+    let start = source_map.location(span);
+    let end = source_map.location(Span {
+        start: span.end,
+        ..span
+    });
+    Json::Object(vec![
+        ("file", (&*start.file).into()),
+        (
+            "start",
+            Json::Object(vec![
+                ("line", start.line.into()),
+                ("column", start.column.into()),
+            ]),
+        ),
+        (
+            "end",
+            Json::Object(vec![
+                ("line", end.line.into()),
+                ("column", end.column.into()),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Code;
+
+    #[test]
+    fn test_escape_strings() {
+        assert_eq!(
+            Json::from("a \"quoted\" \\path\\\n\tend\u{1}é").to_string(),
+            r#""a \"quoted\" \\path\\\n\tend\u0001é""#
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.c".to_owned(), "int x = 10L;\n".to_owned());
+        let span = Span {
+            file,
+            start: 8,
+            end: 11,
+        };
+        let mut diagnostics = Diagnostics::new(0);
+        diagnostics.emit(
+            Diagnostic::error("invalid constant '10L'")
+                .with_code(Code::InvalidConstant)
+                .with_span(span)
+                .with_note(Diagnostic::note("no suffixes"))
+                .with_suggestion("remove the suffix", span, "10"),
+        );
+        diagnostics.emit(Diagnostic::warning("spanless"));
+
+        assert_eq!(
+            render(&source_map, &diagnostics),
+            concat!(
+                r#"[{"severity":"error","code":"E0002","message":"invalid constant '10L'","#,
+                r#""span":{"file":"a.c","start":{"line":1,"column":9},"end":{"line":1,"column":12}},"#,
+                r#""notes":[{"severity":"note","code":null,"message":"no suffixes","span":null,"#,
+                r#""notes":[],"suggestions":[]}],"#,
+                r#""suggestions":[{"message":"remove the suffix","#,
+                r#""span":{"file":"a.c","start":{"line":1,"column":9},"end":{"line":1,"column":12}},"#,
+                r#""replacement":"10"}]},"#,
+                r#"{"severity":"warning","code":null,"message":"spanless","span":null,"#,
+                r#""notes":[],"suggestions":[]}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_nothing() {
+        assert_eq!(render(&SourceMap::new(), &Diagnostics::new(0)), "[]");
+    }
+}
@@ -0,0 +1,236 @@
+//! Diagnostics as a SARIF 2.1.0 log, the format code scanning tools and review bots read.
+//!
+//! Each diagnostic is a result with its code as the rule ID. Notes with a span become related
+//! locations, notes without one are appended to the message, and suggestions become fixes.
+
+use super::{json::Json, Diagnostic, Diagnostics, Suggestion};
+use crate::source_map::{SourceMap, Span};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Writes every diagnostic collected so far as a SARIF log with a single run.
+pub fn render(source_map: &SourceMap, diagnostics: &Diagnostics) -> String {
+    let driver = Json::Object(vec![
+        ("name", env!("CARGO_PKG_NAME").into()),
+        ("version", env!("CARGO_PKG_VERSION").into()),
+    ]);
+    let run = Json::Object(vec![
+        ("tool", Json::Object(vec![("driver", driver)])),
+        // Columns in regions count characters, where our own output counts tabs to tab stops.
+        ("columnKind", "unicodeCodePoints".into()),
+        (
+            "results",
+            Json::Array(
+                diagnostics
+                    .iter()
+                    .map(|diagnostic| result(source_map, diagnostic))
+                    .collect(),
+            ),
+        ),
+    ]);
+
+    Json::Object(vec![
+        ("$schema", SCHEMA.into()),
+        ("version", "2.1.0".into()),
+        ("runs", Json::Array(vec![run])),
+    ])
+    .to_string()
+}
+
+fn result(source_map: &SourceMap, diagnostic: &Diagnostic) -> Json {
+    let mut text = diagnostic.message.clone();
+    for note in diagnostic.notes.iter().filter(|note| note.span.is_none()) {
+        text.push_str(&format!("\nnote: {}", note.message));
+    }
+
+    let mut members = Vec::new();
+    if let Some(code) = diagnostic.code {
+        members.push(("ruleId", code.as_str().into()));
+    }
+    members.push(("level", diagnostic.severity.to_string().as_str().into()));
+    members.push(("message", message(&text)));
+    if let Some(span) = diagnostic.span {
+        members.push((
+            "locations",
+            Json::Array(vec![Json::Object(vec![(
+                "physicalLocation",
+                physical_location(source_map, span),
+            )])]),
+        ));
+    }
+
+    let related: Vec<Json> = diagnostic
+        .notes
+        .iter()
+        .filter_map(|note| Some((note.span?, note)))
+        .enumerate()
+        .map(|(id, (span, note))| {
+            Json::Object(vec![
+                ("id", id.into()),
+                ("physicalLocation", physical_location(source_map, span)),
+                ("message", message(&note.message)),
+            ])
+        })
+        .collect();
+    if !related.is_empty() {
+        members.push(("relatedLocations", Json::Array(related)));
+    }
+
+    if !diagnostic.suggestions.is_empty() {
+        members.push((
+            "fixes",
+            Json::Array(
+                diagnostic
+                    .suggestions
+                    .iter()
+                    .map(|suggestion| fix(source_map, suggestion))
+                    .collect(),
+            ),
+        ));
+    }
+
+    Json::Object(members)
+}
+
+fn fix(source_map: &SourceMap, suggestion: &Suggestion) -> Json {
+    let replacement = Json::Object(vec![
+        ("deletedRegion", region(source_map, suggestion.span)),
+        (
+            "insertedContent",
+            Json::Object(vec![("text", suggestion.replacement.as_str().into())]),
+        ),
+    ]);
+    let change = Json::Object(vec![
+        (
+            "artifactLocation",
+            artifact_location(source_map, suggestion.span),
+        ),
+        ("replacements", Json::Array(vec![replacement])),
+    ]);
+
+    Json::Object(vec![
+        ("description", message(&suggestion.message)),
+        ("artifactChanges", Json::Array(vec![change])),
+    ])
+}
+
+fn message(text: &str) -> Json {
+    Json::Object(vec![("text", text.into())])
+}
+
+fn physical_location(source_map: &SourceMap, span: Span) -> Json {
+    Json::Object(vec![
+        ("artifactLocation", artifact_location(source_map, span)),
+        ("region", region(source_map, span)),
+    ])
+}
+
+fn artifact_location(source_map: &SourceMap, span: Span) -> Json {
+    let file = source_map.location(span).file;
+    Json::Object(vec![("uri", uri(&file).as_str().into())])
+}
+
+fn region(source_map: &SourceMap, span: Span) -> Json {
+    let end = Span {
+        start: span.end,
+        ..span
+    };
+    Json::Object(vec![
+        ("startLine", source_map.location(span).line.into()),
+        ("startColumn", column(source_map, span).into()),
+        ("endLine", source_map.location(end).line.into()),
+        ("endColumn", column(source_map, end).into()),
+    ])
+}
+
+/// The 1-based column `span` starts at, counting characters.
+fn column(source_map: &SourceMap, span: Span) -> usize {
+    let line = source_map.line(span);
+    let start = span.start.min(line.end);
+    source_map.code(span.file)[line.start..start]
+        .chars()
+        .count()
+        + 1
+}
+
+/// `path` as a URI reference: relative paths stay relative, absolute ones become `file://` URIs.
+fn uri(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    if path.starts_with('/') {
+        uri.push_str("file://");
+    }
+    for ch in path.chars() {
+        match ch {
+            ' ' | '%' | '#' | '?' | '"' => uri.push_str(&format!("%{:02X}", ch as u32)),
+            ch => uri.push(ch),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Code;
+
+    #[test]
+    fn test_render() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(
+            "a.i".to_owned(),
+            "# 1 \"/src/my file.c\"\n\tint x = 10L;\n".to_owned(),
+        );
+        source_map.add_line_marker(file, 21, 1, Some("/src/my file.c"));
+        let span = Span {
+            file,
+            start: 30,
+            end: 33,
+        };
+        let mut diagnostics = Diagnostics::new(0);
+        diagnostics.emit(
+            Diagnostic::error("invalid constant '10L'")
+                .with_code(Code::InvalidConstant)
+                .with_span(span)
+                .with_note(Diagnostic::note("no suffixes"))
+                .with_note(Diagnostic::note("declared here").with_span(Span {
+                    file,
+                    start: 26,
+                    end: 27,
+                }))
+                .with_suggestion("remove the suffix", span, "10"),
+        );
+        diagnostics.emit(Diagnostic::warning("spanless"));
+
+        let location = r#"{"artifactLocation":{"uri":"file:///src/my%20file.c"},"region":"#;
+        assert_eq!(
+            render(&source_map, &diagnostics),
+            [
+                r#"{"$schema":"https://json.schemastore.org/sarif-2.1.0.json","version":"2.1.0","#,
+                r#""runs":[{"tool":{"driver":{"name":"Ccomp","version":"0.1.0"}},"#,
+                r#""columnKind":"unicodeCodePoints","results":["#,
+                r#"{"ruleId":"E0002","level":"error","#,
+                r#""message":{"text":"invalid constant '10L'\nnote: no suffixes"},"#,
+                r#""locations":[{"physicalLocation":"#,
+                location,
+                r#"{"startLine":1,"startColumn":10,"endLine":1,"endColumn":13}}}],"#,
+                r#""relatedLocations":[{"id":0,"physicalLocation":"#,
+                location,
+                r#"{"startLine":1,"startColumn":6,"endLine":1,"endColumn":7}},"#,
+                r#""message":{"text":"declared here"}}],"#,
+                r#""fixes":[{"description":{"text":"remove the suffix"},"artifactChanges":["#,
+                r#"{"artifactLocation":{"uri":"file:///src/my%20file.c"},"replacements":["#,
+                r#"{"deletedRegion":{"startLine":1,"startColumn":10,"endLine":1,"endColumn":13},"#,
+                r#""insertedContent":{"text":"10"}}]}]}]},"#,
+                r#"{"level":"warning","message":{"text":"spanless"}}]}]}"#,
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_uri() {
+        assert_eq!(uri("dir/a.c"), "dir/a.c");
+        assert_eq!(uri("/usr/include/stdio.h"), "file:///usr/include/stdio.h");
+        assert_eq!(uri("100% #1?.c"), "100%25%20%231%3F.c");
+    }
+}
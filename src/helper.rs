@@ -15,3 +15,7 @@ pub fn delete_file(path: &Path) -> io::Result<()> {
     fs::remove_file(path)?;
     Ok(())
 }
+
+pub fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(path, contents)
+}
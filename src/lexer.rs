@@ -1,599 +1,885 @@
-use regex::Regex;
+use crate::{
+    diagnostics::{Code, Diagnostic, Diagnostics},
+    source_map::{FileId, SourceMap, Span},
+};
 
 #[allow(dead_code)]
 #[derive(PartialEq, Debug)]
 pub enum Keyword {
     Return,
     Int,
+    Long,
+    Char,
+    Double,
+    Signed,
+    Unsigned,
     Void,
+    Static,
+    Extern,
+    If,
+    Else,
+    Do,
+    While,
+    For,
+    Break,
+    Continue,
+    Typedef,
+    Const,
+    Volatile,
+    Sizeof,
+    Alignof,
+}
+
+#[allow(dead_code)]
+#[derive(PartialEq, Debug)]
+pub enum TokenKind {
+    Identifier(String),
+    Keyword(Keyword),
+    Constant(String),
+    /// Stands in for text that isn't a valid token, which has already been reported, so parsing
+    /// can go on and find further errors.
+    Error,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+    Semicolon,
+    Asterisk,
+    Comma,
+    Ellipsis,
+    OpenBracket,
+    CloseBracket,
+    Dot,
+    Arrow,
+    Increment,
+    Decrement,
+    Ampersand,
+    Plus,
+    Minus,
+    Tilde,
+    Exclamation,
+    Slash,
+    Percent,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    Caret,
+    Pipe,
+    LogicalAnd,
+    LogicalOr,
+    Question,
+    Colon,
+    Assign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
+    PlusAssign,
+    MinusAssign,
+    ShiftLeftAssign,
+    ShiftRightAssign,
+    AmpersandAssign,
+    CaretAssign,
+    PipeAssign,
+    Hash,
+    HashHash,
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
-pub enum Token {
-    Identifier {
-        line: usize,
-        column: usize,
-        value: String,
-    },
-    Keyword {
-        line: usize,
-        column: usize,
-        value: Keyword,
-    },
-    Constant {
-        line: usize,
-        column: usize,
-        value: String,
-    },
-    OpenParen {
-        line: usize,
-        column: usize,
-    },
-    CloseParen {
-        line: usize,
-        column: usize,
-    },
-    OpenBrace {
-        line: usize,
-        column: usize,
-    },
-    CloseBrace {
-        line: usize,
-        column: usize,
-    },
-    Semicolon {
-        line: usize,
-        column: usize,
-    },
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
 }
 
-pub struct Lexer {
-    code: String,
+/// A single-pass scanner over preprocessed C code.
+///
+/// Every token is recognized from its first byte: identifiers and keywords, constants, or
+/// punctuators, where the longest punctuator that matches is taken (maximal munch), so `a<<=b`
+/// is `a`, `<<=`, `b`.
+pub struct Lexer<'a> {
+    source_map: &'a SourceMap,
+    file: FileId,
+    code: &'a str,
+
+    // byte offset of the next character to scan
+    pos: usize,
+}
 
-    identifier_re: Regex,
-    constant_re: Regex,
+impl<'a> Lexer<'a> {
+    pub fn new(source_map: &'a SourceMap, file: FileId) -> Self {
+        Self {
+            source_map,
+            file,
+            code: source_map.code(file),
 
-    // non-whitespace boundaries
-    boundary: Vec<char>,
+            pos: 0,
+        }
+    }
 
-    line: usize,
-    column: usize,
+    /// Converts the "C code" in the file to `Vec<Token>`.
+    ///
+    /// A character that can't start a token, or a constant running into letters, is reported to
+    /// `diagnostics` and becomes a `TokenKind::Error`. Scanning stops early once the error limit
+    /// is reached.
+    pub fn tokenize(&mut self, diagnostics: &mut Diagnostics) -> Vec<Token> {
+        let mut tokens: Vec<Token> = Vec::new();
 
-    start: usize,
-    end: usize,
-}
+        // Whether only whitespace came since the last newline, so a `#` starts a directive.
+        let mut line_start = true;
 
-impl Lexer {
-    pub fn new(code: String) -> Self {
-        Self {
-            code,
+        while let Some(&byte) = self.code.as_bytes().get(self.pos) {
+            if byte == b'\n' {
+                self.pos += 1;
+                line_start = true;
+                continue;
+            }
+            if byte.is_ascii_whitespace() || byte == b'\x0b' {
+                self.pos += 1;
+                continue;
+            }
+            if byte == b'#' && line_start && self.line_marker() {
+                continue;
+            }
+            line_start = false;
+
+            let start = self.pos;
+            let (len, kind) = match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.identifier(),
+                b'0'..=b'9' => self.constant(diagnostics),
+                b'.' if self.rest().get(1).is_some_and(u8::is_ascii_digit) => {
+                    self.constant(diagnostics)
+                }
+                _ => self.punctuator(diagnostics),
+            };
+            self.pos += len;
 
-            identifier_re: Regex::new(r"^[a-zA-Z_]\w*$").unwrap(),
-            constant_re: Regex::new(r"^[0-9]+$").unwrap(),
+            tokens.push(Token {
+                kind,
+                span: self.span(start, self.pos),
+            });
+
+            if diagnostics.limit_reached() {
+                break;
+            }
+        }
+
+        tokens
+    }
 
-            boundary: vec![';', '(', ')', '{', '}'],
+    /// Skips a line marker, `# 12 "file.c" 2` or `#line 12 "file.c"`, and records it in the
+    /// source map, so tokens after it are reported where the preprocessor found them. Returns
+    /// `false`, without moving, if the line is some other directive.
+    fn line_marker(&mut self) -> bool {
+        let line_end = self
+            .rest()
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(self.code.len(), |i| self.pos + i);
+
+        let directive = self.code[self.pos + 1..line_end].trim_start_matches([' ', '\t']);
+        let directive = directive
+            .strip_prefix("line")
+            .filter(|rest| rest.starts_with([' ', '\t']))
+            .unwrap_or(directive)
+            .trim_start_matches([' ', '\t']);
+
+        let digits = directive.bytes().take_while(u8::is_ascii_digit).count();
+        let Ok(line) = directive[..digits].parse() else {
+            return false;
+        };
+        let name = directive[digits..]
+            .trim_start_matches([' ', '\t'])
+            .strip_prefix('"')
+            .map(file_name);
+
+        let next_line = (line_end + 1).min(self.code.len());
+        self.source_map
+            .add_line_marker(self.file, next_line, line, name.as_deref());
+        self.pos = next_line;
+        true
+    }
 
-            line: 1,
-            column: 1,
+    fn rest(&self) -> &'a [u8] {
+        &self.code.as_bytes()[self.pos..]
+    }
 
-            start: 0,
-            end: 0,
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            file: self.file,
+            start,
+            end,
         }
     }
 
-    /// Converts a string containing "C code" to `Vec<Token>`.
-    pub fn tokenize(&mut self) -> Vec<Token> {
-        let mut tokens: Vec<Token> = Vec::new();
+    fn identifier(&self) -> (usize, TokenKind) {
+        let len = self
+            .rest()
+            .iter()
+            .take_while(|b| is_identifier(**b))
+            .count();
+
+        let kind = match &self.code[self.pos..self.pos + len] {
+            "int" => TokenKind::Keyword(Keyword::Int),
+            "long" => TokenKind::Keyword(Keyword::Long),
+            "char" => TokenKind::Keyword(Keyword::Char),
+            "double" => TokenKind::Keyword(Keyword::Double),
+            "signed" => TokenKind::Keyword(Keyword::Signed),
+            "unsigned" => TokenKind::Keyword(Keyword::Unsigned),
+            "void" => TokenKind::Keyword(Keyword::Void),
+            "static" => TokenKind::Keyword(Keyword::Static),
+            "extern" => TokenKind::Keyword(Keyword::Extern),
+            "return" => TokenKind::Keyword(Keyword::Return),
+            "if" => TokenKind::Keyword(Keyword::If),
+            "else" => TokenKind::Keyword(Keyword::Else),
+            "do" => TokenKind::Keyword(Keyword::Do),
+            "while" => TokenKind::Keyword(Keyword::While),
+            "for" => TokenKind::Keyword(Keyword::For),
+            "break" => TokenKind::Keyword(Keyword::Break),
+            "continue" => TokenKind::Keyword(Keyword::Continue),
+            "typedef" => TokenKind::Keyword(Keyword::Typedef),
+            "const" => TokenKind::Keyword(Keyword::Const),
+            "volatile" => TokenKind::Keyword(Keyword::Volatile),
+            "sizeof" => TokenKind::Keyword(Keyword::Sizeof),
+            "_Alignof" => TokenKind::Keyword(Keyword::Alignof),
+            name => TokenKind::Identifier(name.to_owned()),
+        };
+
+        (len, kind)
+    }
 
-        let mut iter_peek = self.code.chars().peekable();
-
-        loop {
-            match iter_peek.next() {
-                Some(ch) => {
-                    if ch.is_whitespace() {
-                        self.end += 1;
-                        self.column += self.end - self.start;
-                        self.start = self.end;
-
-                        if ch == '\n' {
-                            self.line += 1;
-                            self.column = 1;
-                        }
-
-                        continue;
-                    }
-
-                    self.end += 1;
-
-                    match iter_peek.peek() {
-                        Some(next) => {
-                            if next.is_whitespace()
-                                || self.boundary.contains(next)
-                                || self.boundary.contains(&ch)
-                            {
-                                if self.start != self.end {
-                                    tokens.push(self.gen_token());
-                                }
-                                if self.boundary.contains(next) || self.boundary.contains(&ch) {
-                                    self.column += self.end - self.start;
-                                    self.start = self.end;
-                                }
-                            }
-                        }
-                        None => {
-                            if self.start != self.end {
-                                tokens.push(self.gen_token());
-                            }
-                            break;
-                        }
-                    }
-                }
-                None => break,
+    fn constant(&self, diagnostics: &mut Diagnostics) -> (usize, TokenKind) {
+        let rest = self.rest();
+
+        // Everything that could continue a number is part of it, so `123abc` or `1.5.2` is a
+        // single (invalid) token, not a constant followed by something.
+        let mut len = 0;
+        while let Some(&byte) = rest.get(len) {
+            len += match (byte, rest.get(len + 1)) {
+                (b'e' | b'E', Some(b'+' | b'-')) => 2,
+                _ if is_identifier(byte) || byte == b'.' => 1,
+                _ => break,
+            };
+        }
+
+        let text = &self.code[self.pos..self.pos + len];
+        if !is_integer_constant(text) && !is_floating_constant(text) {
+            let span = self.span(self.pos, self.pos + len);
+            let digits_len = text.bytes().take_while(u8::is_ascii_digit).count();
+            let (digits, rest) = text.split_at(digits_len);
+
+            let mut diagnostic = Diagnostic::error(format!("invalid constant '{text}'"))
+                .with_code(Code::InvalidConstant)
+                .with_span(span)
+                .with_note(Diagnostic::note(
+                    "only decimal, octal and hexadecimal integer constants with a 'u' and 'l' \
+                     suffix, and decimal floating-point constants, are supported",
+                ));
+            if !digits.is_empty()
+                && rest.len() <= 3
+                && rest.bytes().all(|b| matches!(b, b'u' | b'U' | b'l' | b'L'))
+            {
+                diagnostic = diagnostic.with_suggestion("remove the suffix", span, digits);
+            }
+            diagnostics.emit(diagnostic);
+
+            return (len, TokenKind::Error);
+        }
+
+        (len, TokenKind::Constant(text.to_owned()))
+    }
+
+    fn punctuator(&self, diagnostics: &mut Diagnostics) -> (usize, TokenKind) {
+        // Longer punctuators come before their prefixes.
+        match self.rest() {
+            [b'(', ..] => (1, TokenKind::OpenParen),
+            [b')', ..] => (1, TokenKind::CloseParen),
+            [b'{', ..] => (1, TokenKind::OpenBrace),
+            [b'}', ..] => (1, TokenKind::CloseBrace),
+            [b'[', ..] => (1, TokenKind::OpenBracket),
+            [b']', ..] => (1, TokenKind::CloseBracket),
+            [b';', ..] => (1, TokenKind::Semicolon),
+            [b',', ..] => (1, TokenKind::Comma),
+            [b'~', ..] => (1, TokenKind::Tilde),
+            [b'?', ..] => (1, TokenKind::Question),
+            [b':', ..] => (1, TokenKind::Colon),
+            [b'.', b'.', b'.', ..] => (3, TokenKind::Ellipsis),
+            [b'.', ..] => (1, TokenKind::Dot),
+            [b'-', b'>', ..] => (2, TokenKind::Arrow),
+            [b'-', b'-', ..] => (2, TokenKind::Decrement),
+            [b'-', b'=', ..] => (2, TokenKind::MinusAssign),
+            [b'-', ..] => (1, TokenKind::Minus),
+            [b'+', b'+', ..] => (2, TokenKind::Increment),
+            [b'+', b'=', ..] => (2, TokenKind::PlusAssign),
+            [b'+', ..] => (1, TokenKind::Plus),
+            [b'&', b'&', ..] => (2, TokenKind::LogicalAnd),
+            [b'&', b'=', ..] => (2, TokenKind::AmpersandAssign),
+            [b'&', ..] => (1, TokenKind::Ampersand),
+            [b'|', b'|', ..] => (2, TokenKind::LogicalOr),
+            [b'|', b'=', ..] => (2, TokenKind::PipeAssign),
+            [b'|', ..] => (1, TokenKind::Pipe),
+            [b'*', b'=', ..] => (2, TokenKind::AsteriskAssign),
+            [b'*', ..] => (1, TokenKind::Asterisk),
+            [b'/', b'=', ..] => (2, TokenKind::SlashAssign),
+            [b'/', ..] => (1, TokenKind::Slash),
+            [b'%', b'=', ..] => (2, TokenKind::PercentAssign),
+            [b'%', ..] => (1, TokenKind::Percent),
+            [b'^', b'=', ..] => (2, TokenKind::CaretAssign),
+            [b'^', ..] => (1, TokenKind::Caret),
+            [b'!', b'=', ..] => (2, TokenKind::NotEqual),
+            [b'!', ..] => (1, TokenKind::Exclamation),
+            [b'=', b'=', ..] => (2, TokenKind::EqualEqual),
+            [b'=', ..] => (1, TokenKind::Assign),
+            [b'<', b'<', b'=', ..] => (3, TokenKind::ShiftLeftAssign),
+            [b'<', b'<', ..] => (2, TokenKind::ShiftLeft),
+            [b'<', b'=', ..] => (2, TokenKind::LessEqual),
+            [b'<', ..] => (1, TokenKind::Less),
+            [b'>', b'>', b'=', ..] => (3, TokenKind::ShiftRightAssign),
+            [b'>', b'>', ..] => (2, TokenKind::ShiftRight),
+            [b'>', b'=', ..] => (2, TokenKind::GreaterEqual),
+            [b'>', ..] => (1, TokenKind::Greater),
+            [b'#', b'#', ..] => (2, TokenKind::HashHash),
+            [b'#', ..] => (1, TokenKind::Hash),
+            _ => {
+                let len = self.code[self.pos..].chars().next().unwrap().len_utf8();
+                let len = len
+                    + self.rest()[len..]
+                        .iter()
+                        .take_while(|b| is_identifier(**b))
+                        .count();
+                let span = self.span(self.pos, self.pos + len);
+                diagnostics.emit(
+                    Diagnostic::error(format!(
+                        "unknown token '{}'",
+                        &self.code[span.start..span.end]
+                    ))
+                    .with_code(Code::UnknownToken)
+                    .with_span(span),
+                );
+                (len, TokenKind::Error)
             }
         }
+    }
+}
 
-        tokens
+fn is_identifier(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Whether `text` is a decimal, octal or hexadecimal integer constant, with an optional `u`
+/// suffix, `l` suffix or both.
+fn is_integer_constant(text: &str) -> bool {
+    let (digits, radix) = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => (digits, 16),
+        None if text.starts_with('0') => (text, 8),
+        None => (text, 10),
+    };
+    let len = digits
+        .bytes()
+        .take_while(|b| char::from(*b).is_digit(radix))
+        .count();
+
+    len > 0
+        && matches!(
+            digits[len..].to_ascii_lowercase().as_str(),
+            "" | "u" | "l" | "ul" | "lu"
+        )
+}
+
+/// Whether `text` is a decimal floating-point constant without a suffix, like `1.5`, `.5`,
+/// `1.` or `15e-1`.
+fn is_floating_constant(text: &str) -> bool {
+    let digits = |text: &str| text.bytes().take_while(u8::is_ascii_digit).count();
+
+    let mut mantissa = digits(text);
+    let mut rest = &text[mantissa..];
+    let fraction = rest.starts_with('.');
+    if fraction {
+        let len = digits(&rest[1..]);
+        mantissa += len;
+        rest = &rest[1 + len..];
+    }
+    let exponent = rest.starts_with(['e', 'E']);
+    if exponent {
+        let sign = usize::from(rest[1..].starts_with(['+', '-']));
+        let len = digits(&rest[1 + sign..]);
+        if len == 0 {
+            return false;
+        }
+        rest = &rest[1 + sign + len..];
     }
 
-    fn gen_token(&self) -> Token {
-        match &self.code[self.start..self.end] {
-            ";" => Token::Semicolon {
-                line: self.line,
-                column: self.column,
-            },
-            "(" => Token::OpenParen {
-                line: self.line,
-                column: self.column,
-            },
-            ")" => Token::CloseParen {
-                line: self.line,
-                column: self.column,
-            },
-            "{" => Token::OpenBrace {
-                line: self.line,
-                column: self.column,
-            },
-            "}" => Token::CloseBrace {
-                line: self.line,
-                column: self.column,
-            },
-            "int" => Token::Keyword {
-                line: self.line,
-                column: self.column,
-                value: Keyword::Int,
-            },
-            "void" => Token::Keyword {
-                line: self.line,
-                column: self.column,
-                value: Keyword::Void,
-            },
-            "return" => Token::Keyword {
-                line: self.line,
-                column: self.column,
-                value: Keyword::Return,
-            },
-            t => {
-                if self.identifier_re.is_match(t) {
-                    Token::Identifier {
-                        line: self.line,
-                        column: self.column,
-                        value: t.to_owned(),
-                    }
-                } else if self.constant_re.is_match(t) {
-                    Token::Constant {
-                        line: self.line,
-                        column: self.column,
-                        value: t.to_owned(),
-                    }
-                } else {
-                    panic!(
-                        "unknown token '{}' at line {}, column {}.",
-                        t, self.line, self.column
-                    );
-                }
-            }
+    mantissa > 0 && (fraction || exponent) && rest.is_empty()
+}
+
+/// The file name in a line marker, starting just after its opening quote, with escapes undone.
+fn file_name(quoted: &str) -> String {
+    let mut name = String::new();
+    let mut chars = quoted.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => break,
+            '\\' => name.extend(chars.next()),
+            _ => name.push(ch),
         }
     }
+    name
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    #[should_panic(expected = "unknown token '@void' at line 1, column 5.")]
-    fn test_tokenize_unknown_token() {
-        let mut lexer = Lexer::new(String::from("int @void return"));
-        let _tokens = lexer.tokenize();
+    /// Tokenizes `code`, returning each token's kind, line and column.
+    fn tokenize(code: &str) -> Vec<(TokenKind, usize, usize)> {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+        assert!(!diagnostics.has_errors());
+
+        tokens
+            .into_iter()
+            .map(|token| {
+                let location = source_map.location(token.span);
+                (token.kind, location.line, location.column)
+            })
+            .collect()
     }
 
-    #[test]
-    fn test_tokenize_keywords() {
-        let mut lexer = Lexer::new(String::from("int void return"));
-        let tokens = lexer.tokenize();
-
-        assert_eq!(tokens.len(), 3);
-
-        match &tokens[0] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 1);
-                assert_eq!(*value, Keyword::Int);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+    /// Tokenizes `code`, which must have exactly one error, returning its code, message and
+    /// location.
+    fn tokenize_error(code: &str) -> (Option<Code>, String, String) {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+
+        let errors: Vec<&Diagnostic> = diagnostics.iter().collect();
+        assert_eq!(errors.len(), 1);
+        (
+            errors[0].code,
+            errors[0].message.clone(),
+            source_map.location(errors[0].span.unwrap()).to_string(),
+        )
+    }
 
-        match &tokens[1] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 5);
-                assert_eq!(*value, Keyword::Void);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+    fn identifier(name: &str) -> TokenKind {
+        TokenKind::Identifier(name.to_owned())
+    }
 
-        match &tokens[2] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 10);
-                assert_eq!(*value, Keyword::Return);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+    fn constant(value: &str) -> TokenKind {
+        TokenKind::Constant(value.to_owned())
     }
 
     #[test]
-    fn test_tokenize_identifiers() {
-        let mut lexer = Lexer::new(String::from("var1 _var2 VAR_3"));
-        let tokens = lexer.tokenize();
-
-        assert_eq!(tokens.len(), 3);
-
-        match &tokens[0] {
-            Token::Identifier {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 1);
-                assert_eq!(value, "var1");
-            }
-            _ => panic!("Expected an Identifier token"),
-        }
-
-        match &tokens[1] {
-            Token::Identifier {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 6);
-                assert_eq!(value, "_var2");
-            }
-            _ => panic!("Expected an Identifier token"),
-        }
+    fn test_tokenize_unknown_token() {
+        assert_eq!(
+            tokenize_error("int @void return"),
+            (
+                Some(Code::UnknownToken),
+                "unknown token '@void'".to_owned(),
+                "test.c:1:5".to_owned()
+            )
+        );
+    }
 
-        match &tokens[2] {
-            Token::Identifier {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 12);
-                assert_eq!(value, "VAR_3");
-            }
-            _ => panic!("Expected an Identifier token"),
-        }
+    #[test]
+    fn test_tokenize_constant_running_into_letters() {
+        assert_eq!(
+            tokenize_error("int\n  123abc;"),
+            (
+                Some(Code::InvalidConstant),
+                "invalid constant '123abc'".to_owned(),
+                "test.c:2:3".to_owned()
+            )
+        );
     }
 
     #[test]
-    fn test_tokenize_constants() {
-        let mut lexer = Lexer::new(String::from("123 456 789"));
-        let tokens = lexer.tokenize();
-
-        assert_eq!(tokens.len(), 3);
-
-        match &tokens[0] {
-            Token::Constant {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 1);
-                assert_eq!(value, "123");
-            }
-            _ => panic!("Expected a Constant token"),
-        }
+    fn test_tokenize_continues_after_errors() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), "int $a = 1x;\nreturn @ `;".to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+
+        let kinds: Vec<&TokenKind> = tokens.iter().map(|token| &token.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &TokenKind::Keyword(Keyword::Int),
+                &TokenKind::Error,
+                &TokenKind::Assign,
+                &TokenKind::Error,
+                &TokenKind::Semicolon,
+                &TokenKind::Keyword(Keyword::Return),
+                &TokenKind::Error,
+                &TokenKind::Error,
+                &TokenKind::Semicolon,
+            ]
+        );
+        assert_eq!(source_map.text(tokens[1].span), "$a");
+        assert_eq!(source_map.text(tokens[3].span), "1x");
+
+        let errors: Vec<String> = diagnostics
+            .iter()
+            .map(|error| source_map.location(error.span.unwrap()).to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec!["test.c:1:5", "test.c:1:10", "test.c:2:8", "test.c:2:10"]
+        );
+    }
 
-        match &tokens[1] {
-            Token::Constant {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 5);
-                assert_eq!(value, "456");
-            }
-            _ => panic!("Expected a Constant token"),
-        }
+    #[test]
+    fn test_suggest_removing_constant_suffix() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), "10LL 1.5f".to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+
+        let errors: Vec<&Diagnostic> = diagnostics.iter().collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].suggestions.len(), 1);
+        assert_eq!(errors[0].suggestions[0].span, errors[0].span.unwrap());
+        assert_eq!(errors[0].suggestions[0].replacement, "10");
+        assert!(errors[1].suggestions.is_empty());
+    }
 
-        match &tokens[2] {
-            Token::Constant {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 9);
-                assert_eq!(value, "789");
-            }
-            _ => panic!("Expected a Constant token"),
-        }
+    #[test]
+    fn test_tokenize_stops_at_error_limit() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), "@ @ @ int".to_owned());
+        let mut diagnostics = Diagnostics::new(2);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(diagnostics.iter().count(), 2);
+        assert!(diagnostics.limit_reached());
     }
 
     #[test]
-    fn test_tokenize_delimiters() {
-        let mut lexer = Lexer::new(String::from("(){};"));
-        let tokens = lexer.tokenize();
+    fn test_tokenize_keywords() {
+        assert_eq!(
+            tokenize("int void return"),
+            vec![
+                (TokenKind::Keyword(Keyword::Int), 1, 1),
+                (TokenKind::Keyword(Keyword::Void), 1, 5),
+                (TokenKind::Keyword(Keyword::Return), 1, 10),
+            ]
+        );
+    }
 
-        assert_eq!(tokens.len(), 5);
+    #[test]
+    fn test_tokenize_statement_and_type_keywords() {
+        let kinds: Vec<TokenKind> = tokenize(
+            "long char double signed unsigned static extern if else do while for break continue",
+        )
+        .into_iter()
+        .map(|(kind, _, _)| kind)
+        .collect();
+
+        assert_eq!(
+            kinds,
+            [
+                Keyword::Long,
+                Keyword::Char,
+                Keyword::Double,
+                Keyword::Signed,
+                Keyword::Unsigned,
+                Keyword::Static,
+                Keyword::Extern,
+                Keyword::If,
+                Keyword::Else,
+                Keyword::Do,
+                Keyword::While,
+                Keyword::For,
+                Keyword::Break,
+                Keyword::Continue,
+            ]
+            .map(TokenKind::Keyword)
+        );
+    }
 
-        match &tokens[0] {
-            Token::OpenParen { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 1);
-            }
-            _ => panic!("Expected an OpenParen token"),
-        }
+    #[test]
+    fn test_tokenize_typedef() {
+        assert_eq!(
+            tokenize("typedef int typedefs;"),
+            vec![
+                (TokenKind::Keyword(Keyword::Typedef), 1, 1),
+                (TokenKind::Keyword(Keyword::Int), 1, 9),
+                (identifier("typedefs"), 1, 13),
+                (TokenKind::Semicolon, 1, 21),
+            ]
+        );
+    }
 
-        match &tokens[1] {
-            Token::CloseParen { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 2);
-            }
-            _ => panic!("Expected a CloseParen token"),
-        }
+    #[test]
+    fn test_tokenize_type_qualifiers() {
+        assert_eq!(
+            tokenize("const volatile int"),
+            vec![
+                (TokenKind::Keyword(Keyword::Const), 1, 1),
+                (TokenKind::Keyword(Keyword::Volatile), 1, 7),
+                (TokenKind::Keyword(Keyword::Int), 1, 16),
+            ]
+        );
+    }
 
-        match &tokens[2] {
-            Token::OpenBrace { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 3);
-            }
-            _ => panic!("Expected an OpenBrace token"),
-        }
+    #[test]
+    fn test_tokenize_sizeof_alignof() {
+        let tokens = tokenize("sizeof(int) _Alignof(int)");
 
-        match &tokens[3] {
-            Token::CloseBrace { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 4);
-            }
-            _ => panic!("Expected a CloseBrace token"),
-        }
+        assert_eq!(tokens.len(), 8);
+        assert_eq!(tokens[0], (TokenKind::Keyword(Keyword::Sizeof), 1, 1));
+        assert_eq!(tokens[4], (TokenKind::Keyword(Keyword::Alignof), 1, 13));
+    }
 
-        match &tokens[4] {
-            Token::Semicolon { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 5);
-            }
-            _ => panic!("Expected a Semicolon token"),
-        }
+    #[test]
+    fn test_tokenize_identifiers() {
+        assert_eq!(
+            tokenize("var1 _var2 VAR_3"),
+            vec![
+                (identifier("var1"), 1, 1),
+                (identifier("_var2"), 1, 6),
+                (identifier("VAR_3"), 1, 12),
+            ]
+        );
     }
 
     #[test]
-    fn test_tokenize_mixed() {
-        let mut lexer = Lexer::new(String::from("int main() { return 42; }"));
-        let tokens = lexer.tokenize();
-
-        assert_eq!(tokens.len(), 9);
-
-        match &tokens[0] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 1);
-                assert_eq!(*value, Keyword::Int);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+    fn test_tokenize_constants() {
+        assert_eq!(
+            tokenize("123 456 789"),
+            vec![
+                (constant("123"), 1, 1),
+                (constant("456"), 1, 5),
+                (constant("789"), 1, 9),
+            ]
+        );
+    }
 
-        match &tokens[1] {
-            Token::Identifier {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 5);
-                assert_eq!(value, "main");
-            }
-            _ => panic!("Expected an Identifier token"),
-        }
+    #[test]
+    fn test_tokenize_constant_forms() {
+        let constants = [
+            "0", "017", "0x1F", "10u", "10L", "10uL", "10LU", "1.5", ".5", "1.", "15e-1", "1E+3",
+        ];
+        let tokens = tokenize(&constants.join(" "));
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|(kind, _, _)| kind).collect();
+        let expected: Vec<TokenKind> = constants.into_iter().map(constant).collect();
+        assert_eq!(kinds, expected);
+    }
 
-        match &tokens[2] {
-            Token::OpenParen { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 9);
-            }
-            _ => panic!("Expected an OpenParen token"),
+    #[test]
+    fn test_tokenize_invalid_constants() {
+        for code in ["09", "0x", "1e", "1.5.2", "1.5f", "10ul2", "0x1.5"] {
+            let (error_code, message, _) = tokenize_error(code);
+            assert_eq!(error_code, Some(Code::InvalidConstant));
+            assert_eq!(message, format!("invalid constant '{code}'"));
         }
+    }
 
-        match &tokens[3] {
-            Token::CloseParen { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 10);
-            }
-            _ => panic!("Expected a CloseParen token"),
-        }
+    #[test]
+    fn test_tokenize_delimiters() {
+        assert_eq!(
+            tokenize("(){};"),
+            vec![
+                (TokenKind::OpenParen, 1, 1),
+                (TokenKind::CloseParen, 1, 2),
+                (TokenKind::OpenBrace, 1, 3),
+                (TokenKind::CloseBrace, 1, 4),
+                (TokenKind::Semicolon, 1, 5),
+            ]
+        );
+    }
 
-        match &tokens[4] {
-            Token::OpenBrace { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 12);
-            }
-            _ => panic!("Expected an OpenBrace token"),
-        }
+    #[test]
+    fn test_tokenize_function_pointer_declarator() {
+        let tokens = tokenize("int (*fp)(int,int);");
 
-        match &tokens[5] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 14);
-                assert_eq!(*value, Keyword::Return);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+        assert_eq!(tokens.len(), 11);
+        assert_eq!(tokens[2], (TokenKind::Asterisk, 1, 6));
+        assert_eq!(tokens[3], (identifier("fp"), 1, 7));
+        assert_eq!(tokens[7], (TokenKind::Comma, 1, 14));
+    }
 
-        match &tokens[6] {
-            Token::Constant {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 21);
-                assert_eq!(value, "42");
-            }
-            _ => panic!("Expected a Constant token"),
-        }
+    #[test]
+    fn test_tokenize_ellipsis() {
+        let tokens = tokenize("int printf(char *fmt, ...);");
 
-        match &tokens[7] {
-            Token::Semicolon { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 23);
-            }
-            _ => panic!("Expected a Semicolon token"),
-        }
+        assert_eq!(tokens.len(), 10);
+        assert_eq!(tokens[7], (TokenKind::Ellipsis, 1, 23));
+        assert_eq!(tokens[8], (TokenKind::CloseParen, 1, 26));
+    }
 
-        match &tokens[8] {
-            Token::CloseBrace { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 25);
-            }
-            _ => panic!("Expected a CloseBrace token"),
-        }
+    #[test]
+    fn test_tokenize_mixed() {
+        assert_eq!(
+            tokenize("int main() { return 42; }"),
+            vec![
+                (TokenKind::Keyword(Keyword::Int), 1, 1),
+                (identifier("main"), 1, 5),
+                (TokenKind::OpenParen, 1, 9),
+                (TokenKind::CloseParen, 1, 10),
+                (TokenKind::OpenBrace, 1, 12),
+                (TokenKind::Keyword(Keyword::Return), 1, 14),
+                (constant("42"), 1, 21),
+                (TokenKind::Semicolon, 1, 23),
+                (TokenKind::CloseBrace, 1, 25),
+            ]
+        );
     }
 
     #[test]
     fn test_tokenize_mixed_with_newline() {
-        let mut lexer = Lexer::new(String::from("int   main() \n  {    \n return 42; \n}"));
-        let tokens = lexer.tokenize();
-
-        assert_eq!(tokens.len(), 9);
-
-        match &tokens[0] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 1);
-                assert_eq!(*value, Keyword::Int);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+        assert_eq!(
+            tokenize("int   main() \n  {    \n return 42; \n}"),
+            vec![
+                (TokenKind::Keyword(Keyword::Int), 1, 1),
+                (identifier("main"), 1, 7),
+                (TokenKind::OpenParen, 1, 11),
+                (TokenKind::CloseParen, 1, 12),
+                (TokenKind::OpenBrace, 2, 3),
+                (TokenKind::Keyword(Keyword::Return), 3, 2),
+                (constant("42"), 3, 9),
+                (TokenKind::Semicolon, 3, 11),
+                (TokenKind::CloseBrace, 4, 1),
+            ]
+        );
+    }
 
-        match &tokens[1] {
-            Token::Identifier {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 7);
-                assert_eq!(value, "main");
-            }
-            _ => panic!("Expected an Identifier token"),
-        }
+    #[test]
+    fn test_tokenize_unknown_multibyte_character() {
+        assert_eq!(
+            tokenize_error("\tint\té;"),
+            (
+                Some(Code::UnknownToken),
+                "unknown token 'é'".to_owned(),
+                "test.c:1:17".to_owned()
+            )
+        );
+    }
 
-        match &tokens[2] {
-            Token::OpenParen { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 11);
-            }
-            _ => panic!("Expected an OpenParen token"),
-        }
+    #[test]
+    fn test_tokenize_tabs() {
+        // Columns are display columns, even though spans are byte offsets.
+        assert_eq!(
+            tokenize("\tint x;\n\t\treturn\tx;"),
+            vec![
+                (TokenKind::Keyword(Keyword::Int), 1, 9),
+                (identifier("x"), 1, 13),
+                (TokenKind::Semicolon, 1, 14),
+                (TokenKind::Keyword(Keyword::Return), 2, 17),
+                (identifier("x"), 2, 25),
+                (TokenKind::Semicolon, 2, 26),
+            ]
+        );
+    }
 
-        match &tokens[3] {
-            Token::CloseParen { line, column } => {
-                assert_eq!(*line, 1);
-                assert_eq!(*column, 12);
-            }
-            _ => panic!("Expected a CloseParen token"),
-        }
+    #[test]
+    fn test_tokenize_line_markers() {
+        let mut source_map = SourceMap::new();
+        let code = "# 0 \"a.c\"\n\
+                    # 1 \"/usr/include/a.h\" 1 3 4\n\
+                    \n\
+                    typedef int T;\n\
+                    # 2 \"a.c\" 2\n\
+                    T x;\n\
+                    #line 40 \"dir\\\\b \\\"c\\\".c\"\n\
+                    int y;\n\
+                    # 7\n\
+                    int z;\n";
+        let file = source_map.add_file("a.i".to_owned(), code.to_owned());
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut Diagnostics::new(0));
+
+        let locations: Vec<String> = tokens
+            .iter()
+            .map(|token| source_map.location(token.span).to_string())
+            .collect();
+        assert_eq!(tokens.len(), 13);
+        assert_eq!(locations[0], "/usr/include/a.h:2:1");
+        assert_eq!(locations[4], "a.c:2:1");
+        assert_eq!(locations[8], "dir\\b \"c\".c:40:5");
+        assert_eq!(locations[11], "dir\\b \"c\".c:7:5");
+    }
 
-        match &tokens[4] {
-            Token::OpenBrace { line, column } => {
-                assert_eq!(*line, 2);
-                assert_eq!(*column, 3);
-            }
-            _ => panic!("Expected an OpenBrace token"),
-        }
+    #[test]
+    fn test_tokenize_hash_outside_line_marker() {
+        let tokens = tokenize("#pragma once\nint a; # 1");
 
-        match &tokens[5] {
-            Token::Keyword {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 3);
-                assert_eq!(*column, 2);
-                assert_eq!(*value, Keyword::Return);
-            }
-            _ => panic!("Expected a Keyword token"),
-        }
+        assert_eq!(tokens.len(), 8);
+        assert_eq!(tokens[0], (TokenKind::Hash, 1, 1));
+        assert_eq!(tokens[6], (TokenKind::Hash, 2, 8));
+        assert_eq!(tokens[7], (constant("1"), 2, 10));
+    }
 
-        match &tokens[6] {
-            Token::Constant {
-                line,
-                column,
-                value,
-            } => {
-                assert_eq!(*line, 3);
-                assert_eq!(*column, 9);
-                assert_eq!(value, "42");
-            }
-            _ => panic!("Expected a Constant token"),
-        }
+    #[test]
+    fn test_token_spans() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), "int x = a<<=b;".to_owned());
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut Diagnostics::new(0));
+
+        let texts: Vec<&str> = tokens
+            .iter()
+            .map(|token| source_map.text(token.span))
+            .collect();
+        assert_eq!(texts, vec!["int", "x", "=", "a", "<<=", "b", ";"]);
+        assert_eq!(
+            tokens[4].span,
+            Span {
+                file,
+                start: 9,
+                end: 12
+            }
+        );
+    }
 
-        match &tokens[7] {
-            Token::Semicolon { line, column } => {
-                assert_eq!(*line, 3);
-                assert_eq!(*column, 11);
-            }
-            _ => panic!("Expected a Semicolon token"),
-        }
+    #[test]
+    fn test_tokenize_maximal_munch() {
+        let punctuators: Vec<TokenKind> = tokenize("a<<=b>>c->d---e&&&f...g..h")
+            .into_iter()
+            .map(|(kind, _, _)| kind)
+            .filter(|kind| !matches!(kind, TokenKind::Identifier(_)))
+            .collect();
+
+        assert_eq!(
+            punctuators,
+            vec![
+                TokenKind::ShiftLeftAssign,
+                TokenKind::ShiftRight,
+                TokenKind::Arrow,
+                TokenKind::Decrement,
+                TokenKind::Minus,
+                TokenKind::LogicalAnd,
+                TokenKind::Ampersand,
+                TokenKind::Ellipsis,
+                TokenKind::Dot,
+                TokenKind::Dot,
+            ]
+        );
+    }
 
-        match &tokens[8] {
-            Token::CloseBrace { line, column } => {
-                assert_eq!(*line, 4);
-                assert_eq!(*column, 1);
-            }
-            _ => panic!("Expected a CloseBrace token"),
-        }
+    #[test]
+    fn test_tokenize_operators() {
+        let tokens =
+            tokenize("[]~?:+ ++ += * *= / /= % %= ^ ^= ! != = == | || |= < <= > >= >>= # ##");
+
+        assert_eq!(tokens.len(), 30);
+        assert_eq!(tokens[0].0, TokenKind::OpenBracket);
+        assert_eq!(tokens[1].0, TokenKind::CloseBracket);
+        assert_eq!(tokens[6].0, TokenKind::Increment);
+        assert_eq!(tokens[7].0, TokenKind::PlusAssign);
+        assert_eq!(tokens[13].0, TokenKind::PercentAssign);
+        assert_eq!(tokens[17].0, TokenKind::NotEqual);
+        assert_eq!(tokens[19].0, TokenKind::EqualEqual);
+        assert_eq!(tokens[22].0, TokenKind::PipeAssign);
+        assert_eq!(tokens[26].0, TokenKind::GreaterEqual);
+        assert_eq!(tokens[27].0, TokenKind::ShiftRightAssign);
+        assert_eq!(tokens[29], (TokenKind::HashHash, 1, 68));
     }
 }
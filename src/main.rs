@@ -1,23 +1,54 @@
 use std::{env, process};
 
+use diagnostics::Diagnostics;
 use lexer::Lexer;
+use parser::Parser;
+use source_map::SourceMap;
 
 mod args;
+mod assembly;
+mod ast;
 mod cc;
+mod diagnostics;
 mod helper;
 mod lexer;
+mod optimizer;
+mod parser;
+mod scope;
+mod semantic;
+mod source_map;
+mod tacky;
+#[cfg(test)]
+mod test_support;
 
 #[derive(Debug, PartialEq)]
 enum CompileStage {
     Lex,
     Parse,
+    Tacky,
     CodeGen,
     EmitCode,
     All,
 }
 
 fn main() {
-    let (c_file_path, compile_stage) = args::parse(env::args().collect::<Vec<String>>());
+    let args = env::args().collect::<Vec<String>>();
+    let (c_file_path, compile_stage, optimizations, diagnostic_options) =
+        match args::parse(args.clone()) {
+            Ok(args) => args,
+            Err(error) => {
+                let mut diagnostics = Diagnostics::new(0);
+                diagnostics.emit(error);
+                diagnostics::report(
+                    &SourceMap::new(),
+                    &diagnostics,
+                    args::diagnostics_format(&args),
+                );
+                // `report` has already exited, since the diagnostic is an error.
+                process::exit(1)
+            }
+        };
+    let mut diagnostics = Diagnostics::new(diagnostic_options.error_limit);
 
     //-------------------------
     // Preprocessor
@@ -30,28 +61,76 @@ fn main() {
     let preprocessed_c_code = helper::read_file(preprocessed_c_file_path.as_path()).unwrap();
     helper::delete_file(preprocessed_c_file_path.as_path()).unwrap();
 
-    let mut lexer = Lexer::new(preprocessed_c_code);
-    let tokens = lexer.tokenize();
-    for token in tokens {
-        println!("{:?}", token);
-    }
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file(
+        preprocessed_c_file_path.display().to_string(),
+        preprocessed_c_code,
+    );
+
+    let mut lexer = Lexer::new(&source_map, file);
+    let tokens = lexer.tokenize(&mut diagnostics);
+    diagnostics::stop_on_errors(&source_map, &diagnostics, diagnostic_options.format);
 
     // Exit if '--lex' flag was passed
-    match compile_stage {
-        CompileStage::Lex => process::exit(0),
-        _ => (),
+    if compile_stage == CompileStage::Lex {
+        for token in tokens {
+            println!("{}: {:?}", source_map.location(token.span), token.kind);
+        }
+        diagnostics::report(&source_map, &diagnostics, diagnostic_options.format);
+        process::exit(0)
     }
 
     // Parser
-    // ...
+    let mut program = Parser::new(&source_map, file, &tokens).parse(&mut diagnostics);
+    diagnostics::stop_on_errors(&source_map, &diagnostics, diagnostic_options.format);
+
+    // Exit if '--parse' flag was passed
+    if compile_stage == CompileStage::Parse {
+        diagnostics::report(&source_map, &diagnostics, diagnostic_options.format);
+        process::exit(0)
+    }
+
+    // Semantic Analysis
+    let symbols = semantic::analyze(&mut program, &mut diagnostics);
+    diagnostics::stop_on_errors(&source_map, &diagnostics, diagnostic_options.format);
+
+    // TACKY Generation
+    let mut tacky = tacky::generation::generate(&program, &symbols);
+
+    // Optimizer
+    optimizer::optimize(&mut tacky, &optimizations, &mut diagnostics);
+    // No later stage finds anything to report.
+    diagnostics::report(&source_map, &diagnostics, diagnostic_options.format);
+
+    // Print the IR and exit if '--tacky' flag was passed
+    if compile_stage == CompileStage::Tacky {
+        print!("{tacky}");
+        process::exit(0)
+    }
 
     // Assembly Generation
-    // ...
+    let mut assembly = assembly::codegen::generate(tacky);
+    assembly::finish(&mut assembly);
+
+    // Exit if '--code-gen' flag was passed
+    if compile_stage == CompileStage::CodeGen {
+        process::exit(0)
+    }
 
     // Code Emission
-    // ...
+    let assembly_file_path = c_file_path.with_extension("s");
+    helper::write_file(assembly_file_path.as_path(), &assembly.to_string()).unwrap();
+
+    // Exit if '-S' flag was passed
+    if compile_stage == CompileStage::EmitCode {
+        process::exit(0)
+    }
 
     //-------------------------
     // Assembler and Linker
-    // gcc ASSEMBLY_FILE -o OUTPUT_FILE
+    cc::assemble_and_link(
+        assembly_file_path.as_path(),
+        &c_file_path.with_extension(""),
+    );
+    helper::delete_file(assembly_file_path.as_path()).unwrap();
 }
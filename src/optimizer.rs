@@ -0,0 +1,764 @@
+pub mod cfg;
+mod common_subexpressions;
+mod constant_folding;
+mod constant_propagation;
+mod copy_propagation;
+pub mod dataflow;
+mod dead_store_elimination;
+mod dominators;
+mod inlining;
+mod liveness;
+mod loop_optimization;
+mod ssa;
+mod unreachable_code;
+
+// The type checker evaluates static initializers the same way folding would.
+pub use constant_folding::{convert, eval_unary};
+
+use std::collections::HashSet;
+
+use crate::{
+    diagnostics::{Diagnostic, Diagnostics},
+    tacky::{Function, Instruction, Program, Storage, SymbolTable, TopLevel, Val},
+};
+
+/// The optimization passes enabled on the command line.
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub struct Optimizations {
+    pub fold_constants: bool,
+    pub propagate_constants: bool,
+    pub eliminate_common_subexpressions: bool,
+    pub eliminate_unreachable_code: bool,
+    pub propagate_copies: bool,
+    pub eliminate_dead_stores: bool,
+    pub inline_functions: bool,
+    pub optimize_loops: bool,
+}
+
+impl Optimizations {
+    /// Every pass, as enabled by `--optimize`.
+    pub fn all() -> Self {
+        Self {
+            fold_constants: true,
+            propagate_constants: true,
+            eliminate_common_subexpressions: true,
+            eliminate_unreachable_code: true,
+            propagate_copies: true,
+            eliminate_dead_stores: true,
+            inline_functions: true,
+            optimize_loops: true,
+        }
+    }
+}
+
+/// Runs the enabled passes over every function in `program`, emitting any warnings they raise
+/// into `diagnostics`.
+///
+/// Each pass can expose opportunities for the others (folding a condition makes a branch
+/// unreachable, propagating a copy makes the copy dead, and so on), so they're repeated until
+/// a function stops changing. Inlining runs once every function has been cleaned up, so callee
+/// sizes reflect the optimized code, and the functions it changes are cleaned up again.
+pub fn optimize(
+    program: &mut Program,
+    optimizations: &Optimizations,
+    diagnostics: &mut Diagnostics,
+) {
+    let mut warnings: Vec<Diagnostic> = Vec::new();
+
+    for top_level in program.top_level.iter_mut() {
+        if let TopLevel::Function(function) = top_level {
+            run_passes(function, &mut program.symbols, optimizations, &mut warnings);
+        }
+    }
+
+    if optimizations.inline_functions {
+        let changed = inlining::inline_functions(program);
+        for top_level in program.top_level.iter_mut() {
+            match top_level {
+                TopLevel::Function(function) if changed.contains(&function.name) => {
+                    run_passes(function, &mut program.symbols, optimizations, &mut warnings);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    for warning in warnings {
+        diagnostics.emit(warning);
+    }
+}
+
+/// Repeats the enabled per-function passes until `function` stops changing, adding any new
+/// warnings to `warnings`.
+fn run_passes(
+    function: &mut Function,
+    symbols: &mut SymbolTable,
+    optimizations: &Optimizations,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    loop {
+        let before = function.body.clone();
+
+        if optimizations.fold_constants {
+            for warning in constant_folding::fold_constants(function, symbols) {
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+        }
+        if optimizations.propagate_constants {
+            constant_propagation::propagate_constants(function, symbols);
+        }
+        if optimizations.eliminate_common_subexpressions {
+            common_subexpressions::eliminate_common_subexpressions(function, symbols);
+        }
+        if optimizations.optimize_loops {
+            loop_optimization::optimize_loops(function, symbols);
+        }
+        if optimizations.eliminate_unreachable_code {
+            unreachable_code::eliminate_unreachable_code(function, symbols);
+        }
+        if optimizations.propagate_copies {
+            copy_propagation::propagate_copies(function, symbols);
+        }
+        if optimizations.eliminate_dead_stores {
+            dead_store_elimination::eliminate_dead_stores(function, symbols);
+        }
+
+        if function.body == before {
+            break;
+        }
+    }
+}
+
+/// The variables a function can't assume it alone modifies: those with static storage, which
+/// other functions can reach, and those whose address it takes, which any store through a
+/// pointer or any call it passes the pointer to can modify.
+struct Aliased<'a> {
+    address_taken: HashSet<String>,
+    symbols: &'a SymbolTable,
+}
+
+impl<'a> Aliased<'a> {
+    fn new(function: &Function, symbols: &'a SymbolTable) -> Self {
+        let address_taken = function
+            .body
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::GetAddress {
+                    src: Val::Var(name),
+                    ..
+                } => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            address_taken,
+            symbols,
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.address_taken.contains(name)
+            || self
+                .symbols
+                .get(name)
+                .is_some_and(|symbol| symbol.storage == Storage::Static)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembly,
+        tacky::BinaryOp,
+        test_support::{
+            c,
+            tacky::{int, ints, span, var},
+        },
+    };
+
+    #[test]
+    fn test_optimize_to_fixed_point() {
+        // int x = 2 * 3; if (x > 5) return x; return 0;
+        let symbols = ints(&["x", "tmp.0"]);
+        let mut program = Program {
+            top_level: vec![TopLevel::Function(Function {
+                name: "main".to_owned(),
+                global: true,
+                params: vec![],
+                body: vec![
+                    Instruction::Binary {
+                        op: BinaryOp::Multiply,
+                        src1: int(2),
+                        src2: int(3),
+                        dst: var("x"),
+                    },
+                    Instruction::Binary {
+                        op: BinaryOp::GreaterThan,
+                        src1: var("x"),
+                        src2: int(5),
+                        dst: var("tmp.0"),
+                    },
+                    Instruction::JumpIfZero {
+                        condition: var("tmp.0"),
+                        target: "else".to_owned(),
+                    },
+                    Instruction::Return(Some(var("x"))),
+                    Instruction::Label("else".to_owned()),
+                    Instruction::Return(Some(int(0))),
+                ],
+                span: span(),
+            })],
+            symbols,
+        };
+        optimize(
+            &mut program,
+            &Optimizations::all(),
+            &mut Diagnostics::new(0),
+        );
+
+        let TopLevel::Function(function) = &program.top_level[0] else {
+            unreachable!()
+        };
+        assert_eq!(function.body, vec![Instruction::Return(Some(int(6)))]);
+    }
+
+    #[test]
+    fn test_collapse_branch_on_constant_flag() {
+        // int flag = 0; int x; if (flag) x = 1; else x = 2; return x;
+        let symbols = ints(&["flag", "x"]);
+        let mut program = Program {
+            top_level: vec![TopLevel::Function(Function {
+                name: "main".to_owned(),
+                global: true,
+                params: vec![],
+                body: vec![
+                    Instruction::Copy {
+                        src: int(0),
+                        dst: var("flag"),
+                    },
+                    Instruction::JumpIfZero {
+                        condition: var("flag"),
+                        target: "else".to_owned(),
+                    },
+                    Instruction::Copy {
+                        src: int(1),
+                        dst: var("x"),
+                    },
+                    Instruction::Jump("end".to_owned()),
+                    Instruction::Label("else".to_owned()),
+                    Instruction::Copy {
+                        src: int(2),
+                        dst: var("x"),
+                    },
+                    Instruction::Label("end".to_owned()),
+                    Instruction::Return(Some(var("x"))),
+                ],
+                span: span(),
+            })],
+            symbols,
+        };
+        optimize(
+            &mut program,
+            &Optimizations::all(),
+            &mut Diagnostics::new(0),
+        );
+
+        let TopLevel::Function(function) = &program.top_level[0] else {
+            unreachable!()
+        };
+        assert_eq!(function.body, vec![Instruction::Return(Some(int(2)))]);
+    }
+
+    #[test]
+    fn test_disabled_passes_do_nothing() {
+        let body = vec![
+            Instruction::Binary {
+                op: BinaryOp::Add,
+                src1: int(1),
+                src2: int(1),
+                dst: var("x"),
+            },
+            Instruction::Return(Some(var("x"))),
+        ];
+        let mut program = Program {
+            top_level: vec![TopLevel::Function(Function {
+                name: "main".to_owned(),
+                global: true,
+                params: vec![],
+                body: body.clone(),
+                span: span(),
+            })],
+            symbols: SymbolTable::new(),
+        };
+        optimize(
+            &mut program,
+            &Optimizations::default(),
+            &mut Diagnostics::new(0),
+        );
+
+        let TopLevel::Function(function) = &program.top_level[0] else {
+            unreachable!()
+        };
+        assert_eq!(function.body, body);
+    }
+
+    #[test]
+    fn test_warnings_are_emitted_once() {
+        // int x = 1 / 0; return x;
+        let symbols = ints(&["x"]);
+        let mut program = Program {
+            top_level: vec![TopLevel::Function(Function {
+                name: "main".to_owned(),
+                global: true,
+                params: vec![],
+                body: vec![
+                    Instruction::Binary {
+                        op: BinaryOp::Divide,
+                        src1: int(1),
+                        src2: int(0),
+                        dst: var("x"),
+                    },
+                    Instruction::Return(Some(var("x"))),
+                ],
+                span: span(),
+            })],
+            symbols,
+        };
+        let mut diagnostics = Diagnostics::new(0);
+        optimize(&mut program, &Optimizations::all(), &mut diagnostics);
+
+        assert_eq!(
+            diagnostics.iter().collect::<Vec<_>>(),
+            vec![&Diagnostic::warning("division by zero in 'x = 1 / 0'").with_span(span())]
+        );
+        assert!(!diagnostics.has_errors());
+    }
+
+    #[test]
+    fn test_inline_then_clean_up() {
+        // static int inc(int a) { return a + 1; } int main(void) { return inc(2); }
+        let symbols = ints(&["a", "tmp.0", "tmp.1"]);
+        let mut program = Program {
+            top_level: vec![
+                TopLevel::Function(Function {
+                    name: "inc".to_owned(),
+                    global: false,
+                    params: vec!["a".to_owned()],
+                    body: vec![
+                        Instruction::Binary {
+                            op: BinaryOp::Add,
+                            src1: var("a"),
+                            src2: int(1),
+                            dst: var("tmp.0"),
+                        },
+                        Instruction::Return(Some(var("tmp.0"))),
+                    ],
+                    span: span(),
+                }),
+                TopLevel::Function(Function {
+                    name: "main".to_owned(),
+                    global: true,
+                    params: vec![],
+                    body: vec![
+                        Instruction::FunCall {
+                            name: "inc".to_owned(),
+                            args: vec![int(2)],
+                            dst: Some(var("tmp.1")),
+                        },
+                        Instruction::Return(Some(var("tmp.1"))),
+                    ],
+                    span: span(),
+                }),
+            ],
+            symbols,
+        };
+        optimize(
+            &mut program,
+            &Optimizations::all(),
+            &mut Diagnostics::new(0),
+        );
+
+        assert_eq!(program.top_level.len(), 1);
+        let TopLevel::Function(function) = &program.top_level[0] else {
+            unreachable!()
+        };
+        assert_eq!(function.body, vec![Instruction::Return(Some(int(3)))]);
+    }
+
+    #[test]
+    fn test_hoist_out_of_loop_then_clean_up() {
+        // int sum = 0; for (int i = 0; i < n; i = i + 1) sum = sum + a * 2; return sum;
+        let symbols = ints(&["a", "n", "i", "sum", "tmp.0", "tmp.1"]);
+        let mut program = Program {
+            top_level: vec![TopLevel::Function(Function {
+                name: "f".to_owned(),
+                global: true,
+                params: vec!["a".to_owned(), "n".to_owned()],
+                body: vec![
+                    Instruction::Copy {
+                        src: int(0),
+                        dst: var("sum"),
+                    },
+                    Instruction::Copy {
+                        src: int(0),
+                        dst: var("i"),
+                    },
+                    Instruction::Label("start".to_owned()),
+                    Instruction::Binary {
+                        op: BinaryOp::LessThan,
+                        src1: var("i"),
+                        src2: var("n"),
+                        dst: var("tmp.0"),
+                    },
+                    Instruction::JumpIfZero {
+                        condition: var("tmp.0"),
+                        target: "end".to_owned(),
+                    },
+                    Instruction::Binary {
+                        op: BinaryOp::Multiply,
+                        src1: var("a"),
+                        src2: int(2),
+                        dst: var("tmp.1"),
+                    },
+                    Instruction::Binary {
+                        op: BinaryOp::Add,
+                        src1: var("sum"),
+                        src2: var("tmp.1"),
+                        dst: var("sum"),
+                    },
+                    Instruction::Binary {
+                        op: BinaryOp::Add,
+                        src1: var("i"),
+                        src2: int(1),
+                        dst: var("i"),
+                    },
+                    Instruction::Jump("start".to_owned()),
+                    Instruction::Label("end".to_owned()),
+                    Instruction::Return(Some(var("sum"))),
+                ],
+                span: span(),
+            })],
+            symbols,
+        };
+        optimize(
+            &mut program,
+            &Optimizations::all(),
+            &mut Diagnostics::new(0),
+        );
+
+        let TopLevel::Function(function) = &program.top_level[0] else {
+            unreachable!()
+        };
+        let start = function
+            .body
+            .iter()
+            .position(|instruction| *instruction == Instruction::Label("start".to_owned()))
+            .unwrap();
+        assert!(function.body[..start].contains(&Instruction::Binary {
+            op: BinaryOp::Multiply,
+            src1: var("a"),
+            src2: int(2),
+            dst: var("tmp.1"),
+        }));
+    }
+
+    #[test]
+    fn test_fold_compiled_program() {
+        let mut program = c::tacky("int main(void) { int x = 10 / 0; return 6 * 7; }");
+        let mut diagnostics = Diagnostics::new(0);
+        optimize(
+            &mut program,
+            &Optimizations {
+                fold_constants: true,
+                ..Default::default()
+            },
+            &mut diagnostics,
+        );
+
+        assert_eq!(
+            program.to_string(),
+            "global function main() {\n\
+            \x20   tmp.0 = 10 / 0\n\
+            \x20   x.0 = tmp.0\n\
+            \x20   tmp.1 = 42\n\
+            \x20   return tmp.1\n\
+            \x20   return 0\n\
+             }\n"
+        );
+        let warnings: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(warnings, ["division by zero in 'tmp.0 = 10 / 0'"]);
+    }
+
+    #[test]
+    fn test_remove_unreachable_code_in_compiled_program() {
+        let mut program = c::tacky("int f(int a) { if (0) a = 5; return a; a = 7; }");
+        optimize(
+            &mut program,
+            &Optimizations {
+                fold_constants: true,
+                eliminate_unreachable_code: true,
+                ..Default::default()
+            },
+            &mut Diagnostics::new(0),
+        );
+
+        assert_eq!(
+            program.to_string(),
+            "global function f(a.0) {\n\
+            \x20   return a.0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_propagate_copies_in_compiled_program() {
+        let mut program = c::tacky("int f(int a) { int b = a; int c = b; return c * 2 + b; }");
+        optimize(
+            &mut program,
+            &Optimizations {
+                propagate_copies: true,
+                ..Default::default()
+            },
+            &mut Diagnostics::new(0),
+        );
+
+        // The copies themselves stay until dead store elimination removes them.
+        assert_eq!(
+            program.to_string(),
+            "global function f(a.0) {\n\
+            \x20   b.1 = a.0\n\
+            \x20   c.2 = a.0\n\
+            \x20   tmp.0 = a.0 * 2\n\
+            \x20   tmp.1 = tmp.0 + a.0\n\
+            \x20   return tmp.1\n\
+            \x20   return 0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_propagate_copies_shrinks_assembly() {
+        let code = "
+            int g(int x);
+            int f(int a) {
+                int enabled = 0;
+                int copy = enabled;
+                if (copy)
+                    a = g(a) + g(a + 1);
+                return a;
+            }
+        ";
+        let size = |optimizations: &Optimizations| -> usize {
+            c::optimized_assembly(code, optimizations)
+                .top_level
+                .iter()
+                .map(|top_level| match top_level {
+                    assembly::TopLevel::Function(function) => function.instructions.len(),
+                    _ => 0,
+                })
+                .sum()
+        };
+        let without = Optimizations {
+            fold_constants: true,
+            eliminate_unreachable_code: true,
+            ..Default::default()
+        };
+        let with = Optimizations {
+            propagate_copies: true,
+            ..without
+        };
+
+        // Propagating `0` into the condition lets folding remove the branch and both calls.
+        assert!(size(&with) < size(&without));
+    }
+
+    #[test]
+    fn test_eliminate_dead_stores_in_compiled_program() {
+        let mut program = c::tacky("int f(int a) { int b = a; int c = b; return c * 2 + b; }");
+        optimize(
+            &mut program,
+            &Optimizations {
+                propagate_copies: true,
+                eliminate_dead_stores: true,
+                ..Default::default()
+            },
+            &mut Diagnostics::new(0),
+        );
+
+        assert_eq!(
+            program.to_string(),
+            "global function f(a.0) {\n\
+            \x20   tmp.0 = a.0 * 2\n\
+            \x20   tmp.1 = tmp.0 + a.0\n\
+            \x20   return tmp.1\n\
+            \x20   return 0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_optimize_loops_in_compiled_program() {
+        let code = "
+            long sum(long *xs, long n) {
+                long total = 0;
+                for (long i = 0; i < n; i = i + 1)
+                    total = total + i * 12 + xs[i];
+                return total;
+            }
+            int main(void) {
+                long xs[5] = {1, 2, 3, 4, 5};
+                return sum(xs, 5) - 135;
+            }
+        ";
+        let optimizations = Optimizations {
+            fold_constants: true,
+            propagate_copies: true,
+            optimize_loops: true,
+            ..Default::default()
+        };
+        let mut program = c::tacky(code);
+        optimize(&mut program, &optimizations, &mut Diagnostics::new(0));
+
+        // `i` is incremented through a temporary, which still makes it an induction variable:
+        // `i * 12` becomes a running sum and `xs[i]` a pointer bumped each iteration.
+        let TopLevel::Function(sum) = &program.top_level[0] else {
+            panic!("expected a function.");
+        };
+        assert!(!sum.body.iter().any(|instruction| matches!(
+            instruction,
+            Instruction::Binary {
+                op: BinaryOp::Multiply,
+                ..
+            }
+        )));
+        assert_eq!(c::run_optimized(code, &optimizations), 0);
+    }
+
+    #[test]
+    fn test_propagate_constants_in_compiled_program() {
+        let code = "
+            int f(int flag) {
+                int x = 3;
+                int y;
+                if (flag)
+                    y = x + 1;
+                else
+                    y = x + 1;
+                return y * x;
+            }
+            int main(void) { return f(1) - 12; }
+        ";
+        let optimizations = Optimizations {
+            fold_constants: true,
+            propagate_constants: true,
+            ..Default::default()
+        };
+        let mut program = c::tacky(code);
+        optimize(&mut program, &optimizations, &mut Diagnostics::new(0));
+
+        // `y` is 4 on both paths, so the product is known where they join.
+        let TopLevel::Function(f) = &program.top_level[0] else {
+            panic!("expected a function.");
+        };
+        assert!(f.body.contains(&Instruction::Return(Some(int(12)))));
+        assert_eq!(c::run_optimized(code, &optimizations), 0);
+    }
+
+    #[test]
+    fn test_eliminate_common_subexpressions_in_compiled_program() {
+        let code = "
+            int f(int a, int b) {
+                int x = (a + b) * 2;
+                int y = (a + b) * 3;
+                return x + y;
+            }
+            int main(void) { return f(2, 3) - 25; }
+        ";
+        let optimizations = Optimizations {
+            eliminate_common_subexpressions: true,
+            propagate_copies: true,
+            eliminate_dead_stores: true,
+            ..Default::default()
+        };
+        let mut program = c::tacky(code);
+        optimize(&mut program, &optimizations, &mut Diagnostics::new(0));
+
+        // `a + b` is computed once; the second add is `x + y`.
+        let TopLevel::Function(f) = &program.top_level[0] else {
+            panic!("expected a function.");
+        };
+        let adds = f
+            .body
+            .iter()
+            .filter(|instruction| {
+                matches!(
+                    instruction,
+                    Instruction::Binary {
+                        op: BinaryOp::Add,
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(adds, 2);
+        assert_eq!(c::run_optimized(code, &optimizations), 0);
+    }
+
+    #[test]
+    fn test_fully_optimized_program_runs() {
+        let code = "
+            static int square(int x) { return x * x; }
+            int sum_squares(int *xs, int n) {
+                int total = 0;
+                for (int i = 0; i < n; i = i + 1)
+                    total = total + square(xs[i]);
+                return total;
+            }
+            int main(void) {
+                int xs[10];
+                int debug = 0;
+                for (int i = 0; i < 10; i = i + 1)
+                    xs[i] = i;
+                if (debug)
+                    return 1;
+                int *p = &xs[3];
+                *p = 30;
+                return sum_squares(xs, 10) - 1176;
+            }
+        ";
+        assert_eq!(c::run(code), 0);
+        assert_eq!(c::run_optimized(code, &Optimizations::all()), 0);
+    }
+
+    #[test]
+    fn test_inline_functions_in_compiled_program() {
+        let code = "
+            static int get(int *p) { return *p; }
+            int twice(int x) { return x * 2; }
+            int main(void) { int x = 20; return get(&x) + twice(x) - 60; }
+        ";
+        let optimizations = Optimizations {
+            inline_functions: true,
+            ..Default::default()
+        };
+        let mut program = c::tacky(code);
+        optimize(&mut program, &optimizations, &mut Diagnostics::new(0));
+
+        // `get` has internal linkage, so it's inlined and dropped; `twice` may be called from
+        // other files, so it stays.
+        let functions: Vec<&str> = program
+            .top_level
+            .iter()
+            .filter_map(|top_level| match top_level {
+                TopLevel::Function(function) => Some(function.name.as_str()),
+                TopLevel::StaticVariable(_) => None,
+            })
+            .collect();
+        assert_eq!(functions, ["twice", "main"]);
+        assert_eq!(c::run_optimized(code, &optimizations), 0);
+    }
+}
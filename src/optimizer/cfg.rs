@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{assembly, tacky};
+
+/// What an instruction does to control flow, which is all a `Cfg` needs to know about it.
+#[derive(Debug, PartialEq)]
+pub enum Flow<'a> {
+    Label(&'a str),
+    Jump(&'a str),
+    ConditionalJump(&'a str),
+    Return,
+    /// Falls through to the next instruction.
+    Next,
+}
+
+/// An instruction a `Cfg` can be built from.
+pub trait CfgInstruction {
+    fn flow(&self) -> Flow<'_>;
+}
+
+impl CfgInstruction for tacky::Instruction {
+    fn flow(&self) -> Flow<'_> {
+        match self {
+            tacky::Instruction::Label(label) => Flow::Label(label),
+            tacky::Instruction::Jump(target) => Flow::Jump(target),
+            tacky::Instruction::JumpIfZero { target, .. }
+            | tacky::Instruction::JumpIfNotZero { target, .. } => Flow::ConditionalJump(target),
+            tacky::Instruction::Return(_) => Flow::Return,
+            _ => Flow::Next,
+        }
+    }
+}
+
+impl CfgInstruction for assembly::Instruction {
+    fn flow(&self) -> Flow<'_> {
+        match self {
+            assembly::Instruction::Label(label) => Flow::Label(label),
+            assembly::Instruction::Jmp(target) => Flow::Jump(target),
+            assembly::Instruction::JmpCC(_, target) => Flow::ConditionalJump(target),
+            assembly::Instruction::Ret | assembly::Instruction::TailCall(_) => Flow::Return,
+            _ => Flow::Next,
+        }
+    }
+}
+
+/// A node of the control-flow graph. `Entry` and `Exit` hold no instructions.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum NodeId {
+    Entry,
+    Block(usize),
+    Exit,
+}
+
+/// The control-flow graph of a function body, made of basic blocks.
+///
+/// A basic block starts at a label or right after a jump or return, and ends at the next jump,
+/// return or label, so control only enters at its first instruction and leaves after its last.
+/// Blocks keep their original program order, which is the order `into_instructions` lays them
+/// back out in, so fallthrough edges stay valid as long as blocks aren't reordered.
+#[derive(Debug)]
+pub struct Cfg<I> {
+    // Indexed by block id; removed blocks leave a `None` so ids stay stable.
+    blocks: Vec<Option<Vec<I>>>,
+    order: Vec<usize>,
+    successors: HashMap<NodeId, Vec<NodeId>>,
+    predecessors: HashMap<NodeId, Vec<NodeId>>,
+}
+
+impl<I: CfgInstruction> Cfg<I> {
+    /// Partitions `instructions` into basic blocks and connects them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an instruction jumps to a label that isn't defined in `instructions`.
+    pub fn new(instructions: Vec<I>) -> Self {
+        let mut blocks = Vec::new();
+        let mut current = Vec::new();
+
+        for instruction in instructions {
+            match instruction.flow() {
+                Flow::Label(_) => {
+                    if !current.is_empty() {
+                        blocks.push(Some(std::mem::take(&mut current)));
+                    }
+                    current.push(instruction);
+                }
+                Flow::Jump(_) | Flow::ConditionalJump(_) | Flow::Return => {
+                    current.push(instruction);
+                    blocks.push(Some(std::mem::take(&mut current)));
+                }
+                Flow::Next => current.push(instruction),
+            }
+        }
+        if !current.is_empty() {
+            blocks.push(Some(current));
+        }
+
+        let mut cfg = Self {
+            order: (0..blocks.len()).collect(),
+            blocks,
+            successors: HashMap::new(),
+            predecessors: HashMap::new(),
+        };
+        cfg.connect();
+        cfg
+    }
+
+    fn connect(&mut self) {
+        let labels: HashMap<String, usize> = self
+            .order
+            .iter()
+            .filter_map(|id| match self.instructions(*id).first().map(I::flow) {
+                Some(Flow::Label(label)) => Some((label.to_owned(), *id)),
+                _ => None,
+            })
+            .collect();
+        let target = |label: &str| match labels.get(label) {
+            Some(id) => NodeId::Block(*id),
+            None => panic!("jump to undefined label '{label}'."),
+        };
+
+        let first = self
+            .order
+            .first()
+            .map_or(NodeId::Exit, |id| NodeId::Block(*id));
+        self.add_edge(NodeId::Entry, first);
+
+        for (i, id) in self.order.clone().into_iter().enumerate() {
+            let node = NodeId::Block(id);
+            let next = self
+                .order
+                .get(i + 1)
+                .map_or(NodeId::Exit, |id| NodeId::Block(*id));
+
+            match self.instructions(id).last().map(I::flow) {
+                Some(Flow::Return) => self.add_edge(node, NodeId::Exit),
+                Some(Flow::Jump(label)) => {
+                    let target = target(label);
+                    self.add_edge(node, target);
+                }
+                Some(Flow::ConditionalJump(label)) => {
+                    let target = target(label);
+                    self.add_edge(node, target);
+                    self.add_edge(node, next);
+                }
+                _ => self.add_edge(node, next),
+            }
+        }
+    }
+
+    /// Ids of the blocks still in the graph, in program order.
+    pub fn block_ids(&self) -> &[usize] {
+        &self.order
+    }
+
+    pub fn instructions(&self, id: usize) -> &[I] {
+        self.blocks[id].as_ref().unwrap()
+    }
+
+    pub fn instructions_mut(&mut self, id: usize) -> &mut Vec<I> {
+        self.blocks[id].as_mut().unwrap()
+    }
+
+    pub fn successors(&self, node: NodeId) -> &[NodeId] {
+        self.successors.get(&node).map_or(&[], |nodes| nodes)
+    }
+
+    pub fn predecessors(&self, node: NodeId) -> &[NodeId] {
+        self.predecessors.get(&node).map_or(&[], |nodes| nodes)
+    }
+
+    pub fn add_edge(&mut self, from: NodeId, to: NodeId) {
+        let successors = self.successors.entry(from).or_default();
+        if !successors.contains(&to) {
+            successors.push(to);
+            self.predecessors.entry(to).or_default().push(from);
+        }
+    }
+
+    pub fn remove_edge(&mut self, from: NodeId, to: NodeId) {
+        if let Some(successors) = self.successors.get_mut(&from) {
+            successors.retain(|node| *node != to);
+        }
+        if let Some(predecessors) = self.predecessors.get_mut(&to) {
+            predecessors.retain(|node| *node != from);
+        }
+    }
+
+    /// Removes a block along with every edge into or out of it.
+    pub fn remove_block(&mut self, id: usize) {
+        let node = NodeId::Block(id);
+        for successor in self.successors(node).to_vec() {
+            self.remove_edge(node, successor);
+        }
+        for predecessor in self.predecessors(node).to_vec() {
+            self.remove_edge(predecessor, node);
+        }
+        self.successors.remove(&node);
+        self.predecessors.remove(&node);
+
+        self.blocks[id] = None;
+        self.order.retain(|block| *block != id);
+    }
+
+    /// Returns every node reachable from `Entry`, in reverse postorder.
+    ///
+    /// Reverse postorder visits a node before its successors (ignoring back edges), which makes
+    /// it the fastest order for forward dataflow analyses to converge in.
+    pub fn reverse_postorder(&self) -> Vec<NodeId> {
+        let mut visited = HashSet::from([NodeId::Entry]);
+        let mut postorder = Vec::new();
+        // Each frame holds a node and the index of its next successor to visit.
+        let mut stack = vec![(NodeId::Entry, 0)];
+
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            match self.successors(node).get(*next) {
+                Some(successor) => {
+                    *next += 1;
+                    if visited.insert(*successor) {
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => {
+                    postorder.push(node);
+                    stack.pop();
+                }
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+
+    /// Lays the blocks back out in program order.
+    pub fn into_instructions(self) -> Vec<I> {
+        let mut blocks = self.blocks;
+        self.order
+            .iter()
+            .flat_map(|id| blocks[*id].take().unwrap())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::Instruction,
+        test_support::tacky::{int, jump, jump_if_zero, label, ret},
+    };
+
+    #[test]
+    fn test_empty_body() {
+        let cfg = Cfg::<Instruction>::new(vec![]);
+
+        assert!(cfg.block_ids().is_empty());
+        assert_eq!(cfg.successors(NodeId::Entry), &[NodeId::Exit]);
+    }
+
+    #[test]
+    fn test_partition_into_blocks() {
+        let body = vec![
+            jump_if_zero("c", "else"),
+            ret(int(0)),
+            label("else"),
+            jump("end"),
+            label("end"),
+            ret(int(0)),
+        ];
+        let cfg = Cfg::new(body.clone());
+
+        assert_eq!(cfg.block_ids(), &[0, 1, 2, 3]);
+        assert_eq!(cfg.instructions(0), &[jump_if_zero("c", "else")]);
+        assert_eq!(cfg.instructions(1), &[ret(int(0))]);
+        assert_eq!(cfg.instructions(2), &[label("else"), jump("end")]);
+        assert_eq!(cfg.instructions(3), &[label("end"), ret(int(0))]);
+        assert_eq!(cfg.into_instructions(), body);
+    }
+
+    #[test]
+    fn test_edges() {
+        let cfg = Cfg::new(vec![
+            jump_if_zero("c", "else"),
+            ret(int(0)),
+            label("else"),
+            jump("end"),
+            label("end"),
+        ]);
+
+        assert_eq!(cfg.successors(NodeId::Entry), &[NodeId::Block(0)]);
+        assert_eq!(
+            cfg.successors(NodeId::Block(0)),
+            &[NodeId::Block(2), NodeId::Block(1)]
+        );
+        assert_eq!(cfg.successors(NodeId::Block(1)), &[NodeId::Exit]);
+        assert_eq!(cfg.successors(NodeId::Block(2)), &[NodeId::Block(3)]);
+        assert_eq!(cfg.successors(NodeId::Block(3)), &[NodeId::Exit]);
+        assert_eq!(
+            cfg.predecessors(NodeId::Exit),
+            &[NodeId::Block(1), NodeId::Block(3)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "jump to undefined label 'nowhere'.")]
+    fn test_jump_to_undefined_label() {
+        Cfg::new(vec![jump("nowhere")]);
+    }
+
+    #[test]
+    fn test_remove_block() {
+        let mut cfg = Cfg::new(vec![jump("end"), label("dead"), label("end"), ret(int(0))]);
+        cfg.remove_block(1);
+
+        assert_eq!(cfg.block_ids(), &[0, 2]);
+        assert_eq!(cfg.predecessors(NodeId::Block(2)), &[NodeId::Block(0)]);
+        assert_eq!(
+            cfg.into_instructions(),
+            vec![jump("end"), label("end"), ret(int(0))]
+        );
+    }
+
+    #[test]
+    fn test_reverse_postorder() {
+        let cfg = Cfg::new(vec![
+            label("loop"),
+            jump_if_zero("c", "end"),
+            jump("loop"),
+            label("dead"),
+            label("end"),
+            ret(int(0)),
+        ]);
+
+        assert_eq!(
+            cfg.reverse_postorder(),
+            vec![
+                NodeId::Entry,
+                NodeId::Block(0),
+                NodeId::Block(1),
+                NodeId::Block(3),
+                NodeId::Exit,
+            ]
+        );
+    }
+}
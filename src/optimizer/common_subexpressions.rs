@@ -0,0 +1,511 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{
+    cfg::NodeId,
+    dominators::Dominators,
+    ssa::{self, Phi, Ssa},
+};
+use crate::tacky::{BinaryOp, Function, Instruction, SymbolTable, Type, Val};
+
+/// A computation, identified by its operation and the value numbers of its operands.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct Expression {
+    /// The instruction with its destination blanked out and each operand replaced by the
+    /// value it's known to equal.
+    instruction: Instruction,
+    ty: Type,
+    /// For expressions reading memory, which of the stretches between stores and calls they
+    /// were computed in. Always `None` for expressions that only read SSA values.
+    epoch: Option<usize>,
+}
+
+/// What holds an expression's value at some point.
+#[derive(Debug, Clone)]
+enum Holder {
+    Value(Val),
+    /// Different variables on each incoming edge of a join, which a phi would merge. The phi
+    /// is only made once something reads it.
+    Join(usize, Vec<(NodeId, Val)>),
+}
+
+/// Global value numbering: replaces computations of a value some earlier instruction already
+/// computed with a copy of that result.
+///
+/// Works on SSA form, walking down the dominator tree, so the earlier computation may be in any
+/// dominating block. A value computed on every path into a join, in a different variable on
+/// each, is reused too, through a new phi. Expressions whose operands are all SSA values never
+/// change, but those reading aliased variables are only reused within a block, and not across
+/// a store, call or assignment to an aliased variable. Loads, calls and anything reading a
+/// volatile variable are never reused.
+pub fn eliminate_common_subexpressions(function: &mut Function, symbols: &mut SymbolTable) {
+    let tracked = ssa::tracked_variables(function, symbols);
+    let mut ssa = Ssa::new(function, symbols);
+    let dominators = Dominators::new(&ssa.cfg);
+
+    let mut numbering = Numbering {
+        tracked,
+        leaders: HashMap::new(),
+        table: HashMap::new(),
+        exits: HashMap::new(),
+        epoch: 0,
+        changed: false,
+    };
+    numbering.run(&mut ssa, &dominators, symbols);
+
+    // Converting back out of SSA form would only rename variables if nothing was replaced.
+    if numbering.changed {
+        function.body = ssa.into_instructions(symbols);
+    }
+}
+
+struct Numbering<'a> {
+    /// The original names of the variables SSA form renames. Where one is still read, it's
+    /// the variable's value on entry, which never changes either.
+    tracked: BTreeSet<&'a str>,
+    /// The value each SSA value is known to equal, if it's not itself.
+    leaders: HashMap<String, Val>,
+    /// The expressions available at the block being numbered.
+    table: HashMap<Expression, Holder>,
+    /// The expressions only reading SSA values available at the end of each block that
+    /// leads to a join.
+    exits: HashMap<usize, HashMap<Expression, Val>>,
+    epoch: usize,
+    changed: bool,
+}
+
+impl Numbering<'_> {
+    fn run(&mut self, ssa: &mut Ssa, dominators: &Dominators, symbols: &mut SymbolTable) {
+        enum Step {
+            Enter(NodeId),
+            // Removes the expressions added to the table on entering a node.
+            Leave(usize, Vec<Expression>),
+        }
+
+        let mut steps = vec![Step::Enter(NodeId::Entry)];
+        while let Some(step) = steps.pop() {
+            let id = match step {
+                Step::Enter(NodeId::Block(id)) => id,
+                Step::Enter(node) => {
+                    for child in dominators.children(node).iter().rev() {
+                        steps.push(Step::Enter(*child));
+                    }
+                    continue;
+                }
+                Step::Leave(id, added) => {
+                    let leads_to_join = ssa
+                        .cfg
+                        .successors(NodeId::Block(id))
+                        .iter()
+                        .any(|successor| ssa.cfg.predecessors(*successor).len() > 1);
+                    if leads_to_join {
+                        let exit = self
+                            .table
+                            .iter()
+                            .filter_map(|(expression, holder)| match holder {
+                                Holder::Value(val) if expression.epoch.is_none() => {
+                                    Some((expression.clone(), val.clone()))
+                                }
+                                _ => None,
+                            })
+                            .collect();
+                        self.exits.insert(id, exit);
+                    }
+                    for expression in added {
+                        self.table.remove(&expression);
+                    }
+                    continue;
+                }
+            };
+
+            self.epoch += 1;
+            let mut added = self.join(ssa, id);
+            self.number_phis(ssa, id);
+
+            for i in 0..ssa.cfg.instructions(id).len() {
+                let instruction = &ssa.cfg.instructions(id)[i];
+                let Some(expression) = self.expression(instruction, ssa, symbols) else {
+                    self.note_effects(instruction, ssa, symbols);
+                    continue;
+                };
+                let Some(Val::Var(dst)) = instruction.destination().cloned() else {
+                    unreachable!()
+                };
+
+                match self.table.get(&expression).cloned() {
+                    Some(holder) => {
+                        let src = self.materialize(&expression, holder, ssa, symbols);
+                        self.leaders.insert(dst.clone(), self.leader(&src));
+                        ssa.cfg.instructions_mut(id)[i] = Instruction::Copy {
+                            src,
+                            dst: Val::Var(dst),
+                        };
+                        self.changed = true;
+                    }
+                    None => {
+                        self.table
+                            .insert(expression.clone(), Holder::Value(Val::Var(dst)));
+                        added.push(expression);
+                    }
+                }
+            }
+
+            steps.push(Step::Leave(id, added));
+            for child in dominators.children(NodeId::Block(id)).iter().rev() {
+                steps.push(Step::Enter(*child));
+            }
+        }
+    }
+
+    /// Makes the expressions computed on every path into block `id`, but held in different
+    /// variables, available in it. Returns the expressions added to the table.
+    fn join(&mut self, ssa: &Ssa, id: usize) -> Vec<Expression> {
+        let predecessors = ssa.cfg.predecessors(NodeId::Block(id));
+        let mut exits = Vec::new();
+        for predecessor in predecessors {
+            match predecessor {
+                // Every predecessor must have been numbered already, so back edges rule a
+                // join out.
+                NodeId::Block(predecessor) if self.exits.contains_key(predecessor) => {
+                    exits.push((*predecessor, &self.exits[predecessor]));
+                }
+                _ => return vec![],
+            }
+        }
+        if exits.len() < 2 {
+            return vec![];
+        }
+
+        let mut joined = Vec::new();
+        for (expression, val) in exits[0].1 {
+            if self.table.contains_key(expression) {
+                continue;
+            }
+            let args: Option<Vec<(NodeId, Val)>> = exits
+                .iter()
+                .map(|(predecessor, exit)| {
+                    let val = exit.get(expression)?;
+                    Some((NodeId::Block(*predecessor), val.clone()))
+                })
+                .collect();
+            if let Some(args) = args {
+                if args.iter().any(|(_, arg)| arg != val) {
+                    joined.push((expression.clone(), Holder::Join(id, args)));
+                }
+            }
+        }
+
+        let mut added = Vec::new();
+        for (expression, holder) in joined {
+            self.table.insert(expression.clone(), holder);
+            added.push(expression);
+        }
+        added
+    }
+
+    /// Gives each phi of block `id` the value of its arguments, when they all agree.
+    fn number_phis(&mut self, ssa: &Ssa, id: usize) {
+        for phi in ssa.phis.get(&id).into_iter().flatten() {
+            let mut args = phi
+                .args
+                .iter()
+                .map(|(_, arg)| self.leader(arg))
+                .filter(|arg| *arg != Val::Var(phi.dst.clone()));
+            if let Some(first) = args.next() {
+                if args.all(|arg| arg == first) {
+                    self.leaders.insert(phi.dst.clone(), first);
+                }
+            }
+        }
+    }
+
+    /// Returns the value held by `holder`, making the phi for a join first if needed.
+    fn materialize(
+        &mut self,
+        expression: &Expression,
+        holder: Holder,
+        ssa: &mut Ssa,
+        symbols: &mut SymbolTable,
+    ) -> Val {
+        match holder {
+            Holder::Value(val) => val,
+            Holder::Join(id, args) => {
+                let Val::Var(name) = &args[0].1 else {
+                    unreachable!()
+                };
+                let var = ssa.original(name).to_owned();
+                let dst = ssa.make_version(&var, symbols);
+                ssa.phis.entry(id).or_default().push(Phi {
+                    var: dst.clone(),
+                    dst: dst.clone(),
+                    args,
+                });
+
+                let val = Val::Var(dst);
+                self.table
+                    .insert(expression.clone(), Holder::Value(val.clone()));
+                val
+            }
+        }
+    }
+
+    /// Returns the expression `instruction` computes, or `None` if it isn't one that can be
+    /// reused.
+    fn expression(
+        &self,
+        instruction: &Instruction,
+        ssa: &Ssa,
+        symbols: &SymbolTable,
+    ) -> Option<Expression> {
+        match instruction {
+            Instruction::Unary { .. }
+            | Instruction::Binary { .. }
+            | Instruction::SignExtend { .. }
+            | Instruction::Truncate { .. }
+            | Instruction::ZeroExtend { .. }
+            | Instruction::DoubleToInt { .. }
+            | Instruction::DoubleToUInt { .. }
+            | Instruction::IntToDouble { .. }
+            | Instruction::UIntToDouble { .. }
+            | Instruction::GetAddress { .. }
+            | Instruction::AddPtr { .. }
+            | Instruction::CopyFromOffset { .. } => (),
+            _ => return None,
+        }
+        let Some(Val::Var(dst)) = instruction.destination() else {
+            return None;
+        };
+        if !ssa.versions.contains_key(dst) {
+            return None;
+        }
+        let ty = symbols.get(ssa.original(dst))?.ty.clone();
+
+        let mut reads_memory = matches!(instruction, Instruction::CopyFromOffset { .. });
+        for val in instruction.sources() {
+            if let Val::Var(name) = val {
+                if symbols.is_volatile(ssa.original(name)) {
+                    return None;
+                }
+                reads_memory |= !self.is_value(name, ssa);
+            }
+        }
+
+        let mut instruction = instruction.clone();
+        *instruction.destination_mut().unwrap() = Val::Var(String::new());
+        for val in instruction.sources_mut() {
+            *val = self.leader(val);
+        }
+        if let Instruction::Binary { op, src1, src2, .. } = &mut instruction {
+            let commutative = matches!(
+                op,
+                BinaryOp::Add
+                    | BinaryOp::Multiply
+                    | BinaryOp::BitAnd
+                    | BinaryOp::BitOr
+                    | BinaryOp::BitXor
+                    | BinaryOp::Equal
+                    | BinaryOp::NotEqual
+            );
+            if commutative && order(src2) < order(src1) {
+                std::mem::swap(src1, src2);
+            }
+        }
+
+        Some(Expression {
+            instruction,
+            ty,
+            epoch: reads_memory.then_some(self.epoch),
+        })
+    }
+
+    /// Records what an instruction that isn't numbered does to the value of copies and memory.
+    fn note_effects(&mut self, instruction: &Instruction, ssa: &Ssa, symbols: &SymbolTable) {
+        match instruction {
+            Instruction::Copy {
+                src,
+                dst: Val::Var(dst),
+            } if ssa.versions.contains_key(dst) => {
+                let src_type = match src {
+                    Val::Constant(c) => Some(c.ty()),
+                    Val::Var(name) if self.is_value(name, ssa) => symbols
+                        .get(ssa.original(name))
+                        .map(|symbol| symbol.ty.clone()),
+                    Val::Var(_) => None,
+                };
+                // A copy between types of the same size but different signedness is a
+                // conversion, so only copies within a type make two values equal.
+                let dst_type = symbols.get(ssa.original(dst)).map(|symbol| &symbol.ty);
+                if src_type.is_some() && src_type.as_ref() == dst_type {
+                    self.leaders.insert(dst.clone(), self.leader(src));
+                }
+            }
+            Instruction::Store { .. }
+            | Instruction::FunCall { .. }
+            | Instruction::CopyToOffset { .. } => self.epoch += 1,
+            instruction => {
+                if let Some(Val::Var(dst)) = instruction.destination() {
+                    if !ssa.versions.contains_key(dst) {
+                        self.epoch += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `name` is an SSA value, which never changes once assigned.
+    fn is_value(&self, name: &str, ssa: &Ssa) -> bool {
+        ssa.versions.contains_key(name) || self.tracked.contains(name)
+    }
+
+    fn leader(&self, val: &Val) -> Val {
+        match val {
+            Val::Var(name) => self.leaders.get(name).cloned().unwrap_or(val.clone()),
+            Val::Constant(_) => val.clone(),
+        }
+    }
+}
+
+/// A canonical order for the operands of commutative operations: variables by name, then
+/// constants.
+fn order(val: &Val) -> (bool, String) {
+    match val {
+        Val::Var(name) => (false, name.clone()),
+        Val::Constant(c) => (true, c.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::Storage,
+        test_support::tacky::{binary, function, int, ints, label, symbol, var},
+    };
+
+    fn count_binary(body: &[Instruction]) -> usize {
+        body.iter()
+            .filter(|instruction| matches!(instruction, Instruction::Binary { .. }))
+            .count()
+    }
+
+    #[test]
+    fn test_reuse_in_dominated_block() {
+        let mut f = function(
+            &["a", "b", "c"],
+            vec![
+                binary(BinaryOp::Add, var("a"), var("b"), "x"),
+                Instruction::JumpIfZero {
+                    condition: var("c"),
+                    target: "end".to_owned(),
+                },
+                binary(BinaryOp::Add, var("b"), var("a"), "y"),
+                Instruction::Return(Some(var("y"))),
+                label("end"),
+                Instruction::Return(Some(var("x"))),
+            ],
+        );
+        let mut symbols = ints(&["a", "b", "c", "x", "y"]);
+        eliminate_common_subexpressions(&mut f, &mut symbols);
+
+        assert_eq!(count_binary(&f.body), 1);
+        assert_eq!(
+            f.body[2],
+            Instruction::Copy {
+                src: var("x.0"),
+                dst: var("y.1"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_reuse_value_computed_in_both_arms() {
+        // if (c) x = a * b; else y = a * b; return a * b;
+        let mut f = function(
+            &["a", "b", "c"],
+            vec![
+                Instruction::JumpIfZero {
+                    condition: var("c"),
+                    target: "else".to_owned(),
+                },
+                binary(BinaryOp::Multiply, var("a"), var("b"), "x"),
+                Instruction::Jump("end".to_owned()),
+                label("else"),
+                binary(BinaryOp::Multiply, var("a"), var("b"), "y"),
+                label("end"),
+                binary(BinaryOp::Multiply, var("a"), var("b"), "z"),
+                Instruction::Return(Some(var("z"))),
+            ],
+        );
+        let mut symbols = ints(&["a", "b", "c", "x", "y", "z"]);
+        eliminate_common_subexpressions(&mut f, &mut symbols);
+
+        assert_eq!(count_binary(&f.body), 2);
+        let end = f
+            .body
+            .iter()
+            .position(|instruction| *instruction == label("end"))
+            .unwrap();
+        assert!(matches!(
+            &f.body[end + 1],
+            Instruction::Copy {
+                src: Val::Var(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_store_and_call_end_reuse_of_aliased_reads() {
+        let body = vec![
+            binary(BinaryOp::Add, var("g"), int(1), "x"),
+            Instruction::Store {
+                src: int(0),
+                dst_ptr: var("p"),
+            },
+            binary(BinaryOp::Add, var("g"), int(1), "y"),
+            Instruction::FunCall {
+                name: "h".to_owned(),
+                args: vec![],
+                dst: None,
+            },
+            binary(BinaryOp::Add, var("g"), int(1), "z"),
+            binary(BinaryOp::Add, var("g"), int(1), "w"),
+            Instruction::Return(Some(var("w"))),
+        ];
+        let mut f = function(&["p"], body);
+        let mut symbols = ints(&["p", "x", "y", "z", "w"]);
+        symbols.insert("g", symbol(Type::Int, Storage::Static, false));
+        eliminate_common_subexpressions(&mut f, &mut symbols);
+
+        assert_eq!(count_binary(&f.body), 3);
+        assert_eq!(
+            f.body[5],
+            Instruction::Copy {
+                src: var("z.2"),
+                dst: var("w.3"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_loads_and_volatile_reads_are_not_reused() {
+        let body = vec![
+            Instruction::Load {
+                src_ptr: var("p"),
+                dst: var("x"),
+            },
+            Instruction::Load {
+                src_ptr: var("p"),
+                dst: var("y"),
+            },
+            binary(BinaryOp::Add, var("v"), int(1), "z"),
+            binary(BinaryOp::Add, var("v"), int(1), "w"),
+            Instruction::Return(Some(var("w"))),
+        ];
+        let mut f = function(&["p"], body.clone());
+        let mut symbols = ints(&["p", "x", "y", "z", "w"]);
+        symbols.insert("v", symbol(Type::Int, Storage::Local, true));
+        eliminate_common_subexpressions(&mut f, &mut symbols);
+
+        assert_eq!(f.body, body);
+    }
+}
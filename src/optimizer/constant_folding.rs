@@ -0,0 +1,648 @@
+use crate::{
+    diagnostics::Diagnostic,
+    tacky::{BinaryOp, Const, Function, Instruction, SymbolTable, Type, UnaryOp, Val},
+};
+
+/// Why an operation on constant operands was left for run time.
+#[derive(Debug, PartialEq)]
+pub enum NotFolded {
+    /// The operands have types this operation is never applied to after type checking.
+    Unsupported,
+    /// Evaluating the operation is undefined behavior, so its result must not be invented.
+    Undefined(&'static str),
+}
+
+/// Evaluates every instruction whose operands are all constants, and resolves conditional jumps
+/// on constant conditions.
+///
+/// Operations whose result is undefined behavior (division by zero, signed overflow, out of
+/// range shifts or conversions) are left in place and reported as warnings at the function,
+/// one per instruction, which are returned.
+pub fn fold_constants(function: &mut Function, symbols: &SymbolTable) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    let mut body = Vec::with_capacity(function.body.len());
+
+    for instruction in function.body.drain(..) {
+        match fold_instruction(&instruction, symbols) {
+            Ok(Some(folded)) => body.push(folded),
+            Ok(None) => (),
+            Err(NotFolded::Undefined(reason)) => {
+                warnings.push(
+                    Diagnostic::warning(format!("{reason} in '{instruction}'"))
+                        .with_span(function.span),
+                );
+                body.push(instruction);
+            }
+            Err(NotFolded::Unsupported) => body.push(instruction),
+        }
+    }
+
+    function.body = body;
+    warnings
+}
+
+/// Returns the instruction that replaces `instruction`, `None` if it should be removed, or why
+/// it can't be folded. Instructions with non-constant operands are returned unchanged.
+fn fold_instruction(
+    instruction: &Instruction,
+    symbols: &SymbolTable,
+) -> Result<Option<Instruction>, NotFolded> {
+    let folded = match instruction {
+        Instruction::Unary {
+            op,
+            src: Val::Constant(c),
+            dst,
+        } => copy(eval_unary(*op, *c)?, dst),
+        Instruction::Binary {
+            op,
+            src1: Val::Constant(a),
+            src2: Val::Constant(b),
+            dst,
+        } => copy(eval_binary(*op, *a, *b)?, dst),
+        Instruction::SignExtend {
+            src: Val::Constant(c),
+            dst,
+        }
+        | Instruction::Truncate {
+            src: Val::Constant(c),
+            dst,
+        }
+        | Instruction::ZeroExtend {
+            src: Val::Constant(c),
+            dst,
+        }
+        | Instruction::DoubleToInt {
+            src: Val::Constant(c),
+            dst,
+        }
+        | Instruction::DoubleToUInt {
+            src: Val::Constant(c),
+            dst,
+        }
+        | Instruction::IntToDouble {
+            src: Val::Constant(c),
+            dst,
+        }
+        | Instruction::UIntToDouble {
+            src: Val::Constant(c),
+            dst,
+        } => {
+            let ty = match dst {
+                Val::Var(name) => match symbols.get(name) {
+                    Some(symbol) => &symbol.ty,
+                    None => return Err(NotFolded::Unsupported),
+                },
+                Val::Constant(_) => return Err(NotFolded::Unsupported),
+            };
+            copy(convert(*c, ty)?, dst)
+        }
+        Instruction::JumpIfZero {
+            condition: Val::Constant(c),
+            target,
+        } => {
+            if is_zero(*c) {
+                Instruction::Jump(target.clone())
+            } else {
+                return Ok(None);
+            }
+        }
+        Instruction::JumpIfNotZero {
+            condition: Val::Constant(c),
+            target,
+        } => {
+            if is_zero(*c) {
+                return Ok(None);
+            } else {
+                Instruction::Jump(target.clone())
+            }
+        }
+        _ => instruction.clone(),
+    };
+
+    Ok(Some(folded))
+}
+
+fn copy(c: Const, dst: &Val) -> Instruction {
+    Instruction::Copy {
+        src: Val::Constant(c),
+        dst: dst.clone(),
+    }
+}
+
+pub fn is_zero(c: Const) -> bool {
+    match c {
+        Const::Double(d) => d == 0.0,
+        c => integer(c).unwrap().1 == 0,
+    }
+}
+
+/// Width and signedness of an integer constant's type.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct IntKind {
+    signed: bool,
+    bits: u32,
+}
+
+impl IntKind {
+    fn of(ty: &Type) -> Option<Self> {
+        let (signed, bits) = match ty {
+            Type::Char | Type::SChar => (true, 8),
+            Type::UChar => (false, 8),
+            Type::Int => (true, 32),
+            Type::UInt => (false, 32),
+            Type::Long => (true, 64),
+            Type::ULong | Type::Pointer(_) => (false, 64),
+            _ => return None,
+        };
+        Some(Self { signed, bits })
+    }
+
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// Builds the constant with exactly this value, which must be in range.
+    fn make(self, value: i128) -> Const {
+        debug_assert!(self.min() <= value && value <= self.max());
+        match (self.signed, self.bits) {
+            (true, 8) => Const::Char(value as i8),
+            (false, 8) => Const::UChar(value as u8),
+            (true, 32) => Const::Int(value as i32),
+            (false, 32) => Const::UInt(value as u32),
+            (true, 64) => Const::Long(value as i64),
+            _ => Const::ULong(value as u64),
+        }
+    }
+
+    /// Builds the constant with `value` reduced modulo 2^bits, which is exact C semantics for
+    /// unsigned types and the two's complement behavior GCC documents for signed conversions.
+    fn wrap(self, value: i128) -> Const {
+        let modulus = 1_i128 << self.bits;
+        let mut value = value.rem_euclid(modulus);
+        if self.signed && value > self.max() {
+            value -= modulus;
+        }
+        self.make(value)
+    }
+
+    /// Builds the result of an arithmetic operation, which wraps for unsigned types and is
+    /// undefined when it overflows a signed type.
+    fn arithmetic(self, value: i128) -> Result<Const, NotFolded> {
+        if !self.signed {
+            Ok(self.wrap(value))
+        } else if value < self.min() || value > self.max() {
+            Err(NotFolded::Undefined("signed integer overflow"))
+        } else {
+            Ok(self.make(value))
+        }
+    }
+}
+
+/// Returns the kind and exact value of an integer constant.
+fn integer(c: Const) -> Option<(IntKind, i128)> {
+    let (ty, value) = match c {
+        Const::Char(v) => (Type::Char, v as i128),
+        Const::UChar(v) => (Type::UChar, v as i128),
+        Const::Int(v) => (Type::Int, v as i128),
+        Const::UInt(v) => (Type::UInt, v as i128),
+        Const::Long(v) => (Type::Long, v as i128),
+        Const::ULong(v) => (Type::ULong, v as i128),
+        Const::Double(_) => return None,
+    };
+    Some((IntKind::of(&ty).unwrap(), value))
+}
+
+fn truth(b: bool) -> Const {
+    Const::Int(b as i32)
+}
+
+pub fn eval_unary(op: UnaryOp, c: Const) -> Result<Const, NotFolded> {
+    if let Const::Double(d) = c {
+        return match op {
+            UnaryOp::Negate => Ok(Const::Double(-d)),
+            UnaryOp::Not => Ok(truth(d == 0.0)),
+            UnaryOp::Complement => Err(NotFolded::Unsupported),
+        };
+    }
+
+    let (kind, value) = integer(c).unwrap();
+    match op {
+        UnaryOp::Complement => Ok(kind.wrap(!value)),
+        UnaryOp::Negate => kind.arithmetic(-value),
+        UnaryOp::Not => Ok(truth(value == 0)),
+    }
+}
+
+pub fn eval_binary(op: BinaryOp, a: Const, b: Const) -> Result<Const, NotFolded> {
+    match (a, b) {
+        (Const::Double(a), Const::Double(b)) => eval_double_binary(op, a, b),
+        (Const::Double(_), _) | (_, Const::Double(_)) => Err(NotFolded::Unsupported),
+        (a, b) => {
+            let (kind, a) = integer(a).unwrap();
+            let (kind_b, b) = integer(b).unwrap();
+
+            // Shift operands are promoted independently, every other operation gets operands
+            // of a common type from the usual arithmetic conversions.
+            match op {
+                BinaryOp::ShiftLeft | BinaryOp::ShiftRight => eval_shift(op, kind, a, b),
+                _ if kind != kind_b => Err(NotFolded::Unsupported),
+                _ => eval_integer_binary(op, kind, a, b),
+            }
+        }
+    }
+}
+
+fn eval_integer_binary(op: BinaryOp, kind: IntKind, a: i128, b: i128) -> Result<Const, NotFolded> {
+    match op {
+        BinaryOp::Add => kind.arithmetic(a + b),
+        BinaryOp::Subtract => kind.arithmetic(a - b),
+        BinaryOp::Multiply => {
+            if kind.signed {
+                kind.arithmetic(a * b)
+            } else {
+                // The exact product of two 64-bit unsigned values may not fit in an i128, but
+                // only its low bits survive anyway.
+                let product = (a as u128).wrapping_mul(b as u128) & ((1_u128 << kind.bits) - 1);
+                Ok(kind.make(product as i128))
+            }
+        }
+        BinaryOp::Divide | BinaryOp::Remainder => {
+            if b == 0 {
+                return Err(NotFolded::Undefined("division by zero"));
+            }
+            // The remainder is undefined whenever the quotient isn't representable.
+            let quotient = kind.arithmetic(a / b)?;
+            match op {
+                BinaryOp::Divide => Ok(quotient),
+                _ => Ok(kind.make(a % b)),
+            }
+        }
+        BinaryOp::BitAnd => Ok(kind.make(a & b)),
+        BinaryOp::BitOr => Ok(kind.make(a | b)),
+        BinaryOp::BitXor => Ok(kind.make(a ^ b)),
+        BinaryOp::Equal => Ok(truth(a == b)),
+        BinaryOp::NotEqual => Ok(truth(a != b)),
+        BinaryOp::LessThan => Ok(truth(a < b)),
+        BinaryOp::LessOrEqual => Ok(truth(a <= b)),
+        BinaryOp::GreaterThan => Ok(truth(a > b)),
+        BinaryOp::GreaterOrEqual => Ok(truth(a >= b)),
+        BinaryOp::ShiftLeft | BinaryOp::ShiftRight => unreachable!(),
+    }
+}
+
+fn eval_shift(op: BinaryOp, kind: IntKind, a: i128, count: i128) -> Result<Const, NotFolded> {
+    if count < 0 || count >= kind.bits as i128 {
+        return Err(NotFolded::Undefined("shift count out of range"));
+    }
+
+    match op {
+        BinaryOp::ShiftLeft if kind.signed && a < 0 => {
+            Err(NotFolded::Undefined("left shift of negative value"))
+        }
+        BinaryOp::ShiftLeft => kind.arithmetic(a << count),
+        // Right shifts of negative values are implementation-defined; GCC shifts arithmetically.
+        _ => Ok(kind.make(a >> count)),
+    }
+}
+
+fn eval_double_binary(op: BinaryOp, a: f64, b: f64) -> Result<Const, NotFolded> {
+    match op {
+        BinaryOp::Add => Ok(Const::Double(a + b)),
+        BinaryOp::Subtract => Ok(Const::Double(a - b)),
+        BinaryOp::Multiply => Ok(Const::Double(a * b)),
+        // IEEE 754 defines division by zero, so this folds to an infinity or NaN like GCC does.
+        BinaryOp::Divide => Ok(Const::Double(a / b)),
+        BinaryOp::Equal => Ok(truth(a == b)),
+        BinaryOp::NotEqual => Ok(truth(a != b)),
+        BinaryOp::LessThan => Ok(truth(a < b)),
+        BinaryOp::LessOrEqual => Ok(truth(a <= b)),
+        BinaryOp::GreaterThan => Ok(truth(a > b)),
+        BinaryOp::GreaterOrEqual => Ok(truth(a >= b)),
+        _ => Err(NotFolded::Unsupported),
+    }
+}
+
+/// Converts `c` to a value of type `ty` the way a conversion instruction would at run time.
+pub fn convert(c: Const, ty: &Type) -> Result<Const, NotFolded> {
+    if *ty == Type::Double {
+        return Ok(match c {
+            Const::Double(d) => Const::Double(d),
+            // Rounds to nearest, ties to even, matching `cvtsi2sd` and the unsigned sequence.
+            c => Const::Double(integer(c).unwrap().1 as f64),
+        });
+    }
+
+    let kind = IntKind::of(ty).ok_or(NotFolded::Unsupported)?;
+    match c {
+        Const::Double(d) => {
+            let truncated = d.trunc();
+            // Both bounds are powers of two, so they are exact as doubles.
+            if truncated.is_nan()
+                || truncated < kind.min() as f64
+                || truncated >= (kind.max() + 1) as f64
+            {
+                return Err(NotFolded::Undefined(
+                    "conversion of out of range floating-point value",
+                ));
+            }
+            Ok(kind.make(truncated as i128))
+        }
+        c => Ok(kind.wrap(integer(c).unwrap().1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::tacky::{constant, function, span, symbols, var};
+
+    #[test]
+    fn test_fold_binary() {
+        let mut f = function(
+            &[],
+            vec![Instruction::Binary {
+                op: BinaryOp::Multiply,
+                src1: constant(Const::Int(6)),
+                src2: constant(Const::Int(7)),
+                dst: var("x"),
+            }],
+        );
+        let warnings = fold_constants(&mut f, &SymbolTable::new());
+
+        assert!(warnings.is_empty());
+        assert_eq!(
+            f.body,
+            vec![Instruction::Copy {
+                src: constant(Const::Int(42)),
+                dst: var("x"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fold_leaves_variables_alone() {
+        let body = vec![Instruction::Binary {
+            op: BinaryOp::Add,
+            src1: var("a"),
+            src2: constant(Const::Int(1)),
+            dst: var("x"),
+        }];
+        let mut f = function(&[], body.clone());
+        fold_constants(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_unsigned_arithmetic_wraps() {
+        assert_eq!(
+            eval_binary(BinaryOp::Add, Const::UInt(u32::MAX), Const::UInt(1)),
+            Ok(Const::UInt(0))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::Subtract, Const::ULong(0), Const::ULong(1)),
+            Ok(Const::ULong(u64::MAX))
+        );
+        assert_eq!(
+            eval_binary(
+                BinaryOp::Multiply,
+                Const::ULong(u64::MAX),
+                Const::ULong(u64::MAX)
+            ),
+            Ok(Const::ULong(1))
+        );
+        assert_eq!(
+            eval_unary(UnaryOp::Negate, Const::UInt(1)),
+            Ok(Const::UInt(u32::MAX))
+        );
+    }
+
+    #[test]
+    fn test_signed_overflow_is_not_folded() {
+        let overflow = Err(NotFolded::Undefined("signed integer overflow"));
+
+        assert_eq!(
+            eval_binary(BinaryOp::Add, Const::Int(i32::MAX), Const::Int(1)),
+            overflow
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::Multiply, Const::Long(i64::MAX), Const::Long(2)),
+            overflow
+        );
+        assert_eq!(eval_unary(UnaryOp::Negate, Const::Int(i32::MIN)), overflow);
+        assert_eq!(
+            eval_binary(BinaryOp::Divide, Const::Int(i32::MIN), Const::Int(-1)),
+            overflow
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::Remainder, Const::Long(i64::MIN), Const::Long(-1)),
+            overflow
+        );
+    }
+
+    #[test]
+    fn test_division_truncates_toward_zero() {
+        assert_eq!(
+            eval_binary(BinaryOp::Divide, Const::Int(-7), Const::Int(2)),
+            Ok(Const::Int(-3))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::Remainder, Const::Int(-7), Const::Int(2)),
+            Ok(Const::Int(-1))
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero_warns() {
+        let instruction = Instruction::Binary {
+            op: BinaryOp::Divide,
+            src1: constant(Const::Int(1)),
+            src2: constant(Const::Int(0)),
+            dst: var("x"),
+        };
+        let mut f = function(&[], vec![instruction.clone()]);
+        let warnings = fold_constants(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![instruction]);
+        assert_eq!(
+            warnings,
+            vec![Diagnostic::warning("division by zero in 'x = 1 / 0'").with_span(span())]
+        );
+    }
+
+    #[test]
+    fn test_double_division_by_zero_folds() {
+        assert_eq!(
+            eval_binary(BinaryOp::Divide, Const::Double(1.0), Const::Double(0.0)),
+            Ok(Const::Double(f64::INFINITY))
+        );
+    }
+
+    #[test]
+    fn test_double_comparisons_with_nan() {
+        assert_eq!(
+            eval_binary(
+                BinaryOp::Equal,
+                Const::Double(f64::NAN),
+                Const::Double(f64::NAN)
+            ),
+            Ok(Const::Int(0))
+        );
+        assert_eq!(
+            eval_binary(
+                BinaryOp::NotEqual,
+                Const::Double(f64::NAN),
+                Const::Double(f64::NAN)
+            ),
+            Ok(Const::Int(1))
+        );
+    }
+
+    #[test]
+    fn test_shifts() {
+        assert_eq!(
+            eval_binary(BinaryOp::ShiftLeft, Const::Long(1), Const::Int(40)),
+            Ok(Const::Long(1 << 40))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::ShiftRight, Const::Int(-8), Const::Int(1)),
+            Ok(Const::Int(-4))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::ShiftLeft, Const::UInt(u32::MAX), Const::Int(4)),
+            Ok(Const::UInt(0xFFFF_FFF0))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::ShiftLeft, Const::Int(1), Const::Int(32)),
+            Err(NotFolded::Undefined("shift count out of range"))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::ShiftLeft, Const::Int(-1), Const::Int(1)),
+            Err(NotFolded::Undefined("left shift of negative value"))
+        );
+        assert_eq!(
+            eval_binary(BinaryOp::ShiftLeft, Const::Int(1), Const::Int(31)),
+            Err(NotFolded::Undefined("signed integer overflow"))
+        );
+    }
+
+    #[test]
+    fn test_mismatched_operand_types_are_not_folded() {
+        assert_eq!(
+            eval_binary(BinaryOp::Add, Const::Int(1), Const::Long(1)),
+            Err(NotFolded::Unsupported)
+        );
+    }
+
+    #[test]
+    fn test_fold_conversions() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::Truncate {
+                    src: constant(Const::Long(0x1_0000_0001)),
+                    dst: var("i"),
+                },
+                Instruction::SignExtend {
+                    src: constant(Const::Int(-1)),
+                    dst: var("u"),
+                },
+                Instruction::DoubleToInt {
+                    src: constant(Const::Double(-2.9)),
+                    dst: var("i"),
+                },
+                Instruction::UIntToDouble {
+                    src: constant(Const::ULong(u64::MAX)),
+                    dst: var("d"),
+                },
+            ],
+        );
+        let symbols = symbols(&[("i", Type::Int), ("u", Type::ULong), ("d", Type::Double)]);
+        let warnings = fold_constants(&mut f, &symbols);
+
+        assert!(warnings.is_empty());
+        assert_eq!(
+            f.body,
+            vec![
+                Instruction::Copy {
+                    src: constant(Const::Int(1)),
+                    dst: var("i"),
+                },
+                Instruction::Copy {
+                    src: constant(Const::ULong(u64::MAX)),
+                    dst: var("u"),
+                },
+                Instruction::Copy {
+                    src: constant(Const::Int(-2)),
+                    dst: var("i"),
+                },
+                Instruction::Copy {
+                    src: constant(Const::Double(18446744073709551616.0)),
+                    dst: var("d"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_double_conversion_is_not_folded() {
+        let undefined = Err(NotFolded::Undefined(
+            "conversion of out of range floating-point value",
+        ));
+
+        assert_eq!(convert(Const::Double(2147483648.0), &Type::Int), undefined);
+        assert_eq!(convert(Const::Double(-1.0), &Type::UInt), undefined);
+        assert_eq!(convert(Const::Double(f64::NAN), &Type::Long), undefined);
+        assert_eq!(
+            convert(Const::Double(-0.5), &Type::UInt),
+            Ok(Const::UInt(0))
+        );
+    }
+
+    #[test]
+    fn test_fold_conditional_jumps() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::JumpIfZero {
+                    condition: constant(Const::Int(0)),
+                    target: "a".to_owned(),
+                },
+                Instruction::JumpIfZero {
+                    condition: constant(Const::Long(3)),
+                    target: "b".to_owned(),
+                },
+                Instruction::JumpIfNotZero {
+                    condition: constant(Const::Double(-0.0)),
+                    target: "c".to_owned(),
+                },
+                Instruction::JumpIfNotZero {
+                    condition: constant(Const::Double(0.5)),
+                    target: "d".to_owned(),
+                },
+            ],
+        );
+        fold_constants(&mut f, &SymbolTable::new());
+
+        assert_eq!(
+            f.body,
+            vec![
+                Instruction::Jump("a".to_owned()),
+                Instruction::Jump("d".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    cfg::{CfgInstruction, Flow, NodeId},
+    constant_folding::{convert, eval_binary, eval_unary, is_zero},
+    ssa::Ssa,
+};
+use crate::tacky::{Const, Function, Instruction, SymbolTable, Val};
+
+/// What's known about a variable's value.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Value {
+    /// No definition that reaches it has been found to execute yet.
+    Unknown,
+    Constant(Const),
+    /// It can hold different values, or one that isn't known at compile time.
+    Varying,
+}
+
+impl Value {
+    fn meet(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Unknown, value) | (value, Value::Unknown) => value,
+            (Value::Constant(a), Value::Constant(b)) if a == b => self,
+            _ => Value::Varying,
+        }
+    }
+}
+
+/// Where a variable is read.
+#[derive(Debug, Clone, Copy)]
+enum Use {
+    Phi(usize, usize),
+    Instruction(usize, usize),
+}
+
+/// Sparse conditional constant propagation: replaces variables with the constants they're
+/// known to hold, and conditional jumps on them with the branch they always take.
+///
+/// Works on SSA form, assuming every block unreachable until a reachable branch can go to it.
+/// Definitions in unreachable blocks don't count, so a variable that's only assigned a
+/// different value on a path that never runs is still constant, which folding each
+/// instruction on its own can't find. The blocks left unreachable are removed by unreachable
+/// code elimination.
+///
+/// Operations that are undefined behavior on their constant operands are left alone, for
+/// constant folding to report.
+pub fn propagate_constants(function: &mut Function, symbols: &mut SymbolTable) {
+    let mut ssa = Ssa::new(function, symbols);
+    let solver = Solver::solve(&ssa, symbols);
+    let Solver {
+        values,
+        visited,
+        executable,
+        ..
+    } = solver;
+
+    let mut changed = false;
+    for id in ssa.cfg.block_ids().to_vec() {
+        if !visited.contains(&id) {
+            continue;
+        }
+        let instructions = ssa.cfg.instructions_mut(id);
+        let before = instructions.clone();
+        *instructions = instructions
+            .drain(..)
+            .filter_map(|instruction| rewrite(instruction, &values))
+            .collect();
+        changed |= *instructions != before;
+    }
+
+    // Rewriting keeps the body in SSA form, so converting it back would only rename
+    // variables if nothing was rewritten.
+    if !changed {
+        return;
+    }
+    for (id, phis) in ssa.phis.iter_mut() {
+        for phi in phis {
+            phi.args
+                .retain(|(node, _)| executable.contains(&(*node, NodeId::Block(*id))));
+        }
+    }
+    function.body = ssa.into_instructions(symbols);
+}
+
+/// Returns what's left of `instruction` once the constants in `values` are substituted in, or
+/// `None` for a conditional jump that's never taken.
+fn rewrite(mut instruction: Instruction, values: &HashMap<String, Value>) -> Option<Instruction> {
+    let constant = |val: &Val| match val {
+        Val::Var(name) => match values.get(name) {
+            Some(Value::Constant(c)) => Some(*c),
+            _ => None,
+        },
+        Val::Constant(_) => None,
+    };
+
+    if let Some(dst) = instruction.destination() {
+        if let Some(c) = constant(dst) {
+            return Some(Instruction::Copy {
+                src: Val::Constant(c),
+                dst: dst.clone(),
+            });
+        }
+    }
+    for val in instruction.sources_mut() {
+        if let Some(c) = constant(val) {
+            *val = Val::Constant(c);
+        }
+    }
+
+    match instruction {
+        Instruction::JumpIfZero {
+            condition: Val::Constant(c),
+            target,
+        } => is_zero(c).then_some(Instruction::Jump(target)),
+        Instruction::JumpIfNotZero {
+            condition: Val::Constant(c),
+            target,
+        } => (!is_zero(c)).then_some(Instruction::Jump(target)),
+        instruction => Some(instruction),
+    }
+}
+
+/// The algorithm of Wegman and Zadeck, which propagates values along SSA def-use chains and
+/// only through the control-flow edges found to be executable so far.
+struct Solver<'a> {
+    ssa: &'a Ssa,
+    symbols: &'a SymbolTable,
+    labels: HashMap<&'a str, usize>,
+    uses: HashMap<&'a str, Vec<Use>>,
+    values: HashMap<String, Value>,
+    visited: HashSet<usize>,
+    executable: HashSet<(NodeId, NodeId)>,
+    edges: Vec<(NodeId, NodeId)>,
+    changed: Vec<&'a str>,
+}
+
+impl<'a> Solver<'a> {
+    fn solve(ssa: &'a Ssa, symbols: &'a SymbolTable) -> Self {
+        let mut labels = HashMap::new();
+        let mut uses: HashMap<&str, Vec<Use>> = HashMap::new();
+        for id in ssa.cfg.block_ids() {
+            let instructions = ssa.cfg.instructions(*id);
+            if let Some(Instruction::Label(label)) = instructions.first() {
+                labels.insert(label.as_str(), *id);
+            }
+            for (i, instruction) in instructions.iter().enumerate() {
+                for val in instruction.sources() {
+                    if let Val::Var(name) = val {
+                        uses.entry(name).or_default().push(Use::Instruction(*id, i));
+                    }
+                }
+            }
+        }
+        for (id, phis) in &ssa.phis {
+            for (i, phi) in phis.iter().enumerate() {
+                for (_, arg) in &phi.args {
+                    if let Val::Var(name) = arg {
+                        uses.entry(name).or_default().push(Use::Phi(*id, i));
+                    }
+                }
+            }
+        }
+
+        let mut solver = Self {
+            ssa,
+            symbols,
+            labels,
+            uses,
+            values: ssa
+                .versions
+                .keys()
+                .map(|name| (name.clone(), Value::Unknown))
+                .collect(),
+            visited: HashSet::new(),
+            executable: HashSet::new(),
+            edges: ssa
+                .cfg
+                .successors(NodeId::Entry)
+                .iter()
+                .map(|successor| (NodeId::Entry, *successor))
+                .collect(),
+            changed: Vec::new(),
+        };
+
+        loop {
+            if let Some(edge) = solver.edges.pop() {
+                solver.visit_edge(edge);
+            } else if let Some(name) = solver.changed.pop() {
+                for read in solver.uses.get(name).cloned().unwrap_or_default() {
+                    match read {
+                        Use::Phi(id, i) if solver.visited.contains(&id) => {
+                            solver.evaluate_phi(id, i)
+                        }
+                        Use::Instruction(id, i) if solver.visited.contains(&id) => {
+                            solver.evaluate_instruction(id, i)
+                        }
+                        _ => (),
+                    }
+                }
+            } else {
+                return solver;
+            }
+        }
+    }
+
+    fn visit_edge(&mut self, edge: (NodeId, NodeId)) {
+        if !self.executable.insert(edge) {
+            return;
+        }
+        let NodeId::Block(id) = edge.1 else {
+            return;
+        };
+
+        for i in 0..self.ssa.phis.get(&id).map_or(0, Vec::len) {
+            self.evaluate_phi(id, i);
+        }
+        if self.visited.insert(id) {
+            let instructions = self.ssa.cfg.instructions(id);
+            for i in 0..instructions.len() {
+                self.evaluate_instruction(id, i);
+            }
+            if !matches!(
+                instructions.last().map(CfgInstruction::flow),
+                Some(Flow::ConditionalJump(_))
+            ) {
+                let node = NodeId::Block(id);
+                for successor in self.ssa.cfg.successors(node) {
+                    self.edges.push((node, *successor));
+                }
+            }
+        }
+    }
+
+    fn evaluate_phi(&mut self, id: usize, i: usize) {
+        let ssa = self.ssa;
+        let phi = &ssa.phis[&id][i];
+        let value = phi
+            .args
+            .iter()
+            .filter(|(node, _)| self.executable.contains(&(*node, NodeId::Block(id))))
+            .fold(Value::Unknown, |value, (_, arg)| {
+                value.meet(self.value(arg))
+            });
+        self.lower(&phi.dst, value);
+    }
+
+    fn evaluate_instruction(&mut self, id: usize, i: usize) {
+        let ssa = self.ssa;
+        let instruction = &ssa.cfg.instructions(id)[i];
+        match instruction {
+            Instruction::JumpIfZero { condition, target }
+            | Instruction::JumpIfNotZero { condition, target } => {
+                let node = NodeId::Block(id);
+                let taken = NodeId::Block(self.labels[target.as_str()]);
+                let jumps = |c| matches!(instruction, Instruction::JumpIfZero { .. }) == is_zero(c);
+
+                match self.value(condition) {
+                    Value::Unknown => (),
+                    Value::Constant(c) if jumps(c) => self.edges.push((node, taken)),
+                    Value::Constant(_) => {
+                        // Falls through, which is the other successor, if there is one.
+                        let next = self.ssa.cfg.successors(node).iter();
+                        let next = next.filter(|successor| **successor != taken);
+                        self.edges.extend(next.map(|successor| (node, *successor)));
+                    }
+                    Value::Varying => {
+                        let successors = self.ssa.cfg.successors(node).iter();
+                        self.edges
+                            .extend(successors.map(|successor| (node, *successor)));
+                    }
+                }
+            }
+            instruction => {
+                if let Some(Val::Var(dst)) = instruction.destination() {
+                    let value = self.evaluate(instruction);
+                    self.lower(dst, value);
+                }
+            }
+        }
+    }
+
+    /// Returns the value `instruction` writes to its destination.
+    fn evaluate(&self, instruction: &Instruction) -> Value {
+        let operands: Vec<Value> = instruction
+            .sources()
+            .into_iter()
+            .map(|val| self.value(val))
+            .collect();
+        if operands.contains(&Value::Unknown) {
+            return Value::Unknown;
+        }
+        let constants: Vec<Const> = operands
+            .iter()
+            .filter_map(|value| match value {
+                Value::Constant(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        if constants.len() < operands.len() {
+            return Value::Varying;
+        }
+
+        let result = match instruction {
+            Instruction::Copy { .. } => Ok(constants[0]),
+            Instruction::Unary { op, .. } => eval_unary(*op, constants[0]),
+            Instruction::Binary { op, .. } => eval_binary(*op, constants[0], constants[1]),
+            Instruction::SignExtend { dst, .. }
+            | Instruction::Truncate { dst, .. }
+            | Instruction::ZeroExtend { dst, .. }
+            | Instruction::DoubleToInt { dst, .. }
+            | Instruction::DoubleToUInt { dst, .. }
+            | Instruction::IntToDouble { dst, .. }
+            | Instruction::UIntToDouble { dst, .. } => {
+                let Val::Var(name) = dst else {
+                    return Value::Varying;
+                };
+                match self.symbols.get(self.ssa.original(name)) {
+                    Some(symbol) => convert(constants[0], &symbol.ty),
+                    None => return Value::Varying,
+                }
+            }
+            _ => return Value::Varying,
+        };
+
+        match result {
+            Ok(c) => Value::Constant(c),
+            Err(_) => Value::Varying,
+        }
+    }
+
+    fn value(&self, val: &Val) -> Value {
+        match val {
+            Val::Constant(c) => Value::Constant(*c),
+            // Anything renaming didn't define, such as a parameter, varies.
+            Val::Var(name) => self.values.get(name).copied().unwrap_or(Value::Varying),
+        }
+    }
+
+    fn lower(&mut self, name: &'a str, value: Value) {
+        let Some(old) = self.values.get_mut(name) else {
+            return;
+        };
+        let new = old.meet(value);
+        if new != *old {
+            *old = new;
+            self.changed.push(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::BinaryOp,
+        test_support::tacky::{copy, function, int, ints, label, var},
+    };
+
+    fn has_conditional_jump(body: &[Instruction]) -> bool {
+        body.iter().any(|instruction| {
+            matches!(
+                instruction,
+                Instruction::JumpIfZero { .. } | Instruction::JumpIfNotZero { .. }
+            )
+        })
+    }
+
+    #[test]
+    fn test_fold_branch_on_propagated_constant() {
+        // int flag = 0; int x; if (flag) x = 1; else x = 2; return x;
+        let mut f = function(
+            &[],
+            vec![
+                copy(int(0), "flag"),
+                Instruction::JumpIfZero {
+                    condition: var("flag"),
+                    target: "else".to_owned(),
+                },
+                copy(int(1), "x"),
+                Instruction::Jump("end".to_owned()),
+                label("else"),
+                copy(int(2), "x"),
+                label("end"),
+                Instruction::Return(Some(var("x"))),
+            ],
+        );
+        let mut symbols = ints(&["flag", "x"]);
+        propagate_constants(&mut f, &mut symbols);
+
+        assert!(!has_conditional_jump(&f.body));
+        assert!(f.body.contains(&Instruction::Jump("else".to_owned())));
+        assert_eq!(f.body.last(), Some(&Instruction::Return(Some(int(2)))));
+    }
+
+    #[test]
+    fn test_ignore_assignments_that_never_run() {
+        // int x = 1; while (i < n) { if (x != 1) x = 2; i = i + 1; } return x;
+        let mut f = function(
+            &["i", "n"],
+            vec![
+                copy(int(1), "x"),
+                label("loop"),
+                Instruction::Binary {
+                    op: BinaryOp::LessThan,
+                    src1: var("i"),
+                    src2: var("n"),
+                    dst: var("c"),
+                },
+                Instruction::JumpIfZero {
+                    condition: var("c"),
+                    target: "end".to_owned(),
+                },
+                Instruction::Binary {
+                    op: BinaryOp::NotEqual,
+                    src1: var("x"),
+                    src2: int(1),
+                    dst: var("d"),
+                },
+                Instruction::JumpIfZero {
+                    condition: var("d"),
+                    target: "next".to_owned(),
+                },
+                copy(int(2), "x"),
+                label("next"),
+                Instruction::Binary {
+                    op: BinaryOp::Add,
+                    src1: var("i"),
+                    src2: int(1),
+                    dst: var("i"),
+                },
+                Instruction::Jump("loop".to_owned()),
+                label("end"),
+                Instruction::Return(Some(var("x"))),
+            ],
+        );
+        let mut symbols = ints(&["i", "n", "x", "c", "d"]);
+        propagate_constants(&mut f, &mut symbols);
+
+        assert_eq!(f.body.last(), Some(&Instruction::Return(Some(int(1)))));
+        assert!(f.body.contains(&Instruction::Jump("next".to_owned())));
+    }
+
+    #[test]
+    fn test_varying_values_leave_body_unchanged() {
+        let body = vec![
+            Instruction::Binary {
+                op: BinaryOp::Add,
+                src1: var("a"),
+                src2: int(1),
+                dst: var("x"),
+            },
+            Instruction::JumpIfZero {
+                condition: var("x"),
+                target: "end".to_owned(),
+            },
+            copy(int(3), "x"),
+            label("end"),
+            Instruction::Return(Some(var("x"))),
+        ];
+        let mut f = function(&["a"], body.clone());
+        let mut symbols = ints(&["a", "x"]);
+        propagate_constants(&mut f, &mut symbols);
+
+        assert_eq!(f.body, body);
+        // The optimizer reruns passes until nothing changes, so one that changes nothing must
+        // not leave the versions of its SSA form behind either.
+        assert!((0..4).all(|id| symbols.get(&format!("x.{id}")).is_none()));
+    }
+
+    #[test]
+    fn test_undefined_operation_varies() {
+        let mut f = function(
+            &[],
+            vec![
+                copy(int(0), "zero"),
+                Instruction::Binary {
+                    op: BinaryOp::Divide,
+                    src1: int(1),
+                    src2: var("zero"),
+                    dst: var("x"),
+                },
+                Instruction::Return(Some(var("x"))),
+            ],
+        );
+        let mut symbols = ints(&["zero", "x"]);
+        propagate_constants(&mut f, &mut symbols);
+
+        assert_eq!(
+            f.body[1],
+            Instruction::Binary {
+                op: BinaryOp::Divide,
+                src1: int(1),
+                src2: int(0),
+                dst: var("x.1"),
+            }
+        );
+        assert_eq!(f.body[2], Instruction::Return(Some(var("x.1"))));
+    }
+}
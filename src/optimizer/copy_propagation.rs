@@ -0,0 +1,408 @@
+use std::collections::HashSet;
+
+use super::{
+    cfg::Cfg,
+    dataflow::{self, Analysis, Direction},
+    Aliased,
+};
+use crate::tacky::{Function, Instruction, SymbolTable, Val};
+
+/// A `dst = src` copy that may be substituted into later reads of `dst`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct CopyFact {
+    src: Val,
+    dst: String,
+}
+
+/// Replaces reads of variables with the value most recently copied into them, wherever that
+/// copy reaches along every path, and removes copies that are already known to hold.
+///
+/// Variables that have their address taken or have static storage can change behind the
+/// function's back on any store or call, so copies involving them are forgotten there.
+/// Copies to or from volatile variables are never tracked.
+pub fn propagate_copies(function: &mut Function, symbols: &SymbolTable) {
+    let aliased = Aliased::new(function, symbols);
+    let cfg = Cfg::new(std::mem::take(&mut function.body));
+
+    let copies = cfg
+        .block_ids()
+        .iter()
+        .flat_map(|id| cfg.instructions(*id))
+        .filter_map(|instruction| trackable_copy(instruction, symbols))
+        .collect();
+    let analysis = ReachingCopies {
+        copies,
+        aliased,
+        symbols,
+    };
+    let solution = dataflow::solve(&cfg, &analysis);
+
+    let mut cfg = cfg;
+    for id in cfg.block_ids().to_vec() {
+        let facts = solution.instruction_facts(&cfg, &analysis, id);
+        let instructions = std::mem::take(cfg.instructions_mut(id));
+
+        *cfg.instructions_mut(id) = instructions
+            .into_iter()
+            .zip(facts)
+            .filter_map(|(instruction, reaching)| rewrite(instruction, &reaching, symbols))
+            .collect();
+    }
+
+    function.body = cfg.into_instructions();
+}
+
+/// Returns the copy `instruction` makes if it's one this pass may propagate.
+///
+/// Copies between types of the same size but different signedness stand in for conversions,
+/// so substituting across them would change the meaning of the instruction reading the value.
+fn trackable_copy(instruction: &Instruction, symbols: &SymbolTable) -> Option<CopyFact> {
+    let Instruction::Copy {
+        src,
+        dst: Val::Var(dst),
+    } = instruction
+    else {
+        return None;
+    };
+    let dst_symbol = symbols.get(dst)?;
+
+    let src_type = match src {
+        Val::Constant(c) => c.ty(),
+        Val::Var(name) => {
+            let symbol = symbols.get(name)?;
+            if symbol.volatile {
+                return None;
+            }
+            symbol.ty.clone()
+        }
+    };
+    if dst_symbol.volatile || src_type != dst_symbol.ty {
+        return None;
+    }
+
+    Some(CopyFact {
+        src: src.clone(),
+        dst: dst.clone(),
+    })
+}
+
+struct ReachingCopies<'a> {
+    /// Every trackable copy in the function.
+    copies: HashSet<CopyFact>,
+    aliased: Aliased<'a>,
+    symbols: &'a SymbolTable,
+}
+
+impl ReachingCopies<'_> {
+    fn kill(&self, fact: &mut HashSet<CopyFact>, mut killed: impl FnMut(&str) -> bool) {
+        fact.retain(|copy| {
+            let src_killed = match &copy.src {
+                Val::Var(name) => killed(name),
+                Val::Constant(_) => false,
+            };
+            !src_killed && !killed(&copy.dst)
+        });
+    }
+}
+
+impl Analysis for ReachingCopies<'_> {
+    type Instruction = Instruction;
+    type Fact = HashSet<CopyFact>;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn boundary(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        self.copies.clone()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.retain(|copy| other.contains(copy));
+    }
+
+    fn transfer(&self, instruction: &Instruction, fact: &mut Self::Fact) {
+        match instruction {
+            Instruction::Copy { dst, .. } => {
+                if let Some(copy) = trackable_copy(instruction, self.symbols) {
+                    if is_redundant(&copy, fact) {
+                        return;
+                    }
+                    self.kill(fact, |name| name == copy.dst);
+                    fact.insert(copy);
+                } else if let Val::Var(dst) = dst {
+                    self.kill(fact, |name| name == dst);
+                }
+            }
+            Instruction::FunCall { dst, .. } => {
+                self.kill(fact, |name| {
+                    self.aliased.contains(name) || Some(name) == var_name(dst.as_ref())
+                });
+            }
+            Instruction::Store { .. } => {
+                self.kill(fact, |name| self.aliased.contains(name));
+            }
+            Instruction::CopyToOffset { dst, .. } => {
+                self.kill(fact, |name| name == dst);
+            }
+            instruction => {
+                if let Some(dst) = var_name(instruction.destination()) {
+                    self.kill(fact, |name| name == dst);
+                }
+            }
+        }
+    }
+}
+
+/// `x = y` changes nothing when `x = y` or `y = x` already holds.
+fn is_redundant(copy: &CopyFact, reaching: &HashSet<CopyFact>) -> bool {
+    if reaching.contains(copy) {
+        return true;
+    }
+    match &copy.src {
+        Val::Var(src) => reaching.contains(&CopyFact {
+            src: Val::Var(copy.dst.clone()),
+            dst: src.clone(),
+        }),
+        Val::Constant(_) => false,
+    }
+}
+
+fn var_name(val: Option<&Val>) -> Option<&str> {
+    match val {
+        Some(Val::Var(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Substitutes the copies in `reaching` into the operands `instruction` reads, or returns
+/// `None` if `instruction` is a copy that's already in effect.
+fn rewrite(
+    mut instruction: Instruction,
+    reaching: &HashSet<CopyFact>,
+    symbols: &SymbolTable,
+) -> Option<Instruction> {
+    if let Some(copy) = trackable_copy(&instruction, symbols) {
+        if is_redundant(&copy, reaching) {
+            return None;
+        }
+    }
+
+    for val in instruction.sources_mut() {
+        let Val::Var(name) = val else {
+            continue;
+        };
+        if let Some(copy) = reaching.iter().find(|copy| copy.dst == *name) {
+            *val = copy.src.clone();
+        }
+    }
+    Some(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::{BinaryOp, Storage, Type},
+        test_support::tacky::{binary, copy, function, int, ints, ret, symbol, symbols, var},
+    };
+
+    #[test]
+    fn test_propagate_constant() {
+        let mut f = function(
+            &[],
+            vec![
+                copy(int(3), "x"),
+                binary(BinaryOp::Add, var("x"), int(1), "y"),
+                ret(var("y")),
+            ],
+        );
+        propagate_copies(&mut f, &ints(&["x", "y"]));
+
+        assert_eq!(
+            f.body,
+            vec![
+                copy(int(3), "x"),
+                binary(BinaryOp::Add, int(3), int(1), "y"),
+                ret(var("y"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_propagate_variable_until_source_changes() {
+        let mut f = function(
+            &[],
+            vec![
+                copy(var("a"), "x"),
+                binary(BinaryOp::Add, var("x"), int(1), "y"),
+                copy(int(1), "a"),
+                binary(BinaryOp::Add, var("x"), int(1), "y"),
+            ],
+        );
+        propagate_copies(&mut f, &ints(&["a", "x", "y"]));
+
+        assert_eq!(
+            f.body,
+            vec![
+                copy(var("a"), "x"),
+                binary(BinaryOp::Add, var("a"), int(1), "y"),
+                copy(int(1), "a"),
+                binary(BinaryOp::Add, var("x"), int(1), "y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_copy_must_reach_along_every_path() {
+        let body = vec![
+            copy(int(1), "x"),
+            Instruction::JumpIfZero {
+                condition: var("c"),
+                target: "end".to_owned(),
+            },
+            copy(int(2), "x"),
+            Instruction::Label("end".to_owned()),
+            ret(var("x")),
+        ];
+        let mut f = function(&[], body.clone());
+        propagate_copies(&mut f, &ints(&["x", "c"]));
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_same_copy_on_both_paths() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::JumpIfZero {
+                    condition: var("c"),
+                    target: "else".to_owned(),
+                },
+                copy(int(2), "x"),
+                Instruction::Jump("end".to_owned()),
+                Instruction::Label("else".to_owned()),
+                copy(int(2), "x"),
+                Instruction::Label("end".to_owned()),
+                ret(var("x")),
+            ],
+        );
+        propagate_copies(&mut f, &ints(&["x", "c"]));
+
+        assert_eq!(f.body.last(), Some(&ret(int(2))));
+    }
+
+    #[test]
+    fn test_loop_carried_copy_is_not_propagated() {
+        let body = vec![
+            copy(int(0), "i"),
+            Instruction::Label("loop".to_owned()),
+            binary(BinaryOp::Add, var("i"), int(1), "i"),
+            Instruction::JumpIfNotZero {
+                condition: var("i"),
+                target: "loop".to_owned(),
+            },
+            ret(var("i")),
+        ];
+        let mut f = function(&[], body.clone());
+        propagate_copies(&mut f, &ints(&["i"]));
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_remove_redundant_copies() {
+        let mut f = function(
+            &[],
+            vec![
+                copy(var("a"), "x"),
+                copy(var("x"), "a"),
+                copy(var("a"), "x"),
+                ret(var("x")),
+            ],
+        );
+        propagate_copies(&mut f, &ints(&["a", "x"]));
+
+        assert_eq!(f.body, vec![copy(var("a"), "x"), ret(var("a"))]);
+    }
+
+    #[test]
+    fn test_call_kills_static_copies() {
+        let mut f = function(
+            &[],
+            vec![
+                copy(int(1), "g"),
+                copy(var("g"), "x"),
+                Instruction::FunCall {
+                    name: "h".to_owned(),
+                    args: vec![],
+                    dst: None,
+                },
+                binary(BinaryOp::Add, var("g"), var("x"), "y"),
+            ],
+        );
+        let mut symbols = ints(&["x", "y"]);
+        symbols.insert("g", symbol(Type::Int, Storage::Static, false));
+        propagate_copies(&mut f, &symbols);
+
+        assert_eq!(f.body[1], copy(int(1), "x"));
+        assert_eq!(f.body[3], binary(BinaryOp::Add, var("g"), var("x"), "y"));
+    }
+
+    #[test]
+    fn test_store_kills_address_taken_copies() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::GetAddress {
+                    src: var("x"),
+                    dst: var("p"),
+                },
+                copy(int(1), "x"),
+                copy(int(2), "y"),
+                Instruction::Store {
+                    src: int(5),
+                    dst_ptr: var("p"),
+                },
+                binary(BinaryOp::Add, var("x"), var("y"), "z"),
+            ],
+        );
+        let mut symbols = ints(&["x", "y", "z"]);
+        symbols.insert(
+            "p",
+            symbol(Type::Pointer(Box::new(Type::Int)), Storage::Local, false),
+        );
+        propagate_copies(&mut f, &symbols);
+
+        assert_eq!(f.body[4], binary(BinaryOp::Add, var("x"), int(2), "z"));
+    }
+
+    #[test]
+    fn test_volatile_is_never_propagated() {
+        let body = vec![
+            copy(int(1), "v"),
+            copy(var("v"), "x"),
+            copy(var("v"), "x"),
+            ret(var("x")),
+        ];
+        let mut f = function(&[], body.clone());
+        let mut symbols = ints(&["x"]);
+        symbols.insert("v", symbol(Type::Int, Storage::Local, true));
+        propagate_copies(&mut f, &symbols);
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_signedness_changing_copy_is_not_propagated() {
+        let body = vec![copy(var("i"), "u"), ret(var("u"))];
+        let mut f = function(&[], body.clone());
+        let symbols = symbols(&[("i", Type::Int), ("u", Type::UInt)]);
+        propagate_copies(&mut f, &symbols);
+
+        assert_eq!(f.body, body);
+    }
+}
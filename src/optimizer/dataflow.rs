@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::cfg::{Cfg, CfgInstruction, NodeId};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// Facts flow from `Entry` along edges, from each instruction to the one after it.
+    Forward,
+    /// Facts flow from `Exit` against edges, from each instruction to the one before it.
+    Backward,
+}
+
+/// A dataflow analysis over the instructions of a `Cfg`, solved by `solve`.
+///
+/// Facts form a semilattice: `meet` combines the facts arriving over several edges, and `top`
+/// is its identity, the optimistic fact every block starts from before anything is known.
+pub trait Analysis {
+    type Instruction: CfgInstruction;
+    type Fact: Clone + PartialEq;
+
+    const DIRECTION: Direction;
+
+    /// The fact that holds at `Entry` for forward analyses, or `Exit` for backward ones.
+    fn boundary(&self) -> Self::Fact;
+
+    fn top(&self) -> Self::Fact;
+
+    /// Merges `other` into `fact`.
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+    /// Updates `fact` to account for `instruction`, in the analysis' direction.
+    fn transfer(&self, instruction: &Self::Instruction, fact: &mut Self::Fact);
+}
+
+/// The fixed point of an analysis: the facts at the start and end of every block, in program
+/// order regardless of the analysis' direction.
+#[derive(Debug)]
+pub struct Solution<F> {
+    block_start: HashMap<usize, F>,
+    block_end: HashMap<usize, F>,
+}
+
+impl<F: Clone> Solution<F> {
+    pub fn block_start(&self, id: usize) -> &F {
+        &self.block_start[&id]
+    }
+
+    pub fn block_end(&self, id: usize) -> &F {
+        &self.block_end[&id]
+    }
+
+    /// Returns, for every instruction of block `id`, the fact just before it for forward
+    /// analyses, or just after it for backward ones. That is the fact a pass rewriting the
+    /// instruction needs.
+    pub fn instruction_facts<A>(&self, cfg: &Cfg<A::Instruction>, analysis: &A, id: usize) -> Vec<F>
+    where
+        A: Analysis<Fact = F>,
+    {
+        let instructions = cfg.instructions(id);
+        let mut facts = Vec::with_capacity(instructions.len());
+
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut fact = self.block_start(id).clone();
+                for instruction in instructions {
+                    facts.push(fact.clone());
+                    analysis.transfer(instruction, &mut fact);
+                }
+            }
+            Direction::Backward => {
+                let mut fact = self.block_end(id).clone();
+                for instruction in instructions.iter().rev() {
+                    facts.push(fact.clone());
+                    analysis.transfer(instruction, &mut fact);
+                }
+                facts.reverse();
+            }
+        }
+
+        facts
+    }
+}
+
+/// Runs `analysis` over `cfg` with a worklist until no block's facts change.
+///
+/// Blocks are first visited in reverse postorder (or postorder, for backward analyses), so
+/// each block usually sees its inputs already computed and loops need few extra rounds.
+pub fn solve<A: Analysis>(cfg: &Cfg<A::Instruction>, analysis: &A) -> Solution<A::Fact> {
+    let mut order: Vec<usize> = cfg
+        .reverse_postorder()
+        .into_iter()
+        .filter_map(|node| match node {
+            NodeId::Block(id) => Some(id),
+            _ => None,
+        })
+        .collect();
+    let reachable: HashSet<usize> = order.iter().copied().collect();
+    order.extend(cfg.block_ids().iter().filter(|id| !reachable.contains(id)));
+    if A::DIRECTION == Direction::Backward {
+        order.reverse();
+    }
+
+    let mut solution = Solution {
+        block_start: order.iter().map(|id| (*id, analysis.top())).collect(),
+        block_end: order.iter().map(|id| (*id, analysis.top())).collect(),
+    };
+    let mut queued: HashSet<usize> = order.iter().copied().collect();
+    let mut worklist: VecDeque<usize> = order.into();
+
+    while let Some(id) = worklist.pop_front() {
+        queued.remove(&id);
+        let node = NodeId::Block(id);
+
+        let (incoming, outgoing) = match A::DIRECTION {
+            Direction::Forward => (cfg.predecessors(node), cfg.successors(node)),
+            Direction::Backward => (cfg.successors(node), cfg.predecessors(node)),
+        };
+
+        let mut fact = analysis.top();
+        for neighbor in incoming {
+            match neighbor {
+                NodeId::Block(other) => match A::DIRECTION {
+                    Direction::Forward => analysis.meet(&mut fact, &solution.block_end[other]),
+                    Direction::Backward => analysis.meet(&mut fact, &solution.block_start[other]),
+                },
+                NodeId::Entry | NodeId::Exit => analysis.meet(&mut fact, &analysis.boundary()),
+            }
+        }
+
+        let mut result = fact.clone();
+        let changed = match A::DIRECTION {
+            Direction::Forward => {
+                for instruction in cfg.instructions(id) {
+                    analysis.transfer(instruction, &mut result);
+                }
+                solution.block_start.insert(id, fact);
+                replace(solution.block_end.get_mut(&id).unwrap(), result)
+            }
+            Direction::Backward => {
+                for instruction in cfg.instructions(id).iter().rev() {
+                    analysis.transfer(instruction, &mut result);
+                }
+                solution.block_end.insert(id, fact);
+                replace(solution.block_start.get_mut(&id).unwrap(), result)
+            }
+        };
+
+        if changed {
+            for neighbor in outgoing {
+                if let NodeId::Block(other) = neighbor {
+                    if queued.insert(*other) {
+                        worklist.push_back(*other);
+                    }
+                }
+            }
+        }
+    }
+
+    solution
+}
+
+/// Stores `new` in `old`, returning whether it differed.
+fn replace<F: PartialEq>(old: &mut F, new: F) -> bool {
+    if *old == new {
+        false
+    } else {
+        *old = new;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tacky::{Const, Instruction, Val};
+
+    /// Collects the labels every path from `Entry` (or to `Exit`) is guaranteed to pass through.
+    struct Labels<const FORWARD: bool>;
+
+    impl<const FORWARD: bool> Analysis for Labels<FORWARD> {
+        type Instruction = Instruction;
+        type Fact = Option<HashSet<String>>;
+
+        const DIRECTION: Direction = if FORWARD {
+            Direction::Forward
+        } else {
+            Direction::Backward
+        };
+
+        fn boundary(&self) -> Self::Fact {
+            Some(HashSet::new())
+        }
+
+        // `None` stands for the set of every label, the identity of intersection.
+        fn top(&self) -> Self::Fact {
+            None
+        }
+
+        fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+            match (fact.as_mut(), other) {
+                (_, None) => (),
+                (None, Some(other)) => *fact = Some(other.clone()),
+                (Some(fact), Some(other)) => fact.retain(|label| other.contains(label)),
+            }
+        }
+
+        fn transfer(&self, instruction: &Instruction, fact: &mut Self::Fact) {
+            if let (Instruction::Label(label), Some(fact)) = (instruction, fact.as_mut()) {
+                fact.insert(label.clone());
+            }
+        }
+    }
+
+    fn set(labels: &[&str]) -> Option<HashSet<String>> {
+        Some(labels.iter().map(|label| label.to_string()).collect())
+    }
+
+    fn diamond() -> Cfg<Instruction> {
+        Cfg::new(vec![
+            Instruction::Label("a".to_owned()),
+            Instruction::JumpIfZero {
+                condition: Val::Var("c".to_owned()),
+                target: "else".to_owned(),
+            },
+            Instruction::Label("then".to_owned()),
+            Instruction::Jump("end".to_owned()),
+            Instruction::Label("else".to_owned()),
+            Instruction::Label("end".to_owned()),
+            Instruction::Return(Some(Val::Constant(Const::Int(0)))),
+        ])
+    }
+
+    #[test]
+    fn test_forward() {
+        let cfg = diamond();
+        let solution = solve(&cfg, &Labels::<true>);
+
+        assert_eq!(*solution.block_end(0), set(&["a"]));
+        assert_eq!(*solution.block_end(1), set(&["a", "then"]));
+        assert_eq!(*solution.block_end(2), set(&["a", "else"]));
+        assert_eq!(*solution.block_start(3), set(&["a"]));
+        assert_eq!(*solution.block_end(3), set(&["a", "end"]));
+    }
+
+    #[test]
+    fn test_backward() {
+        let cfg = diamond();
+        let solution = solve(&cfg, &Labels::<false>);
+
+        assert_eq!(*solution.block_start(3), set(&["end"]));
+        assert_eq!(*solution.block_end(0), set(&["end"]));
+        assert_eq!(*solution.block_start(0), set(&["a", "end"]));
+    }
+
+    #[test]
+    fn test_loop_reaches_fixed_point() {
+        let cfg = Cfg::new(vec![
+            Instruction::Label("head".to_owned()),
+            Instruction::JumpIfZero {
+                condition: Val::Var("c".to_owned()),
+                target: "end".to_owned(),
+            },
+            Instruction::Label("body".to_owned()),
+            Instruction::Jump("head".to_owned()),
+            Instruction::Label("end".to_owned()),
+        ]);
+        let solution = solve(&cfg, &Labels::<true>);
+
+        assert_eq!(*solution.block_start(0), set(&[]));
+        assert_eq!(*solution.block_end(2), set(&["head", "end"]));
+    }
+
+    #[test]
+    fn test_instruction_facts() {
+        let cfg = diamond();
+        let analysis = Labels::<true>;
+        let solution = solve(&cfg, &analysis);
+
+        assert_eq!(
+            solution.instruction_facts(&cfg, &analysis, 3),
+            vec![set(&["a"]), set(&["a", "end"])]
+        );
+
+        let analysis = Labels::<false>;
+        let solution = solve(&cfg, &analysis);
+
+        assert_eq!(
+            solution.instruction_facts(&cfg, &analysis, 0),
+            vec![set(&["end"]), set(&["end"])]
+        );
+    }
+}
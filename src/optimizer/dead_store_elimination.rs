@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use super::{cfg::Cfg, dataflow, liveness::Liveness, Aliased};
+use crate::tacky::{Function, Instruction, SymbolTable, Val};
+
+/// Removes instructions whose only effect is to write a variable that's never read afterwards.
+///
+/// Stores to aliased or volatile variables are kept, as are instructions with other effects:
+/// calls, stores through pointers and loads, which may read a volatile object.
+pub fn eliminate_dead_stores(function: &mut Function, symbols: &SymbolTable) {
+    let analysis = Liveness {
+        aliased: Aliased::new(function, symbols),
+    };
+    let mut cfg = Cfg::new(std::mem::take(&mut function.body));
+    let solution = dataflow::solve(&cfg, &analysis);
+
+    for id in cfg.block_ids().to_vec() {
+        let facts = solution.instruction_facts(&cfg, &analysis, id);
+        let instructions = std::mem::take(cfg.instructions_mut(id));
+
+        *cfg.instructions_mut(id) = instructions
+            .into_iter()
+            .zip(facts)
+            .filter(|(instruction, live)| !is_dead(instruction, live, &analysis.aliased, symbols))
+            .map(|(instruction, _)| instruction)
+            .collect();
+    }
+
+    function.body = cfg.into_instructions();
+}
+
+fn is_dead(
+    instruction: &Instruction,
+    live: &HashSet<String>,
+    aliased: &Aliased,
+    symbols: &SymbolTable,
+) -> bool {
+    let dst = match instruction {
+        Instruction::FunCall { .. } | Instruction::Load { .. } => return false,
+        Instruction::CopyToOffset { dst, .. } => dst,
+        instruction => match instruction.destination() {
+            Some(Val::Var(dst)) => dst,
+            _ => return false,
+        },
+    };
+
+    !live.contains(dst) && !aliased.contains(dst) && !symbols.is_volatile(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::{BinaryOp, Storage, Type},
+        test_support::tacky::{copy, function, int, symbol, var},
+    };
+
+    #[test]
+    fn test_remove_overwritten_store() {
+        let mut f = function(
+            &[],
+            vec![
+                copy(int(1), "x"),
+                copy(int(2), "x"),
+                Instruction::Return(Some(var("x"))),
+            ],
+        );
+        eliminate_dead_stores(&mut f, &SymbolTable::new());
+
+        assert_eq!(
+            f.body,
+            vec![copy(int(2), "x"), Instruction::Return(Some(var("x")))]
+        );
+    }
+
+    #[test]
+    fn test_remove_unused_computation() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::Binary {
+                    op: BinaryOp::Multiply,
+                    src1: var("a"),
+                    src2: var("b"),
+                    dst: var("tmp.0"),
+                },
+                Instruction::Return(Some(var("a"))),
+            ],
+        );
+        eliminate_dead_stores(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![Instruction::Return(Some(var("a")))]);
+    }
+
+    #[test]
+    fn test_keep_store_live_around_loop() {
+        let body = vec![
+            copy(int(0), "i"),
+            Instruction::Label("loop".to_owned()),
+            Instruction::Binary {
+                op: BinaryOp::Add,
+                src1: var("i"),
+                src2: int(1),
+                dst: var("i"),
+            },
+            Instruction::JumpIfNotZero {
+                condition: var("c"),
+                target: "loop".to_owned(),
+            },
+            Instruction::Return(Some(var("i"))),
+        ];
+        let mut f = function(&[], body.clone());
+        eliminate_dead_stores(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_keep_side_effects() {
+        let body = vec![
+            Instruction::FunCall {
+                name: "g".to_owned(),
+                args: vec![],
+                dst: Some(var("unused")),
+            },
+            Instruction::Load {
+                src_ptr: var("p"),
+                dst: var("unused"),
+            },
+            Instruction::Store {
+                src: int(1),
+                dst_ptr: var("p"),
+            },
+            Instruction::Return(None),
+        ];
+        let mut f = function(&[], body.clone());
+        eliminate_dead_stores(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_keep_stores_to_aliased_and_volatile_variables() {
+        let body = vec![
+            copy(int(1), "g"),
+            copy(int(1), "v"),
+            Instruction::GetAddress {
+                src: var("a"),
+                dst: var("p"),
+            },
+            copy(int(1), "a"),
+            Instruction::FunCall {
+                name: "use".to_owned(),
+                args: vec![var("p")],
+                dst: None,
+            },
+            Instruction::Return(None),
+        ];
+        let mut f = function(&[], body.clone());
+        let mut symbols = SymbolTable::new();
+        symbols.insert("g", symbol(Type::Int, Storage::Static, false));
+        symbols.insert("v", symbol(Type::Int, Storage::Local, true));
+        eliminate_dead_stores(&mut f, &symbols);
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_remove_dead_aggregate_write() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::CopyToOffset {
+                    src: int(1),
+                    dst: "arr".to_owned(),
+                    offset: 4,
+                },
+                Instruction::Return(None),
+            ],
+        );
+        eliminate_dead_stores(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![Instruction::Return(None)]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+
+use super::cfg::{Cfg, CfgInstruction, NodeId};
+
+/// The dominator tree of a `Cfg`: node `a` dominates node `b` if every path from `Entry` to
+/// `b` passes through `a`.
+#[derive(Debug)]
+pub struct Dominators {
+    // Every reachable node's immediate dominator; `Entry` is its own.
+    idom: HashMap<NodeId, NodeId>,
+    // The nodes each node immediately dominates, in reverse postorder.
+    children: HashMap<NodeId, Vec<NodeId>>,
+}
+
+impl Dominators {
+    /// Computes the dominator tree with the iterative algorithm of Cooper, Harvey and Kennedy.
+    pub fn new<I: CfgInstruction>(cfg: &Cfg<I>) -> Self {
+        let order = cfg.reverse_postorder();
+        let index: HashMap<NodeId, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (*node, i))
+            .collect();
+        let mut idom = HashMap::from([(NodeId::Entry, NodeId::Entry)]);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for node in order.iter().skip(1) {
+                let mut new_idom = None;
+                for predecessor in cfg.predecessors(*node) {
+                    if !idom.contains_key(predecessor) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *predecessor,
+                        Some(other) => intersect(&idom, &index, *predecessor, other),
+                    });
+                }
+
+                let new_idom = new_idom.unwrap();
+                if idom.insert(*node, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        for node in order.iter().skip(1) {
+            children.entry(idom[node]).or_default().push(*node);
+        }
+
+        Self { idom, children }
+    }
+
+    /// Returns the nodes `node` immediately dominates, its children in the dominator tree.
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        self.children.get(&node).map_or(&[], |nodes| nodes)
+    }
+
+    /// Returns every reachable node's dominance frontier: the nodes it doesn't strictly
+    /// dominate but has a predecessor of. That's where the values defined in it meet values
+    /// from elsewhere.
+    pub fn frontiers<I: CfgInstruction>(&self, cfg: &Cfg<I>) -> HashMap<NodeId, HashSet<NodeId>> {
+        let mut frontiers: HashMap<NodeId, HashSet<NodeId>> = self
+            .idom
+            .keys()
+            .map(|node| (*node, HashSet::new()))
+            .collect();
+
+        for (node, idom) in &self.idom {
+            let predecessors = cfg.predecessors(*node);
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors {
+                let mut runner = *predecessor;
+                while runner != *idom && self.idom.contains_key(&runner) {
+                    frontiers.get_mut(&runner).unwrap().insert(*node);
+                    runner = self.idom[&runner];
+                }
+            }
+        }
+
+        frontiers
+    }
+
+    /// Returns the closest strict dominator of `node`, or `None` for `Entry` and unreachable
+    /// nodes.
+    pub fn immediate_dominator(&self, node: NodeId) -> Option<NodeId> {
+        match node {
+            NodeId::Entry => None,
+            node => self.idom.get(&node).copied(),
+        }
+    }
+
+    /// Whether `a` dominates `b`. Every node dominates itself, and unreachable nodes are
+    /// dominated by nothing.
+    pub fn dominates(&self, a: NodeId, b: NodeId) -> bool {
+        if !self.idom.contains_key(&b) {
+            return false;
+        }
+
+        let mut node = b;
+        loop {
+            if node == a {
+                return true;
+            }
+            match self.immediate_dominator(node) {
+                Some(idom) => node = idom,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Returns the closest common dominator of `a` and `b`, walking up the tree built so far.
+fn intersect(
+    idom: &HashMap<NodeId, NodeId>,
+    index: &HashMap<NodeId, usize>,
+    mut a: NodeId,
+    mut b: NodeId,
+) -> NodeId {
+    while a != b {
+        while index[&a] > index[&b] {
+            a = idom[&a];
+        }
+        while index[&b] > index[&a] {
+            b = idom[&b];
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::Instruction,
+        test_support::tacky::{int, jump_if_zero, label, ret},
+    };
+
+    #[test]
+    fn test_diamond() {
+        let cfg = Cfg::new(vec![
+            jump_if_zero("c", "else"),
+            label("then"),
+            Instruction::Jump("end".to_owned()),
+            label("else"),
+            label("end"),
+            ret(int(0)),
+        ]);
+        let dominators = Dominators::new(&cfg);
+
+        assert_eq!(
+            dominators.immediate_dominator(NodeId::Block(3)),
+            Some(NodeId::Block(0))
+        );
+        assert_eq!(
+            dominators.immediate_dominator(NodeId::Exit),
+            Some(NodeId::Block(3))
+        );
+        assert!(dominators.dominates(NodeId::Block(0), NodeId::Block(2)));
+        assert!(!dominators.dominates(NodeId::Block(1), NodeId::Block(3)));
+        assert!(dominators.dominates(NodeId::Block(3), NodeId::Block(3)));
+        assert_eq!(dominators.immediate_dominator(NodeId::Entry), None);
+    }
+
+    #[test]
+    fn test_loop() {
+        let cfg = Cfg::new(vec![
+            label("head"),
+            jump_if_zero("c", "end"),
+            label("body"),
+            Instruction::Jump("head".to_owned()),
+            label("end"),
+            ret(int(0)),
+        ]);
+        let dominators = Dominators::new(&cfg);
+
+        assert!(dominators.dominates(NodeId::Block(0), NodeId::Block(1)));
+        assert!(!dominators.dominates(NodeId::Block(1), NodeId::Block(0)));
+        assert_eq!(
+            dominators.immediate_dominator(NodeId::Block(2)),
+            Some(NodeId::Block(0))
+        );
+    }
+
+    #[test]
+    fn test_frontiers() {
+        let cfg = Cfg::new(vec![
+            label("head"),
+            jump_if_zero("c", "end"),
+            jump_if_zero("c", "else"),
+            label("then"),
+            Instruction::Jump("head".to_owned()),
+            label("else"),
+            Instruction::Jump("head".to_owned()),
+            label("end"),
+            ret(int(0)),
+        ]);
+        let dominators = Dominators::new(&cfg);
+        let frontiers = dominators.frontiers(&cfg);
+
+        assert_eq!(
+            frontiers[&NodeId::Block(2)],
+            HashSet::from([NodeId::Block(0)])
+        );
+        assert_eq!(
+            frontiers[&NodeId::Block(3)],
+            HashSet::from([NodeId::Block(0)])
+        );
+        assert_eq!(
+            frontiers[&NodeId::Block(0)],
+            HashSet::from([NodeId::Block(0)])
+        );
+        assert_eq!(frontiers[&NodeId::Block(4)], HashSet::new());
+        assert_eq!(
+            dominators.children(NodeId::Block(1)),
+            [NodeId::Block(2), NodeId::Block(3)]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_block() {
+        let cfg = Cfg::new(vec![ret(int(0)), label("dead"), ret(int(0))]);
+        let dominators = Dominators::new(&cfg);
+
+        assert_eq!(dominators.immediate_dominator(NodeId::Block(1)), None);
+        assert!(!dominators.dominates(NodeId::Entry, NodeId::Block(1)));
+    }
+}
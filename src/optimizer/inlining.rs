@@ -0,0 +1,629 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::tacky::{Function, Instruction, Program, Storage, SymbolTable, TopLevel, Val};
+
+/// Functions with at most this many instructions are inlined at every call.
+const SMALL_FUNCTION_SIZE: usize = 10;
+
+/// Calls aren't inlined into a function that would grow past this many instructions.
+const MAX_CALLER_SIZE: usize = 1000;
+
+/// Replaces calls to small `static` functions, and to `static` functions called from a single
+/// place, with their bodies. Returns the names of the functions calls were inlined into.
+///
+/// Functions that can end up calling themselves are never inlined, and neither is anything
+/// that would grow the caller past `MAX_CALLER_SIZE`. Inlined functions that have no callers
+/// left are removed.
+pub fn inline_functions(program: &mut Program) -> Vec<String> {
+    let functions: HashMap<String, Function> = program
+        .top_level
+        .iter()
+        .filter_map(|top_level| match top_level {
+            TopLevel::Function(function) => Some((function.name.clone(), function.clone())),
+            TopLevel::StaticVariable(_) => None,
+        })
+        .collect();
+
+    let mut call_sites: HashMap<&str, usize> = HashMap::new();
+    for function in functions.values() {
+        for callee in callees(function) {
+            *call_sites.entry(callee).or_default() += 1;
+        }
+    }
+    let address_taken: HashSet<&str> = functions
+        .values()
+        .flat_map(|function| &function.body)
+        .filter_map(|instruction| match instruction {
+            Instruction::GetAddress {
+                src: Val::Var(name),
+                ..
+            } if functions.contains_key(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let recursive = recursive_functions(&functions);
+
+    // A function called from a single place, and never through a pointer, goes away entirely
+    // once inlined, so its size doesn't matter.
+    let inlineable: HashMap<&str, &Function> = functions
+        .iter()
+        .filter(|(name, function)| {
+            !function.global
+                && !recursive.contains(name.as_str())
+                && (function.body.len() <= SMALL_FUNCTION_SIZE
+                    || call_sites.get(name.as_str()) == Some(&1)
+                        && !address_taken.contains(name.as_str()))
+        })
+        .map(|(name, function)| (name.as_str(), function))
+        .collect();
+
+    let mut changed = Vec::new();
+    let mut inlined = HashSet::new();
+    for top_level in program.top_level.iter_mut() {
+        if let TopLevel::Function(function) = top_level {
+            if inline_calls(function, &inlineable, &mut program.symbols, &mut inlined) {
+                changed.push(function.name.clone());
+            }
+        }
+    }
+
+    let referenced: HashSet<String> = program
+        .top_level
+        .iter()
+        .filter_map(|top_level| match top_level {
+            TopLevel::Function(function) => Some(function),
+            TopLevel::StaticVariable(_) => None,
+        })
+        .flat_map(|function| &function.body)
+        .filter_map(|instruction| match instruction {
+            Instruction::FunCall { name, .. }
+            | Instruction::GetAddress {
+                src: Val::Var(name),
+                ..
+            } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    program.top_level.retain(|top_level| match top_level {
+        TopLevel::Function(function) => {
+            !inlined.contains(&function.name) || referenced.contains(&function.name)
+        }
+        TopLevel::StaticVariable(_) => true,
+    });
+
+    changed
+}
+
+fn callees(function: &Function) -> impl Iterator<Item = &str> {
+    function
+        .body
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::FunCall { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+}
+
+/// Returns the functions that can reach a call to themselves.
+fn recursive_functions(functions: &HashMap<String, Function>) -> HashSet<&str> {
+    let mut recursive = HashSet::new();
+
+    for name in functions.keys() {
+        let mut visited = HashSet::new();
+        let mut worklist = vec![name.as_str()];
+
+        while let Some(caller) = worklist.pop() {
+            let Some(function) = functions.get(caller) else {
+                continue;
+            };
+            for callee in callees(function) {
+                if callee == name {
+                    recursive.insert(name.as_str());
+                }
+                if visited.insert(callee) {
+                    worklist.push(callee);
+                }
+            }
+        }
+    }
+
+    recursive
+}
+
+/// Inlines every call in `function` to one of `inlineable`, including those that appear in
+/// inlined bodies, while the size budget allows. Returns whether anything was inlined.
+fn inline_calls(
+    function: &mut Function,
+    inlineable: &HashMap<&str, &Function>,
+    symbols: &mut SymbolTable,
+    inlined: &mut HashSet<String>,
+) -> bool {
+    let mut pending: VecDeque<Instruction> = std::mem::take(&mut function.body).into();
+    let mut changed = false;
+
+    while let Some(instruction) = pending.pop_front() {
+        if let Instruction::FunCall { name, args, dst } = &instruction {
+            if let Some(callee) = inlineable.get(name.as_str()) {
+                let size = function.body.len() + pending.len() + callee.body.len();
+                if size <= MAX_CALLER_SIZE {
+                    for instruction in expand(callee, args, dst.as_ref(), symbols)
+                        .into_iter()
+                        .rev()
+                    {
+                        pending.push_front(instruction);
+                    }
+                    inlined.insert(name.clone());
+                    changed = true;
+                    continue;
+                }
+            }
+        }
+
+        function.body.push(instruction);
+    }
+
+    changed
+}
+
+/// Returns a copy of `callee`'s body that takes its parameters from `args` and leaves its
+/// return value in `dst`, with fresh names for its local variables and labels.
+fn expand(
+    callee: &Function,
+    args: &[Val],
+    dst: Option<&Val>,
+    symbols: &mut SymbolTable,
+) -> Vec<Instruction> {
+    let end = symbols.make_label(&format!("{}.return", callee.name));
+    let mut renamer = Renamer {
+        vars: HashMap::new(),
+        labels: HashMap::new(),
+        symbols,
+    };
+    let mut instructions: Vec<Instruction> = callee
+        .params
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| Instruction::Copy {
+            src: arg.clone(),
+            dst: Val::Var(renamer.var(param)),
+        })
+        .collect();
+
+    for instruction in &callee.body {
+        match instruction {
+            Instruction::Return(val) => {
+                match (val, dst) {
+                    (Some(val), Some(dst)) => instructions.push(Instruction::Copy {
+                        src: renamer.val(val),
+                        dst: dst.clone(),
+                    }),
+                    // The value is unused, but reading a volatile object is still observable.
+                    (Some(Val::Var(name)), None) if renamer.symbols.is_volatile(name) => {
+                        let ty = renamer.symbols.get(name).unwrap().ty.clone();
+                        let tmp = renamer.symbols.make_temporary(ty);
+                        instructions.push(Instruction::Copy {
+                            src: renamer.val(&Val::Var(name.clone())),
+                            dst: Val::Var(tmp),
+                        });
+                    }
+                    _ => (),
+                }
+                instructions.push(Instruction::Jump(end.clone()));
+            }
+            instruction => instructions.push(renamer.instruction(instruction)),
+        }
+    }
+
+    instructions.push(Instruction::Label(end));
+    instructions
+}
+
+/// Gives the locals and labels of an inlined body fresh names, the same one for every
+/// occurrence.
+struct Renamer<'a> {
+    vars: HashMap<String, String>,
+    labels: HashMap<String, String>,
+    symbols: &'a mut SymbolTable,
+}
+
+impl Renamer<'_> {
+    fn var(&mut self, name: &str) -> String {
+        // Static variables and functions are shared by every copy of the body.
+        let local = self
+            .symbols
+            .get(name)
+            .is_none_or(|symbol| symbol.storage == Storage::Local);
+        if !local {
+            return name.to_owned();
+        }
+
+        if let Some(renamed) = self.vars.get(name) {
+            return renamed.clone();
+        }
+        let renamed = self.symbols.make_copy(name);
+        self.vars.insert(name.to_owned(), renamed.clone());
+        renamed
+    }
+
+    fn label(&mut self, label: &str) -> String {
+        if let Some(renamed) = self.labels.get(label) {
+            return renamed.clone();
+        }
+        let renamed = self.symbols.make_label(label);
+        self.labels.insert(label.to_owned(), renamed.clone());
+        renamed
+    }
+
+    fn val(&mut self, val: &Val) -> Val {
+        match val {
+            Val::Constant(c) => Val::Constant(*c),
+            Val::Var(name) => Val::Var(self.var(name)),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) -> Instruction {
+        let mut instruction = instruction.clone();
+        for val in instruction.sources_mut() {
+            *val = self.val(val);
+        }
+        if let Some(dst) = instruction.destination_mut() {
+            *dst = self.val(dst);
+        }
+
+        // What's left are operands that name an object instead of reading or writing its
+        // value, and labels.
+        match &mut instruction {
+            Instruction::GetAddress { src, .. } => *src = self.val(src),
+            Instruction::CopyToOffset { dst: name, .. }
+            | Instruction::CopyFromOffset { src: name, .. } => *name = self.var(name),
+            Instruction::Jump(label)
+            | Instruction::Label(label)
+            | Instruction::JumpIfZero { target: label, .. }
+            | Instruction::JumpIfNotZero { target: label, .. } => *label = self.label(label),
+            _ => (),
+        }
+        instruction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::{BinaryOp, Type},
+        test_support::tacky::{int, span, symbol, var},
+    };
+
+    fn call(name: &str, args: Vec<Val>, dst: &str) -> Instruction {
+        Instruction::FunCall {
+            name: name.to_owned(),
+            args,
+            dst: Some(var(dst)),
+        }
+    }
+
+    fn function(name: &str, global: bool, params: &[&str], body: Vec<Instruction>) -> TopLevel {
+        TopLevel::Function(Function {
+            name: name.to_owned(),
+            global,
+            params: params.iter().map(|param| param.to_string()).collect(),
+            body,
+            span: span(),
+        })
+    }
+
+    /// `static int inc(int a) { return a + 1; }`
+    fn inc() -> TopLevel {
+        function(
+            "inc",
+            false,
+            &["a"],
+            vec![
+                Instruction::Binary {
+                    op: BinaryOp::Add,
+                    src1: var("a"),
+                    src2: int(1),
+                    dst: var("tmp.0"),
+                },
+                Instruction::Return(Some(var("tmp.0"))),
+            ],
+        )
+    }
+
+    fn names(program: &Program) -> Vec<&str> {
+        program
+            .top_level
+            .iter()
+            .filter_map(|top_level| match top_level {
+                TopLevel::Function(function) => Some(function.name.as_str()),
+                TopLevel::StaticVariable(_) => None,
+            })
+            .collect()
+    }
+
+    fn body<'a>(program: &'a Program, name: &str) -> &'a [Instruction] {
+        program
+            .top_level
+            .iter()
+            .find_map(|top_level| match top_level {
+                TopLevel::Function(function) if function.name == name => Some(&function.body),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_inline_small_static_function() {
+        let mut program = Program {
+            top_level: vec![
+                inc(),
+                function(
+                    "main",
+                    true,
+                    &[],
+                    vec![
+                        call("inc", vec![int(2)], "x"),
+                        Instruction::Return(Some(var("x"))),
+                    ],
+                ),
+            ],
+            symbols: SymbolTable::new(),
+        };
+
+        assert_eq!(inline_functions(&mut program), vec!["main"]);
+        assert_eq!(names(&program), vec!["main"]);
+        assert_eq!(
+            body(&program, "main"),
+            [
+                Instruction::Copy {
+                    src: int(2),
+                    dst: var("a.1"),
+                },
+                Instruction::Binary {
+                    op: BinaryOp::Add,
+                    src1: var("a.1"),
+                    src2: int(1),
+                    dst: var("tmp.0.2"),
+                },
+                Instruction::Copy {
+                    src: var("tmp.0.2"),
+                    dst: var("x"),
+                },
+                Instruction::Jump("inc.return.0".to_owned()),
+                Instruction::Label("inc.return.0".to_owned()),
+                Instruction::Return(Some(var("x"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_each_copy_gets_fresh_names() {
+        let mut program = Program {
+            top_level: vec![
+                inc(),
+                function(
+                    "main",
+                    true,
+                    &[],
+                    vec![
+                        call("inc", vec![int(1)], "x"),
+                        call("inc", vec![var("x")], "y"),
+                        Instruction::Return(Some(var("y"))),
+                    ],
+                ),
+            ],
+            symbols: SymbolTable::new(),
+        };
+        inline_functions(&mut program);
+
+        let labels: HashSet<&String> = body(&program, "main")
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Label(label) => Some(label),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(labels.len(), 2);
+        assert!(!body(&program, "main")
+            .iter()
+            .any(|instruction| instruction.sources().contains(&&var("a"))));
+    }
+
+    #[test]
+    fn test_rename_aggregate_and_address_operands() {
+        let mut program = Program {
+            top_level: vec![
+                function(
+                    "get",
+                    false,
+                    &["a"],
+                    vec![
+                        Instruction::CopyToOffset {
+                            src: var("a"),
+                            dst: "s".to_owned(),
+                            offset: 4,
+                        },
+                        Instruction::GetAddress {
+                            src: var("s"),
+                            dst: var("p"),
+                        },
+                        Instruction::CopyFromOffset {
+                            src: "s".to_owned(),
+                            offset: 4,
+                            dst: var("r"),
+                        },
+                        Instruction::Return(Some(var("r"))),
+                    ],
+                ),
+                function(
+                    "main",
+                    true,
+                    &[],
+                    vec![
+                        call("get", vec![int(7)], "x"),
+                        Instruction::Return(Some(var("x"))),
+                    ],
+                ),
+            ],
+            symbols: SymbolTable::new(),
+        };
+        inline_functions(&mut program);
+
+        assert_eq!(
+            body(&program, "main"),
+            [
+                Instruction::Copy {
+                    src: int(7),
+                    dst: var("a.1"),
+                },
+                Instruction::CopyToOffset {
+                    src: var("a.1"),
+                    dst: "s.2".to_owned(),
+                    offset: 4,
+                },
+                Instruction::GetAddress {
+                    src: var("s.2"),
+                    dst: var("p.3"),
+                },
+                Instruction::CopyFromOffset {
+                    src: "s.2".to_owned(),
+                    offset: 4,
+                    dst: var("r.4"),
+                },
+                Instruction::Copy {
+                    src: var("r.4"),
+                    dst: var("x"),
+                },
+                Instruction::Jump("get.return.0".to_owned()),
+                Instruction::Label("get.return.0".to_owned()),
+                Instruction::Return(Some(var("x"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_global_and_recursive_functions() {
+        let mut program = Program {
+            top_level: vec![
+                function("get", true, &[], vec![Instruction::Return(Some(int(1)))]),
+                function(
+                    "even",
+                    false,
+                    &["n"],
+                    vec![
+                        call("odd", vec![var("n")], "r"),
+                        Instruction::Return(Some(var("r"))),
+                    ],
+                ),
+                function(
+                    "odd",
+                    false,
+                    &["n"],
+                    vec![
+                        call("even", vec![var("n")], "r"),
+                        Instruction::Return(Some(var("r"))),
+                    ],
+                ),
+                function(
+                    "main",
+                    true,
+                    &[],
+                    vec![
+                        call("get", vec![], "x"),
+                        call("even", vec![var("x")], "y"),
+                        Instruction::Return(Some(var("y"))),
+                    ],
+                ),
+            ],
+            symbols: SymbolTable::new(),
+        };
+        let before = body(&program, "main").to_vec();
+
+        assert!(inline_functions(&mut program).is_empty());
+        assert_eq!(names(&program), vec!["get", "even", "odd", "main"]);
+        assert_eq!(body(&program, "main"), before);
+    }
+
+    #[test]
+    fn test_inline_large_function_called_once() {
+        let large: Vec<Instruction> = (0..=SMALL_FUNCTION_SIZE)
+            .map(|_| Instruction::Copy {
+                src: int(1),
+                dst: var("v"),
+            })
+            .chain([Instruction::Return(None)])
+            .collect();
+        let mut program = Program {
+            top_level: vec![
+                function("once", false, &[], large.clone()),
+                function("twice", false, &[], large),
+                function(
+                    "main",
+                    true,
+                    &[],
+                    vec![
+                        Instruction::FunCall {
+                            name: "once".to_owned(),
+                            args: vec![],
+                            dst: None,
+                        },
+                        Instruction::FunCall {
+                            name: "twice".to_owned(),
+                            args: vec![],
+                            dst: None,
+                        },
+                        Instruction::FunCall {
+                            name: "twice".to_owned(),
+                            args: vec![],
+                            dst: None,
+                        },
+                        Instruction::Return(None),
+                    ],
+                ),
+            ],
+            symbols: SymbolTable::new(),
+        };
+        inline_functions(&mut program);
+
+        assert_eq!(names(&program), vec!["twice", "main"]);
+    }
+
+    #[test]
+    fn test_keep_static_and_volatile_accesses() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("counter", symbol(Type::Int, Storage::Static, true));
+        let mut program = Program {
+            top_level: vec![
+                function(
+                    "read",
+                    false,
+                    &[],
+                    vec![Instruction::Return(Some(var("counter")))],
+                ),
+                function(
+                    "main",
+                    true,
+                    &[],
+                    vec![
+                        Instruction::FunCall {
+                            name: "read".to_owned(),
+                            args: vec![],
+                            dst: None,
+                        },
+                        Instruction::Return(None),
+                    ],
+                ),
+            ],
+            symbols,
+        };
+        inline_functions(&mut program);
+
+        assert_eq!(
+            body(&program, "main")[0],
+            Instruction::Copy {
+                src: var("counter"),
+                dst: var("tmp.1"),
+            }
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+
+use super::{
+    dataflow::{Analysis, Direction},
+    Aliased,
+};
+use crate::tacky::{Instruction, Val};
+
+/// Backward analysis of which variables may still be read before they're overwritten.
+///
+/// Only variables the function fully controls are tracked: aliased variables can be read
+/// through pointers or by other functions at any point, so callers must treat them as always
+/// live.
+pub struct Liveness<'a> {
+    pub aliased: Aliased<'a>,
+}
+
+impl Analysis for Liveness<'_> {
+    type Instruction = Instruction;
+    type Fact = HashSet<String>;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn boundary(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().cloned());
+    }
+
+    fn transfer(&self, instruction: &Instruction, fact: &mut Self::Fact) {
+        if let Some(Val::Var(dst)) = instruction.destination() {
+            fact.remove(dst);
+        }
+
+        let aggregate = match instruction {
+            Instruction::CopyFromOffset { src, .. } => Some(src),
+            _ => None,
+        };
+        let read = instruction
+            .sources()
+            .into_iter()
+            .filter_map(|val| match val {
+                Val::Var(name) => Some(name),
+                Val::Constant(_) => None,
+            })
+            .chain(aggregate);
+
+        for name in read {
+            if !self.aliased.contains(name) {
+                fact.insert(name.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        optimizer::{cfg::Cfg, dataflow},
+        tacky::{BinaryOp, Function, Storage, SymbolTable, Type},
+        test_support::tacky::{span, symbol, var},
+    };
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_liveness() {
+        let body = vec![
+            Instruction::Binary {
+                op: BinaryOp::Add,
+                src1: var("a"),
+                src2: var("b"),
+                dst: var("x"),
+            },
+            Instruction::JumpIfZero {
+                condition: var("c"),
+                target: "end".to_owned(),
+            },
+            Instruction::Copy {
+                src: var("a"),
+                dst: var("x"),
+            },
+            Instruction::Label("end".to_owned()),
+            Instruction::Return(Some(var("x"))),
+        ];
+        let function = Function {
+            name: "f".to_owned(),
+            global: true,
+            params: vec![],
+            body: body.clone(),
+            span: span(),
+        };
+        let symbols = SymbolTable::new();
+        let analysis = Liveness {
+            aliased: Aliased::new(&function, &symbols),
+        };
+        let cfg = Cfg::new(body);
+        let solution = dataflow::solve(&cfg, &analysis);
+
+        assert_eq!(*solution.block_start(0), set(&["a", "b", "c"]));
+        assert_eq!(*solution.block_end(0), set(&["a", "x"]));
+        assert_eq!(*solution.block_start(1), set(&["a"]));
+        assert_eq!(*solution.block_start(2), set(&["x"]));
+        assert_eq!(
+            solution.instruction_facts(&cfg, &analysis, 0),
+            vec![set(&["a", "c", "x"]), set(&["a", "x"])]
+        );
+    }
+
+    #[test]
+    fn test_aliased_variables_are_not_tracked() {
+        let body = vec![Instruction::Return(Some(var("g")))];
+        let function = Function {
+            name: "f".to_owned(),
+            global: true,
+            params: vec![],
+            body: body.clone(),
+            span: span(),
+        };
+        let mut symbols = SymbolTable::new();
+        symbols.insert("g", symbol(Type::Int, Storage::Static, false));
+        let analysis = Liveness {
+            aliased: Aliased::new(&function, &symbols),
+        };
+        let solution = dataflow::solve(&Cfg::new(body), &analysis);
+
+        assert_eq!(*solution.block_start(0), set(&[]));
+    }
+}
@@ -0,0 +1,869 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    cfg::{Cfg, NodeId},
+    constant_folding::{eval_binary, eval_unary, is_zero},
+    dataflow,
+    dominators::Dominators,
+    liveness::Liveness,
+    Aliased,
+};
+use crate::tacky::{BinaryOp, Const, Function, Instruction, SymbolTable, Type, UnaryOp, Val};
+
+/// A natural loop: the blocks that can reach a back edge into `header` without going through
+/// `header` itself.
+#[derive(Debug, PartialEq)]
+struct Loop {
+    header: usize,
+    blocks: HashSet<usize>,
+}
+
+/// Moves computations whose result doesn't change between iterations into the block before
+/// each loop, and turns multiplications by induction variables into additions that follow
+/// the induction variable.
+///
+/// Every loop first gets a preheader: a block that all entries into the loop, and nothing
+/// else, pass through. Inner loops are handled before the loops containing them, so invariant
+/// code can travel out through several levels.
+pub fn optimize_loops(function: &mut Function, symbols: &mut SymbolTable) {
+    insert_preheaders(function, symbols);
+
+    let mut cfg = Cfg::new(function.body.clone());
+    let dominators = Dominators::new(&cfg);
+
+    for lp in natural_loops(&cfg, &dominators) {
+        let Some(preheader) = preheader(&cfg, &lp) else {
+            continue;
+        };
+
+        // `function.body` is only read for the variables whose address it takes, which
+        // moving instructions around doesn't change.
+        let liveness = Liveness {
+            aliased: Aliased::new(function, symbols),
+        };
+        hoist_invariants(&mut cfg, &lp, preheader, &dominators, &liveness, symbols);
+        let reductions = find_reductions(&cfg, &lp, &liveness.aliased, symbols);
+        reduce_strength(&mut cfg, preheader, reductions, symbols);
+    }
+
+    function.body = cfg.into_instructions();
+}
+
+/// Returns the natural loops of `cfg`, innermost first. Loops sharing a header are merged.
+fn natural_loops(cfg: &Cfg<Instruction>, dominators: &Dominators) -> Vec<Loop> {
+    let mut loops: Vec<Loop> = Vec::new();
+
+    for id in cfg.block_ids() {
+        for successor in cfg.successors(NodeId::Block(*id)) {
+            let NodeId::Block(header) = *successor else {
+                continue;
+            };
+            if !dominators.dominates(*successor, NodeId::Block(*id)) {
+                continue;
+            }
+
+            let index = match loops.iter().position(|lp| lp.header == header) {
+                Some(index) => index,
+                None => {
+                    loops.push(Loop {
+                        header,
+                        blocks: HashSet::from([header]),
+                    });
+                    loops.len() - 1
+                }
+            };
+            let lp = &mut loops[index];
+
+            let mut worklist = vec![*id];
+            while let Some(block) = worklist.pop() {
+                if !lp.blocks.insert(block) {
+                    continue;
+                }
+                for predecessor in cfg.predecessors(NodeId::Block(block)) {
+                    // Unreachable blocks may jump into the loop, but aren't part of it.
+                    if let NodeId::Block(predecessor) = predecessor {
+                        if dominators.dominates(*successor, NodeId::Block(*predecessor)) {
+                            worklist.push(*predecessor);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    loops.sort_by_key(|lp| lp.blocks.len());
+    loops
+}
+
+/// Returns the loop's preheader, if it has one: a block outside the loop that is the only way
+/// in and leads nowhere else.
+fn preheader(cfg: &Cfg<Instruction>, lp: &Loop) -> Option<usize> {
+    let outside: Vec<&NodeId> = cfg
+        .predecessors(NodeId::Block(lp.header))
+        .iter()
+        .filter(|node| !matches!(node, NodeId::Block(id) if lp.blocks.contains(id)))
+        .collect();
+
+    match outside[..] {
+        [NodeId::Block(id)]
+            if cfg.successors(NodeId::Block(*id)) == [NodeId::Block(lp.header)]
+                && !matches!(
+                    cfg.instructions(*id).last(),
+                    Some(Instruction::JumpIfZero { .. } | Instruction::JumpIfNotZero { .. })
+                ) =>
+        {
+            Some(*id)
+        }
+        _ => None,
+    }
+}
+
+/// Gives every loop without a preheader a new, empty one, placed right before its header.
+fn insert_preheaders(function: &mut Function, symbols: &mut SymbolTable) {
+    let mut cfg = Cfg::new(std::mem::take(&mut function.body));
+    let dominators = Dominators::new(&cfg);
+    let mut preheaders = Vec::new();
+
+    for lp in natural_loops(&cfg, &dominators) {
+        if preheader(&cfg, &lp).is_some() {
+            continue;
+        }
+        // Back edges are jumps, so every header starts with a label.
+        let Some(Instruction::Label(header)) = cfg.instructions(lp.header).first().cloned() else {
+            continue;
+        };
+        let label = symbols.make_label(&format!("{header}.preheader"));
+
+        let ids = cfg.block_ids();
+        let position = ids.iter().position(|id| *id == lp.header).unwrap();
+        let previous = position.checked_sub(1).map(|i| ids[i]);
+
+        for predecessor in cfg.predecessors(NodeId::Block(lp.header)).to_vec() {
+            let NodeId::Block(id) = predecessor else {
+                continue;
+            };
+            let instructions = cfg.instructions_mut(id);
+
+            if lp.blocks.contains(&id) {
+                // A block inside the loop that used to fall through into the header has to
+                // jump over the preheader instead.
+                let falls_through = !matches!(
+                    instructions.last(),
+                    Some(Instruction::Jump(_) | Instruction::Return(_))
+                );
+                if Some(id) == previous && falls_through {
+                    instructions.push(Instruction::Jump(header.clone()));
+                }
+            } else {
+                match instructions.last_mut() {
+                    Some(
+                        Instruction::Jump(target)
+                        | Instruction::JumpIfZero { target, .. }
+                        | Instruction::JumpIfNotZero { target, .. },
+                    ) if *target == header => *target = label.clone(),
+                    _ => (),
+                }
+            }
+        }
+
+        preheaders.push((header, label));
+    }
+
+    let mut body = cfg.into_instructions();
+    for (header, label) in preheaders {
+        let position = body
+            .iter()
+            .position(|instruction| *instruction == Instruction::Label(header.clone()))
+            .unwrap();
+        body.insert(position, Instruction::Label(label));
+    }
+    function.body = body;
+}
+
+/// Appends `instruction` to the preheader, before the jump it may end with.
+fn append_to_preheader(cfg: &mut Cfg<Instruction>, preheader: usize, instruction: Instruction) {
+    let instructions = cfg.instructions_mut(preheader);
+    let position = match instructions.last() {
+        Some(Instruction::Jump(_)) => instructions.len() - 1,
+        _ => instructions.len(),
+    };
+    instructions.insert(position, instruction);
+}
+
+/// The variable `instruction` writes to, in full or in part, if any.
+fn defined(instruction: &Instruction) -> Option<&str> {
+    match instruction {
+        Instruction::CopyToOffset { dst, .. } => Some(dst),
+        instruction => match instruction.destination() {
+            Some(Val::Var(name)) => Some(name),
+            _ => None,
+        },
+    }
+}
+
+/// Counts the instructions in the loop that write each variable.
+fn definitions(cfg: &Cfg<Instruction>, lp: &Loop) -> HashMap<String, usize> {
+    let mut definitions = HashMap::new();
+    for id in &lp.blocks {
+        for instruction in cfg.instructions(*id) {
+            if let Some(name) = defined(instruction) {
+                *definitions.entry(name.to_owned()).or_default() += 1;
+            }
+        }
+    }
+    definitions
+}
+
+/// Whether `instruction` can run before the loop instead of inside it without trapping or
+/// touching memory, provided its operands don't change in the loop.
+fn is_pure(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Binary {
+            op: BinaryOp::Divide | BinaryOp::Remainder,
+            src2,
+            ..
+        } => match src2 {
+            // Dividing by zero, or the most negative value by -1, traps.
+            Val::Constant(c) => {
+                !is_zero(*c) && !matches!(c, Const::Char(-1) | Const::Int(-1) | Const::Long(-1))
+            }
+            Val::Var(_) => false,
+        },
+        // Out-of-range conversions from `double` are undefined, so they can't be run on
+        // iterations that wouldn't have run them.
+        Instruction::DoubleToInt { .. } | Instruction::DoubleToUInt { .. } => false,
+        Instruction::Copy { .. }
+        | Instruction::Unary { .. }
+        | Instruction::Binary { .. }
+        | Instruction::SignExtend { .. }
+        | Instruction::Truncate { .. }
+        | Instruction::ZeroExtend { .. }
+        | Instruction::IntToDouble { .. }
+        | Instruction::UIntToDouble { .. }
+        | Instruction::GetAddress { .. }
+        | Instruction::AddPtr { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether `val` has the same value everywhere in a loop with the given `definitions`.
+fn is_invariant(
+    val: &Val,
+    definitions: &HashMap<String, usize>,
+    aliased: &Aliased,
+    symbols: &SymbolTable,
+) -> bool {
+    match val {
+        Val::Constant(_) => true,
+        Val::Var(name) => {
+            !definitions.contains_key(name) && !aliased.contains(name) && !symbols.is_volatile(name)
+        }
+    }
+}
+
+/// Moves pure instructions with invariant operands to the preheader, repeating until no more
+/// can move, since each one moved can make others invariant.
+///
+/// An instruction only moves if it's the sole definition of its destination in the loop and
+/// no iteration reads the destination before it runs. Unless it runs on every path out of the
+/// loop, its destination must also be dead once the loop exits.
+fn hoist_invariants(
+    cfg: &mut Cfg<Instruction>,
+    lp: &Loop,
+    preheader: usize,
+    dominators: &Dominators,
+    liveness: &Liveness,
+    symbols: &SymbolTable,
+) {
+    let solution = dataflow::solve(cfg, liveness);
+    let aliased = &liveness.aliased;
+    let blocks: Vec<usize> = cfg
+        .block_ids()
+        .iter()
+        .copied()
+        .filter(|id| lp.blocks.contains(id))
+        .collect();
+
+    let mut exiting = Vec::new();
+    let mut live_after_loop = HashSet::new();
+    for id in &blocks {
+        for successor in cfg.successors(NodeId::Block(*id)) {
+            match successor {
+                NodeId::Block(other) if lp.blocks.contains(other) => continue,
+                NodeId::Block(other) => live_after_loop.extend(solution.block_start(*other)),
+                NodeId::Entry | NodeId::Exit => (),
+            }
+            exiting.push(NodeId::Block(*id));
+        }
+    }
+    let live_into_header = solution.block_start(lp.header);
+    let mut definitions = definitions(cfg, lp);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for id in &blocks {
+            let runs_on_exit = exiting
+                .iter()
+                .all(|exit| dominators.dominates(NodeId::Block(*id), *exit));
+
+            let mut i = 0;
+            while i < cfg.instructions(*id).len() {
+                let instruction = &cfg.instructions(*id)[i];
+                let hoistable = match instruction.destination() {
+                    Some(Val::Var(dst)) if is_pure(instruction) => {
+                        definitions.get(dst) == Some(&1)
+                            && !aliased.contains(dst)
+                            && !symbols.is_volatile(dst)
+                            && !live_into_header.contains(dst)
+                            && (runs_on_exit || !live_after_loop.contains(dst))
+                            && instruction
+                                .sources()
+                                .into_iter()
+                                .all(|src| is_invariant(src, &definitions, aliased, symbols))
+                    }
+                    _ => false,
+                };
+
+                if hoistable {
+                    let instruction = cfg.instructions_mut(*id).remove(i);
+                    definitions.remove(defined(&instruction).unwrap());
+                    append_to_preheader(cfg, preheader, instruction);
+                    changed = true;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A basic induction variable: one whose only definition in the loop adds or subtracts a
+/// constant.
+struct InductionVariable {
+    block: usize,
+    index: usize,
+    op: BinaryOp,
+    step: Const,
+}
+
+/// An instruction computing a linear function of an induction variable, to be replaced by a
+/// copy of a new variable that's kept equal to that function.
+struct Reduction {
+    block: usize,
+    index: usize,
+    dst: String,
+    /// Where the induction variable is defined; the new variable is updated right after.
+    update_after: (usize, usize),
+    /// Computes the new variable's value on entry to the loop, in the preheader.
+    init: Instruction,
+    /// Keeps the new variable in step with the induction variable.
+    update: Instruction,
+}
+
+/// Stands for the new variable of a `Reduction` until it's created. No real variable can have
+/// an empty name.
+const REDUCED: &str = "";
+
+fn is_integer(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Char | Type::SChar | Type::UChar | Type::Int | Type::UInt | Type::Long | Type::ULong
+    )
+}
+
+/// Matches `dst = var + step`, `dst = step + var` or `dst = var - step`.
+fn increment(instruction: &Instruction) -> Option<(BinaryOp, &str, Const, &str)> {
+    match instruction {
+        Instruction::Binary {
+            op: op @ (BinaryOp::Add | BinaryOp::Subtract),
+            src1: Val::Var(var),
+            src2: Val::Constant(step),
+            dst: Val::Var(dst),
+        }
+        | Instruction::Binary {
+            op: op @ BinaryOp::Add,
+            src1: Val::Constant(step),
+            src2: Val::Var(var),
+            dst: Val::Var(dst),
+        } => Some((*op, var, *step, dst)),
+        _ => None,
+    }
+}
+
+/// Finds the induction variables of `lp`, either updated in place (`i = i + 1`) or, as TACKY
+/// generation lowers assignments, through a temporary (`tmp = i + 1; i = tmp`). Either way the
+/// variable is considered updated at its single definition.
+fn induction_variables(
+    cfg: &Cfg<Instruction>,
+    lp: &Loop,
+    definitions: &HashMap<String, usize>,
+    aliased: &Aliased,
+    symbols: &SymbolTable,
+) -> HashMap<String, InductionVariable> {
+    let mut variables = HashMap::new();
+
+    for block in &lp.blocks {
+        let instructions = cfg.instructions(*block);
+        for (index, instruction) in instructions.iter().enumerate() {
+            let (op, step, dst) = match instruction {
+                Instruction::Copy {
+                    src: Val::Var(tmp),
+                    dst: Val::Var(dst),
+                } if definitions.get(tmp) == Some(&1) => {
+                    let earlier = instructions[..index].iter().rev().find_map(|instruction| {
+                        increment(instruction).filter(|(_, _, _, defined)| *defined == tmp)
+                    });
+                    match earlier {
+                        Some((op, var, step, _)) if var == dst => (op, step, dst.as_str()),
+                        _ => continue,
+                    }
+                }
+                instruction => match increment(instruction) {
+                    Some((op, var, step, dst)) if var == dst => (op, step, dst),
+                    _ => continue,
+                },
+            };
+
+            if definitions.get(dst) == Some(&1)
+                && !aliased.contains(dst)
+                && !symbols.is_volatile(dst)
+                && symbols
+                    .get(dst)
+                    .is_some_and(|symbol| is_integer(&symbol.ty))
+            {
+                variables.insert(
+                    dst.to_owned(),
+                    InductionVariable {
+                        block: *block,
+                        index,
+                        op,
+                        step,
+                    },
+                );
+            }
+        }
+    }
+
+    variables
+}
+
+/// Finds multiplications of an induction variable by a constant, and pointer arithmetic with
+/// an induction variable as the index into an invariant pointer.
+fn find_reductions(
+    cfg: &Cfg<Instruction>,
+    lp: &Loop,
+    aliased: &Aliased,
+    symbols: &SymbolTable,
+) -> Vec<Reduction> {
+    let definitions = definitions(cfg, lp);
+    let variables = induction_variables(cfg, lp, &definitions, aliased, symbols);
+    let reduced = || Val::Var(REDUCED.to_owned());
+    let mut reductions = Vec::new();
+
+    for block in &lp.blocks {
+        for (index, instruction) in cfg.instructions(*block).iter().enumerate() {
+            let (init, update, dst) = match instruction {
+                Instruction::Binary {
+                    op: BinaryOp::Multiply,
+                    src1,
+                    src2,
+                    dst: Val::Var(dst),
+                } => {
+                    let (var, factor) = match (src1, src2) {
+                        (Val::Var(var), Val::Constant(c)) | (Val::Constant(c), Val::Var(var)) => {
+                            (var, *c)
+                        }
+                        _ => continue,
+                    };
+                    let Some(iv) = variables.get(var) else {
+                        continue;
+                    };
+                    let Ok(increment) = eval_binary(BinaryOp::Multiply, iv.step, factor) else {
+                        continue;
+                    };
+
+                    let init = Instruction::Binary {
+                        op: BinaryOp::Multiply,
+                        src1: Val::Var(var.clone()),
+                        src2: Val::Constant(factor),
+                        dst: reduced(),
+                    };
+                    let update = Instruction::Binary {
+                        op: iv.op,
+                        src1: reduced(),
+                        src2: Val::Constant(increment),
+                        dst: reduced(),
+                    };
+                    (init, (iv.block, iv.index, update), dst)
+                }
+                Instruction::AddPtr {
+                    ptr,
+                    index: Val::Var(var),
+                    scale,
+                    dst: Val::Var(dst),
+                } if is_invariant(ptr, &definitions, aliased, symbols) => {
+                    let Some(iv) = variables.get(var) else {
+                        continue;
+                    };
+                    let step = match iv.op {
+                        BinaryOp::Subtract => match eval_unary(UnaryOp::Negate, iv.step) {
+                            Ok(step) => step,
+                            Err(_) => continue,
+                        },
+                        _ => iv.step,
+                    };
+
+                    let init = Instruction::AddPtr {
+                        ptr: ptr.clone(),
+                        index: Val::Var(var.clone()),
+                        scale: *scale,
+                        dst: reduced(),
+                    };
+                    let update = Instruction::AddPtr {
+                        ptr: reduced(),
+                        index: Val::Constant(step),
+                        scale: *scale,
+                        dst: reduced(),
+                    };
+                    (init, (iv.block, iv.index, update), dst)
+                }
+                _ => continue,
+            };
+
+            if aliased.contains(dst) || symbols.is_volatile(dst) || symbols.get(dst).is_none() {
+                continue;
+            }
+            let (iv_block, iv_index, update) = update;
+            reductions.push(Reduction {
+                block: *block,
+                index,
+                dst: dst.clone(),
+                update_after: (iv_block, iv_index),
+                init,
+                update,
+            });
+        }
+    }
+
+    reductions
+}
+
+fn reduce_strength(
+    cfg: &mut Cfg<Instruction>,
+    preheader: usize,
+    reductions: Vec<Reduction>,
+    symbols: &mut SymbolTable,
+) {
+    let mut updates = Vec::new();
+
+    for reduction in reductions {
+        let ty = symbols.get(&reduction.dst).unwrap().ty.clone();
+        let name = symbols.make_temporary(ty);
+        let rename = |instruction: Instruction| match instruction {
+            Instruction::Binary { op, src1, src2, .. } => Instruction::Binary {
+                op,
+                src1: rename_reduced(src1, &name),
+                src2: rename_reduced(src2, &name),
+                dst: Val::Var(name.clone()),
+            },
+            Instruction::AddPtr {
+                ptr, index, scale, ..
+            } => Instruction::AddPtr {
+                ptr: rename_reduced(ptr, &name),
+                index,
+                scale,
+                dst: Val::Var(name.clone()),
+            },
+            instruction => instruction,
+        };
+
+        append_to_preheader(cfg, preheader, rename(reduction.init));
+        updates.push((reduction.update_after, rename(reduction.update)));
+        cfg.instructions_mut(reduction.block)[reduction.index] = Instruction::Copy {
+            src: Val::Var(name.clone()),
+            dst: Val::Var(reduction.dst),
+        };
+    }
+
+    // Later positions first, so earlier ones stay valid.
+    updates.sort_by_key(|((block, index), _)| (*block, *index));
+    for ((block, index), update) in updates.into_iter().rev() {
+        cfg.instructions_mut(block).insert(index + 1, update);
+    }
+}
+
+fn rename_reduced(val: Val, name: &str) -> Val {
+    match val {
+        Val::Var(var) if var == REDUCED => Val::Var(name.to_owned()),
+        val => val,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::Storage,
+        test_support::tacky::{
+            binary, function, int, jump, jump_if_zero, label, symbol, symbols, var,
+        },
+    };
+
+    /// `for (i = 0; i < n; i = i + 1) { ... }` around `body`, followed by `return sum`.
+    fn counted_loop(body: Vec<Instruction>) -> Vec<Instruction> {
+        let mut instructions = vec![
+            Instruction::Copy {
+                src: int(0),
+                dst: var("i"),
+            },
+            label("start"),
+            binary(BinaryOp::LessThan, var("i"), var("n"), "cond"),
+            jump_if_zero("cond", "end"),
+        ];
+        instructions.extend(body);
+        instructions.extend([
+            binary(BinaryOp::Add, var("i"), int(1), "i"),
+            jump("start"),
+            label("end"),
+            Instruction::Return(Some(var("sum"))),
+        ]);
+        instructions
+    }
+
+    #[test]
+    fn test_natural_loops_innermost_first() {
+        let cfg = Cfg::new(vec![
+            label("outer"),
+            jump_if_zero("c", "end"),
+            label("inner"),
+            jump_if_zero("d", "next"),
+            jump("inner"),
+            label("next"),
+            jump("outer"),
+            label("end"),
+            Instruction::Return(None),
+        ]);
+        let dominators = Dominators::new(&cfg);
+
+        assert_eq!(
+            natural_loops(&cfg, &dominators),
+            vec![
+                Loop {
+                    header: 1,
+                    blocks: HashSet::from([1, 2]),
+                },
+                Loop {
+                    header: 0,
+                    blocks: HashSet::from([0, 1, 2, 3]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_preheader() {
+        let mut f = function(
+            &["a", "b", "n"],
+            vec![
+                label("loop"),
+                binary(BinaryOp::Add, var("a"), var("b"), "x"),
+                jump_if_zero("x", "loop"),
+                Instruction::Return(Some(var("x"))),
+            ],
+        );
+        insert_preheaders(&mut f, &mut SymbolTable::new());
+
+        assert_eq!(f.body[0], label("loop.preheader.0"));
+        assert_eq!(f.body[1], label("loop"));
+    }
+
+    #[test]
+    fn test_preheader_is_jumped_to() {
+        // A loop laid out with its condition at the bottom, entered by a conditional jump.
+        let mut f = function(
+            &["a", "b", "n"],
+            vec![
+                jump_if_zero("c", "test"),
+                Instruction::Return(None),
+                label("body"),
+                binary(BinaryOp::Add, var("s"), int(1), "s"),
+                label("test"),
+                jump_if_zero("d", "body"),
+                Instruction::Return(None),
+            ],
+        );
+        insert_preheaders(&mut f, &mut SymbolTable::new());
+
+        assert_eq!(
+            f.body,
+            vec![
+                jump_if_zero("c", "test.preheader.0"),
+                Instruction::Return(None),
+                label("body"),
+                binary(BinaryOp::Add, var("s"), int(1), "s"),
+                jump("test"),
+                label("test.preheader.0"),
+                label("test"),
+                jump_if_zero("d", "body"),
+                Instruction::Return(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hoist_invariant_computation() {
+        let mut f = function(
+            &["a", "b", "n"],
+            counted_loop(vec![
+                binary(BinaryOp::Multiply, var("a"), var("b"), "inv"),
+                binary(BinaryOp::Add, var("inv"), int(1), "inv2"),
+                binary(BinaryOp::Add, var("sum"), var("inv2"), "sum"),
+            ]),
+        );
+        optimize_loops(&mut f, &mut SymbolTable::new());
+
+        assert_eq!(
+            f.body[..3],
+            [
+                Instruction::Copy {
+                    src: int(0),
+                    dst: var("i"),
+                },
+                binary(BinaryOp::Multiply, var("a"), var("b"), "inv"),
+                binary(BinaryOp::Add, var("inv"), int(1), "inv2"),
+            ]
+        );
+        assert_eq!(f.body[3], label("start"));
+    }
+
+    #[test]
+    fn test_keep_variant_and_unsafe_computations() {
+        let body = counted_loop(vec![
+            // Read before it's written on every iteration but the first.
+            binary(BinaryOp::Add, var("sum"), var("carried"), "sum"),
+            binary(BinaryOp::Add, var("a"), int(1), "carried"),
+            // Might divide by zero on a path that never divided.
+            binary(BinaryOp::Divide, var("a"), var("b"), "quotient"),
+            binary(BinaryOp::Add, var("sum"), var("quotient"), "sum"),
+            // Depends on the loop.
+            binary(BinaryOp::Add, var("i"), var("a"), "variant"),
+            binary(BinaryOp::Add, var("sum"), var("variant"), "sum"),
+        ]);
+        let mut f = function(&["a", "b", "n"], body.clone());
+        optimize_loops(&mut f, &mut SymbolTable::new());
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_keep_volatile_operands() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("v", symbol(Type::Int, Storage::Local, true));
+        let body = counted_loop(vec![
+            binary(BinaryOp::Add, var("v"), int(1), "x"),
+            binary(BinaryOp::Add, var("sum"), var("x"), "sum"),
+        ]);
+        let mut f = function(&["a", "b", "n"], body.clone());
+        optimize_loops(&mut f, &mut symbols);
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_reduce_multiplication() {
+        let mut symbols = symbols(&[("i", Type::Int), ("offset", Type::Int)]);
+        let mut f = function(
+            &["a", "b", "n"],
+            counted_loop(vec![
+                binary(BinaryOp::Multiply, var("i"), int(4), "offset"),
+                binary(BinaryOp::Add, var("sum"), var("offset"), "sum"),
+            ]),
+        );
+        optimize_loops(&mut f, &mut symbols);
+
+        assert_eq!(
+            f.body,
+            vec![
+                Instruction::Copy {
+                    src: int(0),
+                    dst: var("i"),
+                },
+                binary(BinaryOp::Multiply, var("i"), int(4), "tmp.0"),
+                label("start"),
+                binary(BinaryOp::LessThan, var("i"), var("n"), "cond"),
+                jump_if_zero("cond", "end"),
+                Instruction::Copy {
+                    src: var("tmp.0"),
+                    dst: var("offset"),
+                },
+                binary(BinaryOp::Add, var("sum"), var("offset"), "sum"),
+                binary(BinaryOp::Add, var("i"), int(1), "i"),
+                binary(BinaryOp::Add, var("tmp.0"), int(4), "tmp.0"),
+                jump("start"),
+                label("end"),
+                Instruction::Return(Some(var("sum"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_array_indexing() {
+        let long = |l: i64| Val::Constant(Const::Long(l));
+        let pointer = Type::Pointer(Box::new(Type::Int));
+        let mut symbols = symbols(&[("i", Type::Long), ("p", pointer.clone()), ("arr", pointer)]);
+        let mut f = function(
+            &["a", "b", "n"],
+            vec![
+                Instruction::Copy {
+                    src: long(10),
+                    dst: var("i"),
+                },
+                label("loop"),
+                Instruction::AddPtr {
+                    ptr: var("arr"),
+                    index: var("i"),
+                    scale: 4,
+                    dst: var("p"),
+                },
+                Instruction::Store {
+                    src: int(0),
+                    dst_ptr: var("p"),
+                },
+                binary(BinaryOp::Subtract, var("i"), long(1), "i"),
+                jump_if_zero("i", "end"),
+                jump("loop"),
+                label("end"),
+                Instruction::Return(None),
+            ],
+        );
+        optimize_loops(&mut f, &mut symbols);
+
+        assert_eq!(
+            f.body[1],
+            Instruction::AddPtr {
+                ptr: var("arr"),
+                index: var("i"),
+                scale: 4,
+                dst: var("tmp.0"),
+            }
+        );
+        assert_eq!(
+            f.body[3],
+            Instruction::Copy {
+                src: var("tmp.0"),
+                dst: var("p"),
+            }
+        );
+        assert_eq!(
+            f.body[6],
+            Instruction::AddPtr {
+                ptr: var("tmp.0"),
+                index: long(-1),
+                scale: 4,
+                dst: var("tmp.0"),
+            }
+        );
+    }
+}
@@ -0,0 +1,725 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::{
+    cfg::{Cfg, CfgInstruction, Flow, NodeId},
+    dataflow,
+    dominators::Dominators,
+    liveness::Liveness,
+    Aliased,
+};
+use crate::tacky::{Function, Instruction, SymbolTable, Val};
+
+/// `dst = phi(args)`: picks the value of `args` matching the predecessor control came from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Phi {
+    /// The variable whose versions this phi merges, as named before renaming.
+    pub var: String,
+    pub dst: String,
+    pub args: Vec<(NodeId, Val)>,
+}
+
+/// A function body in static single assignment form: every tracked variable is assigned by
+/// exactly one instruction or phi, and that definition dominates every use.
+///
+/// Only variables the function fully controls are renamed. Aliased and volatile variables,
+/// and aggregates written or read piecewise, keep their names and may be assigned anywhere.
+/// A use no definition reaches keeps the original name too, which stands for the variable's
+/// value on entry, such as a parameter's.
+#[derive(Debug)]
+pub struct Ssa {
+    pub cfg: Cfg<Instruction>,
+    /// The phis at the start of each block, keyed by block id. Each has an argument for every
+    /// reachable predecessor.
+    pub phis: HashMap<usize, Vec<Phi>>,
+    /// The names renaming gave to definitions, each of which is assigned exactly once, and the
+    /// variable each is a version of. They're only declared in the symbol table by
+    /// `into_instructions`, so building SSA form and dropping it leaves the table as it was.
+    pub versions: HashMap<String, String>,
+}
+
+impl Ssa {
+    /// Converts `function` into SSA form, placing phis at the iterated dominance frontier of
+    /// each variable's definitions, wherever the variable is still live.
+    pub fn new(function: &Function, symbols: &mut SymbolTable) -> Self {
+        let cfg = Cfg::new(function.body.clone());
+        let tracked = tracked_variables(function, symbols);
+
+        let liveness = Liveness {
+            aliased: Aliased::new(function, symbols),
+        };
+        let live = dataflow::solve(&cfg, &liveness);
+        let dominators = Dominators::new(&cfg);
+        let frontiers = dominators.frontiers(&cfg);
+
+        let mut definitions: HashMap<&str, Vec<usize>> = HashMap::new();
+        for id in cfg.block_ids() {
+            for instruction in cfg.instructions(*id) {
+                if let Some(Val::Var(name)) = instruction.destination() {
+                    if tracked.contains(name.as_str()) {
+                        definitions.entry(name).or_default().push(*id);
+                    }
+                }
+            }
+        }
+
+        let mut phis: HashMap<usize, Vec<Phi>> = HashMap::new();
+        for var in &tracked {
+            let mut worklist = definitions.get(var).cloned().unwrap_or_default();
+            let mut defined: HashSet<usize> = worklist.iter().copied().collect();
+            let mut placed = HashSet::new();
+
+            while let Some(id) = worklist.pop() {
+                let Some(frontier) = frontiers.get(&NodeId::Block(id)) else {
+                    continue;
+                };
+                for node in frontier {
+                    let NodeId::Block(join) = *node else {
+                        continue;
+                    };
+                    if !live.block_start(join).contains(*var) || !placed.insert(join) {
+                        continue;
+                    }
+                    phis.entry(join).or_default().push(Phi {
+                        var: var.to_string(),
+                        dst: var.to_string(),
+                        args: vec![],
+                    });
+                    if defined.insert(join) {
+                        worklist.push(join);
+                    }
+                }
+            }
+        }
+
+        let mut ssa = Self {
+            cfg,
+            phis,
+            versions: HashMap::new(),
+        };
+        ssa.rename(&tracked, &dominators, symbols);
+        ssa
+    }
+
+    /// Gives every definition of a tracked variable a fresh name, and points every use and
+    /// phi argument at the definition that reaches it, walking down the dominator tree.
+    fn rename(
+        &mut self,
+        tracked: &BTreeSet<&str>,
+        dominators: &Dominators,
+        symbols: &mut SymbolTable,
+    ) {
+        enum Step {
+            Enter(NodeId),
+            // Pops the names pushed for these variables on entering a node.
+            Leave(Vec<String>),
+        }
+
+        let mut names: HashMap<String, Vec<String>> = HashMap::new();
+        let current = |names: &HashMap<String, Vec<String>>, var: &str| {
+            names
+                .get(var)
+                .and_then(|stack| stack.last())
+                .map_or(var, |name| name)
+                .to_owned()
+        };
+        let mut steps = vec![Step::Enter(NodeId::Entry)];
+
+        while let Some(step) = steps.pop() {
+            let node = match step {
+                Step::Enter(node) => node,
+                Step::Leave(defined) => {
+                    for var in defined {
+                        names.get_mut(&var).unwrap().pop();
+                    }
+                    continue;
+                }
+            };
+
+            let mut defined = Vec::new();
+            if let NodeId::Block(id) = node {
+                for phi in self.phis.get_mut(&id).into_iter().flatten() {
+                    phi.dst = make_version(&mut self.versions, &phi.var, symbols);
+                    names
+                        .entry(phi.var.clone())
+                        .or_default()
+                        .push(phi.dst.clone());
+                    defined.push(phi.var.clone());
+                }
+
+                for instruction in self.cfg.instructions_mut(id) {
+                    for val in instruction.sources_mut() {
+                        if let Val::Var(name) = val {
+                            if tracked.contains(name.as_str()) {
+                                *name = current(&names, name);
+                            }
+                        }
+                    }
+                    if let Some(Val::Var(name)) = instruction.destination_mut() {
+                        if tracked.contains(name.as_str()) {
+                            let version = make_version(&mut self.versions, name, symbols);
+                            let var = std::mem::replace(name, version);
+                            names.entry(var.clone()).or_default().push(name.clone());
+                            defined.push(var);
+                        }
+                    }
+                }
+            }
+
+            for successor in self.cfg.successors(node) {
+                let NodeId::Block(successor) = successor else {
+                    continue;
+                };
+                for phi in self.phis.get_mut(successor).into_iter().flatten() {
+                    phi.args.push((node, Val::Var(current(&names, &phi.var))));
+                }
+            }
+
+            steps.push(Step::Leave(defined));
+            for child in dominators.children(node).iter().rev() {
+                steps.push(Step::Enter(*child));
+            }
+        }
+    }
+
+    /// Returns a fresh version of `var`, declared in the symbol table by `into_instructions`.
+    pub fn make_version(&mut self, var: &str, symbols: &mut SymbolTable) -> String {
+        make_version(&mut self.versions, var, symbols)
+    }
+
+    /// The variable `name` is a version of, or `name` itself if it's not a version, to look up
+    /// in the symbol table.
+    pub fn original<'a>(&'a self, name: &'a str) -> &'a str {
+        self.versions.get(name).map_or(name, String::as_str)
+    }
+
+    /// Converts back out of SSA form, replacing each phi with copies on its incoming edges.
+    ///
+    /// A phi's copies can't go at the end of a predecessor that branches elsewhere as well,
+    /// since the other path may still need the old value (the lost-copy problem), so those
+    /// edges get a block of their own. And since all the phis of a block read their arguments
+    /// at once, copies that would overwrite another's argument are ordered after it, with a
+    /// temporary breaking any cycle (the swap problem).
+    pub fn into_instructions(self, symbols: &mut SymbolTable) -> Vec<Instruction> {
+        let Self {
+            mut cfg,
+            phis,
+            versions,
+        } = self;
+        for (version, var) in &versions {
+            if let Some(symbol) = symbols.get(var).cloned() {
+                symbols.insert(version, symbol);
+            }
+        }
+
+        let edge_copies = |from: NodeId, to: usize, symbols: &mut SymbolTable| {
+            let copies = phis
+                .get(&to)
+                .into_iter()
+                .flatten()
+                .filter_map(|phi| {
+                    let (_, arg) = phi.args.iter().find(|(node, _)| *node == from)?;
+                    Some((phi.dst.clone(), arg.clone()))
+                })
+                .collect();
+            sequentialize(copies, symbols)
+        };
+
+        let order = cfg.block_ids().to_vec();
+        let labels: HashMap<String, usize> = order
+            .iter()
+            .filter_map(|id| match cfg.instructions(*id).first() {
+                Some(Instruction::Label(label)) => Some((label.clone(), *id)),
+                _ => None,
+            })
+            .collect();
+
+        let mut body = match order.first() {
+            Some(first) => edge_copies(NodeId::Entry, *first, symbols),
+            None => vec![],
+        };
+        let mut split_edges = Vec::new();
+
+        for (i, id) in order.iter().enumerate() {
+            let node = NodeId::Block(*id);
+            let next = order.get(i + 1);
+            let mut instructions = std::mem::take(cfg.instructions_mut(*id));
+
+            match instructions.last().map(CfgInstruction::flow) {
+                Some(Flow::Return) => body.append(&mut instructions),
+                Some(Flow::Jump(target)) => {
+                    let copies = edge_copies(node, labels[target], symbols);
+                    let jump = instructions.pop().unwrap();
+                    body.extend(instructions.into_iter().chain(copies));
+                    body.push(jump);
+                }
+                Some(Flow::ConditionalJump(target)) => {
+                    let copies = edge_copies(node, labels[target], symbols);
+                    if !copies.is_empty() {
+                        let target = target.to_owned();
+                        let split = symbols.make_label(&target);
+                        match instructions.last_mut() {
+                            Some(
+                                Instruction::JumpIfZero { target, .. }
+                                | Instruction::JumpIfNotZero { target, .. },
+                            ) => *target = split.clone(),
+                            _ => unreachable!(),
+                        }
+                        split_edges.push(Instruction::Label(split));
+                        split_edges.extend(copies);
+                        split_edges.push(Instruction::Jump(target));
+                    }
+
+                    body.append(&mut instructions);
+                    if let Some(next) = next {
+                        body.extend(edge_copies(node, *next, symbols));
+                    }
+                }
+                _ => {
+                    body.append(&mut instructions);
+                    if let Some(next) = next {
+                        body.extend(edge_copies(node, *next, symbols));
+                    }
+                }
+            }
+        }
+
+        if !split_edges.is_empty() {
+            // Keep control that falls off the end of the body from running into the blocks
+            // made for split edges.
+            let falls_through = body.last().is_none_or(|instruction| {
+                !matches!(instruction.flow(), Flow::Jump(_) | Flow::Return)
+            });
+            if falls_through {
+                let end = symbols.make_label("end");
+                body.push(Instruction::Jump(end.clone()));
+                split_edges.push(Instruction::Label(end));
+            }
+            body.append(&mut split_edges);
+        }
+
+        body
+    }
+}
+
+fn make_version(
+    versions: &mut HashMap<String, String>,
+    var: &str,
+    symbols: &mut SymbolTable,
+) -> String {
+    let version = symbols.make_copy_name(var);
+    versions.insert(version.clone(), var.to_owned());
+    version
+}
+
+/// Returns the variables of `function` that can be renamed: everything read or written as a
+/// whole that isn't aliased or volatile.
+pub fn tracked_variables<'a>(function: &'a Function, symbols: &SymbolTable) -> BTreeSet<&'a str> {
+    let aliased = Aliased::new(function, symbols);
+    let mut aggregates = HashSet::new();
+    let mut variables = BTreeSet::new();
+
+    for instruction in &function.body {
+        match instruction {
+            Instruction::CopyToOffset { dst: name, .. }
+            | Instruction::CopyFromOffset { src: name, .. } => {
+                aggregates.insert(name.as_str());
+            }
+            _ => {}
+        }
+        let vals = instruction
+            .sources()
+            .into_iter()
+            .chain(instruction.destination());
+        for val in vals {
+            if let Val::Var(name) = val {
+                variables.insert(name.as_str());
+            }
+        }
+    }
+
+    variables.retain(|name| {
+        !aggregates.contains(name) && !aliased.contains(name) && !symbols.is_volatile(name)
+    });
+    variables
+}
+
+/// Orders the copies `dst = src` so that, run one after another, they have the same effect as
+/// running them all at once.
+///
+/// A copy can run once no other pending copy reads its destination. When none can, the
+/// pending copies form cycles, and one destination's value is saved to a temporary first.
+fn sequentialize(mut copies: Vec<(String, Val)>, symbols: &mut SymbolTable) -> Vec<Instruction> {
+    let reads = |val: &Val, name: &str| matches!(val, Val::Var(var) if var == name);
+    copies.retain(|(dst, src)| !reads(src, dst));
+
+    let mut sequence = Vec::new();
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|(dst, _)| !copies.iter().any(|(_, src)| reads(src, dst)));
+
+        match ready {
+            Some(i) => {
+                let (dst, src) = copies.remove(i);
+                sequence.push(Instruction::Copy {
+                    src,
+                    dst: Val::Var(dst),
+                });
+            }
+            None => {
+                let saved = copies[0].0.clone();
+                let temporary = symbols.make_copy(&saved);
+                for (_, src) in &mut copies {
+                    if reads(src, &saved) {
+                        *src = Val::Var(temporary.clone());
+                    }
+                }
+                sequence.push(Instruction::Copy {
+                    src: Val::Var(saved),
+                    dst: Val::Var(temporary),
+                });
+            }
+        }
+    }
+
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::{BinaryOp, Const, Storage, TopLevel, Type},
+        test_support::{
+            c,
+            tacky::{binary, copy, function, int, ints, symbol, var},
+        },
+    };
+
+    /// Runs `body` on `args` and returns the value it returns.
+    fn run(params: &[&str], body: &[Instruction], args: &[i32]) -> i32 {
+        let labels: HashMap<&str, usize> = body
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instruction)| match instruction {
+                Instruction::Label(label) => Some((label.as_str(), i)),
+                _ => None,
+            })
+            .collect();
+        let mut env: HashMap<String, i32> = params
+            .iter()
+            .zip(args)
+            .map(|(param, arg)| (param.to_string(), *arg))
+            .collect();
+        let value = |env: &HashMap<String, i32>, val: &Val| match val {
+            Val::Constant(Const::Int(i)) => *i,
+            Val::Var(name) => env[name],
+            _ => unreachable!(),
+        };
+
+        let mut pc = 0;
+        for _ in 0..10_000 {
+            match &body[pc] {
+                Instruction::Copy {
+                    src,
+                    dst: Val::Var(dst),
+                } => {
+                    env.insert(dst.clone(), value(&env, src));
+                }
+                Instruction::Binary {
+                    op,
+                    src1,
+                    src2,
+                    dst: Val::Var(dst),
+                } => {
+                    let (a, b) = (value(&env, src1), value(&env, src2));
+                    let result = match op {
+                        BinaryOp::Add => a + b,
+                        BinaryOp::Subtract => a - b,
+                        BinaryOp::Multiply => a * b,
+                        BinaryOp::LessThan => (a < b) as i32,
+                        _ => unreachable!(),
+                    };
+                    env.insert(dst.clone(), result);
+                }
+                Instruction::Jump(target) => {
+                    pc = labels[target.as_str()];
+                    continue;
+                }
+                Instruction::JumpIfZero { condition, target } => {
+                    if value(&env, condition) == 0 {
+                        pc = labels[target.as_str()];
+                        continue;
+                    }
+                }
+                Instruction::JumpIfNotZero { condition, target } => {
+                    if value(&env, condition) != 0 {
+                        pc = labels[target.as_str()];
+                        continue;
+                    }
+                }
+                Instruction::Label(_) => {}
+                Instruction::Return(Some(val)) => return value(&env, val),
+                instruction => panic!("unexpected instruction {instruction}"),
+            }
+            pc += 1;
+        }
+        panic!("program didn't terminate");
+    }
+
+    /// Replaces every use of a copy's destination with its source and drops the copy, as an
+    /// optimization on SSA form would, leaving versions with overlapping lifetimes.
+    fn propagate_copies(ssa: &mut Ssa) {
+        let mut replacements = HashMap::new();
+        for id in ssa.cfg.block_ids().to_vec() {
+            ssa.cfg
+                .instructions_mut(id)
+                .retain(|instruction| match instruction {
+                    Instruction::Copy {
+                        src: src @ Val::Var(_),
+                        dst: Val::Var(dst),
+                    } if ssa.versions.contains_key(dst) => {
+                        replacements.insert(dst.clone(), src.clone());
+                        false
+                    }
+                    _ => true,
+                });
+        }
+        let resolve = |val: &mut Val| {
+            while let Val::Var(name) = val {
+                match replacements.get(name) {
+                    Some(replacement) => *val = replacement.clone(),
+                    None => break,
+                }
+            }
+        };
+
+        for id in ssa.cfg.block_ids().to_vec() {
+            for instruction in ssa.cfg.instructions_mut(id) {
+                instruction.sources_mut().into_iter().for_each(resolve);
+            }
+        }
+        for phi in ssa.phis.values_mut().flatten() {
+            phi.args.iter_mut().for_each(|(_, arg)| resolve(arg));
+        }
+    }
+
+    /// `s = 0; for (i = 0; i < n; i = i + 1) s = s + i; return s;`
+    fn sum_loop() -> Vec<Instruction> {
+        vec![
+            copy(int(0), "s"),
+            copy(int(0), "i"),
+            Instruction::Label("loop".to_owned()),
+            binary(BinaryOp::LessThan, var("i"), var("n"), "c"),
+            Instruction::JumpIfZero {
+                condition: var("c"),
+                target: "end".to_owned(),
+            },
+            binary(BinaryOp::Add, var("s"), var("i"), "s"),
+            binary(BinaryOp::Add, var("i"), int(1), "i"),
+            Instruction::Jump("loop".to_owned()),
+            Instruction::Label("end".to_owned()),
+            Instruction::Return(Some(var("s"))),
+        ]
+    }
+
+    #[test]
+    fn test_phis_in_loop_header() {
+        let function = function(&["n"], sum_loop());
+        let mut symbols = ints(&["n", "s", "i", "c"]);
+        let ssa = Ssa::new(&function, &mut symbols);
+
+        // `c` is dead at the header, so it needs no phi.
+        let phis = &ssa.phis[&1];
+        assert_eq!(
+            phis.iter().map(|phi| phi.var.as_str()).collect::<Vec<_>>(),
+            ["i", "s"]
+        );
+        for phi in phis {
+            assert_eq!(phi.args.len(), 2);
+            assert_eq!(phi.args[0].0, NodeId::Block(0));
+            assert_eq!(ssa.versions[&phi.dst], phi.var);
+            assert!(symbols.get(&phi.dst).is_none());
+        }
+        assert_eq!(ssa.phis.len(), 1);
+
+        // Every variable is assigned once, and `n` keeps its name as the parameter's value.
+        let mut assigned = HashSet::new();
+        for id in ssa.cfg.block_ids() {
+            for instruction in ssa.cfg.instructions(*id) {
+                if let Some(Val::Var(name)) = instruction.destination() {
+                    assert!(assigned.insert(name.clone()));
+                }
+            }
+        }
+        assert!(phis.iter().all(|phi| assigned.insert(phi.dst.clone())));
+        assert_eq!(
+            ssa.cfg.instructions(1)[1],
+            binary(BinaryOp::LessThan, var(&phis[0].dst), var("n"), "c.4")
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let function = function(&["n"], sum_loop());
+        let mut symbols = ints(&["n", "s", "i", "c"]);
+        let body = Ssa::new(&function, &mut symbols).into_instructions(&mut symbols);
+
+        for n in [0, 1, 5] {
+            assert_eq!(run(&["n"], &body, &[n]), run(&["n"], &function.body, &[n]));
+        }
+    }
+
+    #[test]
+    fn test_lost_copy() {
+        // do { y = x; x = x + 1; } while (x < n); return y;
+        let function = function(
+            &["n"],
+            vec![
+                copy(int(1), "x"),
+                Instruction::Label("loop".to_owned()),
+                copy(var("x"), "y"),
+                binary(BinaryOp::Add, var("x"), int(1), "x"),
+                binary(BinaryOp::LessThan, var("x"), var("n"), "c"),
+                Instruction::JumpIfNotZero {
+                    condition: var("c"),
+                    target: "loop".to_owned(),
+                },
+                Instruction::Return(Some(var("y"))),
+            ],
+        );
+        let mut symbols = ints(&["n", "x", "y", "c"]);
+        let mut ssa = Ssa::new(&function, &mut symbols);
+        // `return y` now reads the phi for `x`, which the back edge overwrites.
+        propagate_copies(&mut ssa);
+        let body = ssa.into_instructions(&mut symbols);
+
+        for n in [0, 3, 10] {
+            assert_eq!(run(&["n"], &body, &[n]), run(&["n"], &function.body, &[n]));
+        }
+    }
+
+    #[test]
+    fn test_swap() {
+        // while (n) { t = a; a = b; b = t; n = n - 1; } return a * 10 + b;
+        let function = function(
+            &["a", "b", "n"],
+            vec![
+                Instruction::Label("loop".to_owned()),
+                Instruction::JumpIfZero {
+                    condition: var("n"),
+                    target: "end".to_owned(),
+                },
+                copy(var("a"), "t"),
+                copy(var("b"), "a"),
+                copy(var("t"), "b"),
+                binary(BinaryOp::Subtract, var("n"), int(1), "n"),
+                Instruction::Jump("loop".to_owned()),
+                Instruction::Label("end".to_owned()),
+                binary(BinaryOp::Multiply, var("a"), int(10), "r"),
+                binary(BinaryOp::Add, var("r"), var("b"), "r"),
+                Instruction::Return(Some(var("r"))),
+            ],
+        );
+        let mut symbols = ints(&["a", "b", "n", "t", "r"]);
+        let mut ssa = Ssa::new(&function, &mut symbols);
+        // The phis for `a` and `b` now read each other on the back edge.
+        propagate_copies(&mut ssa);
+        let body = ssa.into_instructions(&mut symbols);
+
+        for n in [0, 1, 2, 3] {
+            assert_eq!(
+                run(&["a", "b", "n"], &body, &[1, 2, n]),
+                run(&["a", "b", "n"], &function.body, &[1, 2, n])
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip_compiled_program() {
+        let code = "
+            int fib(int n) {
+                int a = 0;
+                int b = 1;
+                while (n > 0) {
+                    int t = a;
+                    a = b;
+                    b = t + b;
+                    n = n - 1;
+                }
+                return a;
+            }
+            int last_below(int n) {
+                int x = 0;
+                int y;
+                do {
+                    y = x;
+                    x = x + 1;
+                } while (x < n);
+                return y;
+            }
+            int main(void) {
+                int *p = 0;
+                int x = 3;
+                p = &x;
+                *p = *p + fib(10);
+                return x + last_below(7);
+            }
+        ";
+        let mut program = c::tacky(code);
+        for top_level in program.top_level.iter_mut() {
+            if let TopLevel::Function(function) = top_level {
+                let mut ssa = Ssa::new(function, &mut program.symbols);
+                propagate_copies(&mut ssa);
+                function.body = ssa.into_instructions(&mut program.symbols);
+            }
+        }
+
+        // Copy propagation leaves the swap and lost copy problems for conversion out of SSA
+        // to get right, alongside the calls and aliased variables real code has.
+        assert_eq!(c::run_tacky(program), 3 + 55 + 6);
+    }
+
+    #[test]
+    fn test_sequentialize_cycle() {
+        let mut symbols = ints(&["a", "b", "c"]);
+        let copies = vec![
+            ("a".to_owned(), var("b")),
+            ("b".to_owned(), var("a")),
+            ("c".to_owned(), var("a")),
+        ];
+
+        assert_eq!(
+            sequentialize(copies, &mut symbols),
+            vec![
+                copy(var("a"), "c"),
+                copy(var("a"), "a.0"),
+                copy(var("b"), "a"),
+                copy(var("a.0"), "b"),
+            ]
+        );
+        assert_eq!(symbols.get("a.0").unwrap().ty, Type::Int);
+    }
+
+    #[test]
+    fn test_untracked_variables_keep_their_names() {
+        let body = vec![
+            copy(int(1), "x"),
+            Instruction::GetAddress {
+                src: var("x"),
+                dst: var("p"),
+            },
+            copy(int(2), "g"),
+            Instruction::Return(Some(var("x"))),
+        ];
+        let function = function(&[], body.clone());
+        let mut symbols = ints(&["x", "p"]);
+        symbols.insert("g", symbol(Type::Int, Storage::Static, false));
+        let ssa = Ssa::new(&function, &mut symbols);
+        let instructions = ssa.cfg.instructions(0);
+
+        assert_eq!(instructions[0], body[0]);
+        assert_eq!(instructions[2], body[2]);
+        assert_ne!(instructions[1], body[1]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use super::cfg::{Cfg, NodeId};
+use crate::tacky::{Function, Instruction, SymbolTable, Val};
+
+/// Removes blocks that can't be reached from the function's entry, jumps to the block that
+/// follows anyway, and labels nothing jumps to.
+pub fn eliminate_unreachable_code(function: &mut Function, symbols: &SymbolTable) {
+    let mut cfg = Cfg::new(std::mem::take(&mut function.body));
+
+    remove_unreachable_blocks(&mut cfg);
+    remove_useless_jumps(&mut cfg, symbols);
+
+    function.body = remove_unused_labels(cfg.into_instructions());
+}
+
+fn remove_unreachable_blocks(cfg: &mut Cfg<Instruction>) {
+    let reachable: HashSet<NodeId> = cfg.reverse_postorder().into_iter().collect();
+
+    for id in cfg.block_ids().to_vec() {
+        if !reachable.contains(&NodeId::Block(id)) {
+            cfg.remove_block(id);
+        }
+    }
+}
+
+fn remove_useless_jumps(cfg: &mut Cfg<Instruction>, symbols: &SymbolTable) {
+    let ids = cfg.block_ids().to_vec();
+
+    // The last block has nothing to fall through to, so any jump it ends with is needed.
+    for (i, id) in ids.iter().enumerate().take(ids.len().saturating_sub(1)) {
+        let next = NodeId::Block(ids[i + 1]);
+        let useless = match cfg.instructions(*id).last() {
+            Some(Instruction::Jump(_)) => true,
+            // Reading a volatile condition is observable even when both paths agree.
+            Some(Instruction::JumpIfZero { condition, .. })
+            | Some(Instruction::JumpIfNotZero { condition, .. }) => match condition {
+                Val::Var(name) => !symbols.is_volatile(name),
+                Val::Constant(_) => true,
+            },
+            _ => false,
+        };
+
+        if useless && cfg.successors(NodeId::Block(*id)) == [next] {
+            cfg.instructions_mut(*id).pop();
+        }
+    }
+}
+
+fn remove_unused_labels(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let targets: HashSet<String> = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Jump(target)
+            | Instruction::JumpIfZero { target, .. }
+            | Instruction::JumpIfNotZero { target, .. } => Some(target.clone()),
+            _ => None,
+        })
+        .collect();
+
+    instructions
+        .into_iter()
+        .filter(|instruction| match instruction {
+            Instruction::Label(label) => targets.contains(label),
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tacky::{Storage, Type},
+        test_support::tacky::{copy, function, int, jump, label, ret, symbol},
+    };
+
+    #[test]
+    fn test_remove_code_after_return() {
+        let mut f = function(&[], vec![ret(int(0)), copy(int(1), "x"), ret(int(1))]);
+        eliminate_unreachable_code(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![ret(int(0))]);
+    }
+
+    #[test]
+    fn test_remove_skipped_block() {
+        let mut f = function(
+            &[],
+            vec![jump("end"), copy(int(1), "x"), label("end"), ret(int(0))],
+        );
+        eliminate_unreachable_code(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![ret(int(0))]);
+    }
+
+    #[test]
+    fn test_keep_reachable_loop() {
+        let body = vec![
+            label("loop"),
+            copy(int(1), "x"),
+            Instruction::JumpIfNotZero {
+                condition: Val::Var("c".to_owned()),
+                target: "loop".to_owned(),
+            },
+            ret(int(0)),
+        ];
+        let mut f = function(&[], body.clone());
+        eliminate_unreachable_code(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_remove_jump_to_next_block() {
+        let mut f = function(
+            &[],
+            vec![
+                Instruction::JumpIfZero {
+                    condition: Val::Var("c".to_owned()),
+                    target: "end".to_owned(),
+                },
+                label("end"),
+                ret(int(0)),
+            ],
+        );
+        eliminate_unreachable_code(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![ret(int(0))]);
+    }
+
+    #[test]
+    fn test_keep_jump_on_volatile_condition() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("status", symbol(Type::Int, Storage::Static, true));
+        let body = vec![
+            Instruction::JumpIfZero {
+                condition: Val::Var("status".to_owned()),
+                target: "end".to_owned(),
+            },
+            label("end"),
+            ret(int(0)),
+        ];
+        let mut f = function(&[], body.clone());
+        eliminate_unreachable_code(&mut f, &symbols);
+
+        assert_eq!(f.body, body);
+    }
+
+    #[test]
+    fn test_if_else_with_constant_condition() {
+        // What constant folding leaves behind for `if (1) x = 1; else x = 2; return x;`.
+        let mut f = function(
+            &[],
+            vec![
+                copy(int(1), "x"),
+                jump("end"),
+                label("else"),
+                copy(int(2), "x"),
+                label("end"),
+                ret(int(0)),
+            ],
+        );
+        eliminate_unreachable_code(&mut f, &SymbolTable::new());
+
+        assert_eq!(f.body, vec![copy(int(1), "x"), ret(int(0))]);
+    }
+}
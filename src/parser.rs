@@ -0,0 +1,1797 @@
+use crate::{
+    ast::{
+        BinaryOp, Block, BlockItem, Declaration, Expression, ExpressionKind, ForInit,
+        FunctionDeclaration, Initializer, LoopId, Param, PostfixOp, Program, Statement,
+        StatementKind, StorageClass, Type, UnaryOp, VariableDeclaration,
+    },
+    diagnostics::{Code, Diagnostic, Diagnostics},
+    lexer::{Keyword, Token, TokenKind},
+    scope::{NameKind, Scopes},
+    source_map::{FileId, SourceMap, Span},
+    tacky::Const,
+};
+
+type ParseResult<T> = Result<T, Diagnostic>;
+
+/// Precedence of the assignment operators, the loosest binding ones.
+const ASSIGNMENT_PRECEDENCE: u8 = 1;
+
+/// A recursive descent parser, with precedence climbing for binary operators.
+///
+/// Declarations are parsed the way C writes them: specifiers giving a base type, then a
+/// declarator per declared name that wraps the base type in pointers, arrays and functions.
+/// `process_declarator` turns the two into the declared type. Typedef names are replaced by
+/// the types they name as they're parsed, so typedefs never reach the AST.
+pub struct Parser<'a> {
+    source_map: &'a SourceMap,
+    file: FileId,
+    tokens: &'a [Token],
+
+    // index of the next token to parse
+    pos: usize,
+    // Loops being parsed, innermost last, which `break` and `continue` refer to.
+    loops: Vec<LoopId>,
+    next_loop_id: LoopId,
+    // Names declared so far, to tell typedef names from other identifiers.
+    scopes: Scopes,
+    // Errors found since the last were reported, and where the last was found.
+    errors: Vec<Diagnostic>,
+    last_error: Option<Span>,
+}
+
+/// Type and storage-class specifiers, like `static unsigned long`.
+struct Specifiers {
+    ty: Type,
+    storage_class: Option<StorageClass>,
+    /// Whether the declaration is a `typedef`, which C counts as a storage class.
+    typedef: bool,
+    span: Span,
+}
+
+/// What a declarator wraps around the base type, from the outside in: `*a[3]` is
+/// `Pointer(Array(Ident(a), 3))`, an array of three pointers.
+enum Declarator {
+    /// The declared name, or where it would be in an abstract declarator like `int *`.
+    Ident(Option<String>, Span),
+    Pointer(Box<Declarator>),
+    Array(Box<Declarator>, usize),
+    Function(Vec<(Type, Declarator)>, Box<Declarator>),
+}
+
+/// What a declarator declares, once applied to its base type.
+struct Declared {
+    name: Option<String>,
+    span: Span,
+    ty: Type,
+    /// The parameters, if a function is declared.
+    params: Option<Vec<Param>>,
+}
+
+/// Binary operators, and the ones that parse like them.
+enum Infix {
+    Binary(BinaryOp),
+    Assign,
+    CompoundAssign(BinaryOp),
+    Conditional,
+}
+
+impl<'a> Parser<'a> {
+    /// A parser for `tokens`, lexed from `file` of `source_map`.
+    pub fn new(source_map: &'a SourceMap, file: FileId, tokens: &'a [Token]) -> Self {
+        Self {
+            source_map,
+            file,
+            tokens,
+
+            pos: 0,
+            loops: Vec::new(),
+            next_loop_id: 0,
+            scopes: Scopes::new(),
+            errors: Vec::new(),
+            last_error: None,
+        }
+    }
+
+    /// Parses the tokens as a translation unit: a list of declarations.
+    ///
+    /// Syntax errors are reported to `diagnostics`. After one, parsing picks up again at the
+    /// next statement or declaration, see `synchronize`, until the error limit is reached. The
+    /// program returned is then incomplete.
+    pub fn parse(&mut self, diagnostics: &mut Diagnostics) -> Program {
+        let mut declarations = Vec::new();
+
+        while self.peek().is_some() && !diagnostics.limit_reached() {
+            let parsed = match self.starts_declaration() {
+                true => self.declarations(),
+                false => Err(self.unexpected("a declaration")),
+            };
+            match parsed {
+                Ok(parsed) => declarations.extend(parsed),
+                Err(error) => {
+                    self.record(error);
+                    self.synchronize(false);
+                }
+            }
+            for error in self.errors.drain(..) {
+                diagnostics.emit(error);
+            }
+        }
+
+        Program { declarations }
+    }
+
+    /// Records `error`, unless it's where the last one was, since it's then most likely caused
+    /// by that one, like a missing `}` after an expression cut short by the end of the file.
+    fn record(&mut self, error: Diagnostic) {
+        if error.span.is_none() || error.span != self.last_error {
+            self.last_error = error.span;
+            self.errors.push(error);
+        }
+    }
+
+    /// Skips the rest of a declaration or statement that failed to parse: up to and including
+    /// the next `;` outside of braces, or the `}` closing the braces opened since. In a block,
+    /// a `}` closing the block itself is left for it to parse.
+    fn synchronize(&mut self, in_block: bool) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Semicolon if depth == 0 => {
+                    self.pos += 1;
+                    return;
+                }
+                TokenKind::OpenBrace => depth += 1,
+                TokenKind::CloseBrace if depth == 0 => {
+                    if !in_block {
+                        self.pos += 1;
+                    }
+                    return;
+                }
+                TokenKind::CloseBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                _ => (),
+            }
+            self.pos += 1;
+        }
+    }
+
+    //-------------------------
+    // Declarations
+
+    /// Parses a declaration, which can declare several names, like `int a, *b = &a;`, or
+    /// define a function. A typedef declares nothing the AST keeps.
+    fn declarations(&mut self) -> ParseResult<Vec<Declaration>> {
+        let specifiers = self.specifiers()?;
+        let mut declarations = Vec::new();
+
+        loop {
+            let declarator = self.declarator()?;
+            let declared = self.process_declarator(declarator, specifiers.ty.clone())?;
+            let Some(name) = declared.name else {
+                return Err(self.expected_at("an identifier", declared.span));
+            };
+
+            if specifiers.typedef {
+                self.scopes
+                    .declare(&name, NameKind::Typedef(declared.ty), declared.span)?;
+                if self.eat(&TokenKind::Comma).is_none() {
+                    break;
+                }
+                continue;
+            }
+            self.scopes
+                .declare(&name, NameKind::Ordinary, declared.span)?;
+
+            match declared.params {
+                Some(params) => {
+                    let defined =
+                        declarations.is_empty() && self.peek_kind() == Some(&TokenKind::OpenBrace);
+                    let body = match defined {
+                        true => Some(self.function_body(&params)?),
+                        false => None,
+                    };
+                    declarations.push(Declaration::Function(FunctionDeclaration {
+                        name,
+                        params,
+                        body,
+                        ty: declared.ty,
+                        storage_class: specifiers.storage_class,
+                        span: declared.span,
+                    }));
+                    if defined {
+                        return Ok(declarations);
+                    }
+                }
+                None => {
+                    let init = match self.eat(&TokenKind::Assign) {
+                        Some(_) => Some(self.initializer()?),
+                        None => None,
+                    };
+                    declarations.push(Declaration::Variable(VariableDeclaration {
+                        name,
+                        init,
+                        ty: declared.ty,
+                        storage_class: specifiers.storage_class,
+                        span: declared.span,
+                    }));
+                }
+            }
+
+            if self.eat(&TokenKind::Comma).is_none() {
+                break;
+            }
+        }
+
+        self.expect(&TokenKind::Semicolon, "';'")?;
+        Ok(declarations)
+    }
+
+    fn starts_declaration(&self) -> bool {
+        self.peek_kind()
+            .is_some_and(|kind| self.starts_specifiers(kind))
+    }
+
+    /// Whether `kind` can start a declaration: a specifier keyword, or a typedef name.
+    fn starts_specifiers(&self, kind: &TokenKind) -> bool {
+        match kind {
+            TokenKind::Identifier(name) => self.scopes.is_type_name(name),
+            kind => is_specifier(kind),
+        }
+    }
+
+    fn specifiers(&mut self) -> ParseResult<Specifiers> {
+        let start = self.peek_span();
+        let mut types = Vec::new();
+        let mut typedef_name = None;
+        let mut storage_class = None;
+        let mut typedef = false;
+
+        while let Some(token) = self.peek() {
+            match &token.kind {
+                TokenKind::Keyword(
+                    keyword @ (Keyword::Int
+                    | Keyword::Long
+                    | Keyword::Char
+                    | Keyword::Double
+                    | Keyword::Signed
+                    | Keyword::Unsigned
+                    | Keyword::Void),
+                ) => types.push(keyword),
+                // A typedef name only names a type where no other type specifier has; after
+                // one, like in `long T;`, it's the declared name.
+                TokenKind::Identifier(name) if types.is_empty() && typedef_name.is_none() => {
+                    match self.scopes.typedef(name) {
+                        Some(ty) => typedef_name = Some(ty.clone()),
+                        None => break,
+                    }
+                }
+                TokenKind::Keyword(
+                    keyword @ (Keyword::Static | Keyword::Extern | Keyword::Typedef),
+                ) => {
+                    if storage_class.is_some() || typedef {
+                        return Err(Diagnostic::error(
+                            "multiple storage classes in declaration specifiers",
+                        )
+                        .with_code(Code::InvalidDeclaration)
+                        .with_span(token.span));
+                    }
+                    match keyword {
+                        Keyword::Static => storage_class = Some(StorageClass::Static),
+                        Keyword::Extern => storage_class = Some(StorageClass::Extern),
+                        _ => typedef = true,
+                    }
+                }
+                TokenKind::Keyword(Keyword::Const | Keyword::Volatile) => {
+                    return Err(self.unsupported(token.span));
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        if types.is_empty() && typedef_name.is_none() {
+            return Err(self.unexpected("a type specifier"));
+        }
+        let span = start.to(self.previous_span());
+        let ty = match typedef_name {
+            Some(ty) if types.is_empty() => Some(ty),
+            Some(_) => None,
+            None => specifier_type(&types),
+        }
+        .ok_or_else(|| {
+            Diagnostic::error("invalid combination of type specifiers")
+                .with_code(Code::InvalidDeclaration)
+                .with_span(span)
+        })?;
+
+        Ok(Specifiers {
+            ty,
+            storage_class,
+            typedef,
+            span,
+        })
+    }
+
+    /// Specifiers that must not have a storage class, like a parameter's or a cast's.
+    fn type_specifiers(&mut self, what: &str) -> ParseResult<Type> {
+        let specifiers = self.specifiers()?;
+        match specifiers.storage_class.is_some() || specifiers.typedef {
+            true => Err(
+                Diagnostic::error(format!("storage class specified for {what}"))
+                    .with_code(Code::InvalidDeclaration)
+                    .with_span(specifiers.span),
+            ),
+            false => Ok(specifiers.ty),
+        }
+    }
+
+    fn declarator(&mut self) -> ParseResult<Declarator> {
+        match self.eat(&TokenKind::Asterisk) {
+            Some(_) => Ok(Declarator::Pointer(Box::new(self.declarator()?))),
+            None => self.direct_declarator(),
+        }
+    }
+
+    fn direct_declarator(&mut self) -> ParseResult<Declarator> {
+        let mut declarator = match self.peek_kind() {
+            Some(TokenKind::Identifier(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Declarator::Ident(Some(name), self.previous_span())
+            }
+            // A parenthesized declarator, rather than the parameters of an abstract one, which
+            // may start with a typedef name.
+            Some(TokenKind::OpenParen)
+                if match self.peek_nth_kind(1) {
+                    Some(TokenKind::Asterisk | TokenKind::OpenParen | TokenKind::OpenBracket) => {
+                        true
+                    }
+                    Some(TokenKind::Identifier(name)) => !self.scopes.is_type_name(name),
+                    _ => false,
+                } =>
+            {
+                self.pos += 1;
+                let declarator = self.declarator()?;
+                self.expect(&TokenKind::CloseParen, "')'")?;
+                declarator
+            }
+            _ => Declarator::Ident(None, self.peek_span()),
+        };
+
+        loop {
+            declarator = if self.eat(&TokenKind::OpenParen).is_some() {
+                Declarator::Function(self.params()?, Box::new(declarator))
+            } else if self.eat(&TokenKind::OpenBracket).is_some() {
+                let len = self.array_length()?;
+                self.expect(&TokenKind::CloseBracket, "']'")?;
+                Declarator::Array(Box::new(declarator), len)
+            } else {
+                break;
+            };
+        }
+
+        Ok(declarator)
+    }
+
+    /// Parses a parameter list after its opening parenthesis. `()` is taken to mean no
+    /// parameters, like `(void)`.
+    ///
+    /// The parameters' names are in scope until the end of the list, so they can shadow
+    /// typedef names for the parameters after them.
+    fn params(&mut self) -> ParseResult<Vec<(Type, Declarator)>> {
+        self.scoped(Self::param_list)
+    }
+
+    fn param_list(&mut self) -> ParseResult<Vec<(Type, Declarator)>> {
+        if self.eat(&TokenKind::CloseParen).is_some() {
+            return Ok(Vec::new());
+        }
+        if self.peek_kind() == Some(&TokenKind::Keyword(Keyword::Void))
+            && self.peek_nth_kind(1) == Some(&TokenKind::CloseParen)
+        {
+            self.pos += 2;
+            return Ok(Vec::new());
+        }
+
+        let mut params = Vec::new();
+        loop {
+            if self.peek_kind() == Some(&TokenKind::Ellipsis) {
+                return Err(self.unsupported(self.peek_span()));
+            }
+            let ty = self.type_specifiers("parameter")?;
+            let declarator = self.declarator()?;
+            if let Some(name) = declarator_name(&declarator) {
+                self.scopes
+                    .declare(name, NameKind::Ordinary, declarator_span(&declarator))?;
+            }
+            params.push((ty, declarator));
+
+            if self.eat(&TokenKind::Comma).is_none() {
+                break;
+            }
+        }
+        self.expect(&TokenKind::CloseParen, "')'")?;
+
+        Ok(params)
+    }
+
+    fn array_length(&mut self) -> ParseResult<usize> {
+        let span = self.peek_span();
+        let len = match self.peek_kind() {
+            Some(TokenKind::Constant(text)) => match constant_value(text) {
+                Some(Const::Int(len)) => usize::try_from(len).ok(),
+                Some(Const::UInt(len)) => usize::try_from(len).ok(),
+                Some(Const::Long(len)) => usize::try_from(len).ok(),
+                Some(Const::ULong(len)) => usize::try_from(len).ok(),
+                _ => None,
+            },
+            _ => return Err(self.unexpected("an array length")),
+        };
+        self.pos += 1;
+
+        match len {
+            Some(len) if len > 0 => Ok(len),
+            _ => Err(
+                Diagnostic::error("array length must be a positive integer constant")
+                    .with_code(Code::InvalidDeclaration)
+                    .with_span(span),
+            ),
+        }
+    }
+
+    /// Applies `declarator` to `base`, the type its specifiers give.
+    fn process_declarator(&self, declarator: Declarator, base: Type) -> ParseResult<Declared> {
+        match declarator {
+            Declarator::Ident(name, span) => Ok(Declared {
+                name,
+                span,
+                ty: base,
+                params: None,
+            }),
+            Declarator::Pointer(inner) => {
+                if matches!(base, Type::Function { .. }) {
+                    return Err(unsupported(
+                        "function pointers are",
+                        declarator_span(&inner),
+                    ));
+                }
+                self.process_declarator(*inner, Type::Pointer(Box::new(base)))
+            }
+            Declarator::Array(inner, len) => {
+                if !base.is_complete() {
+                    return Err(Diagnostic::error(format!(
+                        "array elements cannot have type '{base}'"
+                    ))
+                    .with_code(Code::InvalidDeclaration)
+                    .with_span(declarator_span(&inner)));
+                }
+                self.process_declarator(*inner, Type::Array(Box::new(base), len))
+            }
+            Declarator::Function(params, inner) => {
+                if matches!(base, Type::Array(..) | Type::Function { .. }) {
+                    return Err(
+                        Diagnostic::error(format!("function cannot return '{base}'"))
+                            .with_code(Code::InvalidDeclaration)
+                            .with_span(declarator_span(&inner)),
+                    );
+                }
+
+                let mut param_types = Vec::new();
+                let mut param_names = Vec::new();
+                for (ty, declarator) in params {
+                    let param = self.process_declarator(declarator, ty)?;
+                    if matches!(param.ty, Type::Function { .. }) {
+                        return Err(unsupported("function pointers are", param.span));
+                    }
+                    param_types.push(param.ty);
+                    param_names.push(Param {
+                        name: param.name,
+                        span: param.span,
+                    });
+                }
+                let ty = Type::Function {
+                    params: param_types,
+                    ret: Box::new(base),
+                };
+
+                match *inner {
+                    Declarator::Ident(name, span) => Ok(Declared {
+                        name,
+                        span,
+                        ty,
+                        params: Some(param_names),
+                    }),
+                    // Something else made of the function type, like an array of functions.
+                    inner => self.process_declarator(inner, ty),
+                }
+            }
+        }
+    }
+
+    fn initializer(&mut self) -> ParseResult<Initializer> {
+        let Some(open) = self.eat(&TokenKind::OpenBrace) else {
+            return Ok(Initializer::Single(self.expression(ASSIGNMENT_PRECEDENCE)?));
+        };
+
+        let mut initializers = vec![self.initializer()?];
+        while self.eat(&TokenKind::Comma).is_some() {
+            // A trailing comma.
+            if self.peek_kind() == Some(&TokenKind::CloseBrace) {
+                break;
+            }
+            initializers.push(self.initializer()?);
+        }
+        let close = self.expect(&TokenKind::CloseBrace, "'}'")?;
+
+        Ok(Initializer::Compound(initializers, open.to(close)))
+    }
+
+    //-------------------------
+    // Statements
+
+    fn block(&mut self) -> ParseResult<Block> {
+        self.scoped(Self::block_in_scope)
+    }
+
+    /// A function's body, which shares its scope with the parameters.
+    fn function_body(&mut self, params: &[Param]) -> ParseResult<Block> {
+        self.scoped(|parser| {
+            for param in params {
+                if let Some(name) = &param.name {
+                    parser
+                        .scopes
+                        .declare(name, NameKind::Ordinary, param.span)?;
+                }
+            }
+            parser.block_in_scope()
+        })
+    }
+
+    /// A block whose declarations go in the innermost scope.
+    fn block_in_scope(&mut self) -> ParseResult<Block> {
+        let open = self.expect(&TokenKind::OpenBrace, "'{'")?;
+        let mut items = Vec::new();
+
+        while !matches!(self.peek_kind(), Some(TokenKind::CloseBrace) | None) {
+            let parsed = match self.starts_declaration() {
+                true => self.declarations().map(|declarations| {
+                    items.extend(declarations.into_iter().map(BlockItem::Declaration))
+                }),
+                false => self
+                    .statement()
+                    .map(|statement| items.push(BlockItem::Statement(statement))),
+            };
+            if let Err(error) = parsed {
+                self.record(error);
+                self.synchronize(true);
+            }
+        }
+        let close = self.expect(&TokenKind::CloseBrace, "'}'")?;
+
+        Ok(Block {
+            items,
+            span: open.to(close),
+        })
+    }
+
+    fn statement(&mut self) -> ParseResult<Statement> {
+        let Some(token) = self.peek() else {
+            return Err(self.unexpected("a statement"));
+        };
+        let start = token.span;
+
+        let kind = match &token.kind {
+            TokenKind::Keyword(Keyword::Return) => {
+                self.pos += 1;
+                let value = match self.peek_kind() {
+                    Some(TokenKind::Semicolon) => None,
+                    _ => Some(self.expression(0)?),
+                };
+                self.expect(&TokenKind::Semicolon, "';'")?;
+                StatementKind::Return(value)
+            }
+            TokenKind::Keyword(Keyword::If) => {
+                self.pos += 1;
+                let condition = self.condition()?;
+                let then = Box::new(self.statement()?);
+                let otherwise = match self.eat(&TokenKind::Keyword(Keyword::Else)) {
+                    Some(_) => Some(Box::new(self.statement()?)),
+                    None => None,
+                };
+                StatementKind::If {
+                    condition,
+                    then,
+                    otherwise,
+                }
+            }
+            TokenKind::OpenBrace => StatementKind::Compound(self.block()?),
+            TokenKind::Keyword(keyword @ (Keyword::Break | Keyword::Continue)) => {
+                self.pos += 1;
+                let Some(&id) = self.loops.last() else {
+                    let name = match keyword {
+                        Keyword::Break => "break",
+                        _ => "continue",
+                    };
+                    return Err(
+                        Diagnostic::error(format!("'{name}' statement not in a loop"))
+                            .with_code(Code::JumpOutsideLoop)
+                            .with_span(start),
+                    );
+                };
+                self.expect(&TokenKind::Semicolon, "';'")?;
+                match keyword {
+                    Keyword::Break => StatementKind::Break(id),
+                    _ => StatementKind::Continue(id),
+                }
+            }
+            TokenKind::Keyword(Keyword::While) => {
+                self.pos += 1;
+                let condition = self.condition()?;
+                let (body, id) = self.loop_body()?;
+                StatementKind::While {
+                    condition,
+                    body,
+                    id,
+                }
+            }
+            TokenKind::Keyword(Keyword::Do) => {
+                self.pos += 1;
+                let (body, id) = self.loop_body()?;
+                self.expect(&TokenKind::Keyword(Keyword::While), "'while'")?;
+                let condition = self.condition()?;
+                self.expect(&TokenKind::Semicolon, "';'")?;
+                StatementKind::DoWhile {
+                    body,
+                    condition,
+                    id,
+                }
+            }
+            TokenKind::Keyword(Keyword::For) => {
+                self.pos += 1;
+                // A declaration in the header is scoped to the loop.
+                self.scoped(Self::for_loop)?
+            }
+            TokenKind::Semicolon => {
+                self.pos += 1;
+                StatementKind::Null
+            }
+            _ => {
+                let expression = self.expression(0)?;
+                self.expect(&TokenKind::Semicolon, "';'")?;
+                StatementKind::Expression(expression)
+            }
+        };
+
+        Ok(Statement {
+            kind,
+            span: start.to(self.previous_span()),
+        })
+    }
+
+    /// A parenthesized condition, as `if`, `while` and `do` have.
+    fn condition(&mut self) -> ParseResult<Expression> {
+        self.expect(&TokenKind::OpenParen, "'('")?;
+        let condition = self.expression(0)?;
+        self.expect(&TokenKind::CloseParen, "')'")?;
+        Ok(condition)
+    }
+
+    /// Runs `parse` in a new innermost scope.
+    fn scoped<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.scopes.enter();
+        let parsed = parse(self);
+        self.scopes.exit();
+        parsed
+    }
+
+    /// Parses the body of a new loop, returning it with the loop's id.
+    fn loop_body(&mut self) -> ParseResult<(Box<Statement>, LoopId)> {
+        let id = self.next_loop_id;
+        self.next_loop_id += 1;
+
+        self.loops.push(id);
+        let body = self.statement();
+        self.loops.pop();
+
+        Ok((Box::new(body?), id))
+    }
+
+    /// The rest of a `for` statement, after the keyword.
+    fn for_loop(&mut self) -> ParseResult<StatementKind> {
+        self.expect(&TokenKind::OpenParen, "'('")?;
+        let init = match self.starts_declaration() {
+            true => ForInit::Declarations(self.for_declarations()?),
+            false => {
+                let init = self.optional_expression(&TokenKind::Semicolon)?;
+                self.expect(&TokenKind::Semicolon, "';'")?;
+                ForInit::Expression(init)
+            }
+        };
+        let condition = self
+            .optional_expression(&TokenKind::Semicolon)?
+            .map(Box::new);
+        self.expect(&TokenKind::Semicolon, "';'")?;
+        let post = self
+            .optional_expression(&TokenKind::CloseParen)?
+            .map(Box::new);
+        self.expect(&TokenKind::CloseParen, "')'")?;
+        let (body, id) = self.loop_body()?;
+        Ok(StatementKind::For {
+            init,
+            condition,
+            post,
+            body,
+            id,
+        })
+    }
+
+    fn for_declarations(&mut self) -> ParseResult<Vec<VariableDeclaration>> {
+        self.declarations()?
+            .into_iter()
+            .map(|declaration| match declaration {
+                Declaration::Variable(declaration) => Ok(declaration),
+                Declaration::Function(function) => Err(Diagnostic::error(format!(
+                    "function '{}' declared in a 'for' loop initializer",
+                    function.name
+                ))
+                .with_code(Code::InvalidDeclaration)
+                .with_span(function.span)),
+            })
+            .collect()
+    }
+
+    /// An expression, or nothing if the next token is `end`.
+    fn optional_expression(&mut self, end: &TokenKind) -> ParseResult<Option<Expression>> {
+        match self.peek_kind() == Some(end) {
+            true => Ok(None),
+            false => Ok(Some(self.expression(0)?)),
+        }
+    }
+
+    //-------------------------
+    // Expressions
+
+    /// Parses an expression whose binary operators bind at least as tightly as
+    /// `min_precedence`.
+    fn expression(&mut self, min_precedence: u8) -> ParseResult<Expression> {
+        let mut left = self.unary()?;
+
+        while let Some((infix, precedence)) = self.peek_kind().and_then(infix) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+
+            left = match infix {
+                // Right-associative, so the right operand may hold the same operator.
+                Infix::Assign => {
+                    let right = self.expression(precedence)?;
+                    let span = left.span.to(right.span);
+                    Expression::new(
+                        ExpressionKind::Assignment(Box::new(left), Box::new(right)),
+                        span,
+                    )
+                }
+                Infix::CompoundAssign(op) => {
+                    let right = self.expression(precedence)?;
+                    let span = left.span.to(right.span);
+                    Expression::new(
+                        ExpressionKind::CompoundAssignment(op, Box::new(left), Box::new(right)),
+                        span,
+                    )
+                }
+                Infix::Conditional => {
+                    let then = self.expression(0)?;
+                    self.expect(&TokenKind::Colon, "':'")?;
+                    let otherwise = self.expression(precedence)?;
+                    let span = left.span.to(otherwise.span);
+                    Expression::new(
+                        ExpressionKind::Conditional {
+                            condition: Box::new(left),
+                            then: Box::new(then),
+                            otherwise: Box::new(otherwise),
+                        },
+                        span,
+                    )
+                }
+                Infix::Binary(op) => {
+                    let right = self.expression(precedence + 1)?;
+                    let span = left.span.to(right.span);
+                    Expression::new(
+                        ExpressionKind::Binary(op, Box::new(left), Box::new(right)),
+                        span,
+                    )
+                }
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> ParseResult<Expression> {
+        let Some(token) = self.peek() else {
+            return Err(self.unexpected("an expression"));
+        };
+        let span = token.span;
+
+        match &token.kind {
+            TokenKind::Minus
+            | TokenKind::Tilde
+            | TokenKind::Exclamation
+            | TokenKind::Asterisk
+            | TokenKind::Ampersand => {
+                self.pos += 1;
+                let operand = Box::new(self.unary()?);
+                let span = span.to(operand.span);
+                let kind = match token.kind {
+                    TokenKind::Minus => ExpressionKind::Unary(UnaryOp::Negate, operand),
+                    TokenKind::Tilde => ExpressionKind::Unary(UnaryOp::Complement, operand),
+                    TokenKind::Exclamation => ExpressionKind::Unary(UnaryOp::Not, operand),
+                    TokenKind::Asterisk => ExpressionKind::Dereference(operand),
+                    _ => ExpressionKind::AddressOf(operand),
+                };
+                Ok(Expression::new(kind, span))
+            }
+            // `++a` is `a += 1`.
+            TokenKind::Increment | TokenKind::Decrement => {
+                let op = match token.kind {
+                    TokenKind::Increment => BinaryOp::Add,
+                    _ => BinaryOp::Subtract,
+                };
+                self.pos += 1;
+                let operand = self.unary()?;
+                let one = Expression::new(ExpressionKind::Constant(Const::Int(1)), span);
+                let span = span.to(operand.span);
+                Ok(Expression::new(
+                    ExpressionKind::CompoundAssignment(op, Box::new(operand), Box::new(one)),
+                    span,
+                ))
+            }
+            TokenKind::OpenParen
+                if self
+                    .peek_nth_kind(1)
+                    .is_some_and(|kind| self.starts_specifiers(kind)) =>
+            {
+                self.cast()
+            }
+            TokenKind::Plus | TokenKind::Keyword(Keyword::Sizeof | Keyword::Alignof) => {
+                Err(self.unsupported(span))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn cast(&mut self) -> ParseResult<Expression> {
+        let open = self.expect(&TokenKind::OpenParen, "'('")?;
+        let ty = self.type_specifiers("type name")?;
+        let declarator = self.declarator()?;
+        let declared = self.process_declarator(declarator, ty)?;
+        if declared.name.is_some() {
+            return Err(self.expected_at("')'", declared.span));
+        }
+        if declared.params.is_some() {
+            return Err(unsupported("function pointers are", declared.span));
+        }
+        self.expect(&TokenKind::CloseParen, "')'")?;
+
+        let operand = self.unary()?;
+        let span = open.to(operand.span);
+        Ok(Expression::new(
+            ExpressionKind::Cast(declared.ty, Box::new(operand)),
+            span,
+        ))
+    }
+
+    fn postfix(&mut self) -> ParseResult<Expression> {
+        let mut expression = self.primary()?;
+
+        while let Some(token) = self.peek() {
+            let start = expression.span;
+            let kind = match token.kind {
+                TokenKind::OpenBracket => {
+                    self.pos += 1;
+                    let index = self.expression(0)?;
+                    self.expect(&TokenKind::CloseBracket, "']'")?;
+                    ExpressionKind::Subscript(Box::new(expression), Box::new(index))
+                }
+                TokenKind::Increment => {
+                    self.pos += 1;
+                    ExpressionKind::Postfix(PostfixOp::Increment, Box::new(expression))
+                }
+                TokenKind::Decrement => {
+                    self.pos += 1;
+                    ExpressionKind::Postfix(PostfixOp::Decrement, Box::new(expression))
+                }
+                TokenKind::Dot | TokenKind::Arrow => return Err(self.unsupported(token.span)),
+                _ => break,
+            };
+            expression = Expression::new(kind, start.to(self.previous_span()));
+        }
+
+        Ok(expression)
+    }
+
+    fn primary(&mut self) -> ParseResult<Expression> {
+        let Some(token) = self.peek() else {
+            return Err(self.unexpected("an expression"));
+        };
+        let span = token.span;
+
+        match &token.kind {
+            TokenKind::Constant(text) => {
+                let value = constant_value(text).ok_or_else(|| {
+                    Diagnostic::error(format!("integer constant '{text}' is too large"))
+                        .with_code(Code::ConstantTooLarge)
+                        .with_span(span)
+                        .with_note(Diagnostic::note(
+                            "constants must fit in 'unsigned long', or in 'long' if decimal \
+                             without a 'u' suffix",
+                        ))
+                })?;
+                self.pos += 1;
+                Ok(Expression::new(ExpressionKind::Constant(value), span))
+            }
+            TokenKind::Identifier(name) => {
+                let name = name.clone();
+                self.pos += 1;
+                if self.eat(&TokenKind::OpenParen).is_none() {
+                    return Ok(Expression::new(ExpressionKind::Var(name), span));
+                }
+
+                let mut args = Vec::new();
+                if self.peek_kind() != Some(&TokenKind::CloseParen) {
+                    loop {
+                        args.push(self.expression(ASSIGNMENT_PRECEDENCE)?);
+                        if self.eat(&TokenKind::Comma).is_none() {
+                            break;
+                        }
+                    }
+                }
+                let close = self.expect(&TokenKind::CloseParen, "')'")?;
+                Ok(Expression::new(
+                    ExpressionKind::FunctionCall(name, args),
+                    span.to(close),
+                ))
+            }
+            TokenKind::OpenParen => {
+                self.pos += 1;
+                let expression = self.expression(0)?;
+                let close = self.expect(&TokenKind::CloseParen, "')'")?;
+                Ok(Expression {
+                    span: span.to(close),
+                    ..expression
+                })
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    //-------------------------
+    // Tokens
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&'a TokenKind> {
+        self.peek_nth_kind(0)
+    }
+
+    fn peek_nth_kind(&self, n: usize) -> Option<&'a TokenKind> {
+        self.tokens.get(self.pos + n).map(|token| &token.kind)
+    }
+
+    /// The span of the next token, or an empty one at the end of the file if there is none.
+    fn peek_span(&self) -> Span {
+        self.peek().map_or_else(
+            || {
+                let end = self.source_map.code(self.file).len();
+                Span {
+                    file: self.file,
+                    start: end,
+                    end,
+                }
+            },
+            |token| token.span,
+        )
+    }
+
+    /// The span of the last token parsed.
+    fn previous_span(&self) -> Span {
+        self.tokens[self.pos - 1].span
+    }
+
+    /// Skips the next token if it is `kind`, returning its span.
+    fn eat(&mut self, kind: &TokenKind) -> Option<Span> {
+        let token = self.peek().filter(|token| token.kind == *kind)?;
+        self.pos += 1;
+        Some(token.span)
+    }
+
+    /// Skips the next token, which must be `kind`, described as `what` if it isn't.
+    fn expect(&mut self, kind: &TokenKind, what: &str) -> ParseResult<Span> {
+        self.eat(kind).ok_or_else(|| self.unexpected(what))
+    }
+
+    /// "expected `what`", pointing at the next token.
+    fn unexpected(&self, what: &str) -> Diagnostic {
+        self.expected_at(what, self.peek_span())
+    }
+
+    /// "expected `what`", pointing at the token at `span`.
+    fn expected_at(&self, what: &str, span: Span) -> Diagnostic {
+        let found = match span.start == self.source_map.code(self.file).len() {
+            true => "end of file".to_owned(),
+            false => format!("'{}'", self.source_map.text(span)),
+        };
+        Diagnostic::error(format!("expected {what}, found {found}"))
+            .with_code(Code::UnexpectedToken)
+            .with_span(span)
+    }
+
+    /// The construct starting with the token at `span` isn't supported.
+    fn unsupported(&self, span: Span) -> Diagnostic {
+        let feature = match self.source_map.text(span) {
+            "+" => "unary '+' is".to_owned(),
+            "..." => "variadic functions are".to_owned(),
+            "." | "->" => "structures are".to_owned(),
+            keyword => format!("'{keyword}' is"),
+        };
+        unsupported(&feature, span)
+    }
+}
+
+/// "`feature` not supported yet", where `feature` ends with its verb, like "structures are".
+fn unsupported(feature: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(format!("{feature} not supported yet"))
+        .with_code(Code::UnsupportedFeature)
+        .with_span(span)
+}
+
+/// Whether `kind` can start a declaration.
+fn is_specifier(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Keyword(
+            Keyword::Int
+                | Keyword::Long
+                | Keyword::Char
+                | Keyword::Double
+                | Keyword::Signed
+                | Keyword::Unsigned
+                | Keyword::Void
+                | Keyword::Static
+                | Keyword::Extern
+                | Keyword::Typedef
+                | Keyword::Const
+                | Keyword::Volatile
+        )
+    )
+}
+
+/// The type a list of type specifiers names, in any order, or `None` if they don't name one.
+fn specifier_type(specifiers: &[&Keyword]) -> Option<Type> {
+    let has = |keyword| specifiers.contains(&&keyword);
+    let only = |keyword| specifiers == [&keyword];
+
+    let repeated = specifiers
+        .iter()
+        .enumerate()
+        .any(|(i, keyword)| specifiers[..i].contains(keyword));
+    if repeated || (has(Keyword::Signed) && has(Keyword::Unsigned)) {
+        return None;
+    }
+    if has(Keyword::Void) {
+        return only(Keyword::Void).then_some(Type::Void);
+    }
+    if has(Keyword::Double) {
+        return only(Keyword::Double).then_some(Type::Double);
+    }
+    if has(Keyword::Char) {
+        return match specifiers.len() {
+            1 => Some(Type::Char),
+            2 if has(Keyword::Signed) => Some(Type::SChar),
+            2 if has(Keyword::Unsigned) => Some(Type::UChar),
+            _ => None,
+        };
+    }
+
+    match (has(Keyword::Long), has(Keyword::Unsigned)) {
+        (false, false) => Some(Type::Int),
+        (false, true) => Some(Type::UInt),
+        (true, false) => Some(Type::Long),
+        (true, true) => Some(Type::ULong),
+    }
+}
+
+/// The name `declarator` declares, if it isn't abstract.
+fn declarator_name(declarator: &Declarator) -> Option<&str> {
+    match declarator {
+        Declarator::Ident(name, _) => name.as_deref(),
+        Declarator::Pointer(inner)
+        | Declarator::Array(inner, _)
+        | Declarator::Function(_, inner) => declarator_name(inner),
+    }
+}
+
+/// Where the name of `declarator` is, or would be.
+fn declarator_span(declarator: &Declarator) -> Span {
+    match declarator {
+        Declarator::Ident(_, span) => *span,
+        Declarator::Pointer(inner)
+        | Declarator::Array(inner, _)
+        | Declarator::Function(_, inner) => declarator_span(inner),
+    }
+}
+
+/// The binary operator `kind` is, and its precedence: higher binds tighter.
+fn infix(kind: &TokenKind) -> Option<(Infix, u8)> {
+    let binary = |op| Infix::Binary(op);
+    let compound = |op| Infix::CompoundAssign(op);
+    Some(match kind {
+        TokenKind::Asterisk => (binary(BinaryOp::Multiply), 50),
+        TokenKind::Slash => (binary(BinaryOp::Divide), 50),
+        TokenKind::Percent => (binary(BinaryOp::Remainder), 50),
+        TokenKind::Plus => (binary(BinaryOp::Add), 45),
+        TokenKind::Minus => (binary(BinaryOp::Subtract), 45),
+        TokenKind::ShiftLeft => (binary(BinaryOp::ShiftLeft), 40),
+        TokenKind::ShiftRight => (binary(BinaryOp::ShiftRight), 40),
+        TokenKind::Less => (binary(BinaryOp::LessThan), 35),
+        TokenKind::LessEqual => (binary(BinaryOp::LessOrEqual), 35),
+        TokenKind::Greater => (binary(BinaryOp::GreaterThan), 35),
+        TokenKind::GreaterEqual => (binary(BinaryOp::GreaterOrEqual), 35),
+        TokenKind::EqualEqual => (binary(BinaryOp::Equal), 30),
+        TokenKind::NotEqual => (binary(BinaryOp::NotEqual), 30),
+        TokenKind::Ampersand => (binary(BinaryOp::BitAnd), 25),
+        TokenKind::Caret => (binary(BinaryOp::BitXor), 20),
+        TokenKind::Pipe => (binary(BinaryOp::BitOr), 15),
+        TokenKind::LogicalAnd => (binary(BinaryOp::And), 10),
+        TokenKind::LogicalOr => (binary(BinaryOp::Or), 5),
+        TokenKind::Question => (Infix::Conditional, 3),
+        TokenKind::Assign => (Infix::Assign, ASSIGNMENT_PRECEDENCE),
+        TokenKind::PlusAssign => (compound(BinaryOp::Add), ASSIGNMENT_PRECEDENCE),
+        TokenKind::MinusAssign => (compound(BinaryOp::Subtract), ASSIGNMENT_PRECEDENCE),
+        TokenKind::AsteriskAssign => (compound(BinaryOp::Multiply), ASSIGNMENT_PRECEDENCE),
+        TokenKind::SlashAssign => (compound(BinaryOp::Divide), ASSIGNMENT_PRECEDENCE),
+        TokenKind::PercentAssign => (compound(BinaryOp::Remainder), ASSIGNMENT_PRECEDENCE),
+        TokenKind::AmpersandAssign => (compound(BinaryOp::BitAnd), ASSIGNMENT_PRECEDENCE),
+        TokenKind::PipeAssign => (compound(BinaryOp::BitOr), ASSIGNMENT_PRECEDENCE),
+        TokenKind::CaretAssign => (compound(BinaryOp::BitXor), ASSIGNMENT_PRECEDENCE),
+        TokenKind::ShiftLeftAssign => (compound(BinaryOp::ShiftLeft), ASSIGNMENT_PRECEDENCE),
+        TokenKind::ShiftRightAssign => (compound(BinaryOp::ShiftRight), ASSIGNMENT_PRECEDENCE),
+        _ => return None,
+    })
+}
+
+/// The value of a constant token, with the type C gives it: the first type in its list that
+/// can hold it. `None` if none can.
+fn constant_value(text: &str) -> Option<Const> {
+    let hex = text.starts_with("0x") || text.starts_with("0X");
+    if !hex && text.contains(['.', 'e', 'E']) {
+        return text.parse().ok().map(Const::Double);
+    }
+
+    let lower = text.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(['u', 'l']);
+    let suffix = &lower[digits.len()..];
+    let (digits, radix) = match digits.strip_prefix("0x") {
+        Some(digits) => (digits, 16),
+        None if digits.len() > 1 && digits.starts_with('0') => (&digits[1..], 8),
+        None => (digits, 10),
+    };
+    let value = u64::from_str_radix(digits, radix).ok()?;
+
+    // Decimal constants only become unsigned if they say so.
+    let unsigned = suffix.contains('u');
+    let may_be_unsigned = unsigned || radix != 10;
+    let long = suffix.contains('l');
+
+    if !unsigned && !long {
+        if let Ok(value) = i32::try_from(value) {
+            return Some(Const::Int(value));
+        }
+    }
+    if may_be_unsigned && !long {
+        if let Ok(value) = u32::try_from(value) {
+            return Some(Const::UInt(value));
+        }
+    }
+    if !unsigned {
+        if let Ok(value) = i64::try_from(value) {
+            return Some(Const::Long(value));
+        }
+    }
+    may_be_unsigned.then_some(Const::ULong(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    /// Lexes and parses `code`, which must have no errors.
+    fn parse(code: &str) -> Program {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+        let program = Parser::new(&source_map, file, &tokens).parse(&mut diagnostics);
+        assert!(!diagnostics.has_errors());
+        program
+    }
+
+    /// Parses `code` with an error limit of `limit`, returning the message and location of
+    /// each error, and the names of the declarations parsed.
+    fn parse_errors(code: &str, limit: usize) -> (Vec<(String, String)>, Vec<String>) {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+        let mut diagnostics = Diagnostics::new(limit);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+        let program = Parser::new(&source_map, file, &tokens).parse(&mut diagnostics);
+
+        let errors = diagnostics
+            .iter()
+            .map(|error| {
+                (
+                    error.message.clone(),
+                    source_map.location(error.span.unwrap()).to_string(),
+                )
+            })
+            .collect();
+        let names = program
+            .declarations
+            .iter()
+            .map(|declaration| match declaration {
+                Declaration::Function(function) => function.name.clone(),
+                Declaration::Variable(variable) => variable.name.clone(),
+            })
+            .collect();
+        (errors, names)
+    }
+
+    /// Parses `code`, which must have exactly one error, returning its code, message and
+    /// location.
+    fn parse_error(code: &str) -> (Option<Code>, String, String) {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+        Parser::new(&source_map, file, &tokens).parse(&mut diagnostics);
+
+        let errors: Vec<&Diagnostic> = diagnostics.iter().collect();
+        assert_eq!(errors.len(), 1);
+        (
+            errors[0].code,
+            errors[0].message.clone(),
+            source_map.location(errors[0].span.unwrap()).to_string(),
+        )
+    }
+
+    /// The statements in the body of the only function in `program`.
+    fn body(program: &Program) -> Vec<&Statement> {
+        let [Declaration::Function(function)] = &program.declarations[..] else {
+            panic!("expected a single function.");
+        };
+        function
+            .body
+            .iter()
+            .flat_map(|body| &body.items)
+            .filter_map(|item| match item {
+                BlockItem::Statement(statement) => Some(statement),
+                BlockItem::Declaration(_) => None,
+            })
+            .collect()
+    }
+
+    /// Writes `expression` with every operation parenthesized.
+    fn show(expression: &Expression) -> String {
+        match &expression.kind {
+            ExpressionKind::Constant(value) => value.to_string(),
+            ExpressionKind::Var(name) => name.clone(),
+            ExpressionKind::Cast(ty, operand) => format!("(({ty}){})", show(operand)),
+            ExpressionKind::Unary(op, operand) => format!("({op}{})", show(operand)),
+            ExpressionKind::Binary(op, left, right) => {
+                format!("({} {op} {})", show(left), show(right))
+            }
+            ExpressionKind::Assignment(left, right) => {
+                format!("({} = {})", show(left), show(right))
+            }
+            ExpressionKind::CompoundAssignment(op, left, right) => {
+                format!("({} {op}= {})", show(left), show(right))
+            }
+            ExpressionKind::Postfix(op, operand) => format!("({}{op})", show(operand)),
+            ExpressionKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => format!(
+                "({} ? {} : {})",
+                show(condition),
+                show(then),
+                show(otherwise)
+            ),
+            ExpressionKind::FunctionCall(name, args) => {
+                let args: Vec<String> = args.iter().map(show).collect();
+                format!("{name}({})", args.join(", "))
+            }
+            ExpressionKind::Dereference(operand) => format!("(*{})", show(operand)),
+            ExpressionKind::AddressOf(operand) => format!("(&{})", show(operand)),
+            ExpressionKind::Subscript(array, index) => {
+                format!("{}[{}]", show(array), show(index))
+            }
+        }
+    }
+
+    /// Parses `expression`, returned from a function, and shows it.
+    fn parse_expression(expression: &str) -> String {
+        let program = parse(&format!("int f(void) {{ return {expression}; }}"));
+        match &body(&program)[0].kind {
+            StatementKind::Return(Some(value)) => show(value),
+            statement => panic!("expected a return statement, found {statement:?}."),
+        }
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(parse_expression("1 + 2 * 3 - 4"), "((1 + (2 * 3)) - 4)");
+        assert_eq!(
+            parse_expression("a || b && c | d ^ e & f == g < h << i"),
+            "(a || (b && (c | (d ^ (e & (f == (g < (h << i))))))))"
+        );
+        assert_eq!(
+            parse_expression("a = b += c ? d : e"),
+            "(a = (b += (c ? d : e)))"
+        );
+        assert_eq!(
+            parse_expression("a ? b : c ? d : e"),
+            "(a ? b : (c ? d : e))"
+        );
+        assert_eq!(parse_expression("a ? b = 1 : c"), "(a ? (b = 1) : c)");
+        assert_eq!(parse_expression("-~!*&x"), "(-(~(!(*(&x)))))");
+        assert_eq!(parse_expression("-a[1][2]++"), "(-(a[1][2]++))");
+        assert_eq!(parse_expression("++*p--"), "((*(p--)) += 1)");
+        assert_eq!(parse_expression("(a + b) * c"), "((a + b) * c)");
+        assert_eq!(
+            parse_expression("(unsigned long)(double *)p + f(1, g(), x = 2)"),
+            "(((unsigned long)((double *)p)) + f(1, g(), (x = 2)))"
+        );
+    }
+
+    #[test]
+    fn test_parse_declarators() {
+        let program = parse(
+            "static unsigned long a, *b[3];\n\
+             extern char (*c)[2][4];\n\
+             signed char *d(int x, double *, long e[10]);\n\
+             int unsigned f = 1, g = { 2, }, h[2] = { { 3 }, 4 };\n",
+        );
+
+        let declared: Vec<(&str, String, Option<StorageClass>)> = program
+            .declarations
+            .iter()
+            .map(|declaration| match declaration {
+                Declaration::Function(function) => (
+                    function.name.as_str(),
+                    function.ty.to_string(),
+                    function.storage_class,
+                ),
+                Declaration::Variable(variable) => (
+                    variable.name.as_str(),
+                    variable.ty.to_string(),
+                    variable.storage_class,
+                ),
+            })
+            .collect();
+        assert_eq!(
+            declared,
+            vec![
+                ("a", "unsigned long".to_owned(), Some(StorageClass::Static)),
+                (
+                    "b",
+                    "unsigned long *[3]".to_owned(),
+                    Some(StorageClass::Static)
+                ),
+                ("c", "char (*)[2][4]".to_owned(), Some(StorageClass::Extern)),
+                (
+                    "d",
+                    "signed char *(int, double *, long [10])".to_owned(),
+                    None
+                ),
+                ("f", "unsigned int".to_owned(), None),
+                ("g", "unsigned int".to_owned(), None),
+                ("h", "unsigned int [2]".to_owned(), None),
+            ]
+        );
+
+        let Declaration::Function(d) = &program.declarations[3] else {
+            panic!("expected a function.");
+        };
+        let names: Vec<Option<&str>> = d.params.iter().map(|p| p.name.as_deref()).collect();
+        assert_eq!(names, vec![Some("x"), None, Some("e")]);
+        assert!(d.body.is_none());
+    }
+
+    #[test]
+    fn test_parse_typedefs() {
+        let program = parse(
+            "typedef unsigned long size;\n\
+             typedef size *sizes[2], (*row)[3];\n\
+             sizes a;\n\
+             row b = (row)0;\n\
+             int f(long size) { return size * 2; }\n\
+             int g(void) { typedef char size; size c; { int size; size * c; } return 0; }\n\
+             size h(size (x));\n",
+        );
+
+        let declared: Vec<(&str, String)> = program
+            .declarations
+            .iter()
+            .map(|declaration| match declaration {
+                Declaration::Function(function) => {
+                    (function.name.as_str(), function.ty.to_string())
+                }
+                Declaration::Variable(variable) => {
+                    (variable.name.as_str(), variable.ty.to_string())
+                }
+            })
+            .collect();
+        assert_eq!(
+            declared,
+            vec![
+                ("a", "unsigned long *[2]".to_owned()),
+                ("b", "unsigned long (*)[3]".to_owned()),
+                ("f", "int (long)".to_owned()),
+                ("g", "int (void)".to_owned()),
+                ("h", "unsigned long (unsigned long)".to_owned()),
+            ]
+        );
+
+        let Declaration::Variable(b) = &program.declarations[1] else {
+            panic!("expected a variable.");
+        };
+        let Some(Initializer::Single(init)) = &b.init else {
+            panic!("expected a single initializer.");
+        };
+        assert_eq!(show(init), "((unsigned long (*)[3])0)");
+
+        // A variable named like a typedef shadows it, so `size * ...` multiplies.
+        let items = |i: usize| match &program.declarations[i] {
+            Declaration::Function(function) => &function.body.as_ref().unwrap().items,
+            Declaration::Variable(_) => panic!("expected a function."),
+        };
+        let [BlockItem::Statement(Statement {
+            kind: StatementKind::Return(Some(product)),
+            ..
+        })] = &items(2)[..]
+        else {
+            panic!("expected a return statement.");
+        };
+        assert_eq!(show(product), "(size * 2)");
+
+        let [BlockItem::Declaration(Declaration::Variable(c)), BlockItem::Statement(inner), _] =
+            &items(3)[..]
+        else {
+            panic!("expected a declaration and two statements.");
+        };
+        assert_eq!(c.ty, Type::Char);
+        let StatementKind::Compound(inner) = &inner.kind else {
+            panic!("expected a block.");
+        };
+        let BlockItem::Statement(Statement {
+            kind: StatementKind::Expression(product),
+            ..
+        }) = &inner.items[1]
+        else {
+            panic!("expected an expression statement.");
+        };
+        assert_eq!(show(product), "(size * c)");
+    }
+
+    #[test]
+    fn test_parse_statements() {
+        let program = parse(
+            "int main(void) {\n\
+                 int i = 0;\n\
+                 for (int j = 0; j < 3; j++) {\n\
+                     while (1) { if (i) break; else continue; }\n\
+                     if (j) break;\n\
+                 }\n\
+                 for (;;) do ; while (0);\n\
+                 return;\n\
+             }\n",
+        );
+        let statements = body(&program);
+        assert_eq!(statements.len(), 3);
+
+        let StatementKind::For {
+            init: ForInit::Declarations(init),
+            condition: Some(_),
+            post: Some(_),
+            body: outer_body,
+            id: outer,
+        } = &statements[0].kind
+        else {
+            panic!("expected a for loop, found {:?}.", statements[0].kind);
+        };
+        assert_eq!(init.len(), 1);
+        let StatementKind::Compound(block) = &outer_body.kind else {
+            panic!("expected a block.");
+        };
+        let [BlockItem::Statement(inner), BlockItem::Statement(if_j)] = &block.items[..] else {
+            panic!("expected two statements.");
+        };
+        let StatementKind::While {
+            body, id: inner, ..
+        } = &inner.kind
+        else {
+            panic!("expected a while loop.");
+        };
+        let StatementKind::Compound(block) = &body.kind else {
+            panic!("expected a block.");
+        };
+        let [BlockItem::Statement(Statement {
+            kind: StatementKind::If {
+                then, otherwise, ..
+            },
+            ..
+        })] = &block.items[..]
+        else {
+            panic!("expected an if statement.");
+        };
+        assert_eq!(then.kind, StatementKind::Break(*inner));
+        assert_eq!(
+            otherwise.as_ref().unwrap().kind,
+            StatementKind::Continue(*inner)
+        );
+        let StatementKind::If { then, .. } = &if_j.kind else {
+            panic!("expected an if statement.");
+        };
+        assert_eq!(then.kind, StatementKind::Break(*outer));
+        assert_ne!(inner, outer);
+
+        let StatementKind::For {
+            init: ForInit::Expression(None),
+            condition: None,
+            post: None,
+            body,
+            ..
+        } = &statements[1].kind
+        else {
+            panic!("expected a for loop, found {:?}.", statements[1].kind);
+        };
+        assert!(matches!(body.kind, StatementKind::DoWhile { .. }));
+        assert_eq!(statements[2].kind, StatementKind::Return(None));
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        let code = "int f(int n) {\n\
+                    \tlong a[2] = {1, n};\n\
+                    \tif (n > 0)\n\
+                    \t\treturn (long)a[n - 1]++ * f(n);\n\
+                    \treturn 0;\n\
+                    }";
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+        let mut diagnostics = Diagnostics::new(0);
+        let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+        let program = Parser::new(&source_map, file, &tokens).parse(&mut diagnostics);
+        assert!(!diagnostics.has_errors());
+        let text = |span| source_map.text(span);
+
+        let [Declaration::Function(f)] = &program.declarations[..] else {
+            panic!("expected a single function.");
+        };
+        assert_eq!(text(f.span), "f");
+        assert_eq!(text(f.params[0].span), "n");
+        let body = f.body.as_ref().unwrap();
+        assert_eq!(text(body.span), &code[code.find('{').unwrap()..]);
+
+        let [BlockItem::Declaration(Declaration::Variable(a)), BlockItem::Statement(if_statement), BlockItem::Statement(last)] =
+            &body.items[..]
+        else {
+            panic!("expected a declaration and two statements.");
+        };
+        assert_eq!(text(a.span), "a");
+        assert_eq!(text(a.init.as_ref().unwrap().span()), "{1, n}");
+        assert_eq!(
+            text(if_statement.span),
+            "if (n > 0)\n\t\treturn (long)a[n - 1]++ * f(n);"
+        );
+        assert_eq!(text(last.span), "return 0;");
+
+        let StatementKind::If {
+            condition, then, ..
+        } = &if_statement.kind
+        else {
+            panic!("expected an if statement.");
+        };
+        assert_eq!(text(condition.span), "n > 0");
+        assert_eq!(text(then.span), "return (long)a[n - 1]++ * f(n);");
+        let StatementKind::Return(Some(product)) = &then.kind else {
+            panic!("expected a return statement.");
+        };
+        assert_eq!(text(product.span), "(long)a[n - 1]++ * f(n)");
+        let ExpressionKind::Binary(BinaryOp::Multiply, cast, call) = &product.kind else {
+            panic!("expected a multiplication.");
+        };
+        assert_eq!(text(cast.span), "(long)a[n - 1]++");
+        assert_eq!(text(call.span), "f(n)");
+        let ExpressionKind::Cast(_, increment) = &cast.kind else {
+            panic!("expected a cast.");
+        };
+        assert_eq!(text(increment.span), "a[n - 1]++");
+        let ExpressionKind::Postfix(_, element) = &increment.kind else {
+            panic!("expected an increment.");
+        };
+        assert_eq!(text(element.span), "a[n - 1]");
+    }
+
+    #[test]
+    fn test_parse_constant_types() {
+        let constants = [
+            ("2147483647", Const::Int(i32::MAX)),
+            ("2147483648", Const::Long(2147483648)),
+            ("0x7fffffff", Const::Int(i32::MAX)),
+            ("0x80000000", Const::UInt(0x8000_0000)),
+            ("010", Const::Int(8)),
+            ("0", Const::Int(0)),
+            ("4294967296u", Const::ULong(4294967296)),
+            ("1U", Const::UInt(1)),
+            ("1l", Const::Long(1)),
+            ("0xffffffffffffffffL", Const::ULong(u64::MAX)),
+            ("9223372036854775808uL", Const::ULong(1 << 63)),
+            ("1.5", Const::Double(1.5)),
+            (".5e1", Const::Double(5.0)),
+            ("1e-3", Const::Double(0.001)),
+        ];
+        for (text, value) in constants {
+            assert_eq!(constant_value(text), Some(value), "{text}");
+        }
+
+        assert_eq!(constant_value("9223372036854775808"), None);
+        assert_eq!(constant_value("18446744073709551616u"), None);
+    }
+
+    #[test]
+    fn test_parse_recovers_from_errors() {
+        let code = "int f(void) {\n\
+                    \x20 int x = ;\n\
+                    \x20 if (x) { x = 1 +; }\n\
+                    \x20 return x\n\
+                    }\n\
+                    int g(int a b);\n\
+                    int h(void) { return 0; }\n";
+        let error = |message: &str, location: &str| (message.to_owned(), location.to_owned());
+
+        let (errors, names) = parse_errors(code, 0);
+        assert_eq!(
+            errors,
+            vec![
+                error("expected an expression, found ';'", "test.c:2:11"),
+                error("expected an expression, found ';'", "test.c:3:19"),
+                error("expected ';', found '}'", "test.c:5:1"),
+                error("expected ')', found 'b'", "test.c:6:13"),
+            ]
+        );
+        assert_eq!(names, ["f", "h"]);
+
+        let (errors, _) = parse_errors(code, 2);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let errors = [
+            (
+                "int main(void) {\n  return 0\n}",
+                Code::UnexpectedToken,
+                "expected ';', found '}'",
+                "test.c:3:1",
+            ),
+            (
+                "int main(void) { return 1 +",
+                Code::UnexpectedToken,
+                "expected an expression, found end of file",
+                "test.c:1:28",
+            ),
+            (
+                "x = 1;",
+                Code::UnexpectedToken,
+                "expected a declaration, found 'x'",
+                "test.c:1:1",
+            ),
+            (
+                "int;",
+                Code::UnexpectedToken,
+                "expected an identifier, found ';'",
+                "test.c:1:4",
+            ),
+            (
+                "int f(void) { break; }",
+                Code::JumpOutsideLoop,
+                "'break' statement not in a loop",
+                "test.c:1:15",
+            ),
+            (
+                "typedef int T; int T;",
+                Code::RedeclaredAsDifferentKind,
+                "'T' redeclared as different kind of symbol",
+                "test.c:1:20",
+            ),
+            (
+                "typedef int T; typedef long T;",
+                Code::ConflictingDeclaration,
+                "conflicting types for 'T'",
+                "test.c:1:29",
+            ),
+            (
+                "typedef static int T;",
+                Code::InvalidDeclaration,
+                "multiple storage classes in declaration specifiers",
+                "test.c:1:9",
+            ),
+            (
+                "typedef int T; int f(typedef int x);",
+                Code::InvalidDeclaration,
+                "storage class specified for parameter",
+                "test.c:1:22",
+            ),
+            (
+                "int (*f)(void);",
+                Code::UnsupportedFeature,
+                "function pointers are not supported yet",
+                "test.c:1:7",
+            ),
+            (
+                "long x = 99999999999999999999;",
+                Code::ConstantTooLarge,
+                "integer constant '99999999999999999999' is too large",
+                "test.c:1:10",
+            ),
+            (
+                "long char x;",
+                Code::InvalidDeclaration,
+                "invalid combination of type specifiers",
+                "test.c:1:1",
+            ),
+            (
+                "int a[0];",
+                Code::InvalidDeclaration,
+                "array length must be a positive integer constant",
+                "test.c:1:7",
+            ),
+            (
+                "int f(void)[3];",
+                Code::InvalidDeclaration,
+                "function cannot return 'int [3]'",
+                "test.c:1:5",
+            ),
+            (
+                "int a[3](void);",
+                Code::InvalidDeclaration,
+                "array elements cannot have type 'int (void)'",
+                "test.c:1:5",
+            ),
+            (
+                "static extern int x;",
+                Code::InvalidDeclaration,
+                "multiple storage classes in declaration specifiers",
+                "test.c:1:8",
+            ),
+        ];
+
+        for (code, error_code, message, location) in errors {
+            assert_eq!(
+                parse_error(code),
+                (Some(error_code), message.to_owned(), location.to_owned()),
+                "{code}"
+            );
+        }
+    }
+}
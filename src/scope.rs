@@ -0,0 +1,265 @@
+use std::{collections::HashMap, mem};
+
+use crate::{
+    ast::Type,
+    diagnostics::{Code, Diagnostic},
+    source_map::Span,
+};
+
+/// What a name in the ordinary identifier namespace refers to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum NameKind {
+    /// A name introduced by a `typedef` declaration, for the type it names.
+    Typedef(Type),
+    /// Any other ordinary identifier: a variable, function or enumeration constant.
+    Ordinary,
+}
+
+/// Block-scoped table of the ordinary identifier namespace.
+///
+/// `Token::Identifier` does not tell type names apart from other identifiers, so the parser
+/// declares every name here as it goes and asks `is_type_name` whenever an identifier could
+/// start a declaration. A name declared in an inner block shadows the outer one until that
+/// block ends, which is how `typedef int T; { int T; T * x; }` parses as a multiplication.
+pub struct Scopes {
+    // Innermost scope is last; the first entry is file scope and is never popped.
+    // Each name maps to its kind and where it was declared.
+    stack: Vec<HashMap<String, (NameKind, Span)>>,
+}
+
+impl Scopes {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![HashMap::new()],
+        }
+    }
+
+    /// Opens a new block scope.
+    pub fn enter(&mut self) {
+        self.stack.push(HashMap::new());
+    }
+
+    /// Closes the innermost block scope, dropping every name it declared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called at file scope.
+    pub fn exit(&mut self) {
+        if self.stack.len() == 1 {
+            panic!("cannot leave file scope.");
+        }
+        self.stack.pop();
+    }
+
+    /// Declares `name`, spelled at `span`, in the innermost scope.
+    ///
+    /// Redeclaring an ordinary identifier is left to the type checker, since only it knows
+    /// whether the two declarations are compatible. A typedef never reaches it, so redeclaring
+    /// one as a different type is an error here. Redeclaring a name as a different kind of
+    /// symbol in the same scope is an error too. Either way, the earlier declaration is kept.
+    pub fn declare(&mut self, name: &str, kind: NameKind, span: Span) -> Result<(), Diagnostic> {
+        let scope = self.stack.last_mut().unwrap();
+        let previous_declaration =
+            |previous| Diagnostic::note("previous declaration is here").with_span(previous);
+
+        match scope.get(name) {
+            Some((existing, previous))
+                if mem::discriminant(existing) != mem::discriminant(&kind) =>
+            {
+                Err(
+                    Diagnostic::error(format!("'{name}' redeclared as different kind of symbol"))
+                        .with_code(Code::RedeclaredAsDifferentKind)
+                        .with_span(span)
+                        .with_note(previous_declaration(*previous)),
+                )
+            }
+            Some((existing, previous)) if *existing != kind => {
+                Err(Diagnostic::error(format!("conflicting types for '{name}'"))
+                    .with_code(Code::ConflictingDeclaration)
+                    .with_span(span)
+                    .with_note(previous_declaration(*previous)))
+            }
+            _ => {
+                scope.insert(name.to_owned(), (kind, span));
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns `true` if the innermost visible declaration of `name` is a typedef.
+    pub fn is_type_name(&self, name: &str) -> bool {
+        self.typedef(name).is_some()
+    }
+
+    /// Returns the type `name` names, if its innermost visible declaration is a typedef.
+    pub fn typedef(&self, name: &str) -> Option<&Type> {
+        match self.lookup(name)? {
+            NameKind::Typedef(ty) => Some(ty),
+            NameKind::Ordinary => None,
+        }
+    }
+
+    /// Returns the kind of the innermost visible declaration of `name`, if there is one.
+    pub fn lookup(&self, name: &str) -> Option<&NameKind> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).map(|(kind, _)| kind))
+    }
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::SourceMap;
+
+    /// A span to declare names at, and the one after it.
+    fn spans() -> (Span, Span) {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.c".to_owned(), "T T".to_owned());
+        (
+            Span {
+                file,
+                start: 0,
+                end: 1,
+            },
+            Span {
+                file,
+                start: 2,
+                end: 3,
+            },
+        )
+    }
+
+    #[test]
+    fn test_typedef_is_type_name() {
+        let (span, _) = spans();
+        let mut scopes = Scopes::new();
+        scopes
+            .declare("size_t", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+        scopes.declare("x", NameKind::Ordinary, span).unwrap();
+
+        assert!(scopes.is_type_name("size_t"));
+        assert!(!scopes.is_type_name("x"));
+        assert!(!scopes.is_type_name("undeclared"));
+    }
+
+    #[test]
+    fn test_typedef_visible_in_inner_scope() {
+        let (span, _) = spans();
+        let mut scopes = Scopes::new();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+        scopes.enter();
+
+        assert!(scopes.is_type_name("T"));
+    }
+
+    #[test]
+    fn test_variable_shadows_typedef() {
+        let (span, _) = spans();
+        let mut scopes = Scopes::new();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+
+        scopes.enter();
+        scopes.declare("T", NameKind::Ordinary, span).unwrap();
+        assert!(!scopes.is_type_name("T"));
+        assert_eq!(scopes.lookup("T"), Some(&NameKind::Ordinary));
+
+        scopes.exit();
+        assert!(scopes.is_type_name("T"));
+    }
+
+    #[test]
+    fn test_typedef_shadows_variable() {
+        let (span, _) = spans();
+        let mut scopes = Scopes::new();
+        scopes.declare("T", NameKind::Ordinary, span).unwrap();
+
+        scopes.enter();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+        assert!(scopes.is_type_name("T"));
+
+        scopes.exit();
+        assert!(!scopes.is_type_name("T"));
+    }
+
+    #[test]
+    fn test_inner_typedef_dropped_on_exit() {
+        let (span, _) = spans();
+        let mut scopes = Scopes::new();
+        scopes.enter();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+        scopes.exit();
+
+        assert_eq!(scopes.lookup("T"), None);
+    }
+
+    #[test]
+    fn test_redeclare_same_kind() {
+        let (span, _) = spans();
+        let mut scopes = Scopes::new();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), span)
+            .unwrap();
+
+        assert!(scopes.is_type_name("T"));
+    }
+
+    #[test]
+    fn test_redeclare_different_kind() {
+        let (first, second) = spans();
+        let mut scopes = Scopes::new();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), first)
+            .unwrap();
+
+        let error = scopes.declare("T", NameKind::Ordinary, second).unwrap_err();
+        assert_eq!(error.code, Some(Code::RedeclaredAsDifferentKind));
+        assert_eq!(error.message, "'T' redeclared as different kind of symbol");
+        assert_eq!(error.span, Some(second));
+        assert_eq!(error.notes[0].span, Some(first));
+        assert!(scopes.is_type_name("T"));
+    }
+
+    #[test]
+    fn test_redeclare_typedef_as_different_type() {
+        let (first, second) = spans();
+        let mut scopes = Scopes::new();
+        scopes
+            .declare("T", NameKind::Typedef(Type::Int), first)
+            .unwrap();
+
+        let error = scopes
+            .declare("T", NameKind::Typedef(Type::Long), second)
+            .unwrap_err();
+        assert_eq!(error.code, Some(Code::ConflictingDeclaration));
+        assert_eq!(error.message, "conflicting types for 'T'");
+        assert_eq!(error.notes[0].span, Some(first));
+        assert_eq!(scopes.typedef("T"), Some(&Type::Int));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot leave file scope.")]
+    fn test_exit_file_scope() {
+        let mut scopes = Scopes::new();
+        scopes.exit();
+    }
+}
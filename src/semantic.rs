@@ -0,0 +1,22 @@
+//! Checks that a parsed program means something, between parsing and TACKY generation.
+//!
+//! Identifier resolution runs first and gives every variable a name unique in the program,
+//! then type checking types every expression against the declarations those names refer to.
+
+mod identifier_resolution;
+mod type_checking;
+
+pub use type_checking::{Attributes, InitialValue, SymbolTable};
+
+use crate::{ast::Program, diagnostics::Diagnostics};
+
+/// Resolves and type-checks `program` in place, returning every name it declares. Errors are
+/// reported to `diagnostics`; type checking is skipped if resolution fails, since it needs
+/// every name resolved, and the returned table is then empty.
+pub fn analyze(program: &mut Program, diagnostics: &mut Diagnostics) -> SymbolTable {
+    identifier_resolution::resolve(program, diagnostics);
+    if diagnostics.has_errors() {
+        return SymbolTable::default();
+    }
+    type_checking::check(program, diagnostics)
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        Block, BlockItem, Declaration, Expression, ExpressionKind, ForInit, FunctionDeclaration,
+        Initializer, Program, Statement, StatementKind, StorageClass, VariableDeclaration,
+    },
+    diagnostics::{Code, Diagnostic, Diagnostics},
+    source_map::Span,
+};
+
+type ResolveResult<T> = Result<T, Diagnostic>;
+
+/// What a name in scope refers to.
+struct Entry {
+    /// The name every use is renamed to.
+    unique_name: String,
+    /// Whether the name refers to the same thing in every scope declaring it, as functions and
+    /// `extern` variables do.
+    has_linkage: bool,
+    span: Span,
+}
+
+/// Renames every local variable to a name unique in the whole program, so later stages don't
+/// need to know about scopes.
+struct Resolver {
+    // Innermost scope last; the first is file scope.
+    scopes: Vec<HashMap<String, Entry>>,
+    counter: usize,
+}
+
+/// Resolves every identifier in `program` to the declaration it refers to, renaming variables
+/// without linkage to `name.N`. Uses of undeclared names and conflicting declarations in one
+/// scope are reported to `diagnostics`; each ends resolving the top-level declaration it's in.
+pub fn resolve(program: &mut Program, diagnostics: &mut Diagnostics) {
+    let mut resolver = Resolver {
+        scopes: vec![HashMap::new()],
+        counter: 0,
+    };
+
+    for declaration in &mut program.declarations {
+        let resolved = match declaration {
+            Declaration::Function(function) => resolver.function(function),
+            Declaration::Variable(variable) => resolver.file_scope_variable(variable),
+        };
+        if let Err(error) = resolved {
+            diagnostics.emit(error);
+        }
+        if diagnostics.limit_reached() {
+            return;
+        }
+    }
+}
+
+impl Resolver {
+    fn function(&mut self, function: &mut FunctionDeclaration) -> ResolveResult<()> {
+        let block_scope = self.scopes.len() > 1;
+        if block_scope && function.body.is_some() {
+            return Err(Diagnostic::error("function definition is not allowed here")
+                .with_code(Code::InvalidDeclaration)
+                .with_span(function.span));
+        }
+        if block_scope && function.storage_class == Some(StorageClass::Static) {
+            return Err(Diagnostic::error(format!(
+                "invalid storage class for block-scope function '{}'",
+                function.name
+            ))
+            .with_code(Code::InvalidDeclaration)
+            .with_span(function.span));
+        }
+        self.declare(&function.name, function.name.clone(), true, function.span)?;
+
+        // Parameters share a scope with the function body.
+        self.scoped(|resolver| resolver.function_body(function))
+    }
+
+    fn function_body(&mut self, function: &mut FunctionDeclaration) -> ResolveResult<()> {
+        for param in &mut function.params {
+            if let Some(name) = &mut param.name {
+                let unique_name = self.unique_name(name);
+                self.declare(name, unique_name.clone(), false, param.span)?;
+                *name = unique_name;
+            }
+        }
+
+        match &mut function.body {
+            Some(body) => self.block_items(&mut body.items),
+            None => Ok(()),
+        }
+    }
+
+    fn file_scope_variable(&mut self, variable: &mut VariableDeclaration) -> ResolveResult<()> {
+        self.declare(&variable.name, variable.name.clone(), true, variable.span)?;
+        // Initializers at file scope must be constant, but the type checker says so.
+        match &mut variable.init {
+            Some(init) => self.initializer(init),
+            None => Ok(()),
+        }
+    }
+
+    fn local_variable(&mut self, variable: &mut VariableDeclaration) -> ResolveResult<()> {
+        if variable.storage_class == Some(StorageClass::Extern) {
+            return self.declare(&variable.name, variable.name.clone(), true, variable.span);
+        }
+
+        let unique_name = self.unique_name(&variable.name);
+        self.declare(&variable.name, unique_name.clone(), false, variable.span)?;
+        variable.name = unique_name;
+
+        match &mut variable.init {
+            Some(init) => self.initializer(init),
+            None => Ok(()),
+        }
+    }
+
+    /// Declares `name` in the innermost scope. Declaring it again there is only allowed if
+    /// both declarations have linkage, in which case they declare the same thing.
+    fn declare(
+        &mut self,
+        name: &str,
+        unique_name: String,
+        has_linkage: bool,
+        span: Span,
+    ) -> ResolveResult<()> {
+        let scope = self.scopes.last_mut().unwrap();
+
+        if let Some(previous) = scope.get(name) {
+            if !(previous.has_linkage && has_linkage) {
+                return Err(Diagnostic::error(format!("redeclaration of '{name}'"))
+                    .with_code(Code::ConflictingDeclaration)
+                    .with_span(span)
+                    .with_note(
+                        Diagnostic::note("previous declaration is here").with_span(previous.span),
+                    ));
+            }
+        }
+
+        scope.insert(
+            name.to_owned(),
+            Entry {
+                unique_name,
+                has_linkage,
+                span,
+            },
+        );
+        Ok(())
+    }
+
+    fn unique_name(&mut self, name: &str) -> String {
+        let unique_name = format!("{name}.{}", self.counter);
+        self.counter += 1;
+        unique_name
+    }
+
+    fn lookup(&self, name: &str, span: Span) -> ResolveResult<String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map(|entry| entry.unique_name.clone())
+            .ok_or_else(|| {
+                Diagnostic::error(format!("use of undeclared identifier '{name}'"))
+                    .with_code(Code::UndeclaredIdentifier)
+                    .with_span(span)
+            })
+    }
+
+    /// Runs `resolve` in a new innermost scope.
+    fn scoped(
+        &mut self,
+        resolve: impl FnOnce(&mut Self) -> ResolveResult<()>,
+    ) -> ResolveResult<()> {
+        self.scopes.push(HashMap::new());
+        let resolved = resolve(self);
+        self.scopes.pop();
+        resolved
+    }
+
+    fn block(&mut self, block: &mut Block) -> ResolveResult<()> {
+        self.scoped(|resolver| resolver.block_items(&mut block.items))
+    }
+
+    fn block_items(&mut self, items: &mut [BlockItem]) -> ResolveResult<()> {
+        for item in items {
+            match item {
+                BlockItem::Statement(statement) => self.statement(statement)?,
+                BlockItem::Declaration(Declaration::Variable(variable)) => {
+                    self.local_variable(variable)?
+                }
+                BlockItem::Declaration(Declaration::Function(function)) => {
+                    self.function(function)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &mut Statement) -> ResolveResult<()> {
+        match &mut statement.kind {
+            StatementKind::Return(value) => self.optional_expression(value.as_mut()),
+            StatementKind::Expression(expression) => self.expression(expression),
+            StatementKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(condition)?;
+                self.statement(then)?;
+                match otherwise {
+                    Some(otherwise) => self.statement(otherwise),
+                    None => Ok(()),
+                }
+            }
+            StatementKind::Compound(block) => self.block(block),
+            StatementKind::While {
+                condition, body, ..
+            }
+            | StatementKind::DoWhile {
+                body, condition, ..
+            } => {
+                self.expression(condition)?;
+                self.statement(body)
+            }
+            StatementKind::For {
+                init,
+                condition,
+                post,
+                body,
+                ..
+            } => {
+                // Variables declared in the header are scoped to the loop.
+                self.scoped(|resolver| {
+                    match init {
+                        ForInit::Declarations(declarations) => {
+                            for declaration in declarations {
+                                resolver.local_variable(declaration)?;
+                            }
+                        }
+                        ForInit::Expression(init) => resolver.optional_expression(init.as_mut())?,
+                    }
+                    resolver.optional_expression(condition.as_deref_mut())?;
+                    resolver.optional_expression(post.as_deref_mut())?;
+                    resolver.statement(body)
+                })
+            }
+            StatementKind::Break(_) | StatementKind::Continue(_) | StatementKind::Null => Ok(()),
+        }
+    }
+
+    fn initializer(&mut self, initializer: &mut Initializer) -> ResolveResult<()> {
+        match initializer {
+            Initializer::Single(expression) => self.expression(expression),
+            Initializer::Compound(initializers, _) => {
+                for initializer in initializers {
+                    self.initializer(initializer)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn optional_expression(&mut self, expression: Option<&mut Expression>) -> ResolveResult<()> {
+        match expression {
+            Some(expression) => self.expression(expression),
+            None => Ok(()),
+        }
+    }
+
+    fn expression(&mut self, expression: &mut Expression) -> ResolveResult<()> {
+        match &mut expression.kind {
+            ExpressionKind::Constant(_) => Ok(()),
+            ExpressionKind::Var(name) => {
+                *name = self.lookup(name, expression.span)?;
+                Ok(())
+            }
+            ExpressionKind::FunctionCall(name, args) => {
+                *name = self.lookup(name, expression.span)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                Ok(())
+            }
+            ExpressionKind::Cast(_, operand)
+            | ExpressionKind::Unary(_, operand)
+            | ExpressionKind::Postfix(_, operand)
+            | ExpressionKind::Dereference(operand)
+            | ExpressionKind::AddressOf(operand) => self.expression(operand),
+            ExpressionKind::Binary(_, left, right)
+            | ExpressionKind::Assignment(left, right)
+            | ExpressionKind::CompoundAssignment(_, left, right)
+            | ExpressionKind::Subscript(left, right) => {
+                self.expression(left)?;
+                self.expression(right)
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(condition)?;
+                self.expression(then)?;
+                self.expression(otherwise)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::c::{analyze, errors};
+
+    #[test]
+    fn test_resolve_renames_locals() {
+        let (program, _) = analyze(
+            "int x;
+             int f(int x) {
+                 int y = x;
+                 { int x = y; return x; }
+             }",
+        );
+        let Declaration::Function(function) = &program.declarations[1] else {
+            panic!("expected a function.");
+        };
+        assert_eq!(function.params[0].name.as_deref(), Some("x.0"));
+
+        let items = &function.body.as_ref().unwrap().items;
+        let BlockItem::Declaration(Declaration::Variable(y)) = &items[0] else {
+            panic!("expected a declaration.");
+        };
+        assert_eq!(y.name, "y.1");
+        let Some(Initializer::Single(init)) = &y.init else {
+            panic!("expected an initializer.");
+        };
+        assert_eq!(init.kind, ExpressionKind::Var("x.0".to_owned()));
+
+        let BlockItem::Statement(Statement {
+            kind: StatementKind::Compound(inner),
+            ..
+        }) = &items[1]
+        else {
+            panic!("expected a block.");
+        };
+        let BlockItem::Statement(Statement {
+            kind: StatementKind::Return(Some(value)),
+            ..
+        }) = &inner.items[1]
+        else {
+            panic!("expected a return.");
+        };
+        assert_eq!(value.kind, ExpressionKind::Var("x.2".to_owned()));
+    }
+
+    #[test]
+    fn test_resolve_keeps_names_with_linkage() {
+        let (program, _) = analyze(
+            "int f(void) {
+                 extern int x;
+                 int g(void);
+                 return x + g();
+             }
+             int x;",
+        );
+        let Declaration::Function(function) = &program.declarations[0] else {
+            panic!("expected a function.");
+        };
+        let BlockItem::Declaration(Declaration::Variable(x)) =
+            &function.body.as_ref().unwrap().items[0]
+        else {
+            panic!("expected a declaration.");
+        };
+        assert_eq!(x.name, "x");
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let cases = [
+            (
+                "int f(void) { return y; }",
+                Code::UndeclaredIdentifier,
+                "use of undeclared identifier 'y'",
+                "test.c:1:22",
+            ),
+            (
+                "int f(void) { int a; long a; return 0; }",
+                Code::ConflictingDeclaration,
+                "redeclaration of 'a'",
+                "test.c:1:27",
+            ),
+            (
+                "int f(int a, int a);",
+                Code::ConflictingDeclaration,
+                "redeclaration of 'a'",
+                "test.c:1:18",
+            ),
+            (
+                "int f(void) { for (int i = 0; i < 3; i++) ; return i; }",
+                Code::UndeclaredIdentifier,
+                "use of undeclared identifier 'i'",
+                "test.c:1:52",
+            ),
+            (
+                "int f(void) { int g(void) { return 1; } return 0; }",
+                Code::InvalidDeclaration,
+                "function definition is not allowed here",
+                "test.c:1:19",
+            ),
+            (
+                "int f(void) { static int g(void); return 0; }",
+                Code::InvalidDeclaration,
+                "invalid storage class for block-scope function 'g'",
+                "test.c:1:26",
+            ),
+        ];
+        for (code, error_code, message, location) in cases {
+            assert_eq!(
+                errors(code),
+                vec![(Some(error_code), message.to_owned(), location.to_owned())],
+                "{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_reports_each_declaration() {
+        let errors = errors("int f(void) { return a; }\nint g(void) { return b; }");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[1].2, "test.c:2:22");
+    }
+}
@@ -0,0 +1,1456 @@
+use std::{collections::BTreeMap, mem};
+
+use crate::{
+    ast::{
+        BinaryOp, Block, BlockItem, Declaration, Expression, ExpressionKind, ForInit,
+        FunctionDeclaration, Initializer, Program, Statement, StatementKind, StorageClass, Type,
+        UnaryOp, VariableDeclaration,
+    },
+    diagnostics::{Code, Diagnostic, Diagnostics},
+    optimizer::{convert, eval_unary},
+    source_map::Span,
+    tacky::{self, Const, StaticInit},
+};
+
+type CheckResult<T> = Result<T, Diagnostic>;
+
+/// What a name declared in the program refers to, once every declaration of it is checked.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub ty: Type,
+    pub attributes: Attributes,
+    /// The latest declaration, which diagnostics about a conflicting one point at.
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Attributes {
+    Function {
+        defined: bool,
+        global: bool,
+    },
+    /// A variable with static storage duration: declared at file scope, `static` or `extern`.
+    Static {
+        init: InitialValue,
+        global: bool,
+    },
+    /// A parameter or automatic variable.
+    Local,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum InitialValue {
+    /// Declared at file scope without an initializer: zero, unless another declaration of the
+    /// variable has one.
+    Tentative,
+    Initial(Vec<StaticInit>),
+    /// Only declared with `extern`, so defined somewhere else.
+    NoInitializer,
+}
+
+/// Every name in the program after identifier resolution, by its unique name.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    // Ordered, so the static variables generated from it come out the same on every run.
+    symbols: BTreeMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.symbols.iter()
+    }
+
+    fn insert(&mut self, name: &str, symbol: Symbol) {
+        self.symbols.insert(name.to_owned(), symbol);
+    }
+}
+
+struct TypeChecker<'a> {
+    symbols: SymbolTable,
+    diagnostics: &'a mut Diagnostics,
+    /// The return type of the function being checked.
+    return_type: Type,
+}
+
+/// Gives every expression in `program` its type, and wraps each implicit conversion in an
+/// explicit `Cast`, so TACKY generation never has to work out what C converts where.
+///
+/// Also checks that declarations of the same name agree, and works out the linkage and initial
+/// value of every static variable. Errors are reported to `diagnostics`, each ending the
+/// statement or top-level declaration it's in.
+pub fn check(program: &mut Program, diagnostics: &mut Diagnostics) -> SymbolTable {
+    let mut checker = TypeChecker {
+        symbols: SymbolTable::default(),
+        diagnostics,
+        return_type: Type::Int,
+    };
+
+    for declaration in &mut program.declarations {
+        let checked = match declaration {
+            Declaration::Function(function) => checker.function(function),
+            Declaration::Variable(variable) => checker.file_scope_variable(variable),
+        };
+        if let Err(error) = checked {
+            checker.diagnostics.emit(error);
+            checker.declare_after_error(declaration);
+        }
+        if checker.diagnostics.limit_reached() {
+            break;
+        }
+    }
+
+    checker.symbols
+}
+
+impl TypeChecker<'_> {
+    //-------------------------
+    // Declarations
+
+    fn function(&mut self, function: &mut FunctionDeclaration) -> CheckResult<()> {
+        self.function_declaration(function)?;
+        let Some(body) = &mut function.body else {
+            return Ok(());
+        };
+        let Type::Function { params, ret } = &function.ty else {
+            unreachable!("functions have function types.");
+        };
+
+        for (param, ty) in function.params.iter().zip(params) {
+            let Some(name) = &param.name else {
+                return Err(Diagnostic::error("parameter name omitted")
+                    .with_code(Code::InvalidDeclaration)
+                    .with_span(param.span));
+            };
+            self.symbols.insert(
+                name,
+                Symbol {
+                    ty: ty.clone(),
+                    attributes: Attributes::Local,
+                    span: param.span,
+                },
+            );
+        }
+
+        self.return_type = (**ret).clone();
+        self.block(body);
+        Ok(())
+    }
+
+    fn function_declaration(&mut self, function: &mut FunctionDeclaration) -> CheckResult<()> {
+        let Type::Function { params, .. } = &mut function.ty else {
+            unreachable!("functions have function types.");
+        };
+        // Parameters declared as arrays are pointers.
+        for (ty, param) in params.iter_mut().zip(&function.params) {
+            match ty {
+                Type::Array(element, _) => *ty = Type::Pointer(element.clone()),
+                Type::Void => {
+                    return Err(Diagnostic::error("parameter has incomplete type 'void'")
+                        .with_code(Code::InvalidDeclaration)
+                        .with_span(param.span));
+                }
+                _ => (),
+            }
+        }
+
+        let mut defined = function.body.is_some();
+        let mut global = function.storage_class != Some(StorageClass::Static);
+
+        if let Some(previous) = self.symbols.get(&function.name) {
+            let Attributes::Function {
+                defined: previously_defined,
+                global: previously_global,
+            } = previous.attributes
+            else {
+                return Err(redeclared_as_different_kind(
+                    &function.name,
+                    function.span,
+                    previous,
+                ));
+            };
+            if previous.ty != function.ty {
+                return Err(conflicting_types(&function.name, function.span, previous));
+            }
+            if previously_defined && defined {
+                return Err(redefinition(&function.name, function.span, previous));
+            }
+            if previously_global && !global {
+                return Err(conflicting_declaration(
+                    format!(
+                        "static declaration of '{}' follows non-static declaration",
+                        function.name
+                    ),
+                    function.span,
+                    previous,
+                ));
+            }
+            global = previously_global;
+            defined |= previously_defined;
+        }
+
+        self.symbols.insert(
+            &function.name,
+            Symbol {
+                ty: function.ty.clone(),
+                attributes: Attributes::Function { defined, global },
+                span: function.span,
+            },
+        );
+        Ok(())
+    }
+
+    fn file_scope_variable(&mut self, variable: &mut VariableDeclaration) -> CheckResult<()> {
+        check_object_type(&variable.ty, variable.span)?;
+
+        let mut init = match (&mut variable.init, variable.storage_class) {
+            (Some(init), _) => InitialValue::Initial(self.static_initializer(init, &variable.ty)?),
+            (None, Some(StorageClass::Extern)) => InitialValue::NoInitializer,
+            (None, _) => InitialValue::Tentative,
+        };
+        let mut global = variable.storage_class != Some(StorageClass::Static);
+
+        if let Some(previous) = self.symbols.get(&variable.name) {
+            let Attributes::Static {
+                init: previous_init,
+                global: previously_global,
+            } = &previous.attributes
+            else {
+                return Err(redeclared_as_different_kind(
+                    &variable.name,
+                    variable.span,
+                    previous,
+                ));
+            };
+            if previous.ty != variable.ty {
+                return Err(conflicting_types(&variable.name, variable.span, previous));
+            }
+
+            if variable.storage_class == Some(StorageClass::Extern) {
+                global = *previously_global;
+            } else if *previously_global != global {
+                let message = match global {
+                    true => "non-static declaration of '{}' follows static declaration",
+                    false => "static declaration of '{}' follows non-static declaration",
+                };
+                return Err(conflicting_declaration(
+                    message.replace("{}", &variable.name),
+                    variable.span,
+                    previous,
+                ));
+            }
+
+            match (previous_init, &init) {
+                (InitialValue::Initial(_), InitialValue::Initial(_)) => {
+                    return Err(redefinition(&variable.name, variable.span, previous));
+                }
+                (InitialValue::Initial(_), _) => init = previous_init.clone(),
+                (InitialValue::Tentative, InitialValue::NoInitializer) => {
+                    init = InitialValue::Tentative
+                }
+                _ => (),
+            }
+        }
+
+        self.symbols.insert(
+            &variable.name,
+            Symbol {
+                ty: variable.ty.clone(),
+                attributes: Attributes::Static { init, global },
+                span: variable.span,
+            },
+        );
+        Ok(())
+    }
+
+    fn local_variable(&mut self, variable: &mut VariableDeclaration) -> CheckResult<()> {
+        check_object_type(&variable.ty, variable.span)?;
+
+        match variable.storage_class {
+            Some(StorageClass::Extern) => {
+                if let Some(init) = &variable.init {
+                    return Err(Diagnostic::error(format!(
+                        "'extern' variable '{}' cannot have an initializer",
+                        variable.name
+                    ))
+                    .with_code(Code::InvalidInitializer)
+                    .with_span(init.span()));
+                }
+                match self.symbols.get(&variable.name) {
+                    Some(previous) if !matches!(previous.attributes, Attributes::Static { .. }) => {
+                        Err(redeclared_as_different_kind(
+                            &variable.name,
+                            variable.span,
+                            previous,
+                        ))
+                    }
+                    Some(previous) if previous.ty != variable.ty => {
+                        Err(conflicting_types(&variable.name, variable.span, previous))
+                    }
+                    Some(_) => Ok(()),
+                    None => {
+                        self.symbols.insert(
+                            &variable.name,
+                            Symbol {
+                                ty: variable.ty.clone(),
+                                attributes: Attributes::Static {
+                                    init: InitialValue::NoInitializer,
+                                    global: true,
+                                },
+                                span: variable.span,
+                            },
+                        );
+                        Ok(())
+                    }
+                }
+            }
+            Some(StorageClass::Static) => {
+                let init = match &mut variable.init {
+                    Some(init) => self.static_initializer(init, &variable.ty)?,
+                    None => vec![StaticInit::Zero(variable.ty.size())],
+                };
+                self.symbols.insert(
+                    &variable.name,
+                    Symbol {
+                        ty: variable.ty.clone(),
+                        attributes: Attributes::Static {
+                            init: InitialValue::Initial(init),
+                            global: false,
+                        },
+                        span: variable.span,
+                    },
+                );
+                Ok(())
+            }
+            None => {
+                // In scope in its own initializer.
+                self.symbols.insert(
+                    &variable.name,
+                    Symbol {
+                        ty: variable.ty.clone(),
+                        attributes: Attributes::Local,
+                        span: variable.span,
+                    },
+                );
+                match &mut variable.init {
+                    Some(init) => self.initializer(init, &variable.ty),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Declares the name `declaration` declares, if checking it failed before it could, so uses
+    /// of the name don't need to be checked against nothing.
+    fn declare_after_error(&mut self, declaration: &Declaration) {
+        let (name, ty, span) = match declaration {
+            Declaration::Function(function) => (&function.name, &function.ty, function.span),
+            Declaration::Variable(variable) => (&variable.name, &variable.ty, variable.span),
+        };
+        if self.symbols.get(name).is_some() {
+            return;
+        }
+        let attributes = match declaration {
+            Declaration::Function(_) => Attributes::Function {
+                defined: false,
+                global: true,
+            },
+            Declaration::Variable(_) => Attributes::Static {
+                init: InitialValue::NoInitializer,
+                global: true,
+            },
+        };
+        self.symbols.insert(
+            name,
+            Symbol {
+                ty: ty.clone(),
+                attributes,
+                span,
+            },
+        );
+    }
+
+    /// Checks the initializer of an automatic variable of type `ty`.
+    fn initializer(&mut self, initializer: &mut Initializer, ty: &Type) -> CheckResult<()> {
+        match (initializer, ty) {
+            (Initializer::Compound(initializers, _), Type::Array(element, len)) => {
+                check_initializer_count(initializers, *len)?;
+                for initializer in initializers {
+                    self.initializer(initializer, element)?;
+                }
+                Ok(())
+            }
+            (Initializer::Single(expression), ty) if !matches!(ty, Type::Array(..)) => {
+                self.expression_and_convert(expression)?;
+                convert_by_assignment(expression, ty)
+            }
+            (initializer, ty) => Err(invalid_initializer(initializer, ty)),
+        }
+    }
+
+    /// Checks the initializer of a static variable of type `ty`, and returns its value.
+    fn static_initializer(
+        &mut self,
+        initializer: &mut Initializer,
+        ty: &Type,
+    ) -> CheckResult<Vec<StaticInit>> {
+        match (initializer, ty) {
+            (Initializer::Compound(initializers, _), Type::Array(element, len)) => {
+                check_initializer_count(initializers, *len)?;
+                let mut values = Vec::new();
+                for initializer in initializers.iter_mut() {
+                    values.extend(self.static_initializer(initializer, element)?);
+                }
+                let missing = (len - initializers.len()) * element.size();
+                if missing > 0 {
+                    values.push(StaticInit::Zero(missing));
+                }
+                Ok(values)
+            }
+            (Initializer::Single(expression), ty) if !matches!(ty, Type::Array(..)) => {
+                self.expression_and_convert(expression)?;
+                convert_by_assignment(expression, ty)?;
+                let value = static_value(expression).ok_or_else(|| {
+                    Diagnostic::error("initializer element is not a constant")
+                        .with_code(Code::InvalidInitializer)
+                        .with_span(expression.span)
+                })?;
+                Ok(vec![match value {
+                    Const::Double(_) => StaticInit::Const(value),
+                    _ if convert(value, &tacky::Type::Long) == Ok(Const::Long(0)) => {
+                        StaticInit::Zero(ty.size())
+                    }
+                    _ => StaticInit::Const(value),
+                }])
+            }
+            (initializer, ty) => Err(invalid_initializer(initializer, ty)),
+        }
+    }
+
+    //-------------------------
+    // Statements
+
+    /// Checks every item in `block`, reporting errors as it goes.
+    fn block(&mut self, block: &mut Block) {
+        for item in &mut block.items {
+            let checked = match item {
+                BlockItem::Statement(statement) => self.statement(statement),
+                BlockItem::Declaration(Declaration::Variable(variable)) => {
+                    self.local_variable(variable)
+                }
+                BlockItem::Declaration(Declaration::Function(function)) => {
+                    self.function_declaration(function)
+                }
+            };
+            if let Err(error) = checked {
+                self.diagnostics.emit(error);
+                if let BlockItem::Declaration(declaration) = item {
+                    self.declare_after_error(declaration);
+                }
+            }
+            if self.diagnostics.limit_reached() {
+                return;
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement) -> CheckResult<()> {
+        match &mut statement.kind {
+            StatementKind::Return(value) => match (value, self.return_type == Type::Void) {
+                (Some(value), false) => {
+                    self.expression_and_convert(value)?;
+                    convert_by_assignment(value, &self.return_type)
+                }
+                (None, true) => Ok(()),
+                (Some(value), true) => Err(Diagnostic::error(
+                    "function returning 'void' should not return a value",
+                )
+                .with_code(Code::TypeMismatch)
+                .with_span(value.span)),
+                (None, false) => Err(Diagnostic::error(format!(
+                    "function returning '{}' should return a value",
+                    self.return_type
+                ))
+                .with_code(Code::TypeMismatch)
+                .with_span(statement.span)),
+            },
+            StatementKind::Expression(expression) => self.expression_and_convert(expression),
+            StatementKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.condition(condition)?;
+                self.statement(then)?;
+                match otherwise {
+                    Some(otherwise) => self.statement(otherwise),
+                    None => Ok(()),
+                }
+            }
+            StatementKind::Compound(block) => {
+                self.block(block);
+                Ok(())
+            }
+            StatementKind::While {
+                condition, body, ..
+            }
+            | StatementKind::DoWhile {
+                body, condition, ..
+            } => {
+                self.condition(condition)?;
+                self.statement(body)
+            }
+            StatementKind::For {
+                init,
+                condition,
+                post,
+                body,
+                ..
+            } => {
+                match init {
+                    ForInit::Declarations(declarations) => {
+                        for declaration in declarations {
+                            if declaration.storage_class.is_some() {
+                                return Err(Diagnostic::error(format!(
+                                    "declaration of non-automatic variable '{}' in 'for' loop \
+                                     initializer",
+                                    source_name(&declaration.name)
+                                ))
+                                .with_code(Code::InvalidDeclaration)
+                                .with_span(declaration.span));
+                            }
+                            self.local_variable(declaration)?;
+                        }
+                    }
+                    ForInit::Expression(Some(init)) => self.expression_and_convert(init)?,
+                    ForInit::Expression(None) => (),
+                }
+                if let Some(condition) = condition {
+                    self.condition(condition)?;
+                }
+                if let Some(post) = post {
+                    self.expression_and_convert(post)?;
+                }
+                self.statement(body)
+            }
+            StatementKind::Break(_) | StatementKind::Continue(_) | StatementKind::Null => Ok(()),
+        }
+    }
+
+    /// Checks an expression tested for truth, which must be a scalar.
+    fn condition(&mut self, condition: &mut Expression) -> CheckResult<()> {
+        self.expression_and_convert(condition)?;
+        require_scalar(condition)
+    }
+
+    //-------------------------
+    // Expressions
+
+    /// Checks `expression`, then converts it to a pointer to its first element if it's an
+    /// array, as C does wherever an array's value is used.
+    fn expression_and_convert(&mut self, expression: &mut Expression) -> CheckResult<()> {
+        self.expression(expression)?;
+        if let Type::Array(element, _) = expression.ty() {
+            let pointer = Type::Pointer(element.clone());
+            wrap(expression, pointer, ExpressionKind::AddressOf);
+        }
+        Ok(())
+    }
+
+    /// Checks an expression that must designate an object which can be assigned to.
+    fn lvalue(&mut self, expression: &mut Expression) -> CheckResult<()> {
+        self.expression(expression)?;
+        if !is_lvalue(expression) {
+            return Err(Diagnostic::error("expression is not assignable")
+                .with_code(Code::NotAnLvalue)
+                .with_span(expression.span));
+        }
+        if let ty @ Type::Array(..) = expression.ty() {
+            return Err(
+                Diagnostic::error(format!("array type '{ty}' is not assignable"))
+                    .with_code(Code::NotAnLvalue)
+                    .with_span(expression.span),
+            );
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &mut Expression) -> CheckResult<()> {
+        let span = expression.span;
+
+        let ty = match &mut expression.kind {
+            ExpressionKind::Constant(value) => const_type(*value),
+            ExpressionKind::Var(name) => {
+                let symbol = self.symbol(name);
+                if let Type::Function { .. } = symbol.ty {
+                    return Err(type_mismatch(
+                        format!("function '{name}' used as a variable"),
+                        span,
+                    ));
+                }
+                symbol.ty.clone()
+            }
+            ExpressionKind::Cast(ty, operand) => {
+                self.expression_and_convert(operand)?;
+                let from = operand.ty();
+                let valid = match (&*ty, from) {
+                    (Type::Void, _) => true,
+                    (_, Type::Void) | (Type::Array(..) | Type::Function { .. }, _) => false,
+                    (Type::Double, Type::Pointer(_)) | (Type::Pointer(_), Type::Double) => false,
+                    _ => true,
+                };
+                if !valid {
+                    return Err(type_mismatch(
+                        format!("cannot cast '{from}' to '{ty}'"),
+                        span,
+                    ));
+                }
+                ty.clone()
+            }
+            ExpressionKind::Unary(op, operand) => {
+                self.expression_and_convert(operand)?;
+                match op {
+                    UnaryOp::Not => {
+                        require_scalar(operand)?;
+                        Type::Int
+                    }
+                    UnaryOp::Complement | UnaryOp::Negate => {
+                        let valid = match op {
+                            UnaryOp::Complement => operand.ty().is_integer(),
+                            _ => operand.ty().is_arithmetic(),
+                        };
+                        if !valid {
+                            return Err(type_mismatch(
+                                format!("invalid argument type '{}' to unary '{op}'", operand.ty()),
+                                span,
+                            ));
+                        }
+                        let promoted = promote(operand.ty());
+                        cast(operand, &promoted);
+                        promoted
+                    }
+                }
+            }
+            ExpressionKind::Binary(op, left, right) => self.binary(*op, left, right, span)?,
+            ExpressionKind::Assignment(left, right) => {
+                self.lvalue(left)?;
+                self.expression_and_convert(right)?;
+                convert_by_assignment(right, left.ty())?;
+                left.ty().clone()
+            }
+            ExpressionKind::CompoundAssignment(op, left, right) => {
+                self.lvalue(left)?;
+                self.expression_and_convert(right)?;
+                self.compound_assignment(*op, left, right, span)?;
+                left.ty().clone()
+            }
+            ExpressionKind::Postfix(op, operand) => {
+                self.lvalue(operand)?;
+                let ty = operand.ty();
+                if !ty.is_scalar() || is_void_pointer(ty) {
+                    return Err(type_mismatch(
+                        format!("cannot {} a value of type '{ty}'", postfix_verb(*op)),
+                        span,
+                    ));
+                }
+                ty.clone()
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.condition(condition)?;
+                self.expression_and_convert(then)?;
+                self.expression_and_convert(otherwise)?;
+
+                let ty = match (then.ty(), otherwise.ty()) {
+                    (Type::Void, Type::Void) => Some(Type::Void),
+                    (a, b) if a.is_arithmetic() && b.is_arithmetic() => Some(common_type(a, b)),
+                    (a, b) if a.is_pointer() || b.is_pointer() => {
+                        composite_pointer_type(then, otherwise)
+                    }
+                    _ => None,
+                };
+                let Some(ty) = ty else {
+                    return Err(type_mismatch(
+                        format!(
+                            "mismatched types in conditional expression ('{}' and '{}')",
+                            then.ty(),
+                            otherwise.ty()
+                        ),
+                        span,
+                    ));
+                };
+                cast(then, &ty);
+                cast(otherwise, &ty);
+                ty
+            }
+            ExpressionKind::FunctionCall(name, args) => {
+                let symbol = self.symbol(name);
+                let Type::Function { params, ret } = &symbol.ty else {
+                    return Err(type_mismatch(
+                        format!("called object '{}' is not a function", source_name(name)),
+                        span,
+                    ));
+                };
+                if params.len() != args.len() {
+                    let plural = match params.len() {
+                        1 => "",
+                        _ => "s",
+                    };
+                    return Err(Diagnostic::error(format!(
+                        "function '{name}' takes {} argument{plural}, but {} were given",
+                        params.len(),
+                        args.len()
+                    ))
+                    .with_code(Code::ArgumentCount)
+                    .with_span(span)
+                    .with_note(Diagnostic::note("declared here").with_span(symbol.span)));
+                }
+
+                let (params, ret) = (params.clone(), (**ret).clone());
+                for (arg, param) in args.iter_mut().zip(&params) {
+                    self.expression_and_convert(arg)?;
+                    convert_by_assignment(arg, param)?;
+                }
+                ret
+            }
+            ExpressionKind::Dereference(operand) => {
+                self.expression_and_convert(operand)?;
+                match operand.ty() {
+                    Type::Pointer(referenced) if **referenced != Type::Void => {
+                        (**referenced).clone()
+                    }
+                    ty => {
+                        return Err(type_mismatch(
+                            format!("cannot dereference a value of type '{ty}'"),
+                            span,
+                        ))
+                    }
+                }
+            }
+            ExpressionKind::AddressOf(operand) => {
+                self.expression(operand)?;
+                if !is_lvalue(operand) {
+                    return Err(Diagnostic::error("cannot take the address of an rvalue")
+                        .with_code(Code::NotAnLvalue)
+                        .with_span(operand.span));
+                }
+                Type::Pointer(Box::new(operand.ty().clone()))
+            }
+            ExpressionKind::Subscript(left, right) => {
+                self.expression_and_convert(left)?;
+                self.expression_and_convert(right)?;
+
+                let (pointer, index) = match (left.ty(), right.ty()) {
+                    (Type::Pointer(_), ty) if ty.is_integer() => (left, right),
+                    (ty, Type::Pointer(_)) if ty.is_integer() => (right, left),
+                    _ => {
+                        return Err(type_mismatch(
+                            "subscripted value is not an array or pointer".to_owned(),
+                            span,
+                        ))
+                    }
+                };
+                require_complete_pointee(pointer, span)?;
+                cast(index, &Type::Long);
+                let Type::Pointer(referenced) = pointer.ty() else {
+                    unreachable!();
+                };
+                (**referenced).clone()
+            }
+        };
+
+        expression.ty = Some(ty);
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        left: &mut Expression,
+        right: &mut Expression,
+        span: Span,
+    ) -> CheckResult<Type> {
+        self.expression_and_convert(left)?;
+        self.expression_and_convert(right)?;
+        let (l, r) = (left.ty().clone(), right.ty().clone());
+        let invalid = || {
+            type_mismatch(
+                format!("invalid operands to binary '{op}' ('{l}' and '{r}')"),
+                span,
+            )
+        };
+
+        match op {
+            BinaryOp::And | BinaryOp::Or => {
+                require_scalar(left)?;
+                require_scalar(right)?;
+                Ok(Type::Int)
+            }
+            // The operands are promoted separately, and the result has the left one's type.
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+                if !l.is_integer() || !r.is_integer() {
+                    return Err(invalid());
+                }
+                cast(left, &promote(&l));
+                cast(right, &promote(&r));
+                Ok(promote(&l))
+            }
+            BinaryOp::Add | BinaryOp::Subtract if l.is_pointer() && r.is_integer() => {
+                require_complete_pointee(left, span)?;
+                cast(right, &Type::Long);
+                Ok(l)
+            }
+            BinaryOp::Add if l.is_integer() && r.is_pointer() => {
+                require_complete_pointee(right, span)?;
+                cast(left, &Type::Long);
+                Ok(r)
+            }
+            BinaryOp::Subtract if l.is_pointer() && l == r => {
+                require_complete_pointee(left, span)?;
+                Ok(Type::Long)
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual if l.is_pointer() || r.is_pointer() => {
+                let ty = composite_pointer_type(left, right).ok_or_else(invalid)?;
+                cast(left, &ty);
+                cast(right, &ty);
+                Ok(Type::Int)
+            }
+            BinaryOp::LessThan
+            | BinaryOp::LessOrEqual
+            | BinaryOp::GreaterThan
+            | BinaryOp::GreaterOrEqual
+                if l.is_pointer() && l == r =>
+            {
+                Ok(Type::Int)
+            }
+            _ => {
+                let valid = match op {
+                    BinaryOp::Remainder | BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => {
+                        l.is_integer() && r.is_integer()
+                    }
+                    _ => l.is_arithmetic() && r.is_arithmetic(),
+                };
+                if !valid {
+                    return Err(invalid());
+                }
+                let common = common_type(&l, &r);
+                cast(left, &common);
+                cast(right, &common);
+                match op {
+                    BinaryOp::Add
+                    | BinaryOp::Subtract
+                    | BinaryOp::Multiply
+                    | BinaryOp::Divide
+                    | BinaryOp::Remainder
+                    | BinaryOp::BitAnd
+                    | BinaryOp::BitOr
+                    | BinaryOp::BitXor => Ok(common),
+                    _ => Ok(Type::Int),
+                }
+            }
+        }
+    }
+
+    /// Checks `left op= right`, converting `right` to the type the operation is done in. That
+    /// is `left`'s type promoted for shifts, `long` for pointer arithmetic, and the common type
+    /// of both otherwise.
+    fn compound_assignment(
+        &mut self,
+        op: BinaryOp,
+        left: &mut Expression,
+        right: &mut Expression,
+        span: Span,
+    ) -> CheckResult<()> {
+        let (l, r) = (left.ty().clone(), right.ty().clone());
+        let invalid = || {
+            type_mismatch(
+                format!("invalid operands to '{op}=' ('{l}' and '{r}')"),
+                span,
+            )
+        };
+
+        match op {
+            BinaryOp::Add | BinaryOp::Subtract if l.is_pointer() => {
+                if !r.is_integer() {
+                    return Err(invalid());
+                }
+                require_complete_pointee(left, span)?;
+                cast(right, &Type::Long);
+            }
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+                if !l.is_integer() || !r.is_integer() {
+                    return Err(invalid());
+                }
+                cast(right, &promote(&r));
+            }
+            BinaryOp::Remainder | BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => {
+                if !l.is_integer() || !r.is_integer() {
+                    return Err(invalid());
+                }
+                cast(right, &common_type(&l, &r));
+            }
+            _ => {
+                if !l.is_arithmetic() || !r.is_arithmetic() {
+                    return Err(invalid());
+                }
+                cast(right, &common_type(&l, &r));
+            }
+        }
+        Ok(())
+    }
+
+    /// The symbol `name` was resolved to.
+    fn symbol(&self, name: &str) -> &Symbol {
+        self.symbols
+            .get(name)
+            .expect("identifier resolution should have found every name.")
+    }
+}
+
+fn check_object_type(ty: &Type, span: Span) -> CheckResult<()> {
+    match ty {
+        Type::Void => Err(Diagnostic::error("variable has incomplete type 'void'")
+            .with_code(Code::InvalidDeclaration)
+            .with_span(span)),
+        _ => Ok(()),
+    }
+}
+
+fn check_initializer_count(initializers: &[Initializer], len: usize) -> CheckResult<()> {
+    match initializers.get(len) {
+        Some(excess) => Err(Diagnostic::error("excess elements in array initializer")
+            .with_code(Code::InvalidInitializer)
+            .with_span(excess.span())),
+        None => Ok(()),
+    }
+}
+
+fn invalid_initializer(initializer: &Initializer, ty: &Type) -> Diagnostic {
+    let message = match initializer {
+        Initializer::Single(_) => format!("array of type '{ty}' must be initialized with braces"),
+        Initializer::Compound(..) => format!("cannot initialize '{ty}' with braces"),
+    };
+    Diagnostic::error(message)
+        .with_code(Code::InvalidInitializer)
+        .with_span(initializer.span())
+}
+
+/// The value of a static initializer, which must be a constant, possibly converted.
+fn static_value(expression: &Expression) -> Option<Const> {
+    match &expression.kind {
+        ExpressionKind::Constant(value) => Some(*value),
+        ExpressionKind::Cast(ty, operand) if ty.is_scalar() => {
+            convert(static_value(operand)?, &ty.to_tacky()).ok()
+        }
+        ExpressionKind::Unary(op, operand) => {
+            let op = match op {
+                UnaryOp::Complement => tacky::UnaryOp::Complement,
+                UnaryOp::Negate => tacky::UnaryOp::Negate,
+                UnaryOp::Not => tacky::UnaryOp::Not,
+            };
+            eval_unary(op, static_value(operand)?).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Replaces `expression` with `kind` applied to it, which has type `ty`.
+fn wrap(
+    expression: &mut Expression,
+    ty: Type,
+    kind: impl FnOnce(Box<Expression>) -> ExpressionKind,
+) {
+    let span = expression.span;
+    let placeholder = Expression::new(ExpressionKind::Constant(Const::Int(0)), span);
+    let inner = mem::replace(expression, placeholder);
+    *expression = Expression {
+        kind: kind(Box::new(inner)),
+        ty: Some(ty),
+        span,
+    };
+}
+
+/// Converts `expression` to `ty`, if it isn't of that type already.
+fn cast(expression: &mut Expression, ty: &Type) {
+    if expression.ty() != ty {
+        wrap(expression, ty.clone(), |inner| {
+            ExpressionKind::Cast(ty.clone(), inner)
+        });
+    }
+}
+
+/// Converts `expression` to `ty` as assigning it to an object of that type would, if C allows
+/// that implicitly. Besides arithmetic conversions, that's a null pointer constant to any
+/// pointer, and a pointer to `void` to any other pointer or back.
+fn convert_by_assignment(expression: &mut Expression, ty: &Type) -> CheckResult<()> {
+    let from = expression.ty();
+    let allowed = from == ty
+        || (from.is_arithmetic() && ty.is_arithmetic())
+        || (ty.is_pointer() && is_null_pointer_constant(expression))
+        || (is_void_pointer(from) && ty.is_pointer())
+        || (from.is_pointer() && is_void_pointer(ty));
+    if !allowed {
+        return Err(type_mismatch(
+            format!("mismatched types: expected '{ty}', found '{from}'"),
+            expression.span,
+        ));
+    }
+    cast(expression, ty);
+    Ok(())
+}
+
+fn require_scalar(expression: &Expression) -> CheckResult<()> {
+    match expression.ty().is_scalar() {
+        true => Ok(()),
+        false => Err(type_mismatch(
+            format!("'{}' used where a scalar is required", expression.ty()),
+            expression.span,
+        )),
+    }
+}
+
+/// Checks that `pointer` points to something with a size, which pointer arithmetic needs.
+fn require_complete_pointee(pointer: &Expression, span: Span) -> CheckResult<()> {
+    match is_void_pointer(pointer.ty()) {
+        true => Err(type_mismatch(
+            "arithmetic on a pointer to 'void'".to_owned(),
+            span,
+        )),
+        false => Ok(()),
+    }
+}
+
+fn is_void_pointer(ty: &Type) -> bool {
+    matches!(ty, Type::Pointer(referenced) if **referenced == Type::Void)
+}
+
+fn is_lvalue(expression: &Expression) -> bool {
+    matches!(
+        expression.kind,
+        ExpressionKind::Var(_) | ExpressionKind::Dereference(_) | ExpressionKind::Subscript(..)
+    )
+}
+
+/// Whether `expression` is an integer constant zero, which converts to any pointer type.
+fn is_null_pointer_constant(expression: &Expression) -> bool {
+    match expression.kind {
+        ExpressionKind::Constant(Const::Double(_)) => false,
+        ExpressionKind::Constant(value) => convert(value, &tacky::Type::Long) == Ok(Const::Long(0)),
+        _ => false,
+    }
+}
+
+/// The type two pointer operands are converted to: their common type, the pointer's if the
+/// other is a null pointer constant, or a pointer to `void` if either is one and the other is
+/// a pointer too. `None` if there is none.
+fn composite_pointer_type(a: &Expression, b: &Expression) -> Option<Type> {
+    let (ty_a, ty_b) = (a.ty(), b.ty());
+    if ty_a == ty_b || is_null_pointer_constant(b) || (is_void_pointer(ty_a) && ty_b.is_pointer()) {
+        Some(ty_a.clone())
+    } else if is_null_pointer_constant(a) || (ty_a.is_pointer() && is_void_pointer(ty_b)) {
+        Some(ty_b.clone())
+    } else {
+        None
+    }
+}
+
+/// `ty` after the integer promotions, which widen character types to `int`.
+fn promote(ty: &Type) -> Type {
+    match ty.is_character() {
+        true => Type::Int,
+        false => ty.clone(),
+    }
+}
+
+/// The type the usual arithmetic conversions convert operands of types `a` and `b` to.
+fn common_type(a: &Type, b: &Type) -> Type {
+    let (a, b) = (promote(a), promote(b));
+    if a == b {
+        a
+    } else if a == Type::Double || b == Type::Double {
+        Type::Double
+    } else if a.size() == b.size() {
+        match a.is_signed() {
+            true => b,
+            false => a,
+        }
+    } else if a.size() > b.size() {
+        a
+    } else {
+        b
+    }
+}
+
+fn const_type(value: Const) -> Type {
+    match value {
+        Const::Char(_) => Type::Char,
+        Const::UChar(_) => Type::UChar,
+        Const::Int(_) => Type::Int,
+        Const::UInt(_) => Type::UInt,
+        Const::Long(_) => Type::Long,
+        Const::ULong(_) => Type::ULong,
+        Const::Double(_) => Type::Double,
+    }
+}
+
+fn postfix_verb(op: crate::ast::PostfixOp) -> &'static str {
+    match op {
+        crate::ast::PostfixOp::Increment => "increment",
+        crate::ast::PostfixOp::Decrement => "decrement",
+    }
+}
+
+/// The name `name` had in the source, before identifier resolution made it unique.
+fn source_name(name: &str) -> &str {
+    name.split('.').next().unwrap()
+}
+
+fn type_mismatch(message: String, span: Span) -> Diagnostic {
+    Diagnostic::error(message)
+        .with_code(Code::TypeMismatch)
+        .with_span(span)
+}
+
+fn conflicting_declaration(message: String, span: Span, previous: &Symbol) -> Diagnostic {
+    Diagnostic::error(message)
+        .with_code(Code::ConflictingDeclaration)
+        .with_span(span)
+        .with_note(Diagnostic::note("previous declaration is here").with_span(previous.span))
+}
+
+fn conflicting_types(name: &str, span: Span, previous: &Symbol) -> Diagnostic {
+    conflicting_declaration(format!("conflicting types for '{name}'"), span, previous)
+}
+
+fn redefinition(name: &str, span: Span, previous: &Symbol) -> Diagnostic {
+    conflicting_declaration(format!("redefinition of '{name}'"), span, previous)
+}
+
+fn redeclared_as_different_kind(name: &str, span: Span, previous: &Symbol) -> Diagnostic {
+    conflicting_declaration(
+        format!("'{name}' redeclared as different kind of symbol"),
+        span,
+        previous,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::c::{self, analyze, errors};
+
+    /// The value returned by the last statement of the last function in `code`.
+    fn returned(code: &str) -> Expression {
+        let (program, _) = analyze(code);
+        let Some(Declaration::Function(function)) = program.declarations.last() else {
+            panic!("expected a function.");
+        };
+        match function.body.as_ref().unwrap().items.last() {
+            Some(BlockItem::Statement(Statement {
+                kind: StatementKind::Return(Some(value)),
+                ..
+            })) => value.clone(),
+            _ => panic!("expected a return."),
+        }
+    }
+
+    #[test]
+    fn test_check_inserts_conversions() {
+        let value = returned("long f(int a) { return a; }");
+        assert_eq!(value.ty(), &Type::Long);
+        let ExpressionKind::Cast(Type::Long, operand) = &value.kind else {
+            panic!("expected a cast, found {value:?}.");
+        };
+        assert_eq!(operand.ty(), &Type::Int);
+
+        // `c + 1.0` converts `c` to double directly, then the result back to the return type.
+        let value = returned("int f(char c) { return c + 1.0; }");
+        let ExpressionKind::Cast(Type::Int, sum) = &value.kind else {
+            panic!("expected a cast, found {value:?}.");
+        };
+        let ExpressionKind::Binary(BinaryOp::Add, left, _) = &sum.kind else {
+            panic!("expected an addition.");
+        };
+        assert_eq!(left.ty(), &Type::Double);
+        assert!(matches!(left.kind, ExpressionKind::Cast(Type::Double, _)));
+    }
+
+    #[test]
+    fn test_check_pointers() {
+        let int_pointer = Type::Pointer(Box::new(Type::Int));
+
+        // Arrays decay, and the index is widened to long.
+        let value = returned("int *f(void) { int a[3]; return a + 1; }");
+        assert_eq!(value.ty(), &int_pointer);
+        let ExpressionKind::Binary(BinaryOp::Add, array, index) = &value.kind else {
+            panic!("expected an addition.");
+        };
+        assert!(matches!(array.kind, ExpressionKind::AddressOf(_)));
+        assert_eq!(index.ty(), &Type::Long);
+
+        assert_eq!(
+            returned("long f(int *a, int *b) { return a - b; }").ty(),
+            &Type::Long
+        );
+        assert_eq!(
+            returned("int f(int *a) { return a == 0; }").ty(),
+            &Type::Int
+        );
+        assert_eq!(
+            returned("int f(int (*a)[2]) { return a[1][0]; }").ty(),
+            &Type::Int
+        );
+        assert_eq!(
+            returned("int *f(int *a) { return 1 ? a : 0; }").ty(),
+            &int_pointer
+        );
+
+        // A pointer to `void` converts to and from any other pointer.
+        let void_pointer = Type::Pointer(Box::new(Type::Void));
+        let value = returned("int *f(void *p) { return p; }");
+        assert_eq!(value.ty(), &int_pointer);
+        assert!(matches!(value.kind, ExpressionKind::Cast(..)));
+        assert_eq!(
+            returned("void *f(double *p) { return p; }").ty(),
+            &void_pointer
+        );
+        assert_eq!(
+            returned("void *f(int *a, void *b) { return 1 ? a : b; }").ty(),
+            &void_pointer
+        );
+        assert_eq!(
+            returned("int f(long *a, void *b) { return a != b; }").ty(),
+            &Type::Int
+        );
+    }
+
+    #[test]
+    fn test_void_pointers_run() {
+        let code = "
+            void *malloc(unsigned long size);
+            void free(void *ptr);
+
+            int main(void) {
+                long *values = malloc(10 * 8);
+                void *untyped = values;
+                for (int i = 0; i < 10; i = i + 1)
+                    values[i] = i * i;
+                long *again = 1 ? untyped : values;
+                long sum = 0;
+                for (int i = 0; i < 10; i = i + 1)
+                    sum = sum + again[i];
+                int same = untyped == values;
+                free(values);
+                return sum == 285 && same;
+            }
+        ";
+        assert_eq!(c::run(code), 1);
+    }
+
+    #[test]
+    fn test_check_static_initializers() {
+        let (_, symbols) = analyze(
+            "int a;
+             int b = 3;
+             int b;
+             extern int c;
+             static long d[3] = {1, -2};
+             double e = 3;
+             unsigned int u = -1;
+             int f(void) { static int s; return s; }",
+        );
+        let init = |name: &str| match &symbols.get(name).unwrap().attributes {
+            Attributes::Static { init, .. } => init.clone(),
+            attributes => panic!("expected a static variable, found {attributes:?}."),
+        };
+
+        assert_eq!(init("a"), InitialValue::Tentative);
+        assert_eq!(
+            init("b"),
+            InitialValue::Initial(vec![StaticInit::Const(Const::Int(3))])
+        );
+        assert_eq!(init("c"), InitialValue::NoInitializer);
+        assert_eq!(
+            init("d"),
+            InitialValue::Initial(vec![
+                StaticInit::Const(Const::Long(1)),
+                StaticInit::Const(Const::Long(-2)),
+                StaticInit::Zero(8),
+            ])
+        );
+        assert_eq!(
+            init("e"),
+            InitialValue::Initial(vec![StaticInit::Const(Const::Double(3.0))])
+        );
+        assert_eq!(
+            init("u"),
+            InitialValue::Initial(vec![StaticInit::Const(Const::UInt(u32::MAX))])
+        );
+        assert_eq!(
+            init("s.0"),
+            InitialValue::Initial(vec![StaticInit::Zero(4)])
+        );
+        assert_eq!(
+            symbols.get("d").unwrap().attributes,
+            Attributes::Static {
+                init: init("d"),
+                global: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_linkage() {
+        let (_, symbols) = analyze(
+            "static int f(void);
+             int f(void) { return 0; }
+             extern int f(void);",
+        );
+        assert_eq!(
+            symbols.get("f").unwrap().attributes,
+            Attributes::Function {
+                defined: true,
+                global: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_errors() {
+        let cases = [
+            (
+                "int f(int a);\nint f(long a);",
+                Code::ConflictingDeclaration,
+                "conflicting types for 'f'",
+                "test.c:2:5",
+            ),
+            (
+                "int g;\nint g(void);",
+                Code::ConflictingDeclaration,
+                "'g' redeclared as different kind of symbol",
+                "test.c:2:5",
+            ),
+            (
+                "int x = 1;\nint x = 2;",
+                Code::ConflictingDeclaration,
+                "redefinition of 'x'",
+                "test.c:2:5",
+            ),
+            (
+                "int f(void);\nstatic int f(void);",
+                Code::ConflictingDeclaration,
+                "static declaration of 'f' follows non-static declaration",
+                "test.c:2:12",
+            ),
+            (
+                "int f(void) { 1 = 2; return 0; }",
+                Code::NotAnLvalue,
+                "expression is not assignable",
+                "test.c:1:15",
+            ),
+            (
+                "int f(void) { int a[2]; int b[2]; a = b; return 0; }",
+                Code::NotAnLvalue,
+                "array type 'int [2]' is not assignable",
+                "test.c:1:35",
+            ),
+            (
+                "int *f(void) { return &3; }",
+                Code::NotAnLvalue,
+                "cannot take the address of an rvalue",
+                "test.c:1:24",
+            ),
+            (
+                "int f(void) { int *p = 5; return 0; }",
+                Code::TypeMismatch,
+                "mismatched types: expected 'int *', found 'int'",
+                "test.c:1:24",
+            ),
+            (
+                "int f(double d) { return d % 2; }",
+                Code::TypeMismatch,
+                "invalid operands to binary '%' ('double' and 'int')",
+                "test.c:1:26",
+            ),
+            (
+                "int f(void) { return *1; }",
+                Code::TypeMismatch,
+                "cannot dereference a value of type 'int'",
+                "test.c:1:22",
+            ),
+            (
+                "int f(void) { return; }",
+                Code::TypeMismatch,
+                "function returning 'int' should return a value",
+                "test.c:1:15",
+            ),
+            (
+                "void f(void) { return 1; }",
+                Code::TypeMismatch,
+                "function returning 'void' should not return a value",
+                "test.c:1:23",
+            ),
+            (
+                "int f(int a);\nint g(void) { return f(1, 2); }",
+                Code::ArgumentCount,
+                "function 'f' takes 1 argument, but 2 were given",
+                "test.c:2:22",
+            ),
+            (
+                "int a[2] = {1, 2, 3};",
+                Code::InvalidInitializer,
+                "excess elements in array initializer",
+                "test.c:1:19",
+            ),
+            (
+                "int f(void);\nint a = f();",
+                Code::InvalidInitializer,
+                "initializer element is not a constant",
+                "test.c:2:9",
+            ),
+            (
+                "int a[2] = 1;",
+                Code::InvalidInitializer,
+                "array of type 'int [2]' must be initialized with braces",
+                "test.c:1:12",
+            ),
+            (
+                "int f(void) { extern int x = 1; return x; }",
+                Code::InvalidInitializer,
+                "'extern' variable 'x' cannot have an initializer",
+                "test.c:1:30",
+            ),
+            (
+                "void x;",
+                Code::InvalidDeclaration,
+                "variable has incomplete type 'void'",
+                "test.c:1:6",
+            ),
+        ];
+        for (code, error_code, message, location) in cases {
+            assert_eq!(
+                errors(code),
+                vec![(Some(error_code), message.to_owned(), location.to_owned())],
+                "{code}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_reports_each_statement() {
+        let found = errors("int f(void) {\n  *1;\n  1 = 2;\n  return 0;\n}");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].2, "test.c:3:3");
+
+        // Uses of a name whose declaration had an error are still checked.
+        let found = errors("int f(void) {\n  int a[2] = 1;\n  return a[0] + *a[1];\n}");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].2, "test.c:3:17");
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+/// Tab stops are this many columns apart, as in GCC's diagnostics.
+const TAB_WIDTH: usize = 8;
+
+/// Identifies a file added to a `SourceMap`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct FileId(usize);
+
+/// A range of bytes, `start..end`, in one file of a `SourceMap`.
+///
+/// Spans are plain offsets so every token can carry one cheaply; turning them into lines and
+/// columns is left to `SourceMap::location`, which only diagnostics need.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Span {
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The smallest span covering both `self` and `other`, which must be in the same file.
+    pub fn to(self, other: Span) -> Span {
+        debug_assert_eq!(self.file, other.file);
+        Span {
+            file: self.file,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// A position in a file as a person would count it, for printing.
+///
+/// For preprocessed code this is the position in the file the code came from, according to the
+/// line markers in it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Location {
+    pub file: Rc<str>,
+    /// 1-based.
+    pub line: usize,
+    /// 1-based display column: characters, not bytes, with tabs advancing to the next tab stop.
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// The line starting at byte `offset` is line `line` of `file`, and so on until the next marker.
+struct LineMarker {
+    offset: usize,
+    line: usize,
+    file: Rc<str>,
+}
+
+struct SourceFile {
+    name: Rc<str>,
+    code: String,
+    // Byte offset where each line starts, built the first time a location is asked for.
+    line_starts: OnceCell<Vec<usize>>,
+    // Sorted by offset. Added by the lexer as it finds them, hence the `RefCell`.
+    line_markers: RefCell<Vec<LineMarker>>,
+}
+
+impl SourceFile {
+    fn line_starts(&self) -> &[usize] {
+        self.line_starts.get_or_init(|| {
+            std::iter::once(0)
+                .chain(self.code.match_indices('\n').map(|(i, _)| i + 1))
+                .collect()
+        })
+    }
+
+    /// The 1-based number of the line containing byte `offset`, counting every line in the file.
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts().partition_point(|&start| start <= offset)
+    }
+}
+
+/// Owns the code of every file being compiled, and maps spans back to it.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file named `name` (used when printing locations) containing `code`.
+    pub fn add_file(&mut self, name: String, code: String) -> FileId {
+        self.files.push(SourceFile {
+            name: name.into(),
+            code,
+            line_starts: OnceCell::new(),
+            line_markers: RefCell::new(Vec::new()),
+        });
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn code(&self, file: FileId) -> &str {
+        &self.files[file.0].code
+    }
+
+    /// Records a line marker, `# line "name"`, whose next line starts at byte `offset` of
+    /// `file`: from there on locations are reported as `line` and the lines after it in `name`.
+    /// Without a `name` the file stays what the previous marker said.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` comes before the previous marker of `file`.
+    pub fn add_line_marker(&self, file: FileId, offset: usize, line: usize, name: Option<&str>) {
+        let source_file = &self.files[file.0];
+        let mut markers = source_file.line_markers.borrow_mut();
+
+        if markers.last().is_some_and(|marker| marker.offset > offset) {
+            panic!("line markers must be added in order.");
+        }
+        // Headers are entered and left many times, so share the name with the previous marker
+        // when it is the same.
+        let previous = markers
+            .last()
+            .map_or(&source_file.name, |marker| &marker.file);
+        let file = match name {
+            Some(name) if name != &**previous => Rc::from(name),
+            _ => previous.clone(),
+        };
+
+        markers.push(LineMarker { offset, line, file });
+    }
+
+    /// The source text `span` covers.
+    pub fn text(&self, span: Span) -> &str {
+        &self.code(span.file)[span.start..span.end]
+    }
+
+    /// Where `span` starts.
+    pub fn location(&self, span: Span) -> Location {
+        let file = &self.files[span.file.0];
+        let line = file.line(span.start);
+        let column = display_width(&file.code[file.line_starts()[line - 1]..span.start]) + 1;
+
+        let markers = file.line_markers.borrow();
+        let marker = markers
+            .partition_point(|marker| marker.offset <= span.start)
+            .checked_sub(1)
+            .map(|i| &markers[i]);
+        match marker {
+            Some(marker) => Location {
+                file: marker.file.clone(),
+                line: marker.line + line - file.line(marker.offset),
+                column,
+            },
+            None => Location {
+                file: file.name.clone(),
+                line,
+                column,
+            },
+        }
+    }
+
+    /// The line `span` starts on, without its line ending.
+    pub fn line(&self, span: Span) -> Span {
+        let file = &self.files[span.file.0];
+        let line = file.line(span.start);
+        let start = file.line_starts()[line - 1];
+        let end = file
+            .line_starts()
+            .get(line)
+            .map_or(file.code.len(), |next| next - 1);
+        let end = start + file.code[start..end].trim_end_matches('\r').len();
+
+        Span {
+            file: span.file,
+            start,
+            end,
+        }
+    }
+}
+
+/// How many columns `text` takes up when printed from the start of a line.
+pub fn display_width(text: &str) -> usize {
+    text.chars().fold(0, |width, ch| match ch {
+        '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+        _ => width + 1,
+    })
+}
+
+/// `text`, which starts a line, with its tabs replaced by spaces up to the next tab stop.
+pub fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut width = 0;
+    for ch in text.chars() {
+        match ch {
+            '\t' => {
+                let next = (width / TAB_WIDTH + 1) * TAB_WIDTH;
+                expanded.extend(std::iter::repeat_n(' ', next - width));
+                width = next;
+            }
+            _ => {
+                expanded.push(ch);
+                width += 1;
+            }
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(file: FileId, start: usize, end: usize) -> Span {
+        Span { file, start, end }
+    }
+
+    #[test]
+    fn test_location() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.c".to_owned(), "int x;\n\nint y;\n".to_owned());
+
+        assert_eq!(source_map.location(span(file, 0, 3)).to_string(), "a.c:1:1");
+        assert_eq!(source_map.location(span(file, 6, 7)).to_string(), "a.c:1:7");
+        assert_eq!(source_map.location(span(file, 7, 7)).to_string(), "a.c:2:1");
+        assert_eq!(
+            source_map.location(span(file, 12, 13)).to_string(),
+            "a.c:3:5"
+        );
+    }
+
+    #[test]
+    fn test_location_after_tabs_and_multibyte_characters() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.c".to_owned(), "\tx;\n/* é */ y;\n  \t z;".to_owned());
+
+        let x = source_map.location(span(file, 1, 2));
+        assert_eq!((x.line, x.column), (1, 9));
+
+        // `é` is two bytes but one column.
+        let y = source_map.location(span(file, 13, 14));
+        assert_eq!((y.line, y.column), (2, 9));
+
+        let z = source_map.location(span(file, 20, 21));
+        assert_eq!((z.line, z.column), (3, 10));
+    }
+
+    #[test]
+    fn test_text_and_line() {
+        let mut source_map = SourceMap::new();
+        let first = source_map.add_file("a.c".to_owned(), "int a;\r\n".to_owned());
+        let second =
+            source_map.add_file("b.c".to_owned(), "int main(void)\n{ return 0; }".to_owned());
+
+        assert_eq!(source_map.text(span(second, 4, 8)), "main");
+        assert_eq!(
+            source_map.text(span(second, 4, 8).to(span(second, 9, 13))),
+            "main(void"
+        );
+        assert_eq!(
+            source_map.text(source_map.line(span(second, 17, 23))),
+            "{ return 0; }"
+        );
+        assert_eq!(source_map.line(span(first, 4, 5)), span(first, 0, 6));
+        assert_eq!(expand_tabs("\tx\ty"), "        x       y");
+        assert_eq!(&*source_map.location(span(first, 4, 5)).file, "a.c");
+    }
+
+    #[test]
+    fn test_location_after_line_markers() {
+        let mut source_map = SourceMap::new();
+        let code = "# 1 \"a.c\"\n\
+                    # 1 \"a.h\" 1\n\
+                    int f(void);\n\
+                    # 3 \"a.c\" 2\n\
+                    \n\
+                    int x = 1;\n";
+        let file = source_map.add_file("a.i".to_owned(), code.to_owned());
+        source_map.add_line_marker(file, 10, 1, Some("a.c"));
+        source_map.add_line_marker(file, 22, 1, Some("a.h"));
+        source_map.add_line_marker(file, 47, 3, Some("a.c"));
+
+        let location = |start| {
+            source_map
+                .location(span(file, start, start + 1))
+                .to_string()
+        };
+        assert_eq!(location(0), "a.i:1:1");
+        assert_eq!(location(26), "a.h:1:5");
+        assert_eq!(location(52), "a.c:4:5");
+    }
+
+    #[test]
+    fn test_line_marker_without_name() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file("a.i".to_owned(), "a\nb\nc\n".to_owned());
+        source_map.add_line_marker(file, 2, 10, Some("a.c"));
+        source_map.add_line_marker(file, 4, 20, None);
+
+        assert_eq!(
+            source_map.location(span(file, 2, 3)).to_string(),
+            "a.c:10:1"
+        );
+        assert_eq!(
+            source_map.location(span(file, 4, 5)).to_string(),
+            "a.c:20:1"
+        );
+    }
+}
@@ -0,0 +1,801 @@
+//! TACKY: the three-address intermediate representation that sits between the parser and
+//! assembly generation.
+//!
+//! Every instruction reads at most two operands and writes at most one destination, control
+//! flow is expressed with labels and jumps, and intermediate results live in temporaries
+//! created through `SymbolTable::make_temporary`.
+
+pub mod generation;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+};
+
+use crate::source_map::Span;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Type {
+    Char,
+    SChar,
+    UChar,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Double,
+    Void,
+    Pointer(Box<Type>),
+    Array(Box<Type>, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Const {
+    Char(i8),
+    UChar(u8),
+    Int(i32),
+    UInt(u32),
+    Long(i64),
+    ULong(u64),
+    Double(f64),
+}
+
+impl Const {
+    pub fn ty(&self) -> Type {
+        match self {
+            Const::Char(_) => Type::Char,
+            Const::UChar(_) => Type::UChar,
+            Const::Int(_) => Type::Int,
+            Const::UInt(_) => Type::UInt,
+            Const::Long(_) => Type::Long,
+            Const::ULong(_) => Type::ULong,
+            Const::Double(_) => Type::Double,
+        }
+    }
+}
+
+// Doubles compare by bit pattern, so `0.0` and `-0.0` stay distinct and a NaN constant equals
+// itself. Passes compare constants as operands, not as numbers.
+impl PartialEq for Const {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Const::Char(a), Const::Char(b)) => a == b,
+            (Const::UChar(a), Const::UChar(b)) => a == b,
+            (Const::Int(a), Const::Int(b)) => a == b,
+            (Const::UInt(a), Const::UInt(b)) => a == b,
+            (Const::Long(a), Const::Long(b)) => a == b,
+            (Const::ULong(a), Const::ULong(b)) => a == b,
+            (Const::Double(a), Const::Double(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Const {}
+
+impl Hash for Const {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Const::Char(c) => c.hash(state),
+            Const::UChar(c) => c.hash(state),
+            Const::Int(i) => i.hash(state),
+            Const::UInt(u) => u.hash(state),
+            Const::Long(l) => l.hash(state),
+            Const::ULong(u) => u.hash(state),
+            Const::Double(d) => d.to_bits().hash(state),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Val {
+    Constant(Const),
+    Var(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum UnaryOp {
+    Complement,
+    Negate,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Instruction {
+    Return(Option<Val>),
+    SignExtend {
+        src: Val,
+        dst: Val,
+    },
+    Truncate {
+        src: Val,
+        dst: Val,
+    },
+    ZeroExtend {
+        src: Val,
+        dst: Val,
+    },
+    DoubleToInt {
+        src: Val,
+        dst: Val,
+    },
+    DoubleToUInt {
+        src: Val,
+        dst: Val,
+    },
+    IntToDouble {
+        src: Val,
+        dst: Val,
+    },
+    UIntToDouble {
+        src: Val,
+        dst: Val,
+    },
+    Unary {
+        op: UnaryOp,
+        src: Val,
+        dst: Val,
+    },
+    Binary {
+        op: BinaryOp,
+        src1: Val,
+        src2: Val,
+        dst: Val,
+    },
+    Copy {
+        src: Val,
+        dst: Val,
+    },
+    GetAddress {
+        src: Val,
+        dst: Val,
+    },
+    Load {
+        src_ptr: Val,
+        dst: Val,
+    },
+    Store {
+        src: Val,
+        dst_ptr: Val,
+    },
+    AddPtr {
+        ptr: Val,
+        index: Val,
+        scale: usize,
+        dst: Val,
+    },
+    CopyToOffset {
+        src: Val,
+        dst: String,
+        offset: usize,
+    },
+    // Only needed to read structure members, which the parser doesn't accept yet.
+    #[allow(dead_code)]
+    CopyFromOffset {
+        src: String,
+        offset: usize,
+        dst: Val,
+    },
+    Jump(String),
+    JumpIfZero {
+        condition: Val,
+        target: String,
+    },
+    JumpIfNotZero {
+        condition: Val,
+        target: String,
+    },
+    Label(String),
+    FunCall {
+        name: String,
+        args: Vec<Val>,
+        dst: Option<Val>,
+    },
+}
+
+impl Instruction {
+    /// Returns the values this instruction reads.
+    ///
+    /// The operand of `GetAddress` and the aggregate `CopyFromOffset` reads from aren't
+    /// included, since they name objects rather than values.
+    pub fn sources(&self) -> Vec<&Val> {
+        match self {
+            Instruction::Return(val) => val.iter().collect(),
+            Instruction::SignExtend { src, .. }
+            | Instruction::Truncate { src, .. }
+            | Instruction::ZeroExtend { src, .. }
+            | Instruction::DoubleToInt { src, .. }
+            | Instruction::DoubleToUInt { src, .. }
+            | Instruction::IntToDouble { src, .. }
+            | Instruction::UIntToDouble { src, .. }
+            | Instruction::Unary { src, .. }
+            | Instruction::Copy { src, .. }
+            | Instruction::CopyToOffset { src, .. } => vec![src],
+            Instruction::Binary { src1, src2, .. } => vec![src1, src2],
+            Instruction::Load { src_ptr, .. } => vec![src_ptr],
+            Instruction::Store { src, dst_ptr } => vec![src, dst_ptr],
+            Instruction::AddPtr { ptr, index, .. } => vec![ptr, index],
+            Instruction::JumpIfZero { condition, .. }
+            | Instruction::JumpIfNotZero { condition, .. } => vec![condition],
+            Instruction::FunCall { args, .. } => args.iter().collect(),
+            Instruction::GetAddress { .. }
+            | Instruction::CopyFromOffset { .. }
+            | Instruction::Jump(_)
+            | Instruction::Label(_) => vec![],
+        }
+    }
+
+    /// Returns the value this instruction overwrites in full, if any.
+    ///
+    /// `Store` writes through a pointer and `CopyToOffset` writes part of an aggregate, so
+    /// neither has a destination in this sense.
+    pub fn destination(&self) -> Option<&Val> {
+        match self {
+            Instruction::SignExtend { dst, .. }
+            | Instruction::Truncate { dst, .. }
+            | Instruction::ZeroExtend { dst, .. }
+            | Instruction::DoubleToInt { dst, .. }
+            | Instruction::DoubleToUInt { dst, .. }
+            | Instruction::IntToDouble { dst, .. }
+            | Instruction::UIntToDouble { dst, .. }
+            | Instruction::Unary { dst, .. }
+            | Instruction::Binary { dst, .. }
+            | Instruction::Copy { dst, .. }
+            | Instruction::GetAddress { dst, .. }
+            | Instruction::Load { dst, .. }
+            | Instruction::AddPtr { dst, .. }
+            | Instruction::CopyFromOffset { dst, .. } => Some(dst),
+            Instruction::FunCall { dst, .. } => dst.as_ref(),
+            Instruction::Return(_)
+            | Instruction::Store { .. }
+            | Instruction::CopyToOffset { .. }
+            | Instruction::Jump(_)
+            | Instruction::JumpIfZero { .. }
+            | Instruction::JumpIfNotZero { .. }
+            | Instruction::Label(_) => None,
+        }
+    }
+
+    /// Like `sources`, but allows rewriting the values in place.
+    pub fn sources_mut(&mut self) -> Vec<&mut Val> {
+        match self {
+            Instruction::Return(val) => val.iter_mut().collect(),
+            Instruction::SignExtend { src, .. }
+            | Instruction::Truncate { src, .. }
+            | Instruction::ZeroExtend { src, .. }
+            | Instruction::DoubleToInt { src, .. }
+            | Instruction::DoubleToUInt { src, .. }
+            | Instruction::IntToDouble { src, .. }
+            | Instruction::UIntToDouble { src, .. }
+            | Instruction::Unary { src, .. }
+            | Instruction::Copy { src, .. }
+            | Instruction::CopyToOffset { src, .. } => vec![src],
+            Instruction::Binary { src1, src2, .. } => vec![src1, src2],
+            Instruction::Load { src_ptr, .. } => vec![src_ptr],
+            Instruction::Store { src, dst_ptr } => vec![src, dst_ptr],
+            Instruction::AddPtr { ptr, index, .. } => vec![ptr, index],
+            Instruction::JumpIfZero { condition, .. }
+            | Instruction::JumpIfNotZero { condition, .. } => vec![condition],
+            Instruction::FunCall { args, .. } => args.iter_mut().collect(),
+            Instruction::GetAddress { .. }
+            | Instruction::CopyFromOffset { .. }
+            | Instruction::Jump(_)
+            | Instruction::Label(_) => vec![],
+        }
+    }
+
+    /// Like `destination`, but allows rewriting the value in place.
+    pub fn destination_mut(&mut self) -> Option<&mut Val> {
+        match self {
+            Instruction::SignExtend { dst, .. }
+            | Instruction::Truncate { dst, .. }
+            | Instruction::ZeroExtend { dst, .. }
+            | Instruction::DoubleToInt { dst, .. }
+            | Instruction::DoubleToUInt { dst, .. }
+            | Instruction::IntToDouble { dst, .. }
+            | Instruction::UIntToDouble { dst, .. }
+            | Instruction::Unary { dst, .. }
+            | Instruction::Binary { dst, .. }
+            | Instruction::Copy { dst, .. }
+            | Instruction::GetAddress { dst, .. }
+            | Instruction::Load { dst, .. }
+            | Instruction::AddPtr { dst, .. }
+            | Instruction::CopyFromOffset { dst, .. } => Some(dst),
+            Instruction::FunCall { dst, .. } => dst.as_mut(),
+            Instruction::Return(_)
+            | Instruction::Store { .. }
+            | Instruction::CopyToOffset { .. }
+            | Instruction::Jump(_)
+            | Instruction::JumpIfZero { .. }
+            | Instruction::JumpIfNotZero { .. }
+            | Instruction::Label(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub global: bool,
+    pub params: Vec<String>,
+    pub body: Vec<Instruction>,
+    /// Where the function is defined, which diagnostics about its body point at.
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StaticInit {
+    Const(Const),
+    /// `n` zero bytes.
+    Zero(usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub ty: Type,
+    pub init: Vec<StaticInit>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TopLevel {
+    Function(Function),
+    StaticVariable(StaticVariable),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Storage {
+    /// Automatic storage: parameters, locals and temporaries.
+    Local,
+    /// Static storage duration, visible to other functions and therefore to any call.
+    Static,
+    Function,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub ty: Type,
+    pub storage: Storage,
+    /// Accesses to a volatile object are observable and must never be removed, merged or
+    /// reordered by an optimization pass.
+    pub volatile: bool,
+}
+
+/// Every name a TACKY program refers to, along with the counter used to make fresh names.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+    counter: usize,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, symbol: Symbol) {
+        self.symbols.insert(name.to_owned(), symbol);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.symbols.iter()
+    }
+
+    pub fn is_volatile(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|symbol| symbol.volatile)
+    }
+
+    /// Declares a fresh local temporary of type `ty` and returns its name.
+    ///
+    /// Generated names contain a `.`, so they can never collide with a C identifier, and skip
+    /// any name already declared, like a local variable identifier resolution renamed.
+    pub fn make_temporary(&mut self, ty: Type) -> String {
+        let name = self.fresh_name("tmp");
+        self.insert(
+            &name,
+            Symbol {
+                ty,
+                storage: Storage::Local,
+                volatile: false,
+            },
+        );
+        name
+    }
+
+    /// Declares a fresh variable with the same type, storage and qualifiers as `name`, and
+    /// returns its name.
+    pub fn make_copy(&mut self, name: &str) -> String {
+        let copy = self.make_copy_name(name);
+        if let Some(symbol) = self.get(name).cloned() {
+            self.insert(&copy, symbol);
+        }
+        copy
+    }
+
+    /// Returns a fresh name for a copy of `name` like `make_copy`, without declaring it yet.
+    pub fn make_copy_name(&mut self, name: &str) -> String {
+        self.fresh_name(name)
+    }
+
+    /// Returns a fresh label name starting with `prefix`.
+    pub fn make_label(&mut self, prefix: &str) -> String {
+        format!("{prefix}.{}", self.next_id())
+    }
+
+    /// `prefix` followed by the next id that makes a name nothing is declared as yet.
+    fn fresh_name(&mut self, prefix: &str) -> String {
+        loop {
+            let name = format!("{prefix}.{}", self.next_id());
+            if self.get(&name).is_none() {
+                return name;
+            }
+        }
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.counter;
+        self.counter += 1;
+        id
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub top_level: Vec<TopLevel>,
+    pub symbols: SymbolTable,
+}
+
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Const::Char(c) => write!(f, "(char){c}"),
+            Const::UChar(c) => write!(f, "(unsigned char){c}"),
+            Const::Int(i) => write!(f, "{i}"),
+            Const::UInt(u) => write!(f, "{u}U"),
+            Const::Long(l) => write!(f, "{l}L"),
+            Const::ULong(u) => write!(f, "{u}UL"),
+            Const::Double(d) => write!(f, "{d:?}"),
+        }
+    }
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Val::Constant(c) => write!(f, "{c}"),
+            Val::Var(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            UnaryOp::Complement => "~",
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::LessThan => "<",
+            BinaryOp::LessOrEqual => "<=",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::GreaterOrEqual => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Return(Some(val)) => write!(f, "return {val}"),
+            Instruction::Return(None) => write!(f, "return"),
+            Instruction::SignExtend { src, dst } => write!(f, "{dst} = sign_extend {src}"),
+            Instruction::Truncate { src, dst } => write!(f, "{dst} = truncate {src}"),
+            Instruction::ZeroExtend { src, dst } => write!(f, "{dst} = zero_extend {src}"),
+            Instruction::DoubleToInt { src, dst } => write!(f, "{dst} = double_to_int {src}"),
+            Instruction::DoubleToUInt { src, dst } => write!(f, "{dst} = double_to_uint {src}"),
+            Instruction::IntToDouble { src, dst } => write!(f, "{dst} = int_to_double {src}"),
+            Instruction::UIntToDouble { src, dst } => write!(f, "{dst} = uint_to_double {src}"),
+            Instruction::Unary { op, src, dst } => write!(f, "{dst} = {op}{src}"),
+            Instruction::Binary {
+                op,
+                src1,
+                src2,
+                dst,
+            } => write!(f, "{dst} = {src1} {op} {src2}"),
+            Instruction::Copy { src, dst } => write!(f, "{dst} = {src}"),
+            Instruction::GetAddress { src, dst } => write!(f, "{dst} = &{src}"),
+            Instruction::Load { src_ptr, dst } => write!(f, "{dst} = *{src_ptr}"),
+            Instruction::Store { src, dst_ptr } => write!(f, "*{dst_ptr} = {src}"),
+            Instruction::AddPtr {
+                ptr,
+                index,
+                scale,
+                dst,
+            } => write!(f, "{dst} = {ptr} + {index} * {scale}"),
+            Instruction::CopyToOffset { src, dst, offset } => {
+                write!(f, "{dst}[{offset}] = {src}")
+            }
+            Instruction::CopyFromOffset { src, offset, dst } => {
+                write!(f, "{dst} = {src}[{offset}]")
+            }
+            Instruction::Jump(target) => write!(f, "jump {target}"),
+            Instruction::JumpIfZero { condition, target } => {
+                write!(f, "jump_if_zero {condition}, {target}")
+            }
+            Instruction::JumpIfNotZero { condition, target } => {
+                write!(f, "jump_if_not_zero {condition}, {target}")
+            }
+            Instruction::Label(label) => write!(f, "{label}:"),
+            Instruction::FunCall { name, args, dst } => {
+                if let Some(dst) = dst {
+                    write!(f, "{dst} = ")?;
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "call {name}({args})")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.global {
+            write!(f, "global ")?;
+        }
+        writeln!(f, "function {}({}) {{", self.name, self.params.join(", "))?;
+        for instruction in &self.body {
+            match instruction {
+                Instruction::Label(_) => writeln!(f, "  {instruction}")?,
+                _ => writeln!(f, "    {instruction}")?,
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for StaticVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.global {
+            write!(f, "global ")?;
+        }
+        let init = self
+            .init
+            .iter()
+            .map(|init| match init {
+                StaticInit::Const(c) => c.to_string(),
+                StaticInit::Zero(n) => format!("zero[{n}]"),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(f, "static {}: {:?} = {{ {init} }}", self.name, self.ty)
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, top_level) in self.top_level.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match top_level {
+                TopLevel::Function(function) => writeln!(f, "{function}")?,
+                TopLevel::StaticVariable(variable) => writeln!(f, "{variable}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::tacky::{span, var};
+
+    #[test]
+    fn test_make_temporary() {
+        let mut symbols = SymbolTable::new();
+        let tmp0 = symbols.make_temporary(Type::Int);
+        let tmp1 = symbols.make_temporary(Type::Long);
+
+        assert_eq!(tmp0, "tmp.0");
+        assert_eq!(tmp1, "tmp.1");
+        assert_eq!(
+            symbols.get("tmp.1"),
+            Some(&Symbol {
+                ty: Type::Long,
+                storage: Storage::Local,
+                volatile: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_fresh_names_skip_declared_names() {
+        let mut symbols = SymbolTable::new();
+        let symbol = Symbol {
+            ty: Type::Int,
+            storage: Storage::Local,
+            volatile: false,
+        };
+        symbols.insert("tmp.0", symbol.clone());
+        symbols.insert("x.2", symbol);
+
+        assert_eq!(symbols.make_temporary(Type::Int), "tmp.1");
+        assert_eq!(symbols.make_copy_name("x"), "x.3");
+    }
+
+    #[test]
+    fn test_make_label_shares_counter() {
+        let mut symbols = SymbolTable::new();
+        let tmp = symbols.make_temporary(Type::Int);
+        let label = symbols.make_label("end");
+
+        assert_eq!(tmp, "tmp.0");
+        assert_eq!(label, "end.1");
+        assert_eq!(symbols.get("end.1"), None);
+    }
+
+    #[test]
+    fn test_make_copy() {
+        let mut symbols = SymbolTable::new();
+        let symbol = Symbol {
+            ty: Type::Long,
+            storage: Storage::Local,
+            volatile: true,
+        };
+        symbols.insert("x", symbol.clone());
+
+        assert_eq!(symbols.make_copy("x"), "x.0");
+        assert_eq!(symbols.get("x.0"), Some(&symbol));
+    }
+
+    #[test]
+    fn test_const_equality_is_bitwise() {
+        assert_eq!(Const::Double(f64::NAN), Const::Double(f64::NAN));
+        assert_ne!(Const::Double(0.0), Const::Double(-0.0));
+        assert_ne!(Const::Int(1), Const::Long(1));
+    }
+
+    #[test]
+    fn test_sources_and_destination() {
+        let call = Instruction::FunCall {
+            name: "f".to_owned(),
+            args: vec![var("a"), Val::Constant(Const::Int(1))],
+            dst: Some(var("x")),
+        };
+        assert_eq!(
+            call.sources(),
+            vec![&var("a"), &Val::Constant(Const::Int(1))]
+        );
+        assert_eq!(call.destination(), Some(&var("x")));
+
+        let store = Instruction::Store {
+            src: var("a"),
+            dst_ptr: var("p"),
+        };
+        assert_eq!(store.sources(), vec![&var("a"), &var("p")]);
+        assert_eq!(store.destination(), None);
+
+        let address = Instruction::GetAddress {
+            src: var("a"),
+            dst: var("p"),
+        };
+        assert!(address.sources().is_empty());
+        assert_eq!(address.destination(), Some(&var("p")));
+    }
+
+    #[test]
+    fn test_display_consts() {
+        assert_eq!(Const::Int(-3).to_string(), "-3");
+        assert_eq!(Const::UInt(3).to_string(), "3U");
+        assert_eq!(Const::Long(3).to_string(), "3L");
+        assert_eq!(Const::ULong(3).to_string(), "3UL");
+        assert_eq!(Const::Double(3.0).to_string(), "3.0");
+        assert_eq!(Const::Char(-1).to_string(), "(char)-1");
+    }
+
+    #[test]
+    fn test_display_program() {
+        let program = Program {
+            top_level: vec![
+                TopLevel::StaticVariable(StaticVariable {
+                    name: "counter".to_owned(),
+                    global: false,
+                    ty: Type::Int,
+                    init: vec![StaticInit::Const(Const::Int(0))],
+                }),
+                TopLevel::Function(Function {
+                    name: "main".to_owned(),
+                    global: true,
+                    params: vec!["a".to_owned()],
+                    body: vec![
+                        Instruction::Binary {
+                            op: BinaryOp::Add,
+                            src1: var("a"),
+                            src2: Val::Constant(Const::Int(1)),
+                            dst: var("tmp.0"),
+                        },
+                        Instruction::JumpIfZero {
+                            condition: var("tmp.0"),
+                            target: "end.1".to_owned(),
+                        },
+                        Instruction::FunCall {
+                            name: "f".to_owned(),
+                            args: vec![var("a"), var("tmp.0")],
+                            dst: Some(var("tmp.2")),
+                        },
+                        Instruction::Label("end.1".to_owned()),
+                        Instruction::Return(Some(var("tmp.0"))),
+                    ],
+                    span: span(),
+                }),
+            ],
+            symbols: SymbolTable::new(),
+        };
+
+        assert_eq!(
+            program.to_string(),
+            "static counter: Int = { 0 }\n\
+             \n\
+             global function main(a) {\n\
+            \x20   tmp.0 = a + 1\n\
+            \x20   jump_if_zero tmp.0, end.1\n\
+            \x20   tmp.2 = call f(a, tmp.0)\n\
+            \x20 end.1:\n\
+            \x20   return tmp.0\n\
+             }\n"
+        );
+    }
+}
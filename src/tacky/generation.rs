@@ -0,0 +1,895 @@
+//! Lowers the type-checked AST to TACKY.
+
+use crate::{
+    ast::{
+        self, BinaryOp as AstBinaryOp, Block, BlockItem, Declaration, Expression, ExpressionKind,
+        ForInit, FunctionDeclaration, Initializer, LoopId, PostfixOp, Statement, StatementKind,
+        Type as AstType, VariableDeclaration,
+    },
+    optimizer::convert,
+    semantic::{self, Attributes, InitialValue},
+};
+
+use super::{
+    BinaryOp, Const, Function, Instruction, Program, StaticInit, StaticVariable, Storage, Symbol,
+    SymbolTable, TopLevel, UnaryOp, Val,
+};
+
+/// The result of lowering an expression: either a value, or the address of the object the
+/// expression designates, which is only loaded from or stored to once it's known which.
+enum ExpResult {
+    Plain(Val),
+    Dereferenced(Val),
+}
+
+struct Generator<'a> {
+    symbols: &'a mut SymbolTable,
+    body: Vec<Instruction>,
+}
+
+/// Lowers `program`, which semantic analysis has resolved and type-checked into `symbols`.
+///
+/// Every function definition becomes a TACKY function, in source order, followed by every
+/// variable with static storage duration that is defined in this file.
+pub fn generate(program: &ast::Program, symbols: &semantic::SymbolTable) -> Program {
+    let mut tacky = Program {
+        top_level: Vec::new(),
+        symbols: SymbolTable::new(),
+    };
+
+    // Every name is declared up front, so fresh names never collide with one.
+    for (name, symbol) in symbols.iter() {
+        let (ty, storage) = match (&symbol.ty, &symbol.attributes) {
+            (AstType::Function { ret, .. }, _) => (ret.to_tacky(), Storage::Function),
+            (ty, Attributes::Static { .. }) => (ty.to_tacky(), Storage::Static),
+            (ty, _) => (ty.to_tacky(), Storage::Local),
+        };
+        tacky.symbols.insert(
+            name,
+            Symbol {
+                ty,
+                storage,
+                volatile: false,
+            },
+        );
+    }
+
+    for declaration in &program.declarations {
+        if let Declaration::Function(function) = declaration {
+            if function.body.is_some() {
+                let function = Generator::function(function, symbols, &mut tacky.symbols);
+                tacky.top_level.push(TopLevel::Function(function));
+            }
+        }
+    }
+
+    for (name, symbol) in symbols.iter() {
+        let Attributes::Static { init, global } = &symbol.attributes else {
+            continue;
+        };
+        let init = match init {
+            InitialValue::Initial(init) => init.clone(),
+            InitialValue::Tentative => vec![StaticInit::Zero(symbol.ty.size())],
+            InitialValue::NoInitializer => continue,
+        };
+        tacky
+            .top_level
+            .push(TopLevel::StaticVariable(StaticVariable {
+                name: name.clone(),
+                global: *global,
+                ty: symbol.ty.to_tacky(),
+                init,
+            }));
+    }
+
+    tacky
+}
+
+impl Generator<'_> {
+    fn function(
+        function: &FunctionDeclaration,
+        semantic_symbols: &semantic::SymbolTable,
+        symbols: &mut SymbolTable,
+    ) -> Function {
+        let mut generator = Generator {
+            symbols,
+            body: Vec::new(),
+        };
+        generator.block(function.body.as_ref().unwrap());
+
+        // Falling off the end of a function returns zero, which `main` relies on.
+        let AstType::Function { ret, .. } = &function.ty else {
+            unreachable!("functions have function types.");
+        };
+        let value = match **ret {
+            AstType::Void => None,
+            _ => Some(Val::Constant(zero(ret))),
+        };
+        generator.body.push(Instruction::Return(value));
+
+        let global = match semantic_symbols.get(&function.name).map(|s| &s.attributes) {
+            Some(Attributes::Function { global, .. }) => *global,
+            _ => true,
+        };
+        Function {
+            name: function.name.clone(),
+            global,
+            params: function
+                .params
+                .iter()
+                .map(|param| {
+                    param
+                        .name
+                        .clone()
+                        .expect("defined functions name every parameter.")
+                })
+                .collect(),
+            body: generator.body,
+            span: function.span,
+        }
+    }
+
+    //-------------------------
+    // Statements
+
+    fn block(&mut self, block: &Block) {
+        for item in &block.items {
+            match item {
+                BlockItem::Statement(statement) => self.statement(statement),
+                BlockItem::Declaration(Declaration::Variable(variable)) => {
+                    self.local_variable(variable)
+                }
+                // Nothing to do until the function is called.
+                BlockItem::Declaration(Declaration::Function(_)) => (),
+            }
+        }
+    }
+
+    fn local_variable(&mut self, variable: &VariableDeclaration) {
+        // Static and `extern` variables are initialized before the program starts.
+        if variable.storage_class.is_some() {
+            return;
+        }
+        match &variable.init {
+            Some(Initializer::Single(init)) => {
+                let src = self.expression_and_convert(init);
+                self.push(Instruction::Copy {
+                    src,
+                    dst: Val::Var(variable.name.clone()),
+                });
+            }
+            Some(init) => self.compound_initializer(init, &variable.ty, &variable.name, 0),
+            None => (),
+        }
+    }
+
+    /// Initializes the part of array `name` starting at `offset`, which has type `ty`, from
+    /// `init`. Elements without an initializer are set to zero.
+    fn compound_initializer(
+        &mut self,
+        init: &Initializer,
+        ty: &AstType,
+        name: &str,
+        offset: usize,
+    ) {
+        match (init, ty) {
+            (Initializer::Compound(inits, _), AstType::Array(element, len)) => {
+                for i in 0..*len {
+                    let offset = offset + i * element.size();
+                    match inits.get(i) {
+                        Some(init) => self.compound_initializer(init, element, name, offset),
+                        None => self.zero_initialize(element, name, offset),
+                    }
+                }
+            }
+            (Initializer::Single(init), _) => {
+                let src = self.expression_and_convert(init);
+                self.push(Instruction::CopyToOffset {
+                    src,
+                    dst: name.to_owned(),
+                    offset,
+                });
+            }
+            _ => unreachable!("the type checker matches initializers to their types."),
+        }
+    }
+
+    fn zero_initialize(&mut self, ty: &AstType, name: &str, offset: usize) {
+        match ty {
+            AstType::Array(element, len) => {
+                for i in 0..*len {
+                    self.zero_initialize(element, name, offset + i * element.size());
+                }
+            }
+            ty => self.push(Instruction::CopyToOffset {
+                src: Val::Constant(zero(ty)),
+                dst: name.to_owned(),
+                offset,
+            }),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Return(value) => {
+                let value = value
+                    .as_ref()
+                    .map(|value| self.expression_and_convert(value));
+                self.push(Instruction::Return(value));
+            }
+            StatementKind::Expression(expression) => {
+                self.expression(expression);
+            }
+            StatementKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let end = self.symbols.make_label("if_end");
+                let condition = self.expression_and_convert(condition);
+                match otherwise {
+                    None => {
+                        self.jump_if_zero(condition, &end);
+                        self.statement(then);
+                    }
+                    Some(otherwise) => {
+                        let else_label = self.symbols.make_label("else");
+                        self.jump_if_zero(condition, &else_label);
+                        self.statement(then);
+                        self.push(Instruction::Jump(end.clone()));
+                        self.push(Instruction::Label(else_label));
+                        self.statement(otherwise);
+                    }
+                }
+                self.push(Instruction::Label(end));
+            }
+            StatementKind::Compound(block) => self.block(block),
+            StatementKind::Break(id) => self.push(Instruction::Jump(break_label(*id))),
+            StatementKind::Continue(id) => self.push(Instruction::Jump(continue_label(*id))),
+            StatementKind::While {
+                condition,
+                body,
+                id,
+            } => {
+                self.push(Instruction::Label(continue_label(*id)));
+                let condition = self.expression_and_convert(condition);
+                self.jump_if_zero(condition, &break_label(*id));
+                self.statement(body);
+                self.push(Instruction::Jump(continue_label(*id)));
+                self.push(Instruction::Label(break_label(*id)));
+            }
+            StatementKind::DoWhile {
+                body,
+                condition,
+                id,
+            } => {
+                let start = self.symbols.make_label("do_start");
+                self.push(Instruction::Label(start.clone()));
+                self.statement(body);
+                self.push(Instruction::Label(continue_label(*id)));
+                let condition = self.expression_and_convert(condition);
+                self.push(Instruction::JumpIfNotZero {
+                    condition,
+                    target: start,
+                });
+                self.push(Instruction::Label(break_label(*id)));
+            }
+            StatementKind::For {
+                init,
+                condition,
+                post,
+                body,
+                id,
+            } => {
+                match init {
+                    ForInit::Declarations(declarations) => {
+                        for declaration in declarations {
+                            self.local_variable(declaration);
+                        }
+                    }
+                    ForInit::Expression(Some(init)) => {
+                        self.expression(init);
+                    }
+                    ForInit::Expression(None) => (),
+                }
+                let start = self.symbols.make_label("for_start");
+                self.push(Instruction::Label(start.clone()));
+                if let Some(condition) = condition {
+                    let condition = self.expression_and_convert(condition);
+                    self.jump_if_zero(condition, &break_label(*id));
+                }
+                self.statement(body);
+                self.push(Instruction::Label(continue_label(*id)));
+                if let Some(post) = post {
+                    self.expression(post);
+                }
+                self.push(Instruction::Jump(start));
+                self.push(Instruction::Label(break_label(*id)));
+            }
+            StatementKind::Null => (),
+        }
+    }
+
+    //-------------------------
+    // Expressions
+
+    /// Lowers `expression` and loads its value if it designates an object through a pointer.
+    fn expression_and_convert(&mut self, expression: &Expression) -> Val {
+        match self.expression(expression) {
+            ExpResult::Plain(val) => val,
+            ExpResult::Dereferenced(ptr) => {
+                let dst = self.temporary(expression.ty());
+                self.push(Instruction::Load {
+                    src_ptr: ptr,
+                    dst: dst.clone(),
+                });
+                dst
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> ExpResult {
+        let ty = expression.ty();
+
+        match &expression.kind {
+            ExpressionKind::Constant(value) => ExpResult::Plain(Val::Constant(*value)),
+            ExpressionKind::Var(name) => ExpResult::Plain(Val::Var(name.clone())),
+            ExpressionKind::Cast(target, operand) => {
+                let val = self.expression_and_convert(operand);
+                ExpResult::Plain(self.convert(val, operand.ty(), target))
+            }
+            ExpressionKind::Unary(op, operand) => {
+                let src = self.expression_and_convert(operand);
+                let dst = self.temporary(ty);
+                let op = match op {
+                    ast::UnaryOp::Complement => UnaryOp::Complement,
+                    ast::UnaryOp::Negate => UnaryOp::Negate,
+                    ast::UnaryOp::Not => UnaryOp::Not,
+                };
+                self.push(Instruction::Unary {
+                    op,
+                    src,
+                    dst: dst.clone(),
+                });
+                ExpResult::Plain(dst)
+            }
+            ExpressionKind::Binary(AstBinaryOp::And, left, right) => {
+                ExpResult::Plain(self.short_circuit(left, right, true))
+            }
+            ExpressionKind::Binary(AstBinaryOp::Or, left, right) => {
+                ExpResult::Plain(self.short_circuit(left, right, false))
+            }
+            ExpressionKind::Binary(op, left, right) => {
+                let src1 = self.expression_and_convert(left);
+                let src2 = self.expression_and_convert(right);
+                ExpResult::Plain(self.binary(*op, src1, left.ty(), src2, right.ty(), ty))
+            }
+            ExpressionKind::Assignment(left, right) => {
+                let target = self.expression(left);
+                let src = self.expression_and_convert(right);
+                match target {
+                    ExpResult::Plain(dst) => {
+                        self.push(Instruction::Copy {
+                            src,
+                            dst: dst.clone(),
+                        });
+                        ExpResult::Plain(dst)
+                    }
+                    ExpResult::Dereferenced(ptr) => {
+                        self.push(Instruction::Store {
+                            src: src.clone(),
+                            dst_ptr: ptr,
+                        });
+                        ExpResult::Plain(src)
+                    }
+                }
+            }
+            ExpressionKind::CompoundAssignment(op, left, right) => {
+                let target = self.expression(left);
+                let current = self.current_value(&target, ty);
+                let src2 = self.expression_and_convert(right);
+
+                // The operation is done in the type the type checker converted `right` to,
+                // except for shifts, whose operands are promoted separately, and pointer
+                // arithmetic.
+                let op_ty = match op {
+                    _ if ty.is_pointer() => ty.clone(),
+                    AstBinaryOp::ShiftLeft | AstBinaryOp::ShiftRight => promote(ty),
+                    _ => right.ty().clone(),
+                };
+                let src1 = self.convert(current, ty, &op_ty);
+                let result = self.binary(*op, src1, &op_ty, src2, right.ty(), &op_ty);
+                let result = self.convert(result, &op_ty, ty);
+                ExpResult::Plain(self.assign(target, result))
+            }
+            ExpressionKind::Postfix(op, operand) => {
+                let target = self.expression(operand);
+                let current = self.current_value(&target, ty);
+                let old = self.temporary(ty);
+                self.push(Instruction::Copy {
+                    src: current,
+                    dst: old.clone(),
+                });
+
+                let updated = match ty {
+                    AstType::Pointer(referenced) => {
+                        let index = match op {
+                            PostfixOp::Increment => Const::Long(1),
+                            PostfixOp::Decrement => Const::Long(-1),
+                        };
+                        let dst = self.temporary(ty);
+                        self.push(Instruction::AddPtr {
+                            ptr: old.clone(),
+                            index: Val::Constant(index),
+                            scale: referenced.size(),
+                            dst: dst.clone(),
+                        });
+                        dst
+                    }
+                    _ => {
+                        let op_ty = promote(ty);
+                        let one = convert(Const::Int(1), &op_ty.to_tacky())
+                            .expect("1 fits in every arithmetic type.");
+                        let op = match op {
+                            PostfixOp::Increment => BinaryOp::Add,
+                            PostfixOp::Decrement => BinaryOp::Subtract,
+                        };
+                        let src1 = self.convert(old.clone(), ty, &op_ty);
+                        let dst = self.temporary(&op_ty);
+                        self.push(Instruction::Binary {
+                            op,
+                            src1,
+                            src2: Val::Constant(one),
+                            dst: dst.clone(),
+                        });
+                        self.convert(dst, &op_ty, ty)
+                    }
+                };
+                self.assign(target, updated);
+                ExpResult::Plain(old)
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let else_label = self.symbols.make_label("conditional_else");
+                let end = self.symbols.make_label("conditional_end");
+                let result = match ty {
+                    AstType::Void => None,
+                    ty => Some(self.temporary(ty)),
+                };
+
+                let condition = self.expression_and_convert(condition);
+                self.jump_if_zero(condition, &else_label);
+                for (branch, label) in [(then, None), (otherwise, Some(else_label))] {
+                    if let Some(label) = label {
+                        self.push(Instruction::Jump(end.clone()));
+                        self.push(Instruction::Label(label));
+                    }
+                    let val = self.expression_and_convert(branch);
+                    if let Some(result) = &result {
+                        self.push(Instruction::Copy {
+                            src: val,
+                            dst: result.clone(),
+                        });
+                    }
+                }
+                self.push(Instruction::Label(end));
+                ExpResult::Plain(result.unwrap_or_else(void))
+            }
+            ExpressionKind::FunctionCall(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.expression_and_convert(arg))
+                    .collect();
+                let dst = match ty {
+                    AstType::Void => None,
+                    ty => Some(self.temporary(ty)),
+                };
+                self.push(Instruction::FunCall {
+                    name: name.clone(),
+                    args,
+                    dst: dst.clone(),
+                });
+                ExpResult::Plain(dst.unwrap_or_else(void))
+            }
+            ExpressionKind::Dereference(operand) => {
+                ExpResult::Dereferenced(self.expression_and_convert(operand))
+            }
+            ExpressionKind::AddressOf(operand) => match self.expression(operand) {
+                ExpResult::Plain(src) => {
+                    let dst = self.temporary(ty);
+                    self.push(Instruction::GetAddress {
+                        src,
+                        dst: dst.clone(),
+                    });
+                    ExpResult::Plain(dst)
+                }
+                ExpResult::Dereferenced(ptr) => ExpResult::Plain(ptr),
+            },
+            ExpressionKind::Subscript(left, right) => {
+                let (pointer, index) = match left.ty() {
+                    AstType::Pointer(_) => (left, right),
+                    _ => (right, left),
+                };
+                let ptr = self.expression_and_convert(pointer);
+                let index = self.expression_and_convert(index);
+                let dst = self.temporary(pointer.ty());
+                self.push(Instruction::AddPtr {
+                    ptr,
+                    index,
+                    scale: ty.size(),
+                    dst: dst.clone(),
+                });
+                ExpResult::Dereferenced(dst)
+            }
+        }
+    }
+
+    /// Lowers `left && right` if `and`, else `left || right`, evaluating `right` only if
+    /// `left` doesn't decide the result.
+    fn short_circuit(&mut self, left: &Expression, right: &Expression, and: bool) -> Val {
+        let (prefix, decided) = match and {
+            true => ("and_false", Const::Int(0)),
+            false => ("or_true", Const::Int(1)),
+        };
+        let decided_label = self.symbols.make_label(prefix);
+        let end = self.symbols.make_label(&format!("{prefix}_end"));
+        let result = self.temporary(&AstType::Int);
+
+        for operand in [left, right] {
+            let condition = self.expression_and_convert(operand);
+            let target = decided_label.clone();
+            self.push(match and {
+                true => Instruction::JumpIfZero { condition, target },
+                false => Instruction::JumpIfNotZero { condition, target },
+            });
+        }
+        let undecided = match and {
+            true => Const::Int(1),
+            false => Const::Int(0),
+        };
+        self.push(Instruction::Copy {
+            src: Val::Constant(undecided),
+            dst: result.clone(),
+        });
+        self.push(Instruction::Jump(end.clone()));
+        self.push(Instruction::Label(decided_label));
+        self.push(Instruction::Copy {
+            src: Val::Constant(decided),
+            dst: result.clone(),
+        });
+        self.push(Instruction::Label(end));
+        result
+    }
+
+    /// Applies `op` to operands of the given types, with the result in a new temporary of type
+    /// `ty`. Adding an integer to a pointer or subtracting it, and subtracting two pointers,
+    /// are scaled by the size of what the pointers point to.
+    fn binary(
+        &mut self,
+        op: AstBinaryOp,
+        src1: Val,
+        ty1: &AstType,
+        src2: Val,
+        ty2: &AstType,
+        ty: &AstType,
+    ) -> Val {
+        let dst = self.temporary(ty);
+
+        match (op, ty1, ty2) {
+            (AstBinaryOp::Add | AstBinaryOp::Subtract, AstType::Pointer(referenced), ty2)
+                if ty2.is_integer() =>
+            {
+                let index = match op {
+                    AstBinaryOp::Subtract => {
+                        let negated = self.temporary(ty2);
+                        self.push(Instruction::Unary {
+                            op: UnaryOp::Negate,
+                            src: src2,
+                            dst: negated.clone(),
+                        });
+                        negated
+                    }
+                    _ => src2,
+                };
+                self.push(Instruction::AddPtr {
+                    ptr: src1,
+                    index,
+                    scale: referenced.size(),
+                    dst: dst.clone(),
+                });
+            }
+            (AstBinaryOp::Add, ty1, AstType::Pointer(referenced)) if ty1.is_integer() => {
+                self.push(Instruction::AddPtr {
+                    ptr: src2,
+                    index: src1,
+                    scale: referenced.size(),
+                    dst: dst.clone(),
+                });
+            }
+            (AstBinaryOp::Subtract, AstType::Pointer(referenced), AstType::Pointer(_)) => {
+                let difference = self.temporary(&AstType::Long);
+                self.push(Instruction::Binary {
+                    op: BinaryOp::Subtract,
+                    src1,
+                    src2,
+                    dst: difference.clone(),
+                });
+                self.push(Instruction::Binary {
+                    op: BinaryOp::Divide,
+                    src1: difference,
+                    src2: Val::Constant(Const::Long(referenced.size() as i64)),
+                    dst: dst.clone(),
+                });
+            }
+            (op, _, _) => {
+                self.push(Instruction::Binary {
+                    op: binary_op(op),
+                    src1,
+                    src2,
+                    dst: dst.clone(),
+                });
+            }
+        }
+        dst
+    }
+
+    /// The value of the object `target` designates, which has type `ty`.
+    fn current_value(&mut self, target: &ExpResult, ty: &AstType) -> Val {
+        match target {
+            ExpResult::Plain(val) => val.clone(),
+            ExpResult::Dereferenced(ptr) => {
+                let dst = self.temporary(ty);
+                self.push(Instruction::Load {
+                    src_ptr: ptr.clone(),
+                    dst: dst.clone(),
+                });
+                dst
+            }
+        }
+    }
+
+    /// Stores `src` in the object `target` designates, returning the value stored.
+    fn assign(&mut self, target: ExpResult, src: Val) -> Val {
+        match target {
+            ExpResult::Plain(dst) => {
+                self.push(Instruction::Copy {
+                    src,
+                    dst: dst.clone(),
+                });
+                dst
+            }
+            ExpResult::Dereferenced(ptr) => {
+                self.push(Instruction::Store {
+                    src: src.clone(),
+                    dst_ptr: ptr,
+                });
+                src
+            }
+        }
+    }
+
+    /// Converts `val` from type `from` to type `to`.
+    fn convert(&mut self, val: Val, from: &AstType, to: &AstType) -> Val {
+        if from == to {
+            return val;
+        }
+        if *to == AstType::Void {
+            return void();
+        }
+
+        let dst = self.temporary(to);
+        let instruction = match (from, to) {
+            (AstType::Double, to) if to.is_signed() => Instruction::DoubleToInt { src: val, dst },
+            (AstType::Double, _) => Instruction::DoubleToUInt { src: val, dst },
+            (from, AstType::Double) if from.is_signed() => {
+                Instruction::IntToDouble { src: val, dst }
+            }
+            (_, AstType::Double) => Instruction::UIntToDouble { src: val, dst },
+            (from, to) if to.size() == from.size() => Instruction::Copy { src: val, dst },
+            (from, to) if to.size() < from.size() => Instruction::Truncate { src: val, dst },
+            (from, _) if from.is_signed() => Instruction::SignExtend { src: val, dst },
+            _ => Instruction::ZeroExtend { src: val, dst },
+        };
+        let dst = instruction.destination().unwrap().clone();
+        self.push(instruction);
+        dst
+    }
+
+    fn temporary(&mut self, ty: &AstType) -> Val {
+        Val::Var(self.symbols.make_temporary(ty.to_tacky()))
+    }
+
+    fn jump_if_zero(&mut self, condition: Val, target: &str) {
+        self.push(Instruction::JumpIfZero {
+            condition,
+            target: target.to_owned(),
+        });
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        self.body.push(instruction);
+    }
+}
+
+/// Stands in for the value of a `void` expression, which nothing reads.
+fn void() -> Val {
+    Val::Constant(Const::Int(0))
+}
+
+/// Zero of scalar type `ty`.
+fn zero(ty: &AstType) -> Const {
+    convert(Const::Int(0), &ty.to_tacky()).expect("0 fits in every scalar type.")
+}
+
+/// `ty` after the integer promotions.
+fn promote(ty: &AstType) -> AstType {
+    match ty.is_character() {
+        true => AstType::Int,
+        false => ty.clone(),
+    }
+}
+
+fn break_label(id: LoopId) -> String {
+    format!("break.loop{id}")
+}
+
+fn continue_label(id: LoopId) -> String {
+    format!("continue.loop{id}")
+}
+
+fn binary_op(op: AstBinaryOp) -> BinaryOp {
+    match op {
+        AstBinaryOp::Add => BinaryOp::Add,
+        AstBinaryOp::Subtract => BinaryOp::Subtract,
+        AstBinaryOp::Multiply => BinaryOp::Multiply,
+        AstBinaryOp::Divide => BinaryOp::Divide,
+        AstBinaryOp::Remainder => BinaryOp::Remainder,
+        AstBinaryOp::BitAnd => BinaryOp::BitAnd,
+        AstBinaryOp::BitOr => BinaryOp::BitOr,
+        AstBinaryOp::BitXor => BinaryOp::BitXor,
+        AstBinaryOp::ShiftLeft => BinaryOp::ShiftLeft,
+        AstBinaryOp::ShiftRight => BinaryOp::ShiftRight,
+        AstBinaryOp::Equal => BinaryOp::Equal,
+        AstBinaryOp::NotEqual => BinaryOp::NotEqual,
+        AstBinaryOp::LessThan => BinaryOp::LessThan,
+        AstBinaryOp::LessOrEqual => BinaryOp::LessOrEqual,
+        AstBinaryOp::GreaterThan => BinaryOp::GreaterThan,
+        AstBinaryOp::GreaterOrEqual => BinaryOp::GreaterOrEqual,
+        AstBinaryOp::And | AstBinaryOp::Or => {
+            unreachable!("'&&' and '||' are lowered to jumps.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::c::tacky;
+
+    #[test]
+    fn test_generate_expressions() {
+        assert_eq!(
+            tacky("int f(int a, long b) { return -a * 2 + b; }").to_string(),
+            "global function f(a.0, b.1) {\n\
+            \x20   tmp.0 = -a.0\n\
+            \x20   tmp.1 = tmp.0 * 2\n\
+            \x20   tmp.2 = sign_extend tmp.1\n\
+            \x20   tmp.3 = tmp.2 + b.1\n\
+            \x20   tmp.4 = truncate tmp.3\n\
+            \x20   return tmp.4\n\
+            \x20   return 0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_short_circuit() {
+        assert_eq!(
+            tacky("int f(int a, int b) { return a || b; }").to_string(),
+            "global function f(a.0, b.1) {\n\
+            \x20   jump_if_not_zero a.0, or_true.0\n\
+            \x20   jump_if_not_zero b.1, or_true.0\n\
+            \x20   tmp.2 = 0\n\
+            \x20   jump or_true_end.1\n\
+            \x20 or_true.0:\n\
+            \x20   tmp.2 = 1\n\
+            \x20 or_true_end.1:\n\
+            \x20   return tmp.2\n\
+            \x20   return 0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_loops() {
+        assert_eq!(
+            tacky(
+                "void f(int n) {
+                     while (n) {
+                         if (n == 3) continue;
+                         n--;
+                     }
+                 }"
+            )
+            .to_string(),
+            "global function f(n.0) {\n\
+            \x20 continue.loop0:\n\
+            \x20   jump_if_zero n.0, break.loop0\n\
+            \x20   tmp.1 = n.0 == 3\n\
+            \x20   jump_if_zero tmp.1, if_end.0\n\
+            \x20   jump continue.loop0\n\
+            \x20 if_end.0:\n\
+            \x20   tmp.2 = n.0\n\
+            \x20   tmp.3 = tmp.2 - 1\n\
+            \x20   n.0 = tmp.3\n\
+            \x20   jump continue.loop0\n\
+            \x20 break.loop0:\n\
+            \x20   return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_pointers_and_arrays() {
+        assert_eq!(
+            tacky(
+                "int f(void) {
+                     int a[3] = {7};
+                     int *p = a;
+                     *(p + 1) = 2;
+                     return a[1];
+                 }"
+            )
+            .to_string(),
+            "global function f() {\n\
+            \x20   a.0[0] = 7\n\
+            \x20   a.0[4] = 0\n\
+            \x20   a.0[8] = 0\n\
+            \x20   tmp.0 = &a.0\n\
+            \x20   p.1 = tmp.0\n\
+            \x20   tmp.1 = sign_extend 1\n\
+            \x20   tmp.2 = p.1 + tmp.1 * 4\n\
+            \x20   *tmp.2 = 2\n\
+            \x20   tmp.3 = &a.0\n\
+            \x20   tmp.4 = sign_extend 1\n\
+            \x20   tmp.5 = tmp.3 + tmp.4 * 4\n\
+            \x20   tmp.6 = *tmp.5\n\
+            \x20   return tmp.6\n\
+            \x20   return 0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_static_variables() {
+        let program = tacky(
+            "extern int e;
+             static int s = 3;
+             long t;
+             int f(void) { static double d = 1; return s + e; }",
+        );
+        let statics: Vec<String> = program
+            .to_string()
+            .lines()
+            .filter(|line| line.contains("static "))
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(
+            statics,
+            [
+                "static d.0: Double = { 1.0 }",
+                "static s: Int = { 3 }",
+                "global static t: Long = { zero[8] }",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_avoids_resolved_names() {
+        // `tmp` is renamed `tmp.0`, which a temporary must not reuse.
+        let program = tacky("int f(int tmp) { return -tmp; }");
+        assert!(program.to_string().contains("tmp.1 = -tmp.0"));
+    }
+}
@@ -0,0 +1,6 @@
+//! Builders for the IR fixtures tests are written with, shared so every test module spells
+//! them the same way, and C snippets run through the front end for tests of later stages.
+
+pub mod assembly;
+pub mod c;
+pub mod tacky;
@@ -0,0 +1,67 @@
+use super::c;
+use crate::assembly::{
+    codegen, instruction_fixup, pseudo_replacement, register_allocation, Function, Instruction,
+    Operand, Program, Reg, TopLevel,
+};
+
+/// A global function named `f` that saves no callee-saved registers.
+pub fn function(instructions: Vec<Instruction>) -> Function {
+    Function {
+        name: "f".to_owned(),
+        global: true,
+        instructions,
+        callee_saved: vec![],
+        stack_arg_bytes: 0,
+    }
+}
+
+pub fn reg(reg: Reg) -> Operand {
+    Operand::Reg(reg)
+}
+
+/// The stack slot at `offset` from the frame pointer.
+pub fn stack(offset: i64) -> Operand {
+    Operand::Memory(Reg::BP, offset)
+}
+
+pub fn pseudo(name: &str) -> Operand {
+    Operand::Pseudo(name.to_owned())
+}
+
+/// The functions of small C programs, compiled up to but not including peephole
+/// optimization, for checking passes against realistic input rather than one pattern at a
+/// time.
+pub fn programs() -> Vec<Function> {
+    let Program { top_level, symbols } = codegen::generate(c::tacky(PROGRAMS));
+    top_level
+        .into_iter()
+        .filter_map(|top_level| match top_level {
+            TopLevel::Function(mut function) => {
+                register_allocation::allocate_registers(&mut function, &symbols);
+                pseudo_replacement::replace_pseudos(&mut function, &symbols);
+                instruction_fixup::fix_up_instructions(&mut function);
+                Some(function)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// `call_with_stack_arguments` keeps `b` live across a call with stack arguments.
+const PROGRAMS: &str = "
+    int sum_scaled(int *values, int n) {
+        int total = 0;
+        if (n <= 0)
+            return total;
+        for (int i = 0; i < n; i = i + 1)
+            total = total + values[i] * 4;
+        return total;
+    }
+
+    long g(long a, long b, long c, long d, long e, long f, long g, long h, long i);
+
+    long call_with_stack_arguments(long a) {
+        long b = a * 2;
+        return g(1, 2, 3, 4, 5, 6, 7, b, 9) + b;
+    }
+";
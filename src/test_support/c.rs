@@ -0,0 +1,114 @@
+use std::{
+    env, fs, process,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    assembly,
+    ast::Program,
+    diagnostics::{Code, Diagnostics},
+    lexer::Lexer,
+    optimizer::{self, Optimizations},
+    parser::Parser,
+    semantic::{self, SymbolTable},
+    source_map::SourceMap,
+    tacky,
+};
+
+/// Lexes, parses and analyzes `code` as `test.c`, keeping the source map for locations.
+fn compile(code: &str) -> (SourceMap, Program, SymbolTable, Diagnostics) {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("test.c".to_owned(), code.to_owned());
+    let mut diagnostics = Diagnostics::new(0);
+    let tokens = Lexer::new(&source_map, file).tokenize(&mut diagnostics);
+    let mut program = Parser::new(&source_map, file, &tokens).parse(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "test code should parse.");
+    let symbols = semantic::analyze(&mut program, &mut diagnostics);
+    (source_map, program, symbols, diagnostics)
+}
+
+/// The analyzed AST of `code` and the names it declares. `code` must have no errors.
+pub fn analyze(code: &str) -> (Program, SymbolTable) {
+    let (_, program, symbols, diagnostics) = compile(code);
+    assert!(
+        !diagnostics.has_errors(),
+        "test code should have no errors."
+    );
+    (program, symbols)
+}
+
+/// The code, message and location of every error semantic analysis finds in `code`, which
+/// must parse.
+pub fn errors(code: &str) -> Vec<(Option<Code>, String, String)> {
+    let (source_map, _, _, diagnostics) = compile(code);
+    diagnostics
+        .iter()
+        .map(|error| {
+            (
+                error.code,
+                error.message.clone(),
+                source_map.location(error.span.unwrap()).to_string(),
+            )
+        })
+        .collect()
+}
+
+/// `code` lowered to TACKY. `code` must have no errors.
+pub fn tacky(code: &str) -> tacky::Program {
+    let (program, symbols) = analyze(code);
+    tacky::generation::generate(&program, &symbols)
+}
+
+/// `code` compiled to assembly that's ready to emit. `code` must have no errors.
+pub fn assembly(code: &str) -> assembly::Program {
+    optimized_assembly(code, &Optimizations::default())
+}
+
+/// Like `assembly`, with the passes in `optimizations` run on the TACKY first.
+pub fn optimized_assembly(code: &str, optimizations: &Optimizations) -> assembly::Program {
+    let mut tacky = tacky(code);
+    optimizer::optimize(&mut tacky, optimizations, &mut Diagnostics::new(0));
+    lower(tacky)
+}
+
+fn lower(tacky: tacky::Program) -> assembly::Program {
+    let mut program = assembly::codegen::generate(tacky);
+    assembly::finish(&mut program);
+    program
+}
+
+/// Compiles `code`, links it with the C library and runs it, returning its exit status.
+/// `code` must have no errors.
+pub fn run(code: &str) -> i32 {
+    run_optimized(code, &Optimizations::default())
+}
+
+/// Like `run`, with the passes in `optimizations` run on the TACKY first.
+pub fn run_optimized(code: &str, optimizations: &Optimizations) -> i32 {
+    let mut tacky = tacky(code);
+    optimizer::optimize(&mut tacky, optimizations, &mut Diagnostics::new(0));
+    run_tacky(tacky)
+}
+
+/// Like `run`, for a program already lowered to TACKY, such as one a test has transformed.
+pub fn run_tacky(tacky: tacky::Program) -> i32 {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let base = env::temp_dir().join(format!("ccomp-test-{}-{id}", process::id()));
+    let assembly_file = base.with_extension("s");
+
+    fs::write(&assembly_file, lower(tacky).to_string()).unwrap();
+    let linked = Command::new("gcc")
+        .arg(&assembly_file)
+        .arg("-o")
+        .arg(&base)
+        .status()
+        .expect("failed to run gcc");
+    fs::remove_file(&assembly_file).unwrap();
+    assert!(linked.success(), "generated assembly should link.");
+
+    let status = Command::new(&base).status().unwrap();
+    fs::remove_file(&base).unwrap();
+    status.code().expect("test program should exit normally.")
+}
@@ -0,0 +1,96 @@
+use crate::{
+    source_map::{SourceMap, Span},
+    tacky::{BinaryOp, Const, Function, Instruction, Storage, Symbol, SymbolTable, Type, Val},
+};
+
+pub fn var(name: &str) -> Val {
+    Val::Var(name.to_owned())
+}
+
+pub fn int(i: i32) -> Val {
+    Val::Constant(Const::Int(i))
+}
+
+pub fn constant(c: Const) -> Val {
+    Val::Constant(c)
+}
+
+pub fn label(name: &str) -> Instruction {
+    Instruction::Label(name.to_owned())
+}
+
+pub fn jump(target: &str) -> Instruction {
+    Instruction::Jump(target.to_owned())
+}
+
+pub fn jump_if_zero(condition: &str, target: &str) -> Instruction {
+    Instruction::JumpIfZero {
+        condition: var(condition),
+        target: target.to_owned(),
+    }
+}
+
+pub fn copy(src: Val, dst: &str) -> Instruction {
+    Instruction::Copy { src, dst: var(dst) }
+}
+
+pub fn binary(op: BinaryOp, src1: Val, src2: Val, dst: &str) -> Instruction {
+    Instruction::Binary {
+        op,
+        src1,
+        src2,
+        dst: var(dst),
+    }
+}
+
+pub fn ret(val: Val) -> Instruction {
+    Instruction::Return(Some(val))
+}
+
+/// A global function named `f`.
+pub fn function(params: &[&str], body: Vec<Instruction>) -> Function {
+    Function {
+        name: "f".to_owned(),
+        global: true,
+        params: params.iter().map(|param| param.to_string()).collect(),
+        body,
+        span: span(),
+    }
+}
+
+/// The start of an empty file, for functions that weren't compiled from any source.
+pub fn span() -> Span {
+    let file = SourceMap::new().add_file("test.c".to_owned(), String::new());
+    Span {
+        file,
+        start: 0,
+        end: 0,
+    }
+}
+
+pub fn symbol(ty: Type, storage: Storage, volatile: bool) -> Symbol {
+    Symbol {
+        ty,
+        storage,
+        volatile,
+    }
+}
+
+/// A symbol table of non-volatile locals with the given types.
+pub fn symbols(locals: &[(&str, Type)]) -> SymbolTable {
+    let mut symbols = SymbolTable::new();
+    for (name, ty) in locals {
+        symbols.insert(name, symbol(ty.clone(), Storage::Local, false));
+    }
+    symbols
+}
+
+/// A symbol table of non-volatile `int` locals.
+pub fn ints(names: &[&str]) -> SymbolTable {
+    symbols(
+        &names
+            .iter()
+            .map(|name| (*name, Type::Int))
+            .collect::<Vec<_>>(),
+    )
+}